@@ -0,0 +1,261 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+// IMA (DVI) ADPCM, 4 bits per sample. Blocks of `block_align` bytes: a 4 byte
+// header per channel (first sample, step index, reserved), then groups of 4 bytes
+// (8 samples) per channel, interleaved, low nibble first.
+use anyhow::Result;
+
+use super::{AudioDecoder, AudioEncoder, AudioFormat};
+
+const STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66,
+    73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449,
+    494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272,
+    2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493,
+    10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+const INDEX_TABLE: [i32; 8] = [-1, -1, -1, -1, 2, 4, 6, 8];
+
+const HEADER_BYTES: usize = 4;
+const GROUP_BYTES: usize = 4; // 8 samples of a channel
+
+/// Frames in a block: the one in the header plus two per data byte
+pub fn samples_per_block(block_align: u16, channels: u16) -> usize {
+    let channels = channels.max(1) as usize;
+    (block_align as usize).saturating_sub(HEADER_BYTES * channels) * 2 / channels + 1
+}
+
+pub fn valid_format(format: &AudioFormat) -> bool {
+    let channels = format.channels as usize;
+    let data = (format.block_align as usize).saturating_sub(HEADER_BYTES * channels);
+    format.bits_per_sample == 4 && data > 0 && data.is_multiple_of(GROUP_BYTES * channels)
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct ChannelState {
+    predictor: i32,
+    index: i32,
+}
+
+impl ChannelState {
+    fn decode(&mut self, nibble: u8) -> i16 {
+        let step = STEP_TABLE[self.index as usize];
+        let mut diff = step >> 3;
+        if nibble & 1 != 0 {
+            diff += step >> 2;
+        }
+        if nibble & 2 != 0 {
+            diff += step >> 1;
+        }
+        if nibble & 4 != 0 {
+            diff += step;
+        }
+        if nibble & 8 != 0 {
+            diff = -diff;
+        }
+        self.predictor = (self.predictor + diff).clamp(i16::MIN as i32, i16::MAX as i32);
+        self.index = (self.index + INDEX_TABLE[(nibble & 7) as usize]).clamp(0, 88);
+        self.predictor as i16
+    }
+
+    fn encode(&mut self, sample: i16) -> u8 {
+        let mut step = STEP_TABLE[self.index as usize];
+        let mut diff = sample as i32 - self.predictor;
+        let mut nibble = 0;
+        if diff < 0 {
+            nibble = 8;
+            diff = -diff;
+        }
+        for bit in [4, 2, 1] {
+            if diff >= step {
+                nibble |= bit;
+                diff -= step;
+            }
+            step >>= 1;
+        }
+        // Same update as the decoder, so both predictors stay in sync
+        self.decode(nibble);
+        nibble
+    }
+}
+
+pub struct AdpcmDecoder {
+    channels: usize,
+    block_align: usize,
+}
+
+impl AdpcmDecoder {
+    pub fn new(format: &AudioFormat) -> Self {
+        AdpcmDecoder {
+            channels: format.channels.max(1) as usize,
+            block_align: format.block_align as usize,
+        }
+    }
+
+    fn decode_block(&self, block: &[u8], out: &mut Vec<f32>) {
+        let channels = self.channels;
+        if block.len() < HEADER_BYTES * channels {
+            return;
+        }
+        let (header, data) = block.split_at(HEADER_BYTES * channels);
+        let mut states: Vec<ChannelState> = header
+            .chunks_exact(HEADER_BYTES)
+            .map(|h| ChannelState {
+                predictor: i16::from_le_bytes([h[0], h[1]]) as i32,
+                index: (h[2] as i32).clamp(0, 88),
+            })
+            .collect();
+        // A short last block still has whole groups
+        let groups = data.len() / (GROUP_BYTES * channels);
+        let frames = 1 + groups * GROUP_BYTES * 2;
+        let start = out.len();
+        out.resize(start + frames * channels, 0.0);
+        let frames_out = &mut out[start..];
+        for (c, state) in states.iter().enumerate() {
+            frames_out[c] = state.predictor as f32 / i16::MAX as f32;
+        }
+        for (g, group) in data
+            .chunks_exact(GROUP_BYTES * channels)
+            .take(groups)
+            .enumerate()
+        {
+            for (c, bytes) in group.chunks_exact(GROUP_BYTES).enumerate() {
+                for (b, byte) in bytes.iter().enumerate() {
+                    for (n, nibble) in [byte & 0x0F, byte >> 4].into_iter().enumerate() {
+                        let frame = 1 + g * GROUP_BYTES * 2 + b * 2 + n;
+                        frames_out[frame * channels + c] =
+                            states[c].decode(nibble) as f32 / i16::MAX as f32;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl AudioDecoder for AdpcmDecoder {
+    fn decode(&mut self, data: &[u8]) -> Result<Vec<f32>> {
+        let mut out = Vec::with_capacity(data.len() * 2);
+        for block in data.chunks(self.block_align) {
+            self.decode_block(block, &mut out);
+        }
+        Ok(out)
+    }
+}
+
+pub struct AdpcmEncoder {
+    channels: usize,
+    block_align: usize,
+    frames_per_block: usize,
+    states: Vec<ChannelState>,
+    pending: Vec<f32>,
+}
+
+impl AdpcmEncoder {
+    pub fn new(format: &AudioFormat) -> Self {
+        let channels = format.channels.max(1) as usize;
+        AdpcmEncoder {
+            channels,
+            block_align: format.block_align as usize,
+            frames_per_block: samples_per_block(format.block_align, format.channels),
+            states: vec![ChannelState::default(); channels],
+            pending: Vec::new(),
+        }
+    }
+
+    fn encode_block(&mut self, samples: &[f32], out: &mut Vec<u8>) {
+        let channels = self.channels;
+        let start = out.len();
+        // First frame goes as is on the header, the step index carries over
+        for (c, state) in self.states.iter_mut().enumerate() {
+            let first = to_i16(samples[c]);
+            state.predictor = first as i32;
+            out.extend_from_slice(&first.to_le_bytes());
+            out.extend_from_slice(&[state.index as u8, 0]);
+        }
+        let body = &samples[channels..];
+        let frames_per_group = GROUP_BYTES * 2;
+        for group in body.chunks_exact(frames_per_group * channels) {
+            for (c, state) in self.states.iter_mut().enumerate() {
+                for pair in 0..GROUP_BYTES {
+                    let low = state.encode(to_i16(group[(pair * 2) * channels + c]));
+                    let high = state.encode(to_i16(group[(pair * 2 + 1) * channels + c]));
+                    out.push(low | (high << 4));
+                }
+            }
+        }
+        debug_assert_eq!(out.len() - start, self.block_align);
+    }
+}
+
+impl AudioEncoder for AdpcmEncoder {
+    fn encode(&mut self, samples: &[f32]) -> Result<Vec<Vec<u8>>> {
+        self.pending.extend_from_slice(samples);
+        let block_samples = self.frames_per_block * self.channels;
+        let blocks = self.pending.len() / block_samples;
+        if blocks == 0 {
+            return Ok(Vec::new());
+        }
+        // All complete blocks on a single packet
+        let mut packet = Vec::with_capacity(blocks * self.block_align);
+        let pending = std::mem::take(&mut self.pending);
+        for block in pending.chunks_exact(block_samples) {
+            self.encode_block(block, &mut packet);
+        }
+        self.pending = pending[blocks * block_samples..].to_vec();
+        Ok(vec![packet])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{round_trip, snr_db, tone};
+    use super::*;
+
+    #[test]
+    fn adpcm_round_trip() {
+        for (channels, rate) in [(1, 22050), (2, 44100), (2, 48000)] {
+            let format = AudioFormat::ima_adpcm(channels, rate);
+            let input = tone(rate, channels, 1.0, 440.0);
+            let output = round_trip(&format, &input, 480 * channels as usize);
+            // Whole blocks only, the rest waits for more samples
+            let block = format.block_align as usize;
+            let block_samples = samples_per_block(format.block_align, channels) * channels as usize;
+            assert_eq!(output.len(), input.len() / block_samples * block_samples);
+            let snr = snr_db(&input, &output);
+            assert!(snr > 25.0, "{}ch {}Hz: SNR {} dB", channels, rate, snr);
+            // 4 bits per sample, plus the headers
+            assert!((block as f32 / block_samples as f32) < 0.51);
+        }
+    }
+
+    #[test]
+    fn adpcm_known_block() {
+        // Mono block: header with predictor 1000 and step index 0, then the
+        // nibbles 0x7 and 0xF
+        let format = AudioFormat {
+            block_align: 8,
+            ..AudioFormat::ima_adpcm(1, 8000)
+        };
+        let block = [0xE8, 0x03, 0, 0, 0xF7, 0x00, 0x00, 0x00];
+        let mut decoder = AdpcmDecoder::new(&format);
+        let out: Vec<i16> = decoder
+            .decode(&block)
+            .unwrap()
+            .iter()
+            .map(|s| (s * i16::MAX as f32).round() as i16)
+            .collect();
+        // 7 = 4+2+1: step (7) + step/2 (3) + step/4 (1) + step/8 (0) = 11
+        assert_eq!(out[0], 1000);
+        assert_eq!(out[1], 1011);
+        // Index went to 8 (step 16): 16 + 8 + 4 + 2 = 30, negative
+        assert_eq!(out[2], 981);
+        assert_eq!(out.len(), 9);
+    }
+}
@@ -0,0 +1,279 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+// Audio formats of the RDP audio channels (AUDIO_FORMAT, a WAVEFORMATEX) and the
+// codecs for them. Output decodes what the server sends, input encodes the mic.
+// Negotiation prefers compressed formats and falls back to PCM.
+pub mod adpcm;
+pub mod opus;
+pub mod pcm;
+
+use anyhow::{Result, anyhow};
+use shared::log;
+
+pub const WAVE_FORMAT_PCM: u16 = 0x0001;
+pub const WAVE_FORMAT_DVI_ADPCM: u16 = 0x0011; // IMA ADPCM
+pub const WAVE_FORMAT_OPUS: u16 = 0x704F; // As used by FreeRDP
+
+// Best first. Opus sounds like PCM at a fraction of the bandwidth, IMA ADPCM is 4:1
+// and understood by every Windows server.
+const PREFERENCE: [u16; 3] = [WAVE_FORMAT_OPUS, WAVE_FORMAT_DVI_ADPCM, WAVE_FORMAT_PCM];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioFormat {
+    pub tag: u16,
+    pub channels: u16,
+    pub sample_rate: u32,
+    pub avg_bytes_per_sec: u32,
+    pub block_align: u16,
+    pub bits_per_sample: u16,
+    pub extra: Vec<u8>, // cbSize bytes after the WAVEFORMATEX
+}
+
+impl AudioFormat {
+    pub fn pcm(channels: u16, sample_rate: u32, bits_per_sample: u16) -> Self {
+        let block_align = channels * bits_per_sample.div_ceil(8);
+        AudioFormat {
+            tag: WAVE_FORMAT_PCM,
+            channels,
+            sample_rate,
+            avg_bytes_per_sec: sample_rate * block_align as u32,
+            block_align,
+            bits_per_sample,
+            extra: Vec::new(),
+        }
+    }
+
+    /// IMA ADPCM with the block size Windows uses for the rate
+    pub fn ima_adpcm(channels: u16, sample_rate: u32) -> Self {
+        let block_align = 256 * channels * (sample_rate / 11025).max(1) as u16;
+        let samples_per_block = adpcm::samples_per_block(block_align, channels);
+        AudioFormat {
+            tag: WAVE_FORMAT_DVI_ADPCM,
+            channels,
+            sample_rate,
+            avg_bytes_per_sec: sample_rate * block_align as u32 / samples_per_block as u32,
+            block_align,
+            bits_per_sample: 4,
+            extra: (samples_per_block as u16).to_le_bytes().to_vec(),
+        }
+    }
+
+    pub fn opus(channels: u16, sample_rate: u32) -> Self {
+        let bitrate = opus::BITRATE_PER_CHANNEL * channels as u32;
+        AudioFormat {
+            tag: WAVE_FORMAT_OPUS,
+            channels,
+            sample_rate,
+            avg_bytes_per_sec: bitrate / 8,
+            block_align: 1,
+            bits_per_sample: 16,
+            extra: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.tag {
+            WAVE_FORMAT_PCM => "PCM",
+            WAVE_FORMAT_DVI_ADPCM => "IMA ADPCM",
+            WAVE_FORMAT_OPUS => "Opus",
+            _ => "unknown",
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.tag != WAVE_FORMAT_PCM
+    }
+}
+
+impl std::fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}Hz {}ch {}bits",
+            self.name(),
+            self.sample_rate,
+            self.channels,
+            self.bits_per_sample
+        )
+    }
+}
+
+/// Decodes packets from the server to interleaved f32 samples, at the format rate
+/// and channels
+pub trait AudioDecoder: Send {
+    fn decode(&mut self, data: &[u8]) -> Result<Vec<f32>>;
+}
+
+/// Encodes interleaved f32 samples. Encoders with a fixed frame size keep the
+/// remainder for the next call, so zero or more packets come out of each call.
+pub trait AudioEncoder: Send {
+    fn encode(&mut self, samples: &[f32]) -> Result<Vec<Vec<u8>>>;
+}
+
+fn valid_channels(format: &AudioFormat) -> bool {
+    (1..=8).contains(&format.channels) && format.sample_rate > 0
+}
+
+pub fn can_decode(format: &AudioFormat) -> bool {
+    valid_channels(format)
+        && match format.tag {
+            WAVE_FORMAT_PCM => pcm::DECODABLE_BITS.contains(&format.bits_per_sample),
+            WAVE_FORMAT_DVI_ADPCM => adpcm::valid_format(format),
+            WAVE_FORMAT_OPUS => opus::valid_format(format) && opus::opus_available(),
+            _ => false,
+        }
+}
+
+pub fn can_encode(format: &AudioFormat) -> bool {
+    valid_channels(format)
+        && match format.tag {
+            WAVE_FORMAT_PCM => pcm::ENCODABLE_BITS.contains(&format.bits_per_sample),
+            WAVE_FORMAT_DVI_ADPCM => adpcm::valid_format(format),
+            WAVE_FORMAT_OPUS => opus::valid_format(format) && opus::opus_available(),
+            _ => false,
+        }
+}
+
+/// Index of the offered format to use: the preferred codec we can handle, at the
+/// best quality offered for it
+pub fn negotiate(offered: &[AudioFormat], usable: impl Fn(&AudioFormat) -> bool) -> Option<usize> {
+    let chosen = PREFERENCE.iter().find_map(|tag| {
+        offered
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| f.tag == *tag && usable(f))
+            .max_by_key(|(_, f)| (f.sample_rate, f.channels, f.bits_per_sample))
+            .map(|(i, _)| i)
+    });
+    match chosen {
+        Some(i) => log::info!("Audio format negotiated: {}", offered[i]),
+        None => log::warn!(
+            "No usable audio format offered: {:?}",
+            offered.iter().map(|f| f.to_string()).collect::<Vec<_>>()
+        ),
+    }
+    chosen
+}
+
+pub fn create_decoder(format: &AudioFormat) -> Result<Box<dyn AudioDecoder>> {
+    if !can_decode(format) {
+        return Err(anyhow!("Cannot decode audio format {}", format));
+    }
+    Ok(match format.tag {
+        WAVE_FORMAT_DVI_ADPCM => Box::new(adpcm::AdpcmDecoder::new(format)),
+        WAVE_FORMAT_OPUS => Box::new(opus::OpusDecoder::new(format)?),
+        _ => Box::new(pcm::PcmDecoder::new(format)),
+    })
+}
+
+pub fn create_encoder(format: &AudioFormat) -> Result<Box<dyn AudioEncoder>> {
+    if !can_encode(format) {
+        return Err(anyhow!("Cannot encode audio format {}", format));
+    }
+    Ok(match format.tag {
+        WAVE_FORMAT_DVI_ADPCM => Box::new(adpcm::AdpcmEncoder::new(format)),
+        WAVE_FORMAT_OPUS => Box::new(opus::OpusEncoder::new(format)?),
+        _ => Box::new(pcm::PcmEncoder::new(format)),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn tone(rate: u32, channels: u16, seconds: f32, freq: f32) -> Vec<f32> {
+        let frames = (rate as f32 * seconds) as usize;
+        (0..frames)
+            .flat_map(|i| {
+                let t = i as f32 / rate as f32;
+                // Each channel a bit different, so a swap would show
+                (0..channels).map(move |c| {
+                    0.5 * (2.0 * std::f32::consts::PI * freq * (c + 1) as f32 * t).sin()
+                })
+            })
+            .collect()
+    }
+
+    /// Signal to noise ratio of `decoded` against `original`, in dB
+    pub(crate) fn snr_db(original: &[f32], decoded: &[f32]) -> f32 {
+        let len = original.len().min(decoded.len());
+        let signal: f32 = original[..len].iter().map(|s| s * s).sum();
+        let noise: f32 = original[..len]
+            .iter()
+            .zip(&decoded[..len])
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum();
+        10.0 * (signal / noise.max(1e-20)).log10()
+    }
+
+    pub(crate) fn round_trip(format: &AudioFormat, input: &[f32], chunk: usize) -> Vec<f32> {
+        let mut encoder = create_encoder(format).unwrap();
+        let mut decoder = create_decoder(format).unwrap();
+        let mut output = Vec::new();
+        for samples in input.chunks(chunk) {
+            for packet in encoder.encode(samples).unwrap() {
+                output.extend(decoder.decode(&packet).unwrap());
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn negotiation_prefers_compressed() {
+        let offered = [
+            AudioFormat::pcm(2, 22050, 16),
+            AudioFormat::pcm(2, 44100, 16),
+            AudioFormat::ima_adpcm(2, 22050),
+            AudioFormat::ima_adpcm(2, 44100),
+            AudioFormat::opus(2, 48000),
+        ];
+        // Opus depends on the library being there
+        let without_opus = |f: &AudioFormat| f.tag != WAVE_FORMAT_OPUS && can_decode(f);
+        assert_eq!(negotiate(&offered, without_opus), Some(3));
+        assert_eq!(negotiate(&offered, |f| f.tag != WAVE_FORMAT_PCM), Some(4));
+    }
+
+    #[test]
+    fn negotiation_picks_opus_when_available() {
+        let offered = [
+            AudioFormat::pcm(2, 48000, 16),
+            AudioFormat::ima_adpcm(2, 48000),
+            AudioFormat::opus(2, 48000),
+        ];
+        let expected = if opus::opus_available() { 2 } else { 1 };
+        assert_eq!(negotiate(&offered, can_decode), Some(expected));
+        assert_eq!(negotiate(&offered, can_encode), Some(expected));
+    }
+
+    #[test]
+    fn negotiation_falls_back_to_pcm() {
+        let offered = [
+            AudioFormat {
+                tag: 0x0002, // MS ADPCM, not supported
+                ..AudioFormat::pcm(2, 44100, 4)
+            },
+            AudioFormat::pcm(1, 44100, 16),
+            AudioFormat::pcm(2, 44100, 16),
+            AudioFormat::pcm(2, 8000, 16),
+        ];
+        assert_eq!(negotiate(&offered, can_decode), Some(2));
+        assert_eq!(negotiate(&offered[..1], can_decode), None);
+        assert_eq!(negotiate(&[], can_encode), None);
+    }
+
+    #[test]
+    fn formats() {
+        let adpcm = AudioFormat::ima_adpcm(2, 44100);
+        assert_eq!(adpcm.block_align, 2048);
+        assert_eq!(adpcm.extra, 2041u16.to_le_bytes());
+        assert!(can_decode(&adpcm) && can_encode(&adpcm));
+        let pcm = AudioFormat::pcm(2, 44100, 16);
+        assert_eq!((pcm.block_align, pcm.avg_bytes_per_sec), (4, 176400));
+        assert!(!pcm.is_compressed());
+        assert!(!can_decode(&AudioFormat::pcm(0, 44100, 16)));
+        assert!(create_decoder(&AudioFormat::pcm(2, 44100, 12)).is_err());
+    }
+}
@@ -0,0 +1,315 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+// Opus, through libopus loaded at runtime (as OpenH264 for the webcam). Without
+// the library Opus is simply not negotiated. One Opus packet per RDP packet.
+use std::ffi::c_int;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+use anyhow::{Result, anyhow};
+use shared::log;
+
+use super::{AudioDecoder, AudioEncoder, AudioFormat};
+
+pub const BITRATE_PER_CHANNEL: u32 = 32000;
+
+const SAMPLE_RATES: [u32; 5] = [8000, 12000, 16000, 24000, 48000];
+const FRAME_MS: u32 = 20;
+const MAX_FRAME_MS: u32 = 120;
+const MAX_PACKET_BYTES: usize = 4000;
+
+const OPUS_OK: c_int = 0;
+const OPUS_APPLICATION_VOIP: c_int = 2048;
+const OPUS_SET_BITRATE_REQUEST: c_int = 4002;
+
+#[repr(C)]
+struct OpusEncoderState {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+struct OpusDecoderState {
+    _private: [u8; 0],
+}
+
+type EncoderCreateFn = unsafe extern "C" fn(i32, c_int, c_int, *mut c_int) -> *mut OpusEncoderState;
+type EncodeFloatFn =
+    unsafe extern "C" fn(*mut OpusEncoderState, *const f32, c_int, *mut u8, i32) -> i32;
+type EncoderCtlFn = unsafe extern "C" fn(*mut OpusEncoderState, c_int, ...) -> c_int;
+type EncoderDestroyFn = unsafe extern "C" fn(*mut OpusEncoderState);
+type DecoderCreateFn = unsafe extern "C" fn(i32, c_int, *mut c_int) -> *mut OpusDecoderState;
+type DecodeFloatFn =
+    unsafe extern "C" fn(*mut OpusDecoderState, *const u8, i32, *mut f32, c_int, c_int) -> c_int;
+type DecoderDestroyFn = unsafe extern "C" fn(*mut OpusDecoderState);
+
+struct OpusLibrary {
+    encoder_create: EncoderCreateFn,
+    encode_float: EncodeFloatFn,
+    encoder_ctl: EncoderCtlFn,
+    encoder_destroy: EncoderDestroyFn,
+    decoder_create: DecoderCreateFn,
+    decode_float: DecodeFloatFn,
+    decoder_destroy: DecoderDestroyFn,
+    // Keeps the symbols above valid
+    _lib: libloading::Library,
+}
+
+static OPUS: LazyLock<Option<OpusLibrary>> = LazyLock::new(|| match load_library() {
+    Ok(lib) => {
+        log::info!("Opus library loaded successfully.");
+        Some(lib)
+    }
+    Err(e) => {
+        log::warn!("Opus library failed to load (will fallback to other formats): {e}");
+        None
+    }
+});
+
+pub fn opus_available() -> bool {
+    OPUS.is_some()
+}
+
+pub fn valid_format(format: &AudioFormat) -> bool {
+    SAMPLE_RATES.contains(&format.sample_rate) && (1..=2).contains(&format.channels)
+}
+
+fn library_candidates() -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|p| p.to_path_buf()));
+
+    #[cfg(target_os = "windows")]
+    let names: &[&str] = &["opus.dll", "libopus-0.dll"];
+    #[cfg(target_os = "macos")]
+    let names: &[&str] = &["libopus.dylib", "libopus.0.dylib"];
+    #[cfg(target_os = "linux")]
+    let names: &[&str] = &["libopus.so.0", "libopus.so"];
+
+    // Next to the launcher first, then the system search path
+    if let Some(dir) = exe_dir {
+        candidates.extend(names.iter().map(|n| dir.join(n)));
+    }
+    #[cfg(target_os = "macos")]
+    {
+        candidates.push(PathBuf::from("/opt/homebrew/lib/libopus.dylib"));
+        candidates.push(PathBuf::from("/usr/local/lib/libopus.dylib"));
+    }
+    candidates.extend(names.iter().map(PathBuf::from));
+    candidates
+}
+
+fn load_library() -> Result<OpusLibrary> {
+    let mut last_err = None;
+    for candidate in library_candidates() {
+        // Bare names go to the system search path
+        if candidate.components().count() > 1 && !candidate.exists() {
+            continue;
+        }
+        let lib = match unsafe { libloading::Library::new(&candidate) } {
+            Ok(lib) => lib,
+            Err(e) => {
+                last_err = Some(e);
+                continue;
+            }
+        };
+        log::debug!("Opus library found at {:?}", candidate);
+        unsafe {
+            macro_rules! symbol {
+                ($name:literal) => {
+                    *lib.get($name)
+                        .map_err(|e| anyhow!("Missing Opus symbol {:?}: {e}", $name))?
+                };
+            }
+            return Ok(OpusLibrary {
+                encoder_create: symbol!(b"opus_encoder_create"),
+                encode_float: symbol!(b"opus_encode_float"),
+                encoder_ctl: symbol!(b"opus_encoder_ctl"),
+                encoder_destroy: symbol!(b"opus_encoder_destroy"),
+                decoder_create: symbol!(b"opus_decoder_create"),
+                decode_float: symbol!(b"opus_decode_float"),
+                decoder_destroy: symbol!(b"opus_decoder_destroy"),
+                _lib: lib,
+            });
+        }
+    }
+    Err(anyhow!(
+        "Could not find or load the Opus library: {:?}",
+        last_err
+    ))
+}
+
+fn library() -> Result<&'static OpusLibrary> {
+    OPUS.as_ref()
+        .ok_or_else(|| anyhow!("Opus library is not available"))
+}
+
+pub struct OpusDecoder {
+    lib: &'static OpusLibrary,
+    state: *mut OpusDecoderState,
+    channels: usize,
+    max_frames: usize,
+}
+
+// The decoder state is only used from the thread that owns it
+unsafe impl Send for OpusDecoder {}
+
+impl OpusDecoder {
+    pub fn new(format: &AudioFormat) -> Result<Self> {
+        let lib = library()?;
+        let mut error = OPUS_OK;
+        let state = unsafe {
+            (lib.decoder_create)(
+                format.sample_rate as i32,
+                format.channels as c_int,
+                &mut error,
+            )
+        };
+        if error != OPUS_OK || state.is_null() {
+            return Err(anyhow!("opus_decoder_create failed with code {error}"));
+        }
+        Ok(OpusDecoder {
+            lib,
+            state,
+            channels: format.channels as usize,
+            max_frames: (format.sample_rate * MAX_FRAME_MS / 1000) as usize,
+        })
+    }
+}
+
+impl AudioDecoder for OpusDecoder {
+    fn decode(&mut self, data: &[u8]) -> Result<Vec<f32>> {
+        let mut out = vec![0.0f32; self.max_frames * self.channels];
+        let frames = unsafe {
+            (self.lib.decode_float)(
+                self.state,
+                data.as_ptr(),
+                data.len() as i32,
+                out.as_mut_ptr(),
+                self.max_frames as c_int,
+                0,
+            )
+        };
+        if frames < 0 {
+            return Err(anyhow!("opus_decode_float failed with code {frames}"));
+        }
+        out.truncate(frames as usize * self.channels);
+        Ok(out)
+    }
+}
+
+impl Drop for OpusDecoder {
+    fn drop(&mut self) {
+        unsafe { (self.lib.decoder_destroy)(self.state) };
+    }
+}
+
+pub struct OpusEncoder {
+    lib: &'static OpusLibrary,
+    state: *mut OpusEncoderState,
+    frame_samples: usize, // interleaved
+    frames: usize,
+    pending: Vec<f32>,
+}
+
+// The encoder state is only used from the thread that owns it
+unsafe impl Send for OpusEncoder {}
+
+impl OpusEncoder {
+    pub fn new(format: &AudioFormat) -> Result<Self> {
+        let lib = library()?;
+        let mut error = OPUS_OK;
+        let state = unsafe {
+            (lib.encoder_create)(
+                format.sample_rate as i32,
+                format.channels as c_int,
+                OPUS_APPLICATION_VOIP,
+                &mut error,
+            )
+        };
+        if error != OPUS_OK || state.is_null() {
+            return Err(anyhow!("opus_encoder_create failed with code {error}"));
+        }
+        let bitrate = (BITRATE_PER_CHANNEL * format.channels as u32) as i32;
+        let res = unsafe { (lib.encoder_ctl)(state, OPUS_SET_BITRATE_REQUEST, bitrate) };
+        if res != OPUS_OK {
+            log::warn!("Could not set the Opus bitrate to {bitrate}: {res}");
+        }
+        let frames = (format.sample_rate * FRAME_MS / 1000) as usize;
+        Ok(OpusEncoder {
+            lib,
+            state,
+            frame_samples: frames * format.channels as usize,
+            frames,
+            pending: Vec::new(),
+        })
+    }
+}
+
+impl AudioEncoder for OpusEncoder {
+    fn encode(&mut self, samples: &[f32]) -> Result<Vec<Vec<u8>>> {
+        self.pending.extend_from_slice(samples);
+        let mut packets = Vec::new();
+        let mut consumed = 0;
+        while self.pending.len() - consumed >= self.frame_samples {
+            let frame = &self.pending[consumed..consumed + self.frame_samples];
+            let mut packet = vec![0u8; MAX_PACKET_BYTES];
+            let len = unsafe {
+                (self.lib.encode_float)(
+                    self.state,
+                    frame.as_ptr(),
+                    self.frames as c_int,
+                    packet.as_mut_ptr(),
+                    MAX_PACKET_BYTES as i32,
+                )
+            };
+            if len < 0 {
+                return Err(anyhow!("opus_encode_float failed with code {len}"));
+            }
+            packet.truncate(len as usize);
+            packets.push(packet);
+            consumed += self.frame_samples;
+        }
+        self.pending.drain(..consumed);
+        Ok(packets)
+    }
+}
+
+impl Drop for OpusEncoder {
+    fn drop(&mut self) {
+        unsafe { (self.lib.encoder_destroy)(self.state) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{round_trip, tone};
+    use super::*;
+
+    #[test]
+    fn opus_formats() {
+        assert!(valid_format(&AudioFormat::opus(2, 48000)));
+        assert!(valid_format(&AudioFormat::opus(1, 16000)));
+        assert!(!valid_format(&AudioFormat::opus(2, 44100)));
+        assert!(!valid_format(&AudioFormat::opus(6, 48000)));
+    }
+
+    #[test]
+    #[ignore]
+    fn test_manual_opus_round_trip() {
+        assert!(opus_available(), "Opus library not found");
+        let format = AudioFormat::opus(2, 48000);
+        let input = tone(48000, 2, 1.0, 440.0);
+        let output = round_trip(&format, &input, 480 * 2);
+        // One second is exactly 50 packets of 20 ms
+        assert_eq!(output.len(), input.len());
+        // Lossy and delayed by the codec lookahead, compare levels
+        let level = |s: &[f32]| (s.iter().map(|v| v * v).sum::<f32>() / s.len() as f32).sqrt();
+        let tail = input.len() / 2..;
+        let ratio = level(&output[tail.clone()]) / level(&input[tail]);
+        assert!((0.8..1.2).contains(&ratio), "level ratio {}", ratio);
+    }
+}
@@ -0,0 +1,66 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+use anyhow::Result;
+
+use super::{AudioDecoder, AudioEncoder, AudioFormat};
+use crate::audio::tools::{f32_to_pcm, pcm_to_f32};
+
+pub const DECODABLE_BITS: [u16; 4] = [8, 16, 24, 32];
+pub const ENCODABLE_BITS: [u16; 2] = [8, 16];
+
+pub struct PcmDecoder {
+    bits_per_sample: u16,
+}
+
+impl PcmDecoder {
+    pub fn new(format: &AudioFormat) -> Self {
+        PcmDecoder {
+            bits_per_sample: format.bits_per_sample,
+        }
+    }
+}
+
+impl AudioDecoder for PcmDecoder {
+    fn decode(&mut self, data: &[u8]) -> Result<Vec<f32>> {
+        Ok(pcm_to_f32(data, self.bits_per_sample).collect())
+    }
+}
+
+pub struct PcmEncoder {
+    bits_per_sample: u16,
+}
+
+impl PcmEncoder {
+    pub fn new(format: &AudioFormat) -> Self {
+        PcmEncoder {
+            bits_per_sample: format.bits_per_sample,
+        }
+    }
+}
+
+impl AudioEncoder for PcmEncoder {
+    fn encode(&mut self, samples: &[f32]) -> Result<Vec<Vec<u8>>> {
+        if samples.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(vec![f32_to_pcm(samples, self.bits_per_sample)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{round_trip, snr_db, tone};
+    use super::*;
+
+    #[test]
+    fn pcm_round_trip() {
+        let format = AudioFormat::pcm(2, 44100, 16);
+        let input = tone(44100, 2, 0.5, 440.0);
+        let output = round_trip(&format, &input, 882);
+        assert_eq!(output.len(), input.len());
+        assert!(snr_db(&input, &output) > 80.0);
+    }
+}
@@ -0,0 +1,359 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+// Audio device selection and hot-plug detection.
+// The device comes from (first found): env var, the RDP settings of the session
+// or AppData. With none of them, the stream follows the OS default device.
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use shared::{appdata::AppData, log};
+
+pub const OUTPUT_DEVICE_ENV: &str = "UDSLAUNCHER_AUDIO_OUTPUT_DEVICE";
+pub const INPUT_DEVICE_ENV: &str = "UDSLAUNCHER_AUDIO_INPUT_DEVICE";
+
+/// How often the stream threads check for device changes
+pub const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Output,
+    Input,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DeviceSelection {
+    /// Use the OS default device, moving the stream when it changes
+    #[default]
+    FollowDefault,
+    /// Index or (part of) the device name, case insensitive
+    Named(String),
+}
+
+impl DeviceSelection {
+    pub fn parse(value: &str) -> Self {
+        let value = value.trim();
+        if value.is_empty() || value.eq_ignore_ascii_case("default") {
+            DeviceSelection::FollowDefault
+        } else {
+            DeviceSelection::Named(value.to_string())
+        }
+    }
+}
+
+fn select_from(
+    env: Option<String>,
+    session: Option<String>,
+    app_data: Option<String>,
+) -> DeviceSelection {
+    env.or(session)
+        .or(app_data)
+        .map(|v| DeviceSelection::parse(&v))
+        .unwrap_or_default()
+}
+
+/// Device to use, `requested` being the one given by the RDP settings of the session
+pub fn selection(direction: Direction, requested: Option<&str>) -> DeviceSelection {
+    let env_var = match direction {
+        Direction::Output => OUTPUT_DEVICE_ENV,
+        Direction::Input => INPUT_DEVICE_ENV,
+    };
+    let session = requested.map(str::to_string);
+    let env = std::env::var(env_var).ok();
+    // Only read AppData from disk if needed
+    let app_data = if env.is_none() && session.is_none() {
+        let app_data = AppData::load();
+        match direction {
+            Direction::Output => app_data.audio_output_device,
+            Direction::Input => app_data.audio_input_device,
+        }
+    } else {
+        None
+    };
+    select_from(env, session, app_data)
+}
+
+fn device_name(device: &cpal::Device) -> Option<String> {
+    device.description().ok().map(|d| d.name().to_string())
+}
+
+fn devices(host: &cpal::Host, direction: Direction) -> Vec<cpal::Device> {
+    let devices = match direction {
+        Direction::Output => host.output_devices().map(|d| d.collect()),
+        Direction::Input => host.input_devices().map(|d| d.collect()),
+    };
+    devices.unwrap_or_else(|e| {
+        log::warn!("Cannot enumerate {:?} audio devices: {}", direction, e);
+        Vec::new()
+    })
+}
+
+fn device_names(host: &cpal::Host, direction: Direction) -> Vec<String> {
+    devices(host, direction)
+        .iter()
+        .map(|d| device_name(d).unwrap_or_default())
+        .collect()
+}
+
+/// Names of the available devices, for settings UIs
+pub fn list_devices(direction: Direction) -> Vec<String> {
+    device_names(&cpal::default_host(), direction)
+}
+
+fn default_device(host: &cpal::Host, direction: Direction) -> Option<cpal::Device> {
+    match direction {
+        Direction::Output => host.default_output_device(),
+        Direction::Input => host.default_input_device(),
+    }
+}
+
+// Index on the list, exact name, then case insensitive substring
+pub(crate) fn match_name(names: &[String], wanted: &str) -> Option<usize> {
+    if let Ok(idx) = wanted.parse::<usize>()
+        && idx < names.len()
+    {
+        return Some(idx);
+    }
+    let lower = wanted.to_lowercase();
+    names
+        .iter()
+        .position(|n| n == wanted)
+        .or_else(|| names.iter().position(|n| n.to_lowercase().contains(&lower)))
+}
+
+// Whether a stream on `current` must be re-opened
+fn should_reopen(
+    selection: &DeviceSelection,
+    current: Option<&str>,
+    failed: bool,
+    default_name: Option<&str>,
+    available: &[String],
+) -> bool {
+    if failed {
+        return true;
+    }
+    match selection {
+        DeviceSelection::FollowDefault => default_name.is_some() && default_name != current,
+        DeviceSelection::Named(wanted) => match match_name(available, wanted) {
+            // The wanted device (is back) and we are not on it
+            Some(idx) => current != Some(available[idx].as_str()),
+            // Not there, only worth it if we have no device at all
+            None => current.is_none() && default_name.is_some(),
+        },
+    }
+}
+
+/// Keeps track of the device used by a stream, and tells when it must be re-opened
+/// (device unplugged, stream error, OS default changed or wanted device plugged).
+pub struct DeviceWatcher {
+    direction: Direction,
+    selection: DeviceSelection,
+    current: Option<String>,
+    on_selected: bool,
+    failed: Arc<AtomicBool>,
+}
+
+impl DeviceWatcher {
+    pub fn new(direction: Direction, requested: Option<&str>) -> Self {
+        let selection = selection(direction, requested);
+        log::debug!("{:?} audio device selection: {:?}", direction, selection);
+        DeviceWatcher {
+            direction,
+            selection,
+            current: None,
+            on_selected: false,
+            failed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Flag to be set by the stream error callback
+    pub fn error_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.failed)
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    /// Selected device, or the default one if it is not available
+    pub fn open_device(&mut self, host: &cpal::Host) -> Option<cpal::Device> {
+        self.failed.store(false, Ordering::Relaxed);
+        self.on_selected = self.selection == DeviceSelection::FollowDefault;
+        let device = match &self.selection {
+            DeviceSelection::FollowDefault => default_device(host, self.direction),
+            DeviceSelection::Named(wanted) => {
+                let mut available = devices(host, self.direction);
+                let names: Vec<String> = available
+                    .iter()
+                    .map(|d| device_name(d).unwrap_or_default())
+                    .collect();
+                match match_name(&names, wanted) {
+                    Some(idx) => {
+                        self.on_selected = true;
+                        Some(available.swap_remove(idx))
+                    }
+                    None => {
+                        log::warn!(
+                            "Audio device '{}' not found (available: {:?}), using default",
+                            wanted,
+                            names
+                        );
+                        default_device(host, self.direction)
+                    }
+                }
+            }
+        };
+        self.current = device.as_ref().and_then(device_name);
+        log::info!("{:?} audio device: {:?}", self.direction, self.current);
+        device
+    }
+
+    pub fn needs_reopen(&self, host: &cpal::Host) -> bool {
+        let failed = self.failed.load(Ordering::Relaxed);
+        let default_name = default_device(host, self.direction)
+            .as_ref()
+            .and_then(device_name);
+        // Listing devices is slower, only needed while waiting for the wanted one
+        let available = if self.on_selected {
+            self.current.iter().cloned().collect()
+        } else {
+            device_names(host, self.direction)
+        };
+        let reopen = should_reopen(
+            &self.selection,
+            self.current.as_deref(),
+            failed,
+            default_name.as_deref(),
+            &available,
+        );
+        if reopen {
+            log::info!(
+                "{:?} audio device change (current={:?}, default={:?}, failed={})",
+                self.direction,
+                self.current,
+                default_name,
+                failed
+            );
+        }
+        reopen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_selection() {
+        assert_eq!(DeviceSelection::parse(""), DeviceSelection::FollowDefault);
+        assert_eq!(
+            DeviceSelection::parse("Default"),
+            DeviceSelection::FollowDefault
+        );
+        assert_eq!(
+            DeviceSelection::parse(" Headset "),
+            DeviceSelection::Named("Headset".into())
+        );
+    }
+
+    #[test]
+    fn selection_priority() {
+        let s = |v: &str| Some(v.to_string());
+        assert_eq!(
+            select_from(s("env"), s("session"), s("appdata")),
+            DeviceSelection::Named("env".into())
+        );
+        assert_eq!(
+            select_from(None, s("session"), s("appdata")),
+            DeviceSelection::Named("session".into())
+        );
+        assert_eq!(
+            select_from(None, None, s("appdata")),
+            DeviceSelection::Named("appdata".into())
+        );
+        // "default" on a higher level wins over a name on a lower one
+        assert_eq!(
+            select_from(s("default"), None, s("appdata")),
+            DeviceSelection::FollowDefault
+        );
+        assert_eq!(
+            select_from(None, None, None),
+            DeviceSelection::FollowDefault
+        );
+    }
+
+    #[test]
+    fn name_matching() {
+        let list = names(&["Speakers (Realtek)", "Headset (USB)", "Headset"]);
+        assert_eq!(match_name(&list, "Headset"), Some(2)); // exact first
+        assert_eq!(match_name(&list, "usb"), Some(1));
+        assert_eq!(match_name(&list, "0"), Some(0));
+        assert_eq!(match_name(&list, "7"), None);
+        assert_eq!(match_name(&list, "HDMI"), None);
+    }
+
+    #[test]
+    fn reopen_follow_default() {
+        let sel = DeviceSelection::FollowDefault;
+        assert!(!should_reopen(&sel, Some("A"), false, Some("A"), &[]));
+        assert!(should_reopen(&sel, Some("A"), false, Some("B"), &[]));
+        assert!(should_reopen(&sel, Some("A"), true, Some("A"), &[]));
+        // Nothing to move to
+        assert!(!should_reopen(&sel, Some("A"), false, None, &[]));
+        assert!(!should_reopen(&sel, None, false, None, &[]));
+    }
+
+    #[test]
+    fn reopen_named() {
+        let sel = DeviceSelection::Named("headset".into());
+        let plugged = names(&["Speakers", "Headset"]);
+        let unplugged = names(&["Speakers"]);
+        // On the wanted device, default changes do not matter
+        assert!(!should_reopen(
+            &sel,
+            Some("Headset"),
+            false,
+            Some("Speakers"),
+            &plugged
+        ));
+        // Unplugged: the stream error moves us to the default
+        assert!(should_reopen(
+            &sel,
+            Some("Headset"),
+            true,
+            Some("Speakers"),
+            &unplugged
+        ));
+        assert!(!should_reopen(
+            &sel,
+            Some("Speakers"),
+            false,
+            Some("Speakers"),
+            &unplugged
+        ));
+        // Plugged again
+        assert!(should_reopen(
+            &sel,
+            Some("Speakers"),
+            false,
+            Some("Speakers"),
+            &plugged
+        ));
+        // No device at all, anything is better
+        assert!(should_reopen(
+            &sel,
+            None,
+            false,
+            Some("Speakers"),
+            &unplugged
+        ));
+    }
+}
@@ -3,15 +3,18 @@
 // All rights reserved.
 // Authors: Adolfo Gómez, dkmaster at dkmon dot com
 
-use std::collections::VecDeque;
-use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
 use std::thread;
 
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use flume::{Receiver, Sender, unbounded};
+use cpal::traits::{DeviceTrait, StreamTrait};
+use flume::{Receiver, RecvTimeoutError, Sender, unbounded};
 use shared::log;
 
-use super::tools::{ResamplerIterator, f32_to_pcm};
+use super::codecs::{self, AudioFormat};
+use super::devices::{DEVICE_CHECK_INTERVAL, DeviceWatcher, Direction};
+use super::processing::{MicProcessing, ProcessorChain};
+use super::resampler::{ChannelMapper, Resampler};
 
 use rdp::integrations::AudioInputIntegration;
 
@@ -22,141 +25,169 @@ pub enum MicCommand {
 #[derive(Debug, Clone)]
 pub struct MicHandle {
     pub tx: Arc<Mutex<Option<Sender<MicCommand>>>>,
+    // Device and processing requested by the RDP settings of the session
+    device: Option<String>,
+    processing: MicProcessing,
 }
 
 impl MicHandle {
-    pub fn new() -> Self {
+    pub fn new(device: Option<String>, processing: MicProcessing) -> Self {
         MicHandle {
             tx: Arc::new(Mutex::new(None)),
+            device,
+            processing,
         }
     }
 }
 
-impl AudioInputIntegration for MicHandle {
-    fn start(
+// Opens the capture stream on the watcher's device. Packets keep flowing to data_tx,
+// so the server does not notice a device switch.
+fn open_input_stream(
+    host: &cpal::Host,
+    watcher: &mut DeviceWatcher,
+    format: &AudioFormat,
+    frames_per_packet: u32,
+    processing: &MicProcessing,
+    data_tx: &Sender<Vec<u8>>,
+) -> Option<cpal::Stream> {
+    let (sample_rate, channels) = (format.sample_rate, format.channels);
+    let mut encoder = match codecs::create_encoder(format) {
+        Ok(encoder) => encoder,
+        Err(e) => {
+            log::error!("[MicHandle] {}", e);
+            return None;
+        }
+    };
+    let device = match watcher.open_device(host) {
+        Some(d) => d,
+        None => {
+            log::error!("[MicHandle] No input device found");
+            return None;
+        }
+    };
+
+    let config = match get_input_config(&device, sample_rate, channels) {
+        Some(c) => c,
+        None => {
+            log::error!("[MicHandle] No suitable input config found");
+            return None;
+        }
+    };
+
+    let cfg = config.config();
+    let actual_rate = cfg.sample_rate;
+    let actual_channels = cfg.channels;
+
+    log::debug!(
+        "[MicHandle] Capture config: actual_rate={}, actual_channels={}",
+        actual_rate,
+        actual_channels,
+    );
+
+    let out_packet_samples = (frames_per_packet as usize) * (channels as usize);
+    let mapper = ChannelMapper::new(actual_channels, channels);
+    let mut resampler = Resampler::new(channels, actual_rate, sample_rate);
+    let mut pending: Vec<f32> = Vec::with_capacity(out_packet_samples * 2);
+    // On the session format, so the stages do not depend on the device
+    let mut chain = ProcessorChain::from_settings(processing, sample_rate, channels);
+    let failed = watcher.error_flag();
+
+    let stream = match device.build_input_stream(
+        cfg,
+        {
+            let data_tx = data_tx.clone();
+            move |data: &[f32], _| {
+                // Map first, so we resample only the channels we send
+                pending.extend(resampler.process(&mapper.map(data)));
+                while pending.len() >= out_packet_samples {
+                    chain.process(&mut pending[..out_packet_samples]);
+                    match encoder.encode(&pending[..out_packet_samples]) {
+                        Ok(packets) => {
+                            for packet in packets {
+                                let _ = data_tx.send(packet);
+                            }
+                        }
+                        Err(e) => log::warn!("[MicHandle] Encoding failed: {}", e),
+                    }
+                    pending.drain(..out_packet_samples);
+                }
+            }
+        },
+        move |err| {
+            log::error!("[MicHandle] Input stream error: {}", err);
+            failed.store(true, Ordering::Relaxed);
+        },
+        None,
+    ) {
+        Ok(s) => s,
+        Err(e) => {
+            log::error!("[MicHandle] Failed to build input stream: {}", e);
+            return None;
+        }
+    };
+
+    if let Err(e) = stream.play() {
+        log::error!("[MicHandle] Failed to start input stream: {}", e);
+        return None;
+    }
+    Some(stream)
+}
+
+impl MicHandle {
+    /// Index of the server offered format to send, compressed ones first
+    pub fn negotiate(&self, offered: &[AudioFormat]) -> Option<usize> {
+        codecs::negotiate(offered, codecs::can_encode)
+    }
+
+    /// Starts capturing, sending packets in `format`
+    pub fn start_format(
         &self,
-        sample_rate: u32,
-        channels: u16,
-        bits_per_sample: u16,
+        format: &AudioFormat,
         frames_per_packet: u32,
     ) -> anyhow::Result<Receiver<Vec<u8>>> {
         log::debug!(
-            "Initializing mic: sample_rate={}, channels={}, bits_per_sample={}, frames_per_packet={}",
-            sample_rate,
-            channels,
-            bits_per_sample,
+            "Initializing mic: format={}, frames_per_packet={}",
+            format,
             frames_per_packet
         );
+        if !codecs::can_encode(format) {
+            return Err(anyhow::anyhow!("Cannot encode mic audio as {}", format));
+        }
         self.stop();
+        let format = format.clone();
+        let device = self.device.clone();
+        let processing = self.processing;
 
         let (data_tx, data_rx) = unbounded::<Vec<u8>>();
         let (cmd_tx, cmd_rx) = unbounded::<MicCommand>();
 
         thread::spawn(move || {
             let host = cpal::default_host();
-            let device = match host.default_input_device() {
-                Some(d) => {
-                    log::debug!("Default input device: {:?}", d.description());
-                    d
-                }
-                None => {
-                    log::error!("[MicHandle] No input device found");
-                    return;
-                }
+            let mut watcher = DeviceWatcher::new(Direction::Input, device.as_deref());
+            let open = |watcher: &mut DeviceWatcher| {
+                open_input_stream(
+                    &host,
+                    watcher,
+                    &format,
+                    frames_per_packet,
+                    &processing,
+                    &data_tx,
+                )
             };
 
-            let config = match get_input_config(&device, sample_rate, channels) {
-                Some(c) => c,
-                None => {
-                    log::error!("[MicHandle] No suitable input config found");
-                    return;
-                }
-            };
-
-            let cfg = config.config();
-            let actual_rate = cfg.sample_rate;
-            let actual_channels = cfg.channels as usize;
-            let need_resample = actual_rate != sample_rate;
-            let need_downmix = actual_channels > channels as usize;
-
-            log::debug!(
-                "[MicHandle] Capture config: actual_rate={}, actual_channels={}, resample={}, downmix={}",
-                actual_rate,
-                actual_channels,
-                need_resample,
-                need_downmix
-            );
-
-            let out_packet_samples = (frames_per_packet as usize) * (channels as usize);
-
-            let sample_buf: Arc<RwLock<VecDeque<f32>>> =
-                Arc::new(RwLock::new(VecDeque::with_capacity(out_packet_samples * 4)));
-
-            let stream = match device.build_input_stream(
-                cfg,
-                {
-                    let data_tx = data_tx.clone();
-                    let sample_buf = Arc::clone(&sample_buf);
-                    move |data: &[f32], _| {
-                        let mut buf = sample_buf.write().unwrap();
-                        buf.extend(data.iter().copied());
-
-                        let min_input = if need_resample || need_downmix {
-                            (out_packet_samples * 2).max(256)
-                        } else {
-                            out_packet_samples
-                        };
-
-                        while buf.len() >= min_input {
-                            let drain_end = buf.len().min(min_input * 2);
-                            let chunk: Vec<f32> = buf.drain(0..drain_end).collect();
-                            drop(buf);
-
-                            let processed: Vec<f32> = if need_resample {
-                                ResamplerIterator::new(chunk.into_iter(), actual_rate, sample_rate)
-                                    .collect()
-                            } else {
-                                chunk
-                            };
-
-                            let processed: Vec<f32> = if need_downmix {
-                                let mut mono =
-                                    Vec::with_capacity(processed.len() / actual_channels);
-                                for frame in processed.chunks_exact(actual_channels) {
-                                    mono.push(frame.iter().sum::<f32>() / actual_channels as f32);
-                                }
-                                mono
-                            } else {
-                                processed
-                            };
-
-                            for packet in processed.chunks(out_packet_samples) {
-                                let mut pkt = packet.to_vec();
-                                pkt.resize(out_packet_samples, 0.0);
-                                let pcm = f32_to_pcm(&pkt, bits_per_sample);
-                                let _ = data_tx.send(pcm);
-                            }
-                            buf = sample_buf.write().unwrap();
+            let mut stream = open(&mut watcher);
+            log::debug!("[MicHandle] Capture started");
+            loop {
+                match cmd_rx.recv_timeout(DEVICE_CHECK_INTERVAL) {
+                    Ok(MicCommand::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+                    Err(RecvTimeoutError::Timeout) => {
+                        if watcher.needs_reopen(&host) {
+                            drop(stream.take()); // Release the device before opening another
+                            stream = open(&mut watcher);
                         }
                     }
-                },
-                |err| log::error!("[MicHandle] Input stream error: {}", err),
-                None,
-            ) {
-                Ok(s) => s,
-                Err(e) => {
-                    log::error!("[MicHandle] Failed to build input stream: {}", e);
-                    return;
                 }
-            };
-
-            if let Err(e) = stream.play() {
-                log::error!("[MicHandle] Failed to start input stream: {}", e);
-                return;
             }
-
-            log::debug!("[MicHandle] Capture started");
-            let _ = cmd_rx.recv();
             drop(stream);
             log::debug!("[MicHandle] Capture stopped");
         });
@@ -164,6 +195,23 @@ impl AudioInputIntegration for MicHandle {
         *self.tx.lock().unwrap() = Some(cmd_tx);
         Ok(data_rx)
     }
+}
+
+// As with RDPSND, the core AUDIN client only asks for PCM; compressed
+// formats are reached through `negotiate` and `start_format`.
+impl AudioInputIntegration for MicHandle {
+    fn start(
+        &self,
+        sample_rate: u32,
+        channels: u16,
+        bits_per_sample: u16,
+        frames_per_packet: u32,
+    ) -> anyhow::Result<Receiver<Vec<u8>>> {
+        self.start_format(
+            &AudioFormat::pcm(channels, sample_rate, bits_per_sample),
+            frames_per_packet,
+        )
+    }
 
     fn stop(&self) {
         let mut tx_guard = self.tx.lock().unwrap();
@@ -235,7 +283,7 @@ fn get_input_config(
 
 impl Default for MicHandle {
     fn default() -> Self {
-        Self::new()
+        Self::new(None, MicProcessing::default())
     }
 }
 
@@ -245,8 +293,20 @@ mod tests {
 
     #[test]
     fn test_mic_handle_creation() {
-        let handle = MicHandle::new();
+        let handle = MicHandle::default();
         let _rx = handle.start(44100, 1, 16, 480);
         handle.stop();
     }
+
+    #[test]
+    fn test_negotiated_format_capture() {
+        let handle = MicHandle::default();
+        let offered = [AudioFormat::pcm(1, 48000, 16), AudioFormat::opus(1, 48000)];
+        let chosen = handle.negotiate(&offered).unwrap();
+        let expected = if codecs::opus::opus_available() { 1 } else { 0 };
+        assert_eq!(chosen, expected);
+
+        let _rx = handle.start_format(&offered[chosen], 960).unwrap();
+        handle.stop();
+    }
 }
@@ -3,9 +3,19 @@
 // All rights reserved.
 // Authors: Adolfo Gómez, dkmaster at dkmon dot com
 
+pub mod codecs;
+pub mod devices;
 pub mod input;
 pub mod output;
+pub mod processing;
+pub mod resampler;
+#[cfg(test)]
+mod test_utils;
 pub mod tools;
+pub mod volume;
 
+pub use codecs::AudioFormat;
 pub use input::{MicCommand, MicHandle};
-pub use output::{AudioCommand, AudioHandle, AudioStats};
+pub use output::{AudioCommand, AudioHandle, AudioStats, JitterBuffer};
+pub use processing::{AudioProcessor, MicProcessing, ProcessorChain};
+pub use volume::VolumeControl;
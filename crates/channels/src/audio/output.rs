@@ -3,16 +3,22 @@
 // All rights reserved.
 // Authors: Adolfo Gómez, dkmaster at dkmon dot com
 
-use flume::{Sender, unbounded};
+use flume::{RecvTimeoutError, Sender, unbounded};
 use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
+use std::time::Instant;
 
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::traits::{DeviceTrait, StreamTrait};
 use cpal::{FromSample, SampleFormat, SizedSample};
 use shared::log;
 
-use super::tools::{ResamplerIterator, pcm_to_f32};
+use super::codecs::{self, AudioFormat};
+use super::devices::{DEVICE_CHECK_INTERVAL, DeviceWatcher, Direction};
+use super::processing::echo_reference;
+use super::resampler::{ChannelMapper, Resampler};
+use super::volume::{VolumeControl, channel_gain};
 
 use rdp::integrations::AudioOutputIntegration;
 
@@ -26,16 +32,23 @@ pub enum AudioCommand {
 #[derive(Debug, Clone)]
 pub struct AudioHandle {
     pub tx: Arc<Mutex<Option<Sender<AudioCommand>>>>,
-    pub volume: Arc<RwLock<u32>>,
+    // Server and local volume, applied on the stream callback
+    pub volume: Arc<VolumeControl>,
     pub latency: Arc<RwLock<u32>>,
+    // Kept across device switches and re-opens of the handle
+    pub stats: Arc<Mutex<AudioStats>>,
+    // Device requested by the RDP settings of the session, if any
+    device: Option<String>,
 }
 
 impl AudioHandle {
-    pub fn new() -> Self {
+    pub fn new(device: Option<String>) -> Self {
         AudioHandle {
             tx: Arc::new(Mutex::new(None)),
-            volume: Arc::new(RwLock::new(0xFFFFFFFF)),
+            volume: Arc::new(VolumeControl::new()),
             latency: Arc::new(RwLock::new(190)),
+            stats: Arc::new(Mutex::new(AudioStats::new())),
+            device,
         }
     }
 
@@ -69,169 +82,274 @@ fn build_output_stream_typed<T>(
     dev: &cpal::Device,
     cfg: cpal::StreamConfig,
     buffer: Arc<RwLock<VecDeque<f32>>>,
+    volume: Arc<VolumeControl>,
+    failed: Arc<AtomicBool>,
 ) -> Result<cpal::Stream, cpal::Error>
 where
     T: SizedSample + FromSample<f32> + Send + 'static,
 {
+    let channels = cfg.channels as usize;
+    let sample_rate = cfg.sample_rate;
+    let reference = echo_reference();
+    let mut played: Vec<f32> = Vec::new(); // mono, for the mic echo canceller
     dev.build_output_stream(
         cfg,
         move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
             let mut buf_guard = buffer.write().unwrap();
-            for sample in data.iter_mut() {
+            let gains = volume.gains();
+            let track = reference.is_active();
+            played.clear();
+            let mut frame_sum = 0.0;
+            // Buffers always start on a frame boundary
+            for (i, sample) in data.iter_mut().enumerate() {
                 let val = buf_guard.pop_front().unwrap_or(0.0); // silence when no data
+                let val = val * channel_gain(i % channels, channels, gains);
                 *sample = T::from_sample(val);
+                if track {
+                    frame_sum += val;
+                    if i % channels == channels - 1 {
+                        played.push(frame_sum / channels as f32);
+                        frame_sum = 0.0;
+                    }
+                }
+            }
+            if track {
+                reference.push(sample_rate, &played);
             }
         },
-        |err| log::error!("Stream error: {}", err),
+        move |err| {
+            log::error!("Stream error: {}", err);
+            // The device thread will re-open the stream (maybe on another device)
+            failed.store(true, Ordering::Relaxed);
+        },
         None,
     )
 }
 
-impl AudioOutputIntegration for AudioHandle {
+// A playing stream, with the conversion from the RDP format to the device one
+struct OutputStream {
+    _stream: cpal::Stream,
+    sample_rate: u32,
+    channels: u16,
+    resampler: Resampler,
+    mapper: ChannelMapper,
+}
+
+impl OutputStream {
     fn open(
-        &self,
+        host: &cpal::Host,
+        watcher: &mut DeviceWatcher,
         channels: u16,
         sample_rate: u32,
-        bits_per_sample: u16,
-        latency_threshold: Option<u32>,
-    ) {
+        buffer: &Arc<RwLock<VecDeque<f32>>>,
+        volume: &Arc<VolumeControl>,
+    ) -> Option<Self> {
+        let dev = watcher.open_device(host)?;
+        let cfg = AudioHandle::get_stream_config(&dev, sample_rate)?;
+        let sample_format = cfg.sample_format();
+        log::debug!(
+            "Using audio format: {:?}, range={}, channels={}",
+            sample_format,
+            cfg.sample_rate(),
+            cfg.channels()
+        );
+        let cfg = cfg.config();
+        let (device_rate, device_channels) = (cfg.sample_rate, cfg.channels);
+        let buffer = Arc::clone(buffer);
+        let volume = Arc::clone(volume);
+        let failed = watcher.error_flag();
+        // Build a stream matching the device's sample format. Never unwrap:
+        // a failed build must disable audio, not crash the whole launcher.
+        let built = match sample_format {
+            SampleFormat::F32 => {
+                build_output_stream_typed::<f32>(&dev, cfg, buffer, volume, failed)
+            }
+            SampleFormat::I16 => {
+                build_output_stream_typed::<i16>(&dev, cfg, buffer, volume, failed)
+            }
+            SampleFormat::U16 => {
+                build_output_stream_typed::<u16>(&dev, cfg, buffer, volume, failed)
+            }
+            SampleFormat::U8 => build_output_stream_typed::<u8>(&dev, cfg, buffer, volume, failed),
+            SampleFormat::I32 => {
+                build_output_stream_typed::<i32>(&dev, cfg, buffer, volume, failed)
+            }
+            SampleFormat::F64 => {
+                build_output_stream_typed::<f64>(&dev, cfg, buffer, volume, failed)
+            }
+            other => Err(cpal::Error::with_message(
+                cpal::ErrorKind::UnsupportedConfig,
+                format!("unsupported sample format {:?}", other),
+            )),
+        };
+        let stream = match built {
+            Ok(s) => s,
+            Err(e) => {
+                log::error!("Audio disabled: cannot build output stream: {}", e);
+                return None;
+            }
+        };
+        if let Err(e) = stream.play() {
+            log::error!("Audio disabled: cannot start output stream: {}", e);
+            return None;
+        }
+        Some(OutputStream {
+            _stream: stream,
+            sample_rate: device_rate,
+            channels: device_channels,
+            resampler: Resampler::adjustable(channels, sample_rate, device_rate),
+            mapper: ChannelMapper::new(channels, device_channels),
+        })
+    }
+
+    // Decoded server samples to device samples
+    fn convert(&mut self, samples: &[f32]) -> Vec<f32> {
+        self.mapper.map(&self.resampler.process(samples))
+    }
+}
+
+impl AudioHandle {
+    /// Index of the server offered format to play, compressed ones first
+    pub fn negotiate(&self, offered: &[AudioFormat]) -> Option<usize> {
+        codecs::negotiate(offered, codecs::can_decode)
+    }
+
+    /// Opens the output for packets in `format`
+    pub fn open_format(&self, format: &AudioFormat, latency_threshold: Option<u32>) {
         log::debug!(
-            "Initializing audio: channels={}, sample_rate={}, bits_per_sample={}, latency_cushion={:?}",
-            channels,
-            sample_rate,
-            bits_per_sample,
+            "Initializing audio: format={}, latency_cushion={:?}",
+            format,
             latency_threshold
         );
         self.close();
+        let mut decoder = match codecs::create_decoder(format) {
+            Ok(decoder) => decoder,
+            Err(e) => {
+                log::error!("Audio disabled: {}", e);
+                return;
+            }
+        };
+        let (channels, sample_rate) = (format.channels, format.sample_rate);
         let (tx, rx) = unbounded::<AudioCommand>();
 
         let volume = Arc::clone(&self.volume);
         let latency = Arc::clone(&self.latency);
-        let latency_threshold = latency_threshold.map(|lt| (lt as f32).clamp(300.0, 1000.0));
+        let stats = Arc::clone(&self.stats);
+        let device = self.device.clone();
+        let latency_threshold = latency_threshold.map(|lt| (lt as f64).clamp(300.0, 1000.0));
 
         thread::spawn(move || {
             let host = cpal::default_host();
-            let device = host.default_output_device();
-            log::debug!(
-                "Default audio output device: {:?}",
-                device.as_ref().map(|d| d.description())
-            );
-            let mut stream = None;
-
-            // Shared buffer for audio samples
-            let buffer: Arc<RwLock<VecDeque<f32>>> = Arc::new(RwLock::new(VecDeque::new()));
+            let mut watcher = DeviceWatcher::new(Direction::Output, device.as_deref());
 
-            let mut output_sample_rate = sample_rate;
-            if let Some(dev) = device
-                && let Some(cfg) = AudioHandle::get_stream_config(&dev, sample_rate)
-            {
-                let sample_format = cfg.sample_format();
-                log::debug!(
-                    "Using audio format: {:?}, range={}",
-                    sample_format,
-                    cfg.sample_rate()
-                );
-                let cfg = cfg.config();
-                // Store real output sample rate
-                output_sample_rate = cfg.sample_rate;
-                // Build a stream matching the device's sample format. Never unwrap:
-                // a failed build must disable audio, not crash the whole launcher.
-                let built = match sample_format {
-                    SampleFormat::F32 => {
-                        build_output_stream_typed::<f32>(&dev, cfg, Arc::clone(&buffer))
-                    }
-                    SampleFormat::I16 => {
-                        build_output_stream_typed::<i16>(&dev, cfg, Arc::clone(&buffer))
-                    }
-                    SampleFormat::U16 => {
-                        build_output_stream_typed::<u16>(&dev, cfg, Arc::clone(&buffer))
-                    }
-                    SampleFormat::U8 => {
-                        build_output_stream_typed::<u8>(&dev, cfg, Arc::clone(&buffer))
-                    }
-                    SampleFormat::I32 => {
-                        build_output_stream_typed::<i32>(&dev, cfg, Arc::clone(&buffer))
-                    }
-                    SampleFormat::F64 => {
-                        build_output_stream_typed::<f64>(&dev, cfg, Arc::clone(&buffer))
-                    }
-                    other => Err(cpal::Error::with_message(
-                        cpal::ErrorKind::UnsupportedConfig,
-                        format!("unsupported sample format {:?}", other),
-                    )),
-                };
-                stream = match built {
-                    Ok(s) => Some(s),
-                    Err(e) => {
-                        log::error!("Audio disabled: cannot build output stream: {}", e);
-                        None
-                    }
-                };
-            }
+            // Shared buffer for audio samples, in the device format
+            let buffer: Arc<RwLock<VecDeque<f32>>> = Arc::new(RwLock::new(VecDeque::new()));
 
-            if let Some(s) = &stream {
-                let _ = s.play();
-            } else {
+            let mut output =
+                OutputStream::open(&host, &mut watcher, channels, sample_rate, &buffer, &volume);
+            if output.is_none() {
                 log::error!("Audio disabled: cpal init failed");
             }
+            stats.lock().unwrap().set_device(watcher.current(), false);
 
-            let mut stats = AudioStats::new();
+            // The jitter estimation survives device switches, it is about the network
+            let mut jitter = JitterBuffer::new(latency_threshold);
+            let started = Instant::now();
+            let mut prefill = true;
+            let mut last_check = Instant::now();
             // Main loop
             loop {
-                if let Ok(cmd) = rx.recv() {
-                    match cmd {
-                        AudioCommand::Play(data) => {
-                            stats.add_play_call();
-                            if stream.is_some() {
-                                // Convert PCM to f32, resample and push to buffer
-                                let resampled_iter = ResamplerIterator::new(
-                                    pcm_to_f32(&data, bits_per_sample),
-                                    sample_rate,
-                                    output_sample_rate,
-                                );
-                                let mut buf = buffer.write().unwrap();
-                                // Store current buffer length to calculate number of frames added
-                                let added_frames = {
-                                    let buf_len = buf.len();
-                                    buf.extend(resampled_iter);
-                                    (buf.len() - buf_len) as u64 / channels as u64
-                                };
-                                stats.add_frames_played(added_frames);
-
-                                // Update approximate latency
-                                let frames = buf.len() as u32 / channels as u32;
-                                let ms = (frames as f32 / output_sample_rate as f32) * 1000.0;
-                                *latency.write().unwrap() = ms as u32;
-
-                                let latency_threshold = match latency_threshold {
-                                    Some(lt) => lt,
-                                    None => stats.mean_calls_interval() * 2.0, // default to double the mean interval
-                                };
-                                // overflow control: if latency > latency_threshold ms, drop some frames
-                                if ms > latency_threshold {
-                                    // try to get back to ~200 ms latency
-                                    let target_frames = ((stats.mean_calls_interval() / 1000.0)
-                                        * output_sample_rate as f32)
-                                        as usize
-                                        * channels as usize;
-                                    if buf.len() > target_frames {
-                                        let drop = buf.len() - target_frames;
-                                        stats.add_frames_dropped((drop / channels as usize) as u64);
-                                        buf.drain(0..drop);
-                                        log::warn!(
-                                            "Dropped {} frames to recover sync, new latency ~{} ms",
-                                            drop,
-                                            200
-                                        );
-                                        *latency.write().unwrap() = 200; // Proximate latency after drop
-                                    }
+                match rx.recv_timeout(DEVICE_CHECK_INTERVAL) {
+                    Ok(AudioCommand::Play(data)) => {
+                        let decoded = match decoder.decode(&data) {
+                            Ok(decoded) => decoded,
+                            Err(e) => {
+                                log::warn!("Discarding audio packet: {}", e);
+                                continue;
+                            }
+                        };
+                        let mut stats = stats.lock().unwrap();
+                        stats.add_play_call();
+                        if let Some(out) = output.as_mut() {
+                            let device_channels = out.channels as usize;
+                            let samples_per_ms =
+                                out.sample_rate as f64 * device_channels as f64 / 1000.0;
+                            let to_ms = |samples: usize| samples as f64 / samples_per_ms;
+                            // Whole frames only
+                            let to_samples = |ms: f64| {
+                                (ms * samples_per_ms) as usize / device_channels * device_channels
+                            };
+                            let packet_ms = (decoded.len() / channels.max(1) as usize) as f64
+                                * 1000.0
+                                / sample_rate as f64;
+                            let mut buf = buffer.write().unwrap();
+
+                            let level_ms = to_ms(buf.len());
+                            jitter.on_packet(
+                                started.elapsed().as_secs_f64() * 1000.0,
+                                packet_ms,
+                                level_ms,
+                            );
+                            if buf.is_empty() {
+                                if !prefill {
+                                    stats.add_underrun();
                                 }
+                                // Start (or restart) with enough cushion for the jitter
+                                buf.extend(std::iter::repeat_n(
+                                    0.0,
+                                    to_samples(jitter.target_ms()),
+                                ));
+                                prefill = false;
                             }
+
+                            // Resample (at the drift compensating speed), map channels
+                            // and push to buffer
+                            out.resampler.set_speed(jitter.speed());
+                            let samples = out.convert(&decoded);
+                            buf.extend(samples.iter());
+                            stats.add_frames_played((samples.len() / device_channels) as u64);
+
+                            // Only big stalls get here, drift is absorbed by the speed
+                            if to_ms(buf.len()) > jitter.max_latency_ms() {
+                                let drop = buf.len().saturating_sub(to_samples(jitter.target_ms()));
+                                stats.add_frames_dropped((drop / device_channels) as u64);
+                                buf.drain(0..drop);
+                                log::warn!(
+                                    "Dropped {} frames to recover sync, new latency ~{:.0} ms",
+                                    drop / device_channels,
+                                    jitter.target_ms()
+                                );
+                            }
+
+                            let latency_ms = to_ms(buf.len());
+                            *latency.write().unwrap() = latency_ms as u32;
+                            stats.update_latency(&jitter, latency_ms);
                         }
-                        AudioCommand::SetVolume(v) => {
-                            *volume.write().unwrap() = v;
-                        }
-                        AudioCommand::Close => break,
+                    }
+                    Ok(AudioCommand::SetVolume(v)) => {
+                        volume.set_server(v);
+                    }
+                    Ok(AudioCommand::Close) | Err(RecvTimeoutError::Disconnected) => break,
+                    Err(RecvTimeoutError::Timeout) => {}
+                }
+
+                if last_check.elapsed() >= DEVICE_CHECK_INTERVAL {
+                    last_check = Instant::now();
+                    if watcher.needs_reopen(&host) {
+                        // Release the old device first, some backends do not allow two streams
+                        drop(output.take());
+                        // Pending samples are in the old device format
+                        buffer.write().unwrap().clear();
+                        output = OutputStream::open(
+                            &host,
+                            &mut watcher,
+                            channels,
+                            sample_rate,
+                            &buffer,
+                            &volume,
+                        );
+                        stats.lock().unwrap().set_device(watcher.current(), true);
+                        prefill = true;
                     }
                 }
             }
@@ -239,6 +357,23 @@ impl AudioOutputIntegration for AudioHandle {
 
         *self.tx.lock().unwrap() = Some(tx);
     }
+}
+
+// The core RDPSND client only hands over the PCM format it agreed on, so
+// compressed formats are reached through `negotiate` and `open_format`.
+impl AudioOutputIntegration for AudioHandle {
+    fn open(
+        &self,
+        channels: u16,
+        sample_rate: u32,
+        bits_per_sample: u16,
+        latency_threshold: Option<u32>,
+    ) {
+        self.open_format(
+            &AudioFormat::pcm(channels, sample_rate, bits_per_sample),
+            latency_threshold,
+        );
+    }
 
     fn play(&self, data: &[u8]) -> u32 {
         if let Some(ref tx) = *self.tx.lock().unwrap() {
@@ -248,14 +383,13 @@ impl AudioOutputIntegration for AudioHandle {
     }
 
     fn get_volume(&self) -> u32 {
-        *self.volume.read().unwrap()
+        self.volume.server()
     }
 
     fn set_volume(&self, volume: u32) {
-        *self.volume.write().unwrap() = volume;
-        if let Some(ref tx) = *self.tx.lock().unwrap() {
-            let _ = tx.send(AudioCommand::SetVolume(volume));
-        }
+        // Applied by the stream callback right away, no need to go through the thread
+        log::debug!("Server volume: {:#010x}", volume);
+        self.volume.set_server(volume);
     }
 
     fn close(&self) {
@@ -266,8 +400,91 @@ impl AudioOutputIntegration for AudioHandle {
     }
 }
 
+// Jitter buffer control, times in ms
+const JITTER_SMOOTHING: f64 = 16.0; // RFC 3550 interarrival jitter estimator
+const LEVEL_SMOOTHING: f64 = 0.05;
+const MIN_TARGET_MS: f64 = 40.0;
+const JITTER_MARGIN: f64 = 3.0; // target is this times the jitter
+const CONTROL_SPAN_MS: f64 = 100.0; // level error that gets the maximum speed change
+const MAX_DRIFT_CORRECTION: f64 = 0.005; // 0.5% speed change, inaudible
+const DEFAULT_MAX_LATENCY_MS: f64 = 1000.0;
+
+/// Adaptive jitter buffer control. Estimates the arrival jitter of the audio packets
+/// and the drift between server and device clocks, and tells the playback speed
+/// that keeps the buffered audio at a target latency that absorbs that jitter.
+/// It does not hold the samples, so it can be tested without a device.
+#[derive(Debug)]
+pub struct JitterBuffer {
+    last_arrival: Option<(f64, f64)>, // arrival and duration of the last packet
+    jitter_ms: f64,
+    level_ms: Option<f64>, // smoothed buffered audio when packets arrive
+    speed: f64,
+    max_latency_ms: f64,
+}
+
+impl JitterBuffer {
+    pub fn new(max_latency_ms: Option<f64>) -> Self {
+        JitterBuffer {
+            last_arrival: None,
+            jitter_ms: 0.0,
+            level_ms: None,
+            speed: 1.0,
+            max_latency_ms: max_latency_ms.unwrap_or(DEFAULT_MAX_LATENCY_MS),
+        }
+    }
+
+    /// Registers a packet of `packet_ms` arriving at `arrival_ms`, with `level_ms` of
+    /// audio still buffered (before adding the packet).
+    pub fn on_packet(&mut self, arrival_ms: f64, packet_ms: f64, level_ms: f64) {
+        if let Some((last_arrival, last_packet_ms)) = self.last_arrival {
+            // Packets should arrive as fast as they are played
+            let deviation = (arrival_ms - last_arrival - last_packet_ms).abs();
+            self.jitter_ms += (deviation - self.jitter_ms) / JITTER_SMOOTHING;
+        }
+        self.last_arrival = Some((arrival_ms, packet_ms));
+
+        let level = match self.level_ms {
+            Some(l) => l + (level_ms - l) * LEVEL_SMOOTHING,
+            None => level_ms,
+        };
+        self.level_ms = Some(level);
+        // Too much audio buffered means the server clock is faster than ours: play faster
+        let error = (level - self.target_ms()) / CONTROL_SPAN_MS;
+        self.speed = 1.0 + error.clamp(-1.0, 1.0) * MAX_DRIFT_CORRECTION;
+    }
+
+    pub fn jitter_ms(&self) -> f64 {
+        self.jitter_ms
+    }
+
+    /// Audio to keep buffered when a packet arrives
+    pub fn target_ms(&self) -> f64 {
+        (self.jitter_ms * JITTER_MARGIN)
+            .max(MIN_TARGET_MS)
+            .min(self.max_latency_ms / 2.0)
+    }
+
+    /// Over this, audio is dropped. Only happens on big stalls, drift is handled by speed.
+    pub fn max_latency_ms(&self) -> f64 {
+        // Big packets (some servers send ~200 ms ones) must always fit
+        let packet_ms = self.last_arrival.map(|(_, p)| p).unwrap_or_default();
+        self.max_latency_ms.max(self.target_ms() + 2.0 * packet_ms)
+    }
+
+    /// Playback speed, to be applied by resampling
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Estimated drift between server and device clocks, in parts per million
+    pub fn drift_ppm(&self) -> f64 {
+        (self.speed - 1.0) * 1_000_000.0
+    }
+}
+
 // Keep statistics about audio playback
 // Such as time between play calls, dropped frames, etc
+#[derive(Debug)]
 pub struct AudioStats {
     last_call: Option<std::time::Instant>,
     pub total_play_calls: u64,
@@ -275,6 +492,14 @@ pub struct AudioStats {
     pub total_frames_dropped: u64,
     // keep last 32 time between play calls to calculate average
     pub time_between_play_calls: VecDeque<u128>, // time between play calls in ms
+    pub device: Option<String>,
+    pub device_switches: u32,
+    // Jitter buffer
+    pub target_latency_ms: u32,
+    pub latency_ms: u32,
+    pub jitter_ms: f32,
+    pub drift_ppm: f32,
+    pub total_underruns: u64,
 }
 
 #[allow(clippy::new_without_default)]
@@ -286,6 +511,13 @@ impl AudioStats {
             total_frames_played: 0,
             total_frames_dropped: 0,
             time_between_play_calls: VecDeque::with_capacity(32),
+            device: None,
+            device_switches: 0,
+            target_latency_ms: 0,
+            latency_ms: 0,
+            jitter_ms: 0.0,
+            drift_ppm: 0.0,
+            total_underruns: 0,
         }
     }
 
@@ -323,22 +555,158 @@ impl AudioStats {
     pub fn add_frames_dropped(&mut self, frames: u64) {
         self.total_frames_dropped += frames;
     }
+
+    pub fn add_underrun(&mut self) {
+        self.total_underruns += 1;
+    }
+
+    pub fn update_latency(&mut self, jitter: &JitterBuffer, latency_ms: f64) {
+        self.target_latency_ms = jitter.target_ms() as u32;
+        self.latency_ms = latency_ms as u32;
+        self.jitter_ms = jitter.jitter_ms() as f32;
+        self.drift_ppm = jitter.drift_ppm() as f32;
+    }
+
+    pub fn set_device(&mut self, device: Option<&str>, switched: bool) {
+        self.device = device.map(|d| d.to_string());
+        if switched {
+            self.device_switches += 1;
+        }
+    }
 }
 
 impl Default for AudioHandle {
     fn default() -> Self {
-        Self::new()
+        Self::new(None)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::audio::test_utils::Lcg;
 
     #[test]
     fn test_audio_handle_creation() {
-        let handle = AudioHandle::new();
+        let handle = AudioHandle::new(None);
         handle.open(2, 44100, 16, None);
         handle.close();
     }
+
+    #[test]
+    fn test_negotiated_format_playback() {
+        let handle = AudioHandle::new(None);
+        let offered = [
+            AudioFormat::pcm(2, 44100, 16),
+            AudioFormat::ima_adpcm(2, 44100),
+            AudioFormat::opus(2, 48000),
+        ];
+        let chosen = handle.negotiate(&offered).unwrap();
+        let expected = if codecs::opus::opus_available() { 2 } else { 1 };
+        assert_eq!(chosen, expected);
+
+        let format = &offered[chosen];
+        handle.open_format(format, None);
+        let mut encoder = codecs::create_encoder(format).unwrap();
+        let samples = codecs::tests::tone(format.sample_rate, 2, 0.2, 440.0);
+        for packet in encoder.encode(&samples).unwrap() {
+            handle.play(&packet);
+        }
+        handle.close();
+    }
+
+    struct Simulation {
+        underruns: Vec<usize>, // packet numbers
+        levels: Vec<f64>,
+        speeds: Vec<f64>,
+        targets: Vec<f64>,
+        dropped_ms: f64,
+    }
+
+    // Server sends `packet_ms` of audio every `packet_ms * (1 - drift)` ms of our clock,
+    // packets get up to `max_jitter_ms` of random delay (never reordered). The device
+    // plays 1 ms of audio per ms.
+    fn simulate(seconds: f64, packet_ms: f64, drift: f64, max_jitter_ms: f64) -> Simulation {
+        let mut rng = Lcg(42);
+        let mut jitter = JitterBuffer::new(None);
+        let mut sim = Simulation {
+            underruns: Vec::new(),
+            levels: vec![],
+            speeds: vec![],
+            targets: vec![],
+            dropped_ms: 0.0,
+        };
+        let (mut level, mut now, mut last_arrival) = (0.0f64, 0.0f64, 0.0f64);
+        let packets = (seconds * 1000.0 / packet_ms) as usize;
+        for n in 0..packets {
+            let sent = n as f64 * packet_ms * (1.0 - drift);
+            let arrival = (sent + rng.unit() * max_jitter_ms).max(last_arrival);
+            last_arrival = arrival;
+            // Play until arrival
+            level -= arrival - now;
+            now = arrival;
+            if level < 0.0 {
+                if n > 0 {
+                    sim.underruns.push(n);
+                }
+                level = 0.0;
+            }
+            jitter.on_packet(arrival, packet_ms, level);
+            if level == 0.0 {
+                level += jitter.target_ms(); // prefill with silence
+            }
+            level += packet_ms / jitter.speed();
+            if level > jitter.max_latency_ms() {
+                sim.dropped_ms += level - jitter.target_ms();
+                level = jitter.target_ms();
+            }
+            sim.levels.push(level - packet_ms / jitter.speed());
+            sim.speeds.push(jitter.speed());
+            sim.targets.push(jitter.target_ms());
+        }
+        sim
+    }
+
+    fn mean(values: &[f64]) -> f64 {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+
+    #[test]
+    fn test_jitter_buffer_compensates_drift() {
+        for drift in [0.0002, -0.0003] {
+            let sim = simulate(300.0, 20.0, drift, 30.0);
+            // Once settled (second half), no underruns, no drops, and the speed
+            // matches the clock drift
+            let half = sim.levels.len() / 2;
+            assert!(sim.underruns.is_empty());
+            assert_eq!(sim.dropped_ms, 0.0);
+            let speed = mean(&sim.speeds[half..]);
+            assert!((speed - 1.0 - drift).abs() < 0.00005, "speed {}", speed);
+            let (level, target) = (mean(&sim.levels[half..]), mean(&sim.targets[half..]));
+            assert!((level - target).abs() < 15.0, "{} vs {}", level, target);
+        }
+    }
+
+    #[test]
+    fn test_jitter_buffer_target_follows_jitter() {
+        let steady = simulate(60.0, 20.0, 0.0, 0.0);
+        assert_eq!(*steady.targets.last().unwrap(), MIN_TARGET_MS);
+        let jittery = simulate(60.0, 20.0, 0.0, 150.0);
+        assert!(*jittery.targets.last().unwrap() > 60.0);
+        // Only while learning the jitter
+        let half = jittery.levels.len() / 2;
+        assert!(jittery.underruns.iter().all(|&n| n < half));
+    }
+
+    #[test]
+    fn test_stats_kept_across_switches() {
+        let mut stats = AudioStats::new();
+        stats.set_device(Some("Speakers"), false);
+        stats.add_frames_played(100);
+        stats.set_device(Some("Headset"), true);
+        stats.add_frames_played(50);
+        assert_eq!(stats.device.as_deref(), Some("Headset"));
+        assert_eq!(stats.device_switches, 1);
+        assert_eq!(stats.total_frames_played, 150);
+    }
 }
@@ -0,0 +1,743 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+// Microphone processing chain: echo cancellation, noise gate and automatic gain
+// control. Stages work on the interleaved samples sent to the server (session rate
+// and channels) and keep their state between calls, so packet sizes do not matter.
+// The echo canceller uses what the audio output is playing as reference.
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use shared::log;
+
+use super::resampler::Resampler;
+
+pub trait AudioProcessor: Send {
+    fn name(&self) -> &'static str;
+    /// Processes interleaved samples in place
+    fn process(&mut self, samples: &mut [f32]);
+}
+
+/// Enabled stages of the mic chain, none by default (the mic is sent as captured)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MicProcessing {
+    pub echo_cancellation: bool,
+    pub noise_suppression: bool,
+    pub agc: bool,
+}
+
+fn db_to_amplitude(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+// Per sample coefficient of a one pole smoother with the given time constant
+fn smoothing(ms: f32, rate: u32) -> f32 {
+    (-1000.0 / (ms * rate as f32)).exp()
+}
+
+fn frame_power(frame: &[f32]) -> f32 {
+    frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32
+}
+
+#[derive(Default)]
+pub struct ProcessorChain {
+    stages: Vec<Box<dyn AudioProcessor>>,
+}
+
+impl ProcessorChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, stage: impl AudioProcessor + 'static) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    /// Chain for the given settings, in the usual order: echo, noise, gain
+    pub fn from_settings(settings: &MicProcessing, sample_rate: u32, channels: u16) -> Self {
+        let mut chain = Self::new();
+        if settings.echo_cancellation {
+            chain = chain.with(EchoCanceller::new(sample_rate, channels, echo_reference()));
+        }
+        if settings.noise_suppression {
+            chain = chain.with(NoiseGate::new(sample_rate, channels));
+        }
+        if settings.agc {
+            chain = chain.with(AutoGain::new(sample_rate, channels));
+        }
+        log::debug!("Mic processing chain: {:?}", chain.names());
+        chain
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.stages.iter().map(|s| s.name()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for stage in self.stages.iter_mut() {
+            stage.process(samples);
+        }
+    }
+}
+
+// Level the gain aims at, and the range it can move in
+const AGC_TARGET_DB: f32 = -20.0;
+const AGC_MAX_GAIN_DB: f32 = 24.0;
+const AGC_MIN_GAIN_DB: f32 = -12.0;
+// Below this the input is considered silence and the gain is kept
+const AGC_ACTIVITY_DB: f32 = -50.0;
+// Output peak limit
+const AGC_LIMIT: f32 = 0.98;
+
+/// Automatic gain control: moves the level towards a target, lowering the gain
+/// quickly and raising it slowly, with a peak limiter so boosted speech never clips.
+pub struct AutoGain {
+    channels: usize,
+    target: f32,
+    activity: f32,
+    min_gain: f32,
+    max_gain: f32,
+    gain: f32,
+    power: f32,
+    power_coef: f32,
+    attack_coef: f32,
+    release_coef: f32,
+}
+
+impl AutoGain {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        AutoGain {
+            channels: channels.max(1) as usize,
+            target: db_to_amplitude(AGC_TARGET_DB),
+            activity: db_to_amplitude(AGC_ACTIVITY_DB).powi(2),
+            min_gain: db_to_amplitude(AGC_MIN_GAIN_DB),
+            max_gain: db_to_amplitude(AGC_MAX_GAIN_DB),
+            gain: 1.0,
+            power: 0.0,
+            power_coef: smoothing(300.0, sample_rate),
+            attack_coef: smoothing(100.0, sample_rate),
+            release_coef: smoothing(1500.0, sample_rate),
+        }
+    }
+
+    pub fn gain(&self) -> f32 {
+        self.gain
+    }
+}
+
+impl AudioProcessor for AutoGain {
+    fn name(&self) -> &'static str {
+        "agc"
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_mut(self.channels) {
+            self.power =
+                self.power * self.power_coef + frame_power(frame) * (1.0 - self.power_coef);
+            if self.power > self.activity {
+                let wanted = (self.target / self.power.sqrt()).clamp(self.min_gain, self.max_gain);
+                let coef = if wanted < self.gain {
+                    self.attack_coef
+                } else {
+                    self.release_coef
+                };
+                self.gain = self.gain * coef + wanted * (1.0 - coef);
+            }
+            let peak = frame.iter().fold(0.0f32, |p, s| p.max(s.abs()));
+            if peak * self.gain > AGC_LIMIT {
+                self.gain = AGC_LIMIT / peak;
+            }
+            frame.iter_mut().for_each(|s| *s *= self.gain);
+        }
+    }
+}
+
+// Gate opens this much over the noise floor
+const GATE_OPEN_DB: f32 = 9.0;
+// And never below this level, so digital silence does not open it
+const GATE_MIN_OPEN_DB: f32 = -65.0;
+// Attenuation when closed. Not muted, that sounds choppy
+const GATE_CLOSED_DB: f32 = -30.0;
+// Noise floor rise speed. It drops immediately
+const GATE_FLOOR_RISE_DB_PER_SEC: f32 = 4.0;
+const GATE_INITIAL_FLOOR_DB: f32 = -60.0;
+const GATE_HOLD_MS: f32 = 150.0;
+
+/// Noise gate over an estimated noise floor: attenuates everything that does not
+/// stand out of the background (fans, hum, keyboard far away...).
+pub struct NoiseGate {
+    channels: usize,
+    envelope: f32,
+    envelope_coef: f32,
+    floor: f32,
+    floor_rise: f32,
+    open_ratio: f32,
+    min_open: f32,
+    closed_gain: f32,
+    gain: f32,
+    attack_coef: f32,
+    release_coef: f32,
+    hold: usize,
+    hold_samples: usize,
+}
+
+impl NoiseGate {
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        let floor = db_to_amplitude(GATE_INITIAL_FLOOR_DB).powi(2);
+        NoiseGate {
+            channels: channels.max(1) as usize,
+            envelope: floor,
+            envelope_coef: smoothing(10.0, sample_rate),
+            floor,
+            floor_rise: 10f32.powf(GATE_FLOOR_RISE_DB_PER_SEC / 10.0 / sample_rate as f32),
+            open_ratio: db_to_amplitude(GATE_OPEN_DB).powi(2),
+            min_open: db_to_amplitude(GATE_MIN_OPEN_DB).powi(2),
+            closed_gain: db_to_amplitude(GATE_CLOSED_DB),
+            gain: 1.0,
+            attack_coef: smoothing(2.0, sample_rate),
+            release_coef: smoothing(100.0, sample_rate),
+            hold: 0,
+            hold_samples: (GATE_HOLD_MS * sample_rate as f32 / 1000.0) as usize,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.hold > 0
+    }
+}
+
+impl AudioProcessor for NoiseGate {
+    fn name(&self) -> &'static str {
+        "noise_gate"
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        for frame in samples.chunks_mut(self.channels) {
+            self.envelope = self.envelope * self.envelope_coef
+                + frame_power(frame) * (1.0 - self.envelope_coef);
+            self.floor = (self.floor * self.floor_rise).min(self.envelope.max(f32::MIN_POSITIVE));
+            if self.envelope > (self.floor * self.open_ratio).max(self.min_open) {
+                self.hold = self.hold_samples;
+            } else {
+                self.hold = self.hold.saturating_sub(1);
+            }
+            let (wanted, coef) = if self.hold > 0 {
+                (1.0, self.attack_coef)
+            } else {
+                (self.closed_gain, self.release_coef)
+            };
+            self.gain = self.gain * coef + wanted * (1.0 - coef);
+            frame.iter_mut().for_each(|s| *s *= self.gain);
+        }
+    }
+}
+
+// Samples kept by the reference when nobody takes them
+const REFERENCE_MAX_MS: u32 = 1000;
+
+/// What the audio output is playing, mono, for the echo canceller. Only filled
+/// while a canceller is alive.
+#[derive(Default)]
+pub struct EchoReference {
+    consumers: AtomicUsize,
+    played: Mutex<(u32, VecDeque<f32>)>, // rate, samples
+}
+
+impl EchoReference {
+    pub fn is_active(&self) -> bool {
+        self.consumers.load(Ordering::Relaxed) > 0
+    }
+
+    /// Called by the output with the samples handed to the device
+    pub fn push(&self, sample_rate: u32, mono: &[f32]) {
+        let mut played = self.played.lock().unwrap();
+        if played.0 != sample_rate {
+            played.0 = sample_rate;
+            played.1.clear();
+        }
+        played.1.extend(mono);
+        let max = (sample_rate * REFERENCE_MAX_MS / 1000) as usize;
+        if played.1.len() > max {
+            let excess = played.1.len() - max;
+            played.1.drain(..excess);
+        }
+    }
+
+    // Moves the pending samples to `out`, returning their rate
+    fn take(&self, out: &mut Vec<f32>) -> u32 {
+        let mut played = self.played.lock().unwrap();
+        out.extend(played.1.drain(..));
+        played.0
+    }
+
+    fn attach(&self) {
+        self.consumers.fetch_add(1, Ordering::Relaxed);
+        self.played.lock().unwrap().1.clear(); // Stale
+    }
+
+    fn detach(&self) {
+        self.consumers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+static ECHO_REFERENCE: LazyLock<Arc<EchoReference>> =
+    LazyLock::new(|| Arc::new(EchoReference::default()));
+
+/// Reference shared by the audio output and the mic
+pub fn echo_reference() -> Arc<EchoReference> {
+    Arc::clone(&ECHO_REFERENCE)
+}
+
+// Echo path covered by the adaptive filter, starting at the estimated delay
+const AEC_FILTER_MS: u32 = 16;
+// Longest delay between playing a sample and hearing it on the mic
+const AEC_MAX_DELAY_MS: u32 = 300;
+// Reference kept ahead of the mic, absorbs the jitter between both callbacks. Delays
+// the reference, so it must stay below the output plus input latency.
+const AEC_FIFO_TARGET_MS: u32 = 10;
+const AEC_FIFO_MAX_MS: u32 = 80;
+// Envelopes used for the delay estimation
+const AEC_ENVELOPE_BLOCK_MS: u32 = 2;
+const AEC_ENVELOPE_BLOCKS: usize = 1000; // 2 seconds
+const AEC_ESTIMATE_EVERY: usize = 500; // 1 second
+const AEC_MIN_CORRELATION: f32 = 0.5;
+const AEC_STEP: f32 = 0.3;
+// Geigel double talk detector: mic louder than this fraction of the recent
+// reference peak means someone is talking, adaptation is paused
+const AEC_DOUBLE_TALK: f32 = 0.6;
+const AEC_PEAK_EVERY: usize = 64;
+
+/// Acoustic echo canceller: a NLMS adaptive filter over the reference, placed at the
+/// delay found by correlating reference and mic envelopes. Adaptation pauses while
+/// the near end talks, so the filter does not learn the voice.
+pub struct EchoCanceller {
+    channels: usize,
+    sample_rate: u32,
+    reference: Arc<EchoReference>,
+    resampler: Option<(u32, Resampler)>,
+    scratch: Vec<f32>,
+    fifo: VecDeque<f32>,
+    primed: bool,
+    fifo_target: usize,
+    fifo_max: usize,
+    // Reference history, aligned with the mic. Compacted when it doubles
+    far: Vec<f32>,
+    history: usize,
+    taps: Vec<f32>, // taps[i] multiplies the sample `delay + taps.len() - 1 - i` ago
+    delay: usize,
+    max_delay: usize,
+    far_peak: f32,
+    peak_countdown: usize,
+    // Delay estimation
+    block_len: usize,
+    block_pos: usize,
+    block_far: f32,
+    block_mic: f32,
+    far_env: VecDeque<f32>,
+    mic_env: VecDeque<f32>,
+    blocks_to_estimate: usize,
+}
+
+impl EchoCanceller {
+    pub fn new(sample_rate: u32, channels: u16, reference: Arc<EchoReference>) -> Self {
+        let ms = |ms: u32| (sample_rate * ms / 1000) as usize;
+        let taps = ms(AEC_FILTER_MS).max(1);
+        let max_delay = ms(AEC_MAX_DELAY_MS);
+        reference.attach();
+        EchoCanceller {
+            channels: channels.max(1) as usize,
+            sample_rate,
+            reference,
+            resampler: None,
+            scratch: Vec::new(),
+            fifo: VecDeque::new(),
+            primed: false,
+            fifo_target: ms(AEC_FIFO_TARGET_MS),
+            fifo_max: ms(AEC_FIFO_MAX_MS),
+            far: vec![0.0; max_delay + taps],
+            history: max_delay + taps,
+            taps: vec![0.0; taps],
+            delay: 0,
+            max_delay,
+            far_peak: 0.0,
+            peak_countdown: 0,
+            block_len: ms(AEC_ENVELOPE_BLOCK_MS).max(1),
+            block_pos: 0,
+            block_far: 0.0,
+            block_mic: 0.0,
+            far_env: VecDeque::with_capacity(AEC_ENVELOPE_BLOCKS),
+            mic_env: VecDeque::with_capacity(AEC_ENVELOPE_BLOCKS),
+            blocks_to_estimate: AEC_ESTIMATE_EVERY,
+        }
+    }
+
+    /// Current estimation of the echo delay, in samples
+    pub fn delay(&self) -> usize {
+        self.delay
+    }
+
+    // Moves what the output played since last call to the fifo, at our rate
+    fn pull_reference(&mut self) {
+        self.scratch.clear();
+        let rate = self.reference.take(&mut self.scratch);
+        if self.scratch.is_empty() {
+            return;
+        }
+        if rate != self.sample_rate {
+            if self.resampler.as_ref().is_none_or(|(r, _)| *r != rate) {
+                self.resampler = Some((rate, Resampler::new(1, rate, self.sample_rate)));
+            }
+            if let Some((_, resampler)) = self.resampler.as_mut() {
+                self.scratch = resampler.process(&self.scratch);
+            }
+        }
+        if !self.primed {
+            self.fifo.extend(std::iter::repeat_n(0.0, self.fifo_target));
+            self.primed = true;
+        }
+        self.fifo.extend(&self.scratch);
+        if self.fifo.len() > self.fifo_max {
+            let excess = self.fifo.len() - self.fifo_target;
+            log::debug!("Echo reference ahead, dropping {} samples", excess);
+            self.fifo.drain(..excess);
+        }
+    }
+
+    fn next_far(&mut self) -> f32 {
+        match self.fifo.pop_front() {
+            Some(s) => s,
+            None => {
+                // Output stopped (or late). Prefill again when it comes back
+                self.primed = false;
+                0.0
+            }
+        }
+    }
+
+    // Finds the lag that best correlates reference and mic envelopes
+    fn estimate_delay(&mut self) {
+        let n = self.far_env.len();
+        let max_lag = (self.max_delay / self.block_len).min(n / 2);
+        let mean = |v: &[f32]| v.iter().sum::<f32>() / v.len() as f32;
+        let far: Vec<f32> = self.far_env.iter().copied().collect();
+        let mic: Vec<f32> = self.mic_env.iter().copied().collect();
+        let mut best = (0, 0.0f32);
+        for lag in 0..=max_lag {
+            let f = &far[..n - lag];
+            let m = &mic[lag..];
+            let (fm, mm) = (mean(f), mean(m));
+            let (mut cross, mut ff, mut mmsq) = (0.0, 0.0, 0.0);
+            for (a, b) in f.iter().zip(m) {
+                cross += (a - fm) * (b - mm);
+                ff += (a - fm) * (a - fm);
+                mmsq += (b - mm) * (b - mm);
+            }
+            if ff > 0.0 && mmsq > 0.0 {
+                let corr = cross / (ff * mmsq).sqrt();
+                if corr > best.1 {
+                    best = (lag, corr);
+                }
+            }
+        }
+        if best.1 < AEC_MIN_CORRELATION {
+            return;
+        }
+        // Moved only if the peak leaves the first half of the filter, estimations
+        // jitter a block or two and every move throws away what was learnt
+        let peak = best.0 * self.block_len;
+        if peak < self.delay || peak > self.delay + self.taps.len() / 2 {
+            log::debug!(
+                "Echo delay: {} ms (correlation {:.2})",
+                peak * 1000 / self.sample_rate as usize,
+                best.1
+            );
+            // Start the filter a bit before the peak, the echo path spreads around it
+            self.delay = peak.saturating_sub(self.taps.len() / 4);
+            self.taps.iter_mut().for_each(|t| *t = 0.0);
+        }
+    }
+
+    fn track_envelopes(&mut self, far: f32, mic: f32) {
+        self.block_far += far * far;
+        self.block_mic += mic * mic;
+        self.block_pos += 1;
+        if self.block_pos < self.block_len {
+            return;
+        }
+        if self.far_env.len() == AEC_ENVELOPE_BLOCKS {
+            self.far_env.pop_front();
+            self.mic_env.pop_front();
+        }
+        self.far_env.push_back(self.block_far.sqrt());
+        self.mic_env.push_back(self.block_mic.sqrt());
+        (self.block_pos, self.block_far, self.block_mic) = (0, 0.0, 0.0);
+        self.blocks_to_estimate -= 1;
+        if self.blocks_to_estimate == 0 {
+            self.blocks_to_estimate = AEC_ESTIMATE_EVERY;
+            if self.far_env.len() == AEC_ENVELOPE_BLOCKS {
+                self.estimate_delay();
+            }
+        }
+    }
+
+    fn cancel(&mut self, mic: f32) -> f32 {
+        let end = self.far.len() - self.delay;
+        let window = &self.far[end - self.taps.len()..end];
+        if self.peak_countdown == 0 {
+            self.peak_countdown = AEC_PEAK_EVERY;
+            self.far_peak = window.iter().fold(0.0f32, |p, s| p.max(s.abs()));
+        }
+        self.peak_countdown -= 1;
+        let estimate: f32 = self.taps.iter().zip(window).map(|(t, x)| t * x).sum();
+        let error = mic - estimate;
+        let double_talk = mic.abs() > AEC_DOUBLE_TALK * self.far_peak;
+        if self.far_peak > 0.0 && !double_talk {
+            let power: f32 = window.iter().map(|x| x * x).sum();
+            let step = AEC_STEP * error / (power + 1e-6 * self.taps.len() as f32);
+            for (t, x) in self.taps.iter_mut().zip(window) {
+                *t += step * x;
+            }
+        }
+        estimate
+    }
+}
+
+impl Drop for EchoCanceller {
+    fn drop(&mut self) {
+        self.reference.detach();
+    }
+}
+
+impl AudioProcessor for EchoCanceller {
+    fn name(&self) -> &'static str {
+        "echo_cancellation"
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        self.pull_reference();
+        for frame in samples.chunks_mut(self.channels) {
+            let far = self.next_far();
+            if self.far.len() >= self.history * 2 {
+                self.far.drain(..self.far.len() - self.history);
+            }
+            self.far.push(far);
+            // Cancel on the mean of the channels, the same estimate goes to all of them
+            let mic = frame.iter().sum::<f32>() / frame.len() as f32;
+            self.track_envelopes(far, mic);
+            let estimate = self.cancel(mic);
+            frame.iter_mut().for_each(|s| *s -= estimate);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::test_utils::Lcg;
+
+    const RATE: u32 = 16000;
+
+    fn sine(seconds: f32, freq: f32, db: f32) -> Vec<f32> {
+        let amp = db_to_amplitude(db);
+        (0..(seconds * RATE as f32) as usize)
+            .map(|i| amp * (2.0 * std::f32::consts::PI * freq * i as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    fn noise(rng: &mut Lcg, seconds: f32, db: f32) -> Vec<f32> {
+        // Uniform noise has an rms of 1/sqrt(3)
+        let amp = db_to_amplitude(db) * 3f32.sqrt();
+        (0..(seconds * RATE as f32) as usize)
+            .map(|_| amp * rng.signed())
+            .collect()
+    }
+
+    fn rms_db(samples: &[f32]) -> f32 {
+        10.0 * frame_power(samples).max(1e-20).log10()
+    }
+
+    fn run(stage: &mut dyn AudioProcessor, input: &[f32], chunk: usize) -> Vec<f32> {
+        let mut out = input.to_vec();
+        out.chunks_mut(chunk).for_each(|c| stage.process(c));
+        out
+    }
+
+    #[test]
+    fn agc_reaches_target() {
+        let mut agc = AutoGain::new(RATE, 1);
+        // Quiet speaker is raised
+        let out = run(&mut agc, &sine(6.0, 440.0, -35.0), 160);
+        let tail = &out[out.len() - RATE as usize..];
+        assert!(
+            (rms_db(tail) - AGC_TARGET_DB).abs() < 2.0,
+            "{}",
+            rms_db(tail)
+        );
+        // Loud one is lowered, and never clips
+        let mut agc = AutoGain::new(RATE, 1);
+        let out = run(&mut agc, &sine(3.0, 440.0, -3.0), 160);
+        assert!(out.iter().all(|s| s.abs() <= AGC_LIMIT + 1e-6));
+        let tail = &out[out.len() - RATE as usize..];
+        assert!(rms_db(tail) < AGC_TARGET_DB + 10.0, "{}", rms_db(tail));
+    }
+
+    #[test]
+    fn agc_keeps_gain_on_silence() {
+        let mut agc = AutoGain::new(RATE, 1);
+        let mut rng = Lcg(1);
+        run(&mut agc, &noise(&mut rng, 5.0, -70.0), 160);
+        assert!((agc.gain() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn gate_attenuates_background() {
+        let mut gate = NoiseGate::new(RATE, 1);
+        let mut rng = Lcg(2);
+        let background = noise(&mut rng, 4.0, -50.0);
+        let out = run(&mut gate, &background, 160);
+        let tail = RATE as usize * 3..;
+        assert!(!gate.is_open());
+        assert!(rms_db(&background[tail.clone()]) - rms_db(&out[tail]) > 25.0);
+
+        // Speech over the background goes through
+        let speech: Vec<f32> = sine(1.0, 300.0, -20.0)
+            .iter()
+            .zip(noise(&mut rng, 1.0, -50.0))
+            .map(|(s, n)| s + n)
+            .collect();
+        let out = run(&mut gate, &speech, 160);
+        let tail = RATE as usize / 10..;
+        assert!((rms_db(&speech[tail.clone()]) - rms_db(&out[tail])).abs() < 0.5);
+    }
+
+    // Far end speech-like signal: noise with a syllable envelope and pauses
+    fn far_end(rng: &mut Lcg, seconds: f32) -> Vec<f32> {
+        let n = (seconds * RATE as f32) as usize;
+        (0..n)
+            .map(|i| {
+                let t = i as f32 / RATE as f32;
+                let syllables = (2.0 * std::f32::consts::PI * 3.0 * t).sin().max(0.0);
+                let phrases = if (t * 0.7).fract() < 0.8 { 1.0 } else { 0.0 };
+                0.3 * rng.signed() * syllables * phrases
+            })
+            .collect()
+    }
+
+    // Plays `far` and captures it through a room (delay and a short echo path)
+    // plus `near`, in packets as the real streams do
+    fn simulate_echo(
+        aec: &mut EchoCanceller,
+        reference: &EchoReference,
+        far: &[f32],
+        near: &[f32],
+        delay: usize,
+    ) -> (Vec<f32>, Vec<f32>) {
+        let path = [(0, 0.5), (5, 0.25), (17, -0.15), (40, 0.05)];
+        let mic: Vec<f32> = (0..far.len())
+            .map(|i| {
+                let echo: f32 = path
+                    .iter()
+                    .filter(|(d, _)| i >= delay + d)
+                    .map(|(d, g)| g * far[i - delay - d])
+                    .sum();
+                echo + near[i]
+            })
+            .collect();
+        let mut out = mic.clone();
+        let packet = RATE as usize / 100;
+        for (i, chunk) in out.chunks_mut(packet).enumerate() {
+            reference.push(RATE, &far[i * packet..i * packet + chunk.len()]);
+            aec.process(chunk);
+        }
+        (mic, out)
+    }
+
+    #[test]
+    fn aec_removes_echo() {
+        let reference = Arc::new(EchoReference::default());
+        let mut aec = EchoCanceller::new(RATE, 1, Arc::clone(&reference));
+        assert!(reference.is_active());
+        let mut rng = Lcg(3);
+        let far = far_end(&mut rng, 8.0);
+        let room_noise = noise(&mut rng, 8.0, -60.0);
+        let delay = RATE as usize * 60 / 1000;
+        let (mic, out) = simulate_echo(&mut aec, &reference, &far, &room_noise, delay);
+
+        // The fifo delays the reference, so the echo comes that much earlier
+        let expected = delay - (RATE * AEC_FIFO_TARGET_MS / 1000) as usize;
+        assert!(
+            aec.delay() <= expected && expected < aec.delay() + aec.taps.len(),
+            "delay {} expected {}",
+            aec.delay(),
+            expected
+        );
+        let tail = far.len() - 2 * RATE as usize..;
+        let erle = rms_db(&mic[tail.clone()]) - rms_db(&out[tail]);
+        assert!(erle > 20.0, "ERLE {} dB", erle);
+
+        drop(aec);
+        assert!(!reference.is_active());
+    }
+
+    #[test]
+    fn aec_keeps_near_end() {
+        let reference = Arc::new(EchoReference::default());
+        let mut aec = EchoCanceller::new(RATE, 1, Arc::clone(&reference));
+        let mut rng = Lcg(4);
+        let far = far_end(&mut rng, 6.0);
+        let silence = vec![0.0; far.len()];
+        let delay = RATE as usize * 100 / 1000;
+        simulate_echo(&mut aec, &reference, &far, &silence, delay);
+
+        // Near end only, output does not play
+        let near = sine(1.0, 500.0, -20.0);
+        let out = run(&mut aec, &near, 160);
+        let tail = RATE as usize / 2..;
+        assert!((rms_db(&near[tail.clone()]) - rms_db(&out[tail])).abs() < 1.0);
+    }
+
+    #[test]
+    fn chain_from_settings() {
+        let all = MicProcessing {
+            echo_cancellation: true,
+            noise_suppression: true,
+            agc: true,
+        };
+        let all = ProcessorChain::from_settings(&all, RATE, 1);
+        assert_eq!(all.names(), ["echo_cancellation", "noise_gate", "agc"]);
+        let mut chain = ProcessorChain::from_settings(&MicProcessing::default(), RATE, 2);
+        assert!(chain.is_empty());
+        let mut samples = vec![0.25, -0.5];
+        chain.process(&mut samples);
+        assert_eq!(samples, [0.25, -0.5]);
+    }
+
+    #[test]
+    fn chunk_size_does_not_matter() {
+        let mut rng = Lcg(5);
+        let input: Vec<f32> = sine(2.0, 440.0, -30.0)
+            .iter()
+            .zip(noise(&mut rng, 2.0, -55.0))
+            .map(|(s, n)| s + n)
+            .collect();
+        let chain = || {
+            ProcessorChain::new()
+                .with(NoiseGate::new(RATE, 2))
+                .with(AutoGain::new(RATE, 2))
+        };
+        let (mut a, mut b) = (chain(), chain());
+        let mut big = input.clone();
+        big.chunks_mut(960).for_each(|c| a.process(c));
+        let mut small = input.clone();
+        small.chunks_mut(62).for_each(|c| b.process(c));
+        assert_eq!(big, small);
+    }
+}
@@ -0,0 +1,499 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+// Channel aware audio conversion: a polyphase windowed-sinc resampler that keeps
+// per-channel history between calls (so packet boundaries are inaudible) and a
+// channel mapper with up/down-mix matrices.
+// Channel order is the WAVE one: FL, FR, FC, LFE, BL, BR, SL, SR.
+
+// Zero crossings of the sinc on each side, for upsampling. Downsampling widens the
+// filter by the rate ratio so the transition band stays the same in output terms.
+const HALF_TAPS: usize = 16;
+const MAX_TAPS: usize = 256;
+// Phase table limit. Usual rate pairs (44100/48000, 22050/48000, 8000/48000...)
+// are below it and get an exact table; odd ones use the nearest phase.
+const MAX_PHASES: usize = 1024;
+// Kaiser window beta, ~80 dB of stopband attenuation
+const KAISER_BETA: f64 = 8.0;
+// Speed changes allowed to adjustable resamplers (1% is ~17 cents, barely audible)
+const MAX_SPEED_CHANGE: f64 = 0.01;
+// Cutoff relative to the lowest Nyquist frequency, leaves room for the transition band
+const CUTOFF: f64 = 0.91;
+
+const MINUS_3DB: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+// Modified Bessel function of the first kind, order 0 (series expansion)
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..64 {
+        term *= (half / k as f64) * (half / k as f64);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-12 {
+        1.0
+    } else {
+        let px = std::f64::consts::PI * x;
+        px.sin() / px
+    }
+}
+
+pub struct Resampler {
+    channels: usize,
+    passthrough: bool,
+    // Output step, in input samples, is `step / up` (reduced fraction)
+    up: u64,
+    step: u64,
+    taps: usize,
+    phases: usize,
+    coeffs: Vec<f32>, // phases * taps
+    history: Vec<Vec<f32>>,
+    // Position of the next output: history index of its first tap + frac/up
+    start: usize,
+    frac: f64,
+    // Input consumed per output, relative to the nominal rates (clock drift compensation)
+    speed: f64,
+}
+
+impl Resampler {
+    pub fn new(channels: u16, input_rate: u32, output_rate: u32) -> Self {
+        Self::build(channels, input_rate, output_rate, false)
+    }
+
+    /// Resampler whose speed can be changed on the fly with `set_speed`, even
+    /// for equal rates. Uses the full phase table, so fine speed changes are smooth.
+    pub fn adjustable(channels: u16, input_rate: u32, output_rate: u32) -> Self {
+        Self::build(channels, input_rate, output_rate, true)
+    }
+
+    fn build(channels: u16, input_rate: u32, output_rate: u32, adjustable: bool) -> Self {
+        let channels = channels.max(1) as usize;
+        let input_rate = input_rate.max(1);
+        let output_rate = output_rate.max(1);
+        let divisor = gcd(input_rate, output_rate);
+        let up = (output_rate / divisor) as u64;
+        let step = (input_rate / divisor) as u64;
+
+        let ratio = output_rate as f64 / input_rate as f64;
+        let cutoff = CUTOFF * ratio.min(1.0); // relative to input Nyquist
+        let taps = ((2 * HALF_TAPS) as f64 / ratio.min(1.0)).ceil() as usize;
+        let taps = (taps + taps % 2).min(MAX_TAPS);
+        let phases = if adjustable {
+            MAX_PHASES
+        } else {
+            (up as usize).min(MAX_PHASES)
+        };
+
+        let half = (taps / 2) as f64;
+        let window_norm = bessel_i0(KAISER_BETA);
+        let mut coeffs = Vec::with_capacity(phases * taps);
+        for phase in 0..phases {
+            let t = phase as f64 / phases as f64;
+            let start = coeffs.len();
+            for k in 0..taps {
+                // Distance from the output instant to this tap, in input samples
+                let d = k as f64 - half + 1.0 - t;
+                let w = (1.0 - (d / half).powi(2)).max(0.0);
+                let window = bessel_i0(KAISER_BETA * w.sqrt()) / window_norm;
+                coeffs.push((cutoff * sinc(cutoff * d) * window) as f32);
+            }
+            // Unity gain at DC for every phase
+            let sum: f32 = coeffs[start..].iter().sum();
+            coeffs[start..].iter_mut().for_each(|c| *c /= sum);
+        }
+
+        Resampler {
+            channels,
+            passthrough: input_rate == output_rate && !adjustable,
+            up,
+            step,
+            taps,
+            phases,
+            coeffs,
+            // Output 0 is centered on input 0
+            history: vec![vec![0.0; taps / 2 - 1]; channels],
+            start: 0,
+            frac: 0.0,
+            speed: 1.0,
+        }
+    }
+
+    /// Speeds up (> 1.0) or slows down playback slightly. Only for adjustable resamplers.
+    pub fn set_speed(&mut self, speed: f64) {
+        if !self.passthrough {
+            self.speed = speed.clamp(1.0 - MAX_SPEED_CHANGE, 1.0 + MAX_SPEED_CHANGE);
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    /// Resamples interleaved samples. Output is delayed by half the filter length,
+    /// the remaining input is kept for the next call.
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if self.passthrough {
+            return input.to_vec();
+        }
+        for frame in input.chunks_exact(self.channels) {
+            for (history, &sample) in self.history.iter_mut().zip(frame) {
+                history.push(sample);
+            }
+        }
+
+        let available = self.history[0].len();
+        let mut output =
+            Vec::with_capacity((input.len() as u64 * self.up / self.step) as usize + self.channels);
+        let (up, advance) = (self.up as f64, self.step as f64 * self.speed);
+        while self.start + self.taps <= available {
+            let phase = ((self.frac * self.phases as f64 / up) as usize).min(self.phases - 1);
+            let coeffs = &self.coeffs[phase * self.taps..(phase + 1) * self.taps];
+            for history in &self.history {
+                let window = &history[self.start..self.start + self.taps];
+                output.push(window.iter().zip(coeffs).map(|(s, c)| s * c).sum());
+            }
+            // Exact for integer steps at speed 1.0
+            self.frac += advance;
+            let whole = (self.frac / up).floor();
+            self.start += whole as usize;
+            self.frac -= whole * up;
+        }
+
+        // Forget consumed input
+        let consumed = self.start.min(available);
+        for history in self.history.iter_mut() {
+            history.drain(0..consumed);
+        }
+        self.start -= consumed;
+        output
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Speaker {
+    Left,
+    Right,
+    Center,
+    Lfe,
+}
+
+fn speaker(index: usize) -> Speaker {
+    match index {
+        0 | 4 | 6 => Speaker::Left,
+        1 | 5 | 7 => Speaker::Right,
+        3 => Speaker::Lfe,
+        _ => Speaker::Center,
+    }
+}
+
+/// Maps interleaved frames from one channel layout to another
+pub struct ChannelMapper {
+    inputs: usize,
+    outputs: usize,
+    matrix: Vec<f32>, // outputs * inputs, row per output channel
+}
+
+impl ChannelMapper {
+    pub fn new(input_channels: u16, output_channels: u16) -> Self {
+        let inputs = input_channels.max(1) as usize;
+        let outputs = output_channels.max(1) as usize;
+        let mut matrix = vec![0.0; outputs * inputs];
+        let mut set = |o: usize, i: usize, gain: f32| matrix[o * inputs + i] += gain;
+
+        if outputs == 1 {
+            // Everything but the LFE into the single channel
+            for i in 0..inputs {
+                match speaker(i) {
+                    Speaker::Lfe => {}
+                    _ if i < 2 => set(0, i, 1.0),
+                    _ => set(0, i, MINUS_3DB),
+                }
+            }
+        } else if inputs == 1 {
+            // Mono goes to both front speakers
+            set(0, 0, 1.0);
+            set(1, 0, 1.0);
+        } else {
+            // Shared channels pass through, the extra ones are folded into the front pair
+            for i in 0..inputs {
+                if i < outputs {
+                    set(i, i, 1.0);
+                    continue;
+                }
+                match speaker(i) {
+                    Speaker::Left => set(0, i, MINUS_3DB),
+                    Speaker::Right => set(1, i, MINUS_3DB),
+                    Speaker::Center => {
+                        set(0, i, MINUS_3DB);
+                        set(1, i, MINUS_3DB);
+                    }
+                    Speaker::Lfe => {}
+                }
+            }
+        }
+
+        // Full scale input must not clip
+        for row in matrix.chunks_mut(inputs) {
+            let sum: f32 = row.iter().sum();
+            if sum > 1.0 {
+                row.iter_mut().for_each(|g| *g /= sum);
+            }
+        }
+        ChannelMapper {
+            inputs,
+            outputs,
+            matrix,
+        }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.inputs == self.outputs
+    }
+
+    pub fn map(&self, input: &[f32]) -> Vec<f32> {
+        if self.is_identity() {
+            return input.to_vec();
+        }
+        let mut output = Vec::with_capacity(input.len() / self.inputs * self.outputs);
+        for frame in input.chunks_exact(self.inputs) {
+            for row in self.matrix.chunks_exact(self.inputs) {
+                output.push(frame.iter().zip(row).map(|(s, g)| s * g).sum());
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tools::ResamplerIterator;
+    use super::*;
+
+    const TAU: f64 = std::f64::consts::TAU;
+
+    fn tone(freq: f64, rate: u32, frames: usize) -> Vec<f32> {
+        (0..frames)
+            .map(|n| (0.5 * (TAU * freq * n as f64 / rate as f64).sin()) as f32)
+            .collect()
+    }
+
+    fn interleave(left: &[f32], right: &[f32]) -> Vec<f32> {
+        left.iter().zip(right).flat_map(|(&l, &r)| [l, r]).collect()
+    }
+
+    fn channel(data: &[f32], channels: usize, index: usize) -> Vec<f32> {
+        data.iter().skip(index).step_by(channels).copied().collect()
+    }
+
+    // Distortion (plus noise) over the fundamental in dB: the best fitting sine at
+    // `freq` is removed and what remains is compared with it. Edges are skipped.
+    fn thd_db(signal: &[f32], freq: f64, rate: u32) -> f64 {
+        let signal = &signal[signal.len() / 8..signal.len() * 7 / 8];
+        let w = TAU * freq / rate as f64;
+        let (mut ss, mut sc, mut cc, mut ys, mut yc) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for (n, &y) in signal.iter().enumerate() {
+            let (s, c) = (w * n as f64).sin_cos();
+            ss += s * s;
+            sc += s * c;
+            cc += c * c;
+            ys += y as f64 * s;
+            yc += y as f64 * c;
+        }
+        let det = ss * cc - sc * sc;
+        let a = (ys * cc - yc * sc) / det;
+        let b = (yc * ss - ys * sc) / det;
+        let (mut fundamental, mut residual) = (0.0, 0.0);
+        for (n, &y) in signal.iter().enumerate() {
+            let (s, c) = (w * n as f64).sin_cos();
+            let fit = a * s + b * c;
+            fundamental += fit * fit;
+            residual += (y as f64 - fit).powi(2);
+        }
+        10.0 * (residual / fundamental).log10()
+    }
+
+    // Level of a signal relative to a full sine of the given amplitude, in dB
+    fn level_db(signal: &[f32], amplitude: f64) -> f64 {
+        let signal = &signal[signal.len() / 8..signal.len() * 7 / 8];
+        let power = signal.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / signal.len() as f64;
+        10.0 * (power / (amplitude * amplitude / 2.0)).log10()
+    }
+
+    fn resample_chunked(resampler: &mut Resampler, data: &[f32], chunk_frames: usize) -> Vec<f32> {
+        let chunk = chunk_frames * resampler.channels() as usize;
+        data.chunks(chunk)
+            .flat_map(|c| resampler.process(c))
+            .collect()
+    }
+
+    #[test]
+    fn output_length_follows_rate() {
+        let input = tone(1000.0, 44100, 44100);
+        let mut resampler = Resampler::new(1, 44100, 48000);
+        let out = resample_chunked(&mut resampler, &input, 441);
+        // Only the filter delay is missing
+        assert!(48000 - out.len() <= HALF_TAPS * 2);
+    }
+
+    #[test]
+    fn passthrough_is_exact() {
+        let input = interleave(&tone(440.0, 48000, 512), &tone(880.0, 48000, 512));
+        let mut resampler = Resampler::new(2, 48000, 48000);
+        assert_eq!(resampler.process(&input), input);
+    }
+
+    #[test]
+    fn chunking_does_not_change_output() {
+        let input = interleave(&tone(1000.0, 22050, 4000), &tone(300.0, 22050, 4000));
+        let whole = Resampler::new(2, 22050, 48000).process(&input);
+        let mut resampler = Resampler::new(2, 22050, 48000);
+        // Packet sizes that do not align with anything
+        let mut chunked = Vec::new();
+        let mut rest = &input[..];
+        for size in [2usize, 6, 882, 10, 1000, 14].iter().cycle() {
+            if rest.is_empty() {
+                break;
+            }
+            let (head, tail) = rest.split_at((*size).min(rest.len()));
+            chunked.extend(resampler.process(head));
+            rest = tail;
+        }
+        assert_eq!(whole.len(), chunked.len());
+        for (a, b) in whole.iter().zip(&chunked) {
+            assert!((a - b).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn thd_better_than_linear() {
+        let input = tone(1000.0, 44100, 44100);
+        let mut resampler = Resampler::new(1, 44100, 48000);
+        let polyphase = thd_db(
+            &resample_chunked(&mut resampler, &input, 441),
+            1000.0,
+            48000,
+        );
+        let linear: Vec<f32> = ResamplerIterator::new(input.into_iter(), 44100, 48000).collect();
+        let linear = thd_db(&linear, 1000.0, 48000);
+        assert!(polyphase < -80.0, "polyphase THD {:.1} dB", polyphase);
+        assert!(
+            polyphase < linear - 20.0,
+            "{:.1} vs {:.1}",
+            polyphase,
+            linear
+        );
+    }
+
+    #[test]
+    fn stereo_channels_do_not_mix() {
+        let (left, right) = (tone(1000.0, 44100, 44100), tone(5000.0, 44100, 44100));
+        let input = interleave(&left, &right);
+        let mut resampler = Resampler::new(2, 44100, 48000);
+        let out = resample_chunked(&mut resampler, &input, 1024);
+        assert!(thd_db(&channel(&out, 2, 0), 1000.0, 48000) < -80.0);
+        assert!(thd_db(&channel(&out, 2, 1), 5000.0, 48000) < -80.0);
+
+        // The current iterator interpolates across L and R
+        let linear: Vec<f32> = ResamplerIterator::new(input.into_iter(), 44100, 48000).collect();
+        assert!(thd_db(&channel(&linear, 2, 0), 1000.0, 48000) > -20.0);
+    }
+
+    #[test]
+    fn downsampling_rejects_aliases() {
+        // 15 kHz does not fit in 22050 Hz, it must be filtered instead of folding to 7050 Hz
+        let input = tone(15000.0, 48000, 48000);
+        let mut resampler = Resampler::new(1, 48000, 22050);
+        let filtered = level_db(&resample_chunked(&mut resampler, &input, 480), 0.5);
+        let linear: Vec<f32> = ResamplerIterator::new(input.into_iter(), 48000, 22050).collect();
+        let linear = level_db(&linear, 0.5);
+        assert!(filtered < -60.0, "alias level {:.1} dB", filtered);
+        assert!(linear > -20.0, "linear alias level {:.1} dB", linear);
+
+        // While tones in the passband are kept
+        let input = tone(5000.0, 48000, 48000);
+        let mut resampler = Resampler::new(1, 48000, 22050);
+        let out = resample_chunked(&mut resampler, &input, 480);
+        assert!(level_db(&out, 0.5).abs() < 0.1);
+        assert!(thd_db(&out, 5000.0, 22050) < -70.0);
+    }
+
+    #[test]
+    fn adjustable_speed() {
+        let input = tone(1000.0, 48000, 48000);
+        // Same rates, nominal speed: the tone is untouched
+        let mut resampler = Resampler::adjustable(1, 48000, 48000);
+        let out = resample_chunked(&mut resampler, &input, 480);
+        assert!(48000 - out.len() <= HALF_TAPS * 2);
+        assert!(thd_db(&out, 1000.0, 48000) < -70.0);
+
+        // 0.5% faster: less output, tone shifted up by the same amount
+        let mut resampler = Resampler::adjustable(1, 48000, 48000);
+        resampler.set_speed(1.005);
+        let out = resample_chunked(&mut resampler, &input, 480);
+        let expected = (48000.0 / 1.005) as usize;
+        assert!(expected.abs_diff(out.len()) <= HALF_TAPS * 2);
+        assert!(thd_db(&out, 1005.0, 48000) < -70.0);
+
+        // Limited to small changes
+        resampler.set_speed(2.0);
+        assert!((resampler.speed() - (1.0 + MAX_SPEED_CHANGE)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mapper_mono_to_stereo() {
+        let mapper = ChannelMapper::new(1, 2);
+        assert_eq!(mapper.map(&[0.5, -0.25]), vec![0.5, 0.5, -0.25, -0.25]);
+    }
+
+    #[test]
+    fn mapper_stereo_to_mono() {
+        let mapper = ChannelMapper::new(2, 1);
+        assert_eq!(mapper.map(&[1.0, 0.0, 0.5, 0.5]), vec![0.5, 0.5]);
+    }
+
+    #[test]
+    fn mapper_stereo_to_surround() {
+        let mapper = ChannelMapper::new(2, 6);
+        assert_eq!(
+            mapper.map(&[0.5, -0.5]),
+            vec![0.5, -0.5, 0.0, 0.0, 0.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn mapper_surround_to_stereo_does_not_clip() {
+        let mapper = ChannelMapper::new(6, 2);
+        let out = mapper.map(&[1.0; 6]);
+        assert!(out.iter().all(|&s| s <= 1.0 + 1e-6));
+        // Left only content stays on the left
+        let out = mapper.map(&[1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        assert!(out[0] > 0.5 && out[1] == 0.0);
+        // LFE is dropped
+        assert_eq!(mapper.map(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0]), vec![0.0, 0.0]);
+    }
+
+    #[test]
+    fn mapper_identity() {
+        let mapper = ChannelMapper::new(2, 2);
+        assert!(mapper.is_identity());
+        assert_eq!(mapper.map(&[0.1, 0.2]), vec![0.1, 0.2]);
+    }
+}
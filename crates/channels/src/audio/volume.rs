@@ -0,0 +1,175 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+// Output volume. Two levels are applied: the one set by the server, in RDP format
+// (u32, low word left channel, high word right channel, 0..0xFFFF each) and the
+// local one chosen by the user (percent + mute).
+// Read from the audio callback, so everything is atomic.
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+pub const FULL_RDP_VOLUME: u32 = 0xFFFF_FFFF;
+pub const MAX_LOCAL_VOLUME: u32 = 100;
+pub const LOCAL_VOLUME_STEP: u32 = 10;
+
+/// Left and right gains (0.0..=1.0) of a RDP volume value
+pub fn unpack_rdp_volume(volume: u32) -> (f32, f32) {
+    let left = (volume & 0xFFFF) as f32 / 0xFFFF as f32;
+    let right = (volume >> 16) as f32 / 0xFFFF as f32;
+    (left, right)
+}
+
+/// Gain of a channel from the left and right ones. WAVE channel order: even channels
+/// are on the left, odd on the right, except front center and LFE, that get both.
+pub fn channel_gain(channel: usize, channels: usize, (left, right): (f32, f32)) -> f32 {
+    match channel {
+        _ if channels == 1 => (left + right) / 2.0,
+        // Quad has no center, its 2 and 3 are the back ones
+        2 | 3 if channels != 4 => (left + right) / 2.0,
+        c if c % 2 == 0 => left,
+        _ => right,
+    }
+}
+
+#[derive(Debug)]
+pub struct VolumeControl {
+    server: AtomicU32,
+    local: AtomicU32,
+    muted: AtomicBool,
+}
+
+impl VolumeControl {
+    pub fn new() -> Self {
+        VolumeControl {
+            server: AtomicU32::new(FULL_RDP_VOLUME),
+            local: AtomicU32::new(MAX_LOCAL_VOLUME),
+            muted: AtomicBool::new(false),
+        }
+    }
+
+    pub fn server(&self) -> u32 {
+        self.server.load(Ordering::Relaxed)
+    }
+
+    pub fn set_server(&self, volume: u32) {
+        self.server.store(volume, Ordering::Relaxed);
+    }
+
+    /// Local volume, in percent
+    pub fn local(&self) -> u32 {
+        self.local.load(Ordering::Relaxed)
+    }
+
+    pub fn set_local(&self, percent: u32) {
+        self.local
+            .store(percent.min(MAX_LOCAL_VOLUME), Ordering::Relaxed);
+    }
+
+    /// Raises or lowers the local volume one step, returning the new one.
+    /// Changing the volume unmutes.
+    pub fn step_local(&self, up: bool) -> u32 {
+        let current = self.local();
+        let volume = if up {
+            (current + LOCAL_VOLUME_STEP).min(MAX_LOCAL_VOLUME)
+        } else {
+            current.saturating_sub(LOCAL_VOLUME_STEP)
+        };
+        self.set_local(volume);
+        self.set_muted(false);
+        volume
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    /// Returns the new mute state
+    pub fn toggle_mute(&self) -> bool {
+        !self.muted.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    /// Effective left and right gains
+    pub fn gains(&self) -> (f32, f32) {
+        if self.is_muted() {
+            return (0.0, 0.0);
+        }
+        // Squared, so steps sound evenly spaced
+        let local = (self.local() as f32 / MAX_LOCAL_VOLUME as f32).powi(2);
+        let (left, right) = unpack_rdp_volume(self.server());
+        (left * local, right * local)
+    }
+
+    /// Scales interleaved samples of `channels` channels in place
+    pub fn apply(&self, samples: &mut [f32], channels: usize) {
+        let gains = self.gains();
+        let channels = channels.max(1);
+        for (i, sample) in samples.iter_mut().enumerate() {
+            *sample *= channel_gain(i % channels, channels, gains);
+        }
+    }
+}
+
+impl Default for VolumeControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scaled(control: &VolumeControl, samples: &[f32], channels: usize) -> Vec<f32> {
+        let mut samples = samples.to_vec();
+        control.apply(&mut samples, channels);
+        samples
+    }
+
+    #[test]
+    fn rdp_volume_format() {
+        assert_eq!(unpack_rdp_volume(FULL_RDP_VOLUME), (1.0, 1.0));
+        assert_eq!(unpack_rdp_volume(0), (0.0, 0.0));
+        assert_eq!(unpack_rdp_volume(0x0000_FFFF), (1.0, 0.0));
+        let (left, right) = unpack_rdp_volume(0xFFFF_7FFF);
+        assert!((left - 0.5).abs() < 0.001 && right == 1.0);
+    }
+
+    #[test]
+    fn server_volume_per_channel() {
+        let control = VolumeControl::new();
+        control.set_server(0xFFFF_0000); // right only
+        assert_eq!(
+            scaled(&control, &[1.0, 1.0, 0.5, 0.5], 2),
+            [0.0, 1.0, 0.0, 0.5]
+        );
+        // Mono gets the mean
+        assert_eq!(scaled(&control, &[1.0], 1), [0.5]);
+        // 5.1: center and LFE get the mean, back channels follow their side
+        assert_eq!(
+            scaled(&control, &[1.0; 6], 6),
+            [0.0, 1.0, 0.5, 0.5, 0.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn local_volume_and_mute() {
+        let control = VolumeControl::new();
+        assert_eq!(control.gains(), (1.0, 1.0));
+        assert_eq!(control.step_local(false), 90);
+        assert!((control.gains().0 - 0.81).abs() < 0.001);
+        assert!(control.toggle_mute());
+        assert_eq!(control.gains(), (0.0, 0.0));
+        assert_eq!(scaled(&control, &[1.0, 1.0], 2), [0.0, 0.0]);
+        // Changing the volume unmutes
+        assert_eq!(control.step_local(true), 100);
+        assert!(!control.is_muted());
+        assert_eq!(control.step_local(true), 100);
+        control.set_local(0);
+        assert_eq!(control.step_local(false), 0);
+    }
+}
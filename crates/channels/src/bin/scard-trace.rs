@@ -0,0 +1,36 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! Print a smartcard trace (`UDS_SMARTCARD_TRACE`) with the GIDS APDUs decoded.
+
+use channels::smartcard::trace::{describe_event, read_trace};
+
+const USAGE: &str = "Usage: scard-trace <trace file>
+
+  Prints the SCard calls of a session trace, with the time (seconds from the
+  first call) and the GIDS commands and status words decoded. PIN bytes are
+  masked in the trace (**).";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let path = match args.as_slice() {
+        [arg] if arg != "-h" && arg != "--help" => arg,
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+    let events = match read_trace(path) {
+        Ok(events) => events,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let start = events.first().map(|e| e.time).unwrap_or_default();
+    for event in &events {
+        println!("{}", describe_event(event, start));
+    }
+}
@@ -0,0 +1,230 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! Ephemeral per-session card: a key pair generated in memory for one session.
+//!
+//! The launcher generates an RSA 2048 key, sends a PKCS#10 CSR of it to the broker
+//! and gets back a short-lived certificate (issued by a CA the domain trusts for
+//! smartcard logon). [`EphemeralKey::into_session_card`] keeps the pair for the
+//! next session, whose `ephemeral` emulated spec takes it as a container. The key
+//! never touches the disk: it lives in the container signer and is zeroized when
+//! the card is dropped, on disconnect.
+
+use std::sync::{LazyLock, Mutex};
+
+use rsa::pkcs8::EncodePublicKey;
+use rsa::{Pkcs1v15Sign, RsaPrivateKey};
+use sha2::Digest;
+
+use super::signer::{self, Mechanism, Signer};
+
+const KEY_BITS: usize = 2048;
+
+/// `sha256WithRSAEncryption` AlgorithmIdentifier (with its NULL parameters).
+const SHA256_WITH_RSA: &[u8] = &[
+    0x30, 0x0D, 0x06, 0x09, 0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x0B, 0x05, 0x00,
+];
+
+/// DigestInfo prefix of a SHA-256 hash.
+const SHA256_DIGEST_INFO: &[u8] = &[
+    0x30, 0x31, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05,
+    0x00, 0x04, 0x20,
+];
+
+/// `id-at-commonName` OID content.
+const COMMON_NAME_OID: &[u8] = &[0x55, 0x04, 0x03];
+
+/// Issued card waiting for its session.
+static SESSION_CARD: LazyLock<Mutex<Option<SessionCard>>> = LazyLock::new(|| Mutex::new(None));
+
+struct SessionCard {
+    cert_der: Vec<u8>,
+    signer: EphemeralSigner,
+}
+
+/// Key pair of a session card, before its certificate is issued.
+pub struct EphemeralKey {
+    key: RsaPrivateKey,
+}
+
+impl std::fmt::Debug for EphemeralKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EphemeralKey").finish()
+    }
+}
+
+impl EphemeralKey {
+    pub fn generate() -> Result<Self, String> {
+        let key = RsaPrivateKey::new(&mut rsa::rand_core::OsRng, KEY_BITS)
+            .map_err(|e| format!("ephemeral key: {}", e))?;
+        Ok(EphemeralKey { key })
+    }
+
+    /// PEM of a PKCS#10 request for the key. The subject only has the common name
+    /// (if any): the broker CA sets the names and extensions smartcard logon needs
+    /// (UPN, EKU) from the ticket user.
+    pub fn csr_pem(&self, common_name: Option<&str>) -> Result<String, String> {
+        let spki = self.spki()?;
+        let subject = match common_name {
+            Some(cn) => der_tlv(
+                0x30,
+                &der_tlv(
+                    0x31,
+                    &der_tlv(
+                        0x30,
+                        &[der_tlv(0x06, COMMON_NAME_OID), der_tlv(0x0C, cn.as_bytes())].concat(),
+                    ),
+                ),
+            ),
+            None => der_tlv(0x30, &[]),
+        };
+        // version 0, subject, subjectPKInfo, no attributes
+        let info = der_tlv(
+            0x30,
+            &[&[0x02, 0x01, 0x00][..], &subject, &spki, &[0xA0, 0x00]].concat(),
+        );
+        let digest_info = [SHA256_DIGEST_INFO, &sha2::Sha256::digest(&info)].concat();
+        let signature = self
+            .key
+            .sign(Pkcs1v15Sign::new_unprefixed(), &digest_info)
+            .map_err(|e| format!("CSR signature: {}", e))?;
+        // BIT STRING without unused bits
+        let signature = der_tlv(0x03, &[&[0x00][..], &signature].concat());
+        let request = der_tlv(0x30, &[&info[..], SHA256_WITH_RSA, &signature].concat());
+        Ok(pem::Pem::new("CERTIFICATE REQUEST", request).to_string())
+    }
+
+    /// Keep the key with its issued certificate (PEM) for the next session. The
+    /// certificate must be for this key. Replaces (and drops) a card not used yet.
+    pub fn into_session_card(self, cert_pem: &str) -> Result<(), String> {
+        let cert_der = signer::certificate_from_pem(cert_pem)?;
+        // The SubjectPublicKeyInfo is embedded as is in the certificate
+        let spki = self.spki()?;
+        if !cert_der.windows(spki.len()).any(|w| w == spki) {
+            return Err("the issued certificate is not for the session key".to_string());
+        }
+        *SESSION_CARD.lock().unwrap() = Some(SessionCard {
+            cert_der,
+            signer: EphemeralSigner { key: self.key },
+        });
+        Ok(())
+    }
+
+    fn spki(&self) -> Result<Vec<u8>, String> {
+        self.key
+            .to_public_key()
+            .to_public_key_der()
+            .map(|doc| doc.into_vec())
+            .map_err(|e| format!("public key: {}", e))
+    }
+}
+
+/// The issued session card (certificate DER and its key), taken once.
+pub(super) fn take_session_card() -> Option<(Vec<u8>, Box<dyn Signer>)> {
+    SESSION_CARD
+        .lock()
+        .unwrap()
+        .take()
+        .map(|card| (card.cert_der, Box::new(card.signer) as Box<dyn Signer>))
+}
+
+/// Signs with the in-memory key; `RsaPrivateKey` zeroizes itself on drop.
+struct EphemeralSigner {
+    key: RsaPrivateKey,
+}
+
+impl std::fmt::Debug for EphemeralSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EphemeralSigner").finish()
+    }
+}
+
+impl Signer for EphemeralSigner {
+    fn sign(&self, mechanism: Mechanism, data: &[u8]) -> Result<Vec<u8>, String> {
+        if mechanism != Mechanism::RsaPkcs1 {
+            return Err("the ephemeral key is RSA".to_string());
+        }
+        self.key
+            .sign(Pkcs1v15Sign::new_unprefixed(), data)
+            .map_err(|e| format!("ephemeral key: {}", e))
+    }
+}
+
+/// DER TLV with a 1-byte tag.
+fn der_tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut tlv = Vec::with_capacity(4 + content.len());
+    tlv.push(tag);
+    match content.len() {
+        len if len < 0x80 => tlv.push(len as u8),
+        len if len < 0x100 => tlv.extend_from_slice(&[0x81, len as u8]),
+        len => tlv.extend_from_slice(&[0x82, (len >> 8) as u8, (len & 0xFF) as u8]),
+    }
+    tlv.extend_from_slice(content);
+    tlv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsa::pkcs8::DecodePublicKey;
+
+    /// Minimal "certificate" around a SubjectPublicKeyInfo, as the broker would
+    /// issue it (only what the card reads: the key in the tbsCertificate).
+    fn issued_cert(spki: &[u8]) -> String {
+        let cert = der_tlv(0x30, &der_tlv(0x30, spki));
+        pem::Pem::new("CERTIFICATE", cert).to_string()
+    }
+
+    #[test]
+    fn csr_is_signed_by_the_key() {
+        let key = EphemeralKey::generate().unwrap();
+        let csr = pem::parse(key.csr_pem(Some("jdoe")).unwrap()).unwrap();
+        assert_eq!(csr.tag(), "CERTIFICATE REQUEST");
+        let der = csr.contents();
+
+        // CertificationRequest: info || algorithm || signature
+        let info_len = 4 + ((der[6] as usize) << 8 | der[7] as usize);
+        let info = &der[4..4 + info_len];
+        let spki = key.spki().unwrap();
+        assert!(info.windows(spki.len()).any(|w| w == spki));
+        assert!(info.windows(4).any(|w| w == b"jdoe"));
+        let rest = &der[4 + info_len..];
+        assert_eq!(&rest[..SHA256_WITH_RSA.len()], SHA256_WITH_RSA);
+        let signature = &rest[SHA256_WITH_RSA.len() + 5..];
+        assert_eq!(signature.len(), KEY_BITS / 8);
+
+        let public = rsa::RsaPublicKey::from_public_key_der(&spki).unwrap();
+        let digest_info = [SHA256_DIGEST_INFO, &sha2::Sha256::digest(info)].concat();
+        public
+            .verify(Pkcs1v15Sign::new_unprefixed(), &digest_info, signature)
+            .unwrap();
+    }
+
+    #[test]
+    fn session_card_is_taken_once() {
+        let key = EphemeralKey::generate().unwrap();
+        let spki = key.spki().unwrap();
+        let other = EphemeralKey::generate().unwrap();
+        assert!(
+            EphemeralKey::generate()
+                .unwrap()
+                .into_session_card(&issued_cert(&other.spki().unwrap()))
+                .is_err()
+        );
+
+        key.into_session_card(&issued_cert(&spki)).unwrap();
+        let (cert_der, signer) = take_session_card().unwrap();
+        assert!(take_session_card().is_none());
+        assert_eq!(cert_der, der_tlv(0x30, &der_tlv(0x30, &spki)));
+
+        let digest_info = [SHA256_DIGEST_INFO, &[7u8; 32]].concat();
+        let signature = signer.sign(Mechanism::RsaPkcs1, &digest_info).unwrap();
+        let public = rsa::RsaPublicKey::from_public_key_der(&spki).unwrap();
+        public
+            .verify(Pkcs1v15Sign::new_unprefixed(), &digest_info, &signature)
+            .unwrap();
+        assert!(signer.sign(Mechanism::Ecdsa, &[7u8; 32]).is_err());
+    }
+}
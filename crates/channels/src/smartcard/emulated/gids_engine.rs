@@ -14,10 +14,25 @@
 //! The public key (`7F 49`), certificate (`DF 24`), card id and container GUID are
 //! derived from the loaded certificate + private key.
 //!
+//! The card can hold several containers (one per certificate + key pair, up to
+//! [`MAX_CONTAINERS`]): each one gets its cmapfile record, its certificate file in
+//! the card application table (`DF 24`, `DF 25`...) and its key reference (`81`,
+//! `82`...), which MSE SET uses to pick the signing key.
+//!
+//! Besides RSA, EC P-256 and P-384 keys are supported: the container is then a
+//! signature container (`ksc00` instead of `kxc00`, key size in the signature slot
+//! of the cmapfile), the `7F 49` carries the EC point (tag `86`) and PSO returns a
+//! raw `r || s` ECDSA signature of the hash sent by the minidriver.
+//!
+//! A container key can also stay out of the card, in an external signer (see
+//! `signer`): PSO then hands it the DigestInfo or hash to sign, and the public key
+//! is taken from the certificate.
+//!
 //! PIN handling mirrors a real smartcard: the certificate (public) is served without
 //! any PIN. Signing is gated by a VERIFY. The "PIN" is the private key's password
 //! when the key is encrypted: VERIFY succeeds only if the entered PIN decrypts the
-//! key. If the key has no password, no PIN is required at all.
+//! key. If the key has no password, no PIN is required at all. The retry counter
+//! and the PIN policy (including entering the PIN on the client) are in `pin`.
 
 use std::io::Write;
 use std::sync::LazyLock;
@@ -25,11 +40,16 @@ use std::sync::LazyLock;
 use flate2::Compression;
 use flate2::write::ZlibEncoder;
 use num_bigint::BigUint;
+use p256::ecdsa::signature::hazmat::PrehashSigner;
 use rsa::RsaPrivateKey;
 use rsa::pkcs1::EncodeRsaPrivateKey;
 use rsa::pkcs8::DecodePrivateKey;
+use sha2::Digest;
+use zeroize::Zeroizing;
 
 use super::helpers::*;
+use super::pin::{MemoryStore, PinCounter, PinPolicy, RetryStore};
+use super::signer::{Mechanism, Signer};
 
 // ============================================================================
 // GIDS protocol constants (from the reference card)
@@ -68,6 +88,7 @@ const SW_AUTH_METHOD_BLOCKED: u16 = 0x6983;
 const SW_VERIFY_FAILED: u16 = 0x63C0;
 const SW_FILE_NOT_FOUND: u16 = 0x6A88;
 const SW_INVALID_P1P2: u16 = 0x6A86;
+const SW_INCORRECT_PARAMS: u16 = 0x6A80;
 const SW_F_INTERNAL_ERROR: u16 = 0x6581;
 
 /// SELECT GIDS AID (P2=00) response: FCI with the AID (Application Template).
@@ -116,7 +137,50 @@ fn bytes_from_hex(hex: &str) -> Vec<u8> {
     out
 }
 
-static CARDAPPS: LazyLock<Vec<u8>> = LazyLock::new(|| bytes_from_hex(CARDAPPS_HEX));
+/// Content of the reference card application table (a `01` byte and its 28-byte
+/// records) without the DO header and the `kxc00` record: the card files, to which
+/// the certificate file of each container is appended.
+static CARDAPPS_BASE: LazyLock<Vec<u8>> = LazyLock::new(|| {
+    let cardapps = bytes_from_hex(CARDAPPS_HEX);
+    cardapps[4..cardapps.len() - 28].to_vec()
+});
+
+/// Containers (certificates) the card can hold: their certificates use the data
+/// objects `DF 24` to `DF 2B`.
+pub const MAX_CONTAINERS: usize = 8;
+
+/// Data object of the first container certificate (`DF 24`).
+const FIRST_CERT_DO: u8 = 0x24;
+
+/// Key reference of the first container (`84 01 81`).
+const FIRST_KEY_REFERENCE: u8 = 0x81;
+
+/// cmapfile (`DF 23`) record length.
+const CMAP_RECORD_LEN: usize = 86;
+
+/// Header of a cached `ContainerInfo_XX`, followed by the data length (LE u32).
+const CONTAINER_INFO_HEADER: &[u8] = &[
+    0x00, 0x00, 0x01, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// CALG_RSA_KEYX, algorithm of the key exchange public key blob.
+const CALG_RSA_KEYX: u32 = 0x0000_A400;
+
+/// GIDS key type identifiers: the low nibble of the MSE SET algorithm reference
+/// (`80 01 57` on the reference card = RSA PKCS#1 with its 2048-bit key).
+const GIDS_RSA_1024: u8 = 0x06;
+const GIDS_RSA_2048: u8 = 0x07;
+const GIDS_RSA_3072: u8 = 0x08;
+const GIDS_RSA_4096: u8 = 0x09;
+const GIDS_ECC_256: u8 = 0x0C;
+const GIDS_ECC_384: u8 = 0x0D;
+
+/// id-ecPublicKey (1.2.840.10045.2.1)
+const EC_PUBLIC_KEY_OID: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01];
+/// prime256v1 (1.2.840.10045.3.1.7)
+const P256_OID: &[u8] = &[0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
+/// secp384r1 (1.3.132.0.34)
+const P384_OID: &[u8] = &[0x2B, 0x81, 0x04, 0x00, 0x22];
 
 // ============================================================================
 // GIDS engine
@@ -125,47 +189,169 @@ static CARDAPPS: LazyLock<Vec<u8>> = LazyLock::new(|| bytes_from_hex(CARDAPPS_HE
 /// How the "PIN" gates the private key:
 /// - `None`: the private key has no password -> signing needs no PIN.
 /// - `KeyPassword`: the private key is encrypted; the PIN IS its password. VERIFY
-///   succeeds only if the entered PIN actually decrypts the key.
+///   succeeds only if the entered PIN actually decrypts the key (or logs in the
+///   external signer holding it, e.g. a PKCS#11 token).
 #[derive(Clone, Copy, PartialEq, Debug)]
 enum PinMode {
     None,
     KeyPassword,
 }
 
-pub struct GidsEngine {
+/// Curves supported for EC keys.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum EcCurve {
+    P256,
+    P384,
+}
+
+impl EcCurve {
+    fn bits(self) -> u16 {
+        match self {
+            EcCurve::P256 => 256,
+            EcCurve::P384 => 384,
+        }
+    }
+
+    fn gids_identifier(self) -> u8 {
+        match self {
+            EcCurve::P256 => GIDS_ECC_256,
+            EcCurve::P384 => GIDS_ECC_384,
+        }
+    }
+
+    /// BCRYPT_ECDSA_PUBLIC_P256_MAGIC ("ECS1") / BCRYPT_ECDSA_PUBLIC_P384_MAGIC ("ECS3").
+    fn ecdsa_public_magic(self) -> u32 {
+        match self {
+            EcCurve::P256 => 0x3153_4345,
+            EcCurve::P384 => 0x3353_4345,
+        }
+    }
+}
+
+/// ECDSA private key of one of the supported curves.
+enum EcSigner {
+    P256(p256::ecdsa::SigningKey),
+    P384(p384::ecdsa::SigningKey),
+}
+
+impl EcSigner {
+    /// Unencrypted PKCS#8 (`PRIVATE KEY`) or SEC1 (`EC PRIVATE KEY`) PEM.
+    fn from_pem(pem: &str) -> Option<Self> {
+        if let Ok(key) =
+            p256::SecretKey::from_pkcs8_pem(pem).or_else(|_| p256::SecretKey::from_sec1_pem(pem))
+        {
+            return Some(EcSigner::P256(key.into()));
+        }
+        p384::SecretKey::from_pkcs8_pem(pem)
+            .or_else(|_| p384::SecretKey::from_sec1_pem(pem))
+            .ok()
+            .map(|key| EcSigner::P384(key.into()))
+    }
+
+    /// Encrypted PKCS#8 PEM of a key on `curve`.
+    fn from_encrypted_pem(pem: &str, password: &[u8], curve: EcCurve) -> Option<Self> {
+        match curve {
+            EcCurve::P256 => p256::SecretKey::from_pkcs8_encrypted_pem(pem, password)
+                .ok()
+                .map(|key| EcSigner::P256(key.into())),
+            EcCurve::P384 => p384::SecretKey::from_pkcs8_encrypted_pem(pem, password)
+                .ok()
+                .map(|key| EcSigner::P384(key.into())),
+        }
+    }
+
+    fn curve(&self) -> EcCurve {
+        match self {
+            EcSigner::P256(_) => EcCurve::P256,
+            EcSigner::P384(_) => EcCurve::P384,
+        }
+    }
+
+    /// Uncompressed public point (`04 || X || Y`).
+    fn public_point(&self) -> Vec<u8> {
+        match self {
+            EcSigner::P256(key) => key
+                .verifying_key()
+                .to_encoded_point(false)
+                .as_bytes()
+                .to_vec(),
+            EcSigner::P384(key) => key
+                .verifying_key()
+                .to_encoded_point(false)
+                .as_bytes()
+                .to_vec(),
+        }
+    }
+
+    /// ECDSA over an already computed hash, as `r || s`.
+    fn sign(&self, hash: &[u8]) -> Result<Vec<u8>, String> {
+        match self {
+            EcSigner::P256(key) => {
+                let sig: p256::ecdsa::Signature =
+                    key.sign_prehash(hash).map_err(|e| format!("ECDSA: {e}"))?;
+                Ok(sig.to_bytes().to_vec())
+            }
+            EcSigner::P384(key) => {
+                let sig: p384::ecdsa::Signature =
+                    key.sign_prehash(hash).map_err(|e| format!("ECDSA: {e}"))?;
+                Ok(sig.to_bytes().to_vec())
+            }
+        }
+    }
+}
+
+/// The card key pair. The private half is `None` until an encrypted key is
+/// decrypted by a correct VERIFY.
+enum CardKey {
+    Rsa {
+        n: BigUint,
+        e: BigUint,
+        d: Option<BigUint>,
+    },
+    Ec {
+        curve: EcCurve,
+        /// Uncompressed public point
+        point: Vec<u8>,
+        signer: Option<EcSigner>,
+    },
+}
+
+/// Private key of a container: a PEM (unencrypted, or encrypted with the card
+/// PIN), or an external signer that keeps the key (PKCS#11 token, HTTP service).
+pub(crate) enum KeySource {
+    Pem(String),
+    Signer(Box<dyn Signer>),
+}
+
+impl From<String> for KeySource {
+    fn from(pem: String) -> Self {
+        KeySource::Pem(pem)
+    }
+}
+
+/// One key container of the card: a certificate and its key pair.
+struct Container {
+    /// Container GUID in the cmapfile.
+    guid: String,
+    /// SHA-256 of the certificate (hex), keys its PIN failures.
+    fingerprint: String,
     /// `Cached_GeneralFile/mscp/kxcXX` content: `01 00` + uncompressed cert length
     /// (2 bytes LITTLE-ENDIAN) + zlib-compressed certificate DER. The BaseCSP
     /// expects this compressed format (the reference card stores it this way).
     cert_content: Vec<u8>,
-    pin_mode: PinMode,
     /// Encrypted PKCS#8 PEM of the private key, kept to decrypt on VERIFY. `None`
     /// when the key is not encrypted.
     key_pem: Option<String>,
-    n: BigUint,
-    e: BigUint,
-    /// Private exponent; `None` until the encrypted key is decrypted by a correct
-    /// VERIFY (always `Some` when `pin_mode == PinMode::None`).
-    d: Option<BigUint>,
+    /// Signs for the container when the private key is not on the card; `key`
+    /// then only has the public half (from the certificate).
+    signer: Option<Box<dyn Signer>>,
+    key: CardKey,
+    /// RSA modulus length, or EC field length, in bytes.
     key_size: usize,
-    pin_verified: bool,
-    pin_retries: u8,
-    chaining: Option<Vec<u8>>,
-}
-
-impl std::fmt::Debug for GidsEngine {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("GidsEngine")
-            .field("key_size", &self.key_size)
-            .field("pin_mode", &self.pin_mode)
-            .field("pin_verified", &self.pin_verified)
-            .finish()
-    }
 }
 
-const DEFAULT_PIN_RETRIES: u8 = 3;
-
-impl GidsEngine {
-    pub fn new(cert_der: Vec<u8>, key_pem: String) -> Result<Self, String> {
+impl Container {
+    fn new(index: usize, cert_der: Vec<u8>, key: KeySource) -> Result<Self, String> {
         if cert_der.len() > u16::MAX as usize {
             return Err("certificate exceeds the GIDS length field".to_string());
         }
@@ -183,56 +369,395 @@ impl GidsEngine {
         cert_content.extend_from_slice(&(cert_der.len() as u16).to_le_bytes());
         cert_content.extend_from_slice(&compressed);
 
-        // NOTE: the cardid and container GUID are FIXED to the reference card's
-        // values. The cardid identifies the card type (and thus how Windows/msclmd
-        // treats it), so an arbitrary value could make the card look like a
-        // different/unknown type. The ATR + AID + cardid together reproduce the
-        // reference GIDS card; only the crypto material (7F49, DF24, sign) differs.
-
-        // Try the private key WITHOUT a password (unencrypted). If that fails the
-        // key is encrypted and its password will act as the card PIN.
-        if let Ok(key) = RsaPrivateKey::from_pkcs8_pem(&key_pem) {
-            let pkcs1 = key
-                .to_pkcs1_der()
-                .map_err(|e| format!("serialize key: {e}"))?;
-            let (n, e, d) = parse_rsa_pkcs1_components(pkcs1.as_bytes())
-                .ok_or_else(|| "parse key components".to_string())?;
-            let key_size = (n.bits() as usize).div_ceil(8);
-            return Ok(GidsEngine {
-                cert_content,
-                pin_mode: PinMode::None,
-                key_pem: None,
-                n,
-                e,
-                d: Some(d),
-                key_size,
-                pin_verified: true,
-                pin_retries: DEFAULT_PIN_RETRIES,
-                chaining: None,
-            });
+        // NOTE: the cardid and the first container GUID are FIXED to the reference
+        // card's values. The cardid identifies the card type (and thus how
+        // Windows/msclmd treats it), so an arbitrary value could make the card look
+        // like a different/unknown type. The ATR + AID + cardid together reproduce
+        // the reference GIDS card; only the crypto material (7F49, DF24, sign)
+        // differs. Further containers get a GUID derived from their certificate,
+        // stable across sessions.
+        let guid = if index == 0 {
+            REFERENCE_GUID.to_string()
+        } else {
+            derived_guid(&cert_der)
+        };
+
+        let (key, key_pem, signer) = match key {
+            KeySource::Pem(key_pem) => {
+                let (key, key_pem) = parse_private_key(&cert_der, key_pem)?;
+                (key, key_pem, None)
+            }
+            // The public key comes from the certificate, as with encrypted keys
+            KeySource::Signer(signer) => (extract_public_from_cert(&cert_der)?, None, Some(signer)),
+        };
+        let key_size = match &key {
+            CardKey::Rsa { n, .. } => (n.bits() as usize).div_ceil(8),
+            CardKey::Ec { curve, .. } => curve.bits() as usize / 8,
+        };
+        let fingerprint = sha2::Sha256::digest(&cert_der)
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        Ok(Container {
+            guid,
+            fingerprint,
+            cert_content,
+            key_pem,
+            signer,
+            key,
+            key_size,
+        })
+    }
+
+    fn is_ec(&self) -> bool {
+        matches!(self.key, CardKey::Ec { .. })
+    }
+
+    /// Whether the card PIN unlocks the key: an encrypted key, or a signer that
+    /// logs in with it.
+    fn uses_pin(&self) -> bool {
+        self.key_pem.is_some() || self.signer.as_ref().is_some_and(|s| s.uses_pin())
+    }
+
+    /// GIDS key type identifier of the key, `None` for unusual RSA sizes.
+    fn key_identifier(&self) -> Option<u8> {
+        match &self.key {
+            CardKey::Rsa { .. } => match self.key_size {
+                128 => Some(GIDS_RSA_1024),
+                256 => Some(GIDS_RSA_2048),
+                384 => Some(GIDS_RSA_3072),
+                512 => Some(GIDS_RSA_4096),
+                _ => None,
+            },
+            CardKey::Ec { curve, .. } => Some(curve.gids_identifier()),
         }
+    }
 
-        // Encrypted key: the public part must come from the certificate (it is
-        // served without PIN in the 7F49 during discovery).
-        let (n, e) = extract_rsa_public_from_cert(&cert_der)?;
-        let key_size = (n.bits() as usize).div_ceil(8);
-        log::info!(
-            "GIDS: private key is encrypted; PIN = key password (verified by decrypting the key)"
+    /// Name of the certificate file in `mscp`: `kxcXX` for key exchange (RSA)
+    /// keys, `kscXX` for signature-only (ECDSA) keys.
+    fn cert_file_name(&self, index: usize) -> String {
+        let kind = if self.is_ec() { "ksc" } else { "kxc" };
+        format!("{kind}{index:02x}")
+    }
+
+    /// cmapfile record (86 bytes): the GUID in UTF-16 (40 chars with the
+    /// terminator), flags (valid, + default for the first one), reserved, and the
+    /// signature / key exchange key sizes in bits (little-endian). RSA keys are
+    /// key exchange keys (as on the reference card), EC ones can only sign.
+    fn cmap_record(&self, default: bool) -> Vec<u8> {
+        let mut record = Vec::with_capacity(CMAP_RECORD_LEN);
+        for unit in self.guid.encode_utf16().take(39) {
+            record.extend_from_slice(&unit.to_le_bytes());
+        }
+        record.resize(80, 0);
+        record.extend_from_slice(&[if default { 0x03 } else { 0x01 }, 0x00]);
+        let bits = (self.key_size * 8) as u16;
+        let (sig_bits, kx_bits) = if self.is_ec() { (bits, 0) } else { (0, bits) };
+        record.extend_from_slice(&sig_bits.to_le_bytes());
+        record.extend_from_slice(&kx_bits.to_le_bytes());
+        record
+    }
+
+    /// Decrypt the private key with the PIN. `Err(SW_VERIFY_FAILED)` when the PIN
+    /// is not the key password.
+    fn unlock(&mut self, pin: &str) -> Result<(), u16> {
+        if let Some(signer) = self.signer.as_mut() {
+            return match signer.login(pin) {
+                Ok(true) => Ok(()),
+                Ok(false) => Err(SW_VERIFY_FAILED),
+                Err(e) => {
+                    log::error!("GIDS: signer login: {}", e);
+                    Err(SW_F_INTERNAL_ERROR)
+                }
+            };
+        }
+        let pem = self.key_pem.as_ref().ok_or(SW_F_INTERNAL_ERROR)?;
+        match &mut self.key {
+            CardKey::Rsa { d, .. } => {
+                let key = RsaPrivateKey::from_pkcs8_encrypted_pem(pem, pin.as_bytes())
+                    .map_err(|_| SW_VERIFY_FAILED)?;
+                let pkcs1 = key.to_pkcs1_der().map_err(|_| SW_F_INTERNAL_ERROR)?;
+                let (_, _, private) =
+                    parse_rsa_pkcs1_components(pkcs1.as_bytes()).ok_or(SW_F_INTERNAL_ERROR)?;
+                *d = Some(private);
+            }
+            CardKey::Ec { curve, signer, .. } => {
+                let key = EcSigner::from_encrypted_pem(pem, pin.as_bytes(), *curve)
+                    .ok_or(SW_VERIFY_FAILED)?;
+                *signer = Some(key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sign with the private key, `None` while it is still encrypted (or the
+    /// signer is not logged in).
+    fn sign(&self, data: &[u8]) -> Option<Result<Vec<u8>, String>> {
+        if let Some(signer) = &self.signer {
+            let mechanism = if self.is_ec() {
+                Mechanism::Ecdsa
+            } else {
+                Mechanism::RsaPkcs1
+            };
+            return signer.ready().then(|| signer.sign(mechanism, data));
+        }
+        match &self.key {
+            CardKey::Rsa { n, d: Some(d), .. } => Some(self.rsa_pkcs1_sign(n, d, data)),
+            CardKey::Ec {
+                signer: Some(signer),
+                ..
+            } => Some(signer.sign(data)),
+            _ => None,
+        }
+    }
+
+    /// The `7F 49` public-key DO: big-endian modulus + exponent for RSA, the
+    /// uncompressed point (tag `86`) for EC.
+    fn pubkey_do(&self) -> Vec<u8> {
+        let (n, e) = match &self.key {
+            CardKey::Rsa { n, e, .. } => (n, e),
+            CardKey::Ec { point, .. } => {
+                // At most 97 bytes (P-384), so short BER lengths
+                let mut do_ = Vec::with_capacity(5 + point.len());
+                do_.extend_from_slice(&[0x7F, 0x49, (point.len() + 2) as u8]);
+                do_.extend_from_slice(&[0x86, point.len() as u8]);
+                do_.extend_from_slice(point);
+                return do_;
+            }
+        };
+        let modulus = self.modulus_be(n);
+        let exp = e.to_bytes_be();
+
+        let mut content = Vec::with_capacity(4 + modulus.len() + 2 + exp.len());
+        content.extend_from_slice(&[0x81, 0x82, 0x01, 0x00]); // modulus tag + 256-byte length
+        content.extend_from_slice(&modulus);
+        content.push(0x82); // exponent tag
+        content.push(exp.len() as u8);
+        content.extend_from_slice(&exp);
+
+        let mut do_ = Vec::with_capacity(5 + content.len());
+        do_.extend_from_slice(&[0x7F, 0x49, 0x82, 0x01, 0x09]); // 265-byte content
+        do_.extend_from_slice(&content);
+        do_
+    }
+
+    /// `ContainerInfo_XX` as msclmd caches it (see `NativeBackend::get_container_info`):
+    /// 12-byte CSP header + data length + CONTAINER_INFO (version, reserved,
+    /// signature and key exchange key lengths) + the public key blob. RSA keys are
+    /// a key exchange PUBLICKEYBLOB (little-endian modulus), EC keys a signature
+    /// BCRYPT_ECCKEY_BLOB (big-endian X and Y).
+    fn container_info(&self) -> Vec<u8> {
+        let (sig_key, kx_key) = match &self.key {
+            CardKey::Rsa { n, e, .. } => {
+                let mut blob = Vec::with_capacity(20 + self.key_size);
+                blob.extend_from_slice(&[0x06, 0x02, 0x00, 0x00]); // PUBLICKEYBLOB, v2
+                blob.extend_from_slice(&CALG_RSA_KEYX.to_le_bytes());
+                blob.extend_from_slice(b"RSA1");
+                blob.extend_from_slice(&((self.key_size * 8) as u32).to_le_bytes());
+                let mut exp = e.to_bytes_le();
+                exp.resize(4, 0);
+                blob.extend_from_slice(&exp[..4]);
+                blob.extend(self.modulus_be(n).iter().rev());
+                (Vec::new(), blob)
+            }
+            CardKey::Ec { curve, point, .. } => {
+                let mut blob = Vec::with_capacity(8 + point.len());
+                blob.extend_from_slice(&curve.ecdsa_public_magic().to_le_bytes());
+                blob.extend_from_slice(&(self.key_size as u32).to_le_bytes());
+                blob.extend_from_slice(&point[1..]); // X || Y, without the 04 prefix
+                (blob, Vec::new())
+            }
+        };
+        let mut data = Vec::with_capacity(16 + sig_key.len() + kx_key.len());
+        data.extend_from_slice(&[0u8; 8]); // dwVersion, dwReserved
+        data.extend_from_slice(&(sig_key.len() as u32).to_le_bytes());
+        data.extend_from_slice(&(kx_key.len() as u32).to_le_bytes());
+        data.extend_from_slice(&sig_key);
+        data.extend_from_slice(&kx_key);
+
+        let mut info = Vec::with_capacity(16 + data.len());
+        info.extend_from_slice(CONTAINER_INFO_HEADER);
+        info.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        info.extend_from_slice(&data);
+        info
+    }
+
+    // ---------------------------------------------------------------------
+    // RSA
+    // ---------------------------------------------------------------------
+    fn modulus_be(&self, n: &BigUint) -> Vec<u8> {
+        let mut modulus = n.to_bytes_be();
+        if modulus.len() < self.key_size {
+            let mut padded = vec![0u8; self.key_size - modulus.len()];
+            padded.extend_from_slice(&modulus);
+            modulus = padded;
+        }
+        modulus
+    }
+
+    fn rsa_raw(&self, n: &BigUint, d: &BigUint, value: &[u8]) -> Vec<u8> {
+        let v = BigUint::from_bytes_be(value);
+        let result = v.modpow(d, n);
+        let mut bytes = result.to_bytes_be();
+        if bytes.len() < self.key_size {
+            let mut padded = vec![0u8; self.key_size - bytes.len()];
+            padded.extend_from_slice(&bytes);
+            bytes = padded;
+        }
+        bytes
+    }
+
+    fn rsa_pkcs1_sign(&self, n: &BigUint, d: &BigUint, data: &[u8]) -> Result<Vec<u8>, String> {
+        if data.len() + 11 > self.key_size {
+            return Err("Data too large".to_string());
+        }
+        let mut em = vec![0u8; self.key_size];
+        em[0] = 0x00;
+        em[1] = 0x01;
+        let ps_len = self.key_size - data.len() - 3;
+        em[2..2 + ps_len].fill(0xFF);
+        em[2 + ps_len] = 0x00;
+        em[3 + ps_len..].copy_from_slice(data);
+        Ok(self.rsa_raw(n, d, &em))
+    }
+}
+
+pub struct GidsEngine {
+    containers: Vec<Container>,
+    pin_mode: PinMode,
+    /// Container whose key was selected by MSE SET, used by PSO.
+    selected: usize,
+    pin_verified: bool,
+    pin: PinCounter,
+    chaining: Option<Vec<u8>>,
+}
+
+impl std::fmt::Debug for GidsEngine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keys: Vec<String> = self
+            .containers
+            .iter()
+            .map(|c| match &c.key {
+                CardKey::Rsa { .. } => format!("RSA {}", c.key_size * 8),
+                CardKey::Ec { curve, .. } => format!("EC {:?}", curve),
+            })
+            .collect();
+        f.debug_struct("GidsEngine")
+            .field("containers", &keys)
+            .field("pin_mode", &self.pin_mode)
+            .field("pin_verified", &self.pin_verified)
+            .finish()
+    }
+}
+
+impl GidsEngine {
+    pub fn new(cert_der: Vec<u8>, key_pem: String) -> Result<Self, String> {
+        Self::with_containers(vec![(cert_der, key_pem.into())])
+    }
+
+    /// A card with one container per (certificate DER, private key) pair. The
+    /// first one is the default container. Encrypted keys (and signers that log in
+    /// with the PIN) should share their password, as it is the card PIN.
+    pub(crate) fn with_containers(pairs: Vec<(Vec<u8>, KeySource)>) -> Result<Self, String> {
+        if pairs.is_empty() {
+            return Err("no certificate for the card".to_string());
+        }
+        if pairs.len() > MAX_CONTAINERS {
+            return Err(format!(
+                "too many certificates for the card ({}, at most {})",
+                pairs.len(),
+                MAX_CONTAINERS
+            ));
+        }
+        let containers = pairs
+            .into_iter()
+            .enumerate()
+            .map(|(index, (cert_der, key))| {
+                Container::new(index, cert_der, key).map_err(|e| format!("container {index}: {e}"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let pin_mode = if containers.iter().any(Container::uses_pin) {
+            log::info!(
+                "GIDS: private key is encrypted; PIN = key password (verified by decrypting the key)"
+            );
+            PinMode::KeyPassword
+        } else {
+            PinMode::None
+        };
+        let pin = PinCounter::new(
+            PinPolicy::default(),
+            pin_fingerprints(&containers),
+            Box::new(MemoryStore::default()),
         );
         Ok(GidsEngine {
-            cert_content,
-            pin_mode: PinMode::KeyPassword,
-            key_pem: Some(key_pem),
-            n,
-            e,
-            d: None,
-            key_size,
-            pin_verified: false,
-            pin_retries: DEFAULT_PIN_RETRIES,
+            containers,
+            pin_mode,
+            selected: 0,
+            pin_verified: pin_mode == PinMode::None,
+            pin,
             chaining: None,
         })
     }
 
+    /// Apply a PIN policy, with the store of the PIN failures (loaded now).
+    pub(crate) fn set_pin_policy(&mut self, policy: PinPolicy, store: Box<dyn RetryStore>) {
+        self.pin = PinCounter::new(policy, pin_fingerprints(&self.containers), store);
+    }
+
+    /// Tries left when `apdu` is a VERIFY whose PIN is entered on the client (`local`
+    /// policy) and not verified yet. The caller asks for it without holding the card
+    /// and gives it to `verify_local`.
+    pub(crate) fn local_pin_wanted(&mut self, apdu: &[u8]) -> Option<u8> {
+        let header = parse_apdu_header(apdu)?;
+        let (data, _) = extract_apdu_data(apdu);
+        if (header.ins, header.p1, header.p2) != (0x20, 0x00, 0x80)
+            || data.is_empty()
+            || self.pin_mode == PinMode::None
+            || !self.pin.policy().local
+            || self.pin_verified
+        {
+            return None;
+        }
+        Some(self.pin.remaining()).filter(|&retries| retries > 0)
+    }
+
+    /// VERIFY with the PIN entered on the client, `None` if the user cancelled.
+    pub(crate) fn verify_local(&mut self, pin: Option<Zeroizing<String>>) -> Vec<u8> {
+        self.chaining = None;
+        // Another call may have got here first while the user typed
+        if self.pin_verified {
+            return make_status(SW_SUCCESS);
+        }
+        if self.pin.remaining() == 0 {
+            return make_status(SW_AUTH_METHOD_BLOCKED);
+        }
+        match pin {
+            Some(pin) => self.verify_pin(&pin),
+            None => {
+                log::info!("GIDS: PIN entry cancelled");
+                make_status(SW_SECURITY_STATUS_NOT_SATISFIED)
+            }
+        }
+    }
+
+    /// Number of key containers on the card.
+    pub fn container_count(&self) -> usize {
+        self.containers.len()
+    }
+
+    /// `ContainerInfo_XX` of a container, `None` if there is no such container.
+    pub fn container_info(&self, index: usize) -> Option<Vec<u8>> {
+        self.containers.get(index).map(Container::container_info)
+    }
+
+    /// Container of a key reference (`0x81` for the first one).
+    fn container_of(&self, key_reference: u8) -> Option<usize> {
+        key_reference
+            .checked_sub(FIRST_KEY_REFERENCE)
+            .map(|index| index as usize)
+            .filter(|&index| index < self.containers.len())
+    }
+
     /// Process a raw APDU and return the raw response (data + SW1SW2).
     pub fn process_apdu(&mut self, apdu: &[u8]) -> Vec<u8> {
         let Some(header) = parse_apdu_header(apdu) else {
@@ -249,7 +774,7 @@ impl GidsEngine {
             0xCB => self.get_data(header.p1, header.p2, data, le),
             0xC0 => self.get_response(le),
             0x20 => self.verify(header.p1, header.p2, data),
-            0x22 => self.mse_set(),
+            0x22 => self.mse_set(data),
             0x2A => self.pso_sign(header.p1, header.p2, data),
             _ => make_status(SW_COMMAND_NOT_ALLOWED),
         }
@@ -293,7 +818,10 @@ impl GidsEngine {
         };
 
         match (thi, tlo) {
-            (0xDF, 0x1F) => make_response(&CARDAPPS, SW_SUCCESS),
+            (0xDF, 0x1F) => {
+                let cardapps = self.cardapps_do();
+                self.handle_chaining(&cardapps, le)
+            }
             (0xDF, 0x20) => {
                 if p1 == 0xA0 && p2 == 0x00 {
                     make_response(CARD_PIN_INFO, SW_SUCCESS)
@@ -307,34 +835,34 @@ impl GidsEngine {
             }
             (0xDF, 0x22) => make_response(CARD_CONFIG, SW_SUCCESS),
             (0xDF, 0x23) => {
-                let mut resp = Vec::with_capacity(89);
-                resp.extend_from_slice(&[0xDF, 0x23, 0x56]);
-                for unit in REFERENCE_GUID.encode_utf16() {
-                    resp.extend_from_slice(&unit.to_le_bytes());
-                }
-                resp.extend_from_slice(&[0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x08]);
-                make_response(&resp, SW_SUCCESS)
+                let records: Vec<u8> = self
+                    .containers
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(index, c)| c.cmap_record(index == 0))
+                    .collect();
+                let cmapfile = wrap_do(0xDF, 0x23, &records);
+                self.handle_chaining(&cmapfile, le)
             }
-            (0xDF, 0x24) => {
-                // Wrap the (compressed) certificate content in the `DF 24` DO.
-                let content = &self.cert_content;
-                let mut do_ = Vec::with_capacity(5 + content.len());
-                do_.extend_from_slice(&[0xDF, 0x24]);
-                match content.len() {
-                    len if len < 0x80 => do_.push(len as u8),
-                    len if len < 0x100 => {
-                        do_.extend_from_slice(&[0x81, len as u8]);
-                    }
-                    len => {
-                        do_.extend_from_slice(&[0x82, (len >> 8) as u8, (len & 0xFF) as u8]);
-                    }
-                }
-                do_.extend_from_slice(content);
+            (0xDF, tlo) if (FIRST_CERT_DO..FIRST_CERT_DO + MAX_CONTAINERS as u8).contains(&tlo) => {
+                // Certificate of each container: DF 24, DF 25...
+                let Some(container) = self.containers.get((tlo - FIRST_CERT_DO) as usize) else {
+                    return make_status(SW_FILE_NOT_FOUND);
+                };
+                let do_ = wrap_do(0xDF, tlo, &container.cert_content);
                 self.handle_chaining(&do_, le)
             }
             (0x7F, 0x73) => make_status(SW_FILE_NOT_FOUND),
             (0x7F, 0x49) => {
-                let pk = self.pubkey_do();
+                // Key of the container in the key reference (`84 01 81` = first)
+                let index = match tlv_value(data, 0x84).and_then(|r| r.first().copied()) {
+                    Some(key_reference) => match self.container_of(key_reference) {
+                        Some(index) => index,
+                        None => return make_status(SW_FILE_NOT_FOUND),
+                    },
+                    None => 0,
+                };
+                let pk = self.containers[index].pubkey_do();
                 self.handle_chaining(&pk, le)
             }
             _ => make_status(SW_FILE_NOT_FOUND),
@@ -355,8 +883,14 @@ impl GidsEngine {
     // VERIFY (INS=0x20)
     //
     // The PIN is the private key's password when the key is encrypted: VERIFY
-    // succeeds only if the entered PIN actually decrypts the key. If the key has
-    // no password, any VERIFY succeeds (the card needs no PIN).
+    // succeeds only if the entered PIN actually decrypts the key (at least one
+    // of them with several containers). If no key has a password, any VERIFY
+    // succeeds (the card needs no PIN).
+    //
+    // Without data, VERIFY only reports the PIN status (9000 when verified, else
+    // the tries left). With a `local` policy the PIN sent by the server is
+    // ignored: the client asks for it (see `local_pin_wanted`). Failures are
+    // counted by `PinCounter`.
     // ---------------------------------------------------------------------
     fn verify(&mut self, p1: u8, p2: u8, data: &[u8]) -> Vec<u8> {
         if p1 != 0x00 {
@@ -364,44 +898,31 @@ impl GidsEngine {
         }
         match p2 {
             0x80 => {
-                if self.pin_retries == 0 {
+                if self.pin_mode == PinMode::None {
+                    self.pin_verified = true;
+                    return make_status(SW_SUCCESS);
+                }
+                let retries = self.pin.remaining();
+                if retries == 0 {
                     return make_status(SW_AUTH_METHOD_BLOCKED);
                 }
-                match self.pin_mode {
-                    PinMode::None => {
-                        self.pin_verified = true;
-                        make_status(SW_SUCCESS)
-                    }
-                    PinMode::KeyPassword => {
-                        let pem = self.key_pem.as_ref().unwrap();
-                        let pin = String::from_utf8_lossy(data);
-                        match RsaPrivateKey::from_pkcs8_encrypted_pem(pem, pin.as_bytes()) {
-                            Ok(key) => {
-                                let pkcs1 = match key.to_pkcs1_der() {
-                                    Ok(p) => p,
-                                    Err(_) => return make_status(SW_F_INTERNAL_ERROR),
-                                };
-                                match parse_rsa_pkcs1_components(pkcs1.as_bytes()) {
-                                    Some((_, _, d)) => {
-                                        self.d = Some(d);
-                                        self.pin_verified = true;
-                                        self.pin_retries = DEFAULT_PIN_RETRIES;
-                                        make_status(SW_SUCCESS)
-                                    }
-                                    None => {
-                                        self.pin_verified = false;
-                                        make_status(SW_F_INTERNAL_ERROR)
-                                    }
-                                }
-                            }
-                            Err(_) => {
-                                self.pin_verified = false;
-                                self.pin_retries -= 1;
-                                make_status(SW_VERIFY_FAILED | self.pin_retries as u16)
-                            }
-                        }
-                    }
+                if data.is_empty() {
+                    return make_status(if self.pin_verified {
+                        SW_SUCCESS
+                    } else {
+                        SW_VERIFY_FAILED | retries as u16
+                    });
+                }
+                if self.pin.policy().local {
+                    // Only the PIN entered on the client for this session counts
+                    return make_status(if self.pin_verified {
+                        SW_SUCCESS
+                    } else {
+                        SW_SECURITY_STATUS_NOT_SATISFIED
+                    });
                 }
+                let pin = Zeroizing::new(String::from_utf8_lossy(data).into_owned());
+                self.verify_pin(&pin)
             }
             // Second PIN slot (unused on the reference card; msclmd probes it).
             0x82 => make_status(SW_SUCCESS),
@@ -409,10 +930,67 @@ impl GidsEngine {
         }
     }
 
+    fn verify_pin(&mut self, pin: &str) -> Vec<u8> {
+        let mut unlocked = false;
+        let mut error = None;
+        // A PIN out of the policy is just a wrong one
+        if self.pin.policy().check(pin).is_ok() {
+            for container in self.containers.iter_mut() {
+                if !container.uses_pin() {
+                    continue;
+                }
+                match container.unlock(pin) {
+                    Ok(()) => unlocked = true,
+                    Err(SW_VERIFY_FAILED) => {}
+                    Err(sw) => error = Some(sw),
+                }
+            }
+        }
+        if unlocked {
+            self.pin_verified = true;
+            self.pin.succeeded();
+            return make_status(SW_SUCCESS);
+        }
+        self.pin_verified = false;
+        if let Some(sw) = error {
+            return make_status(sw);
+        }
+        let retries = self.pin.failed();
+        log::warn!("GIDS: wrong PIN, {} tries left", retries);
+        make_status(SW_VERIFY_FAILED | retries as u16)
+    }
+
     // ---------------------------------------------------------------------
     // MSE SET (INS=0x22) — select a key reference for subsequent PSO.
+    //
+    // The key reference (`84 01 xx`) picks the container, and the algorithm
+    // reference (`80 01 xx`) must be for the type of its key.
     // ---------------------------------------------------------------------
-    fn mse_set(&self) -> Vec<u8> {
+    fn mse_set(&mut self, data: &[u8]) -> Vec<u8> {
+        let selected = match tlv_value(data, 0x84).and_then(|r| r.first().copied()) {
+            Some(key_reference) => match self.container_of(key_reference) {
+                Some(index) => index,
+                None => {
+                    log::warn!("GIDS: MSE SET for unknown key {:#04x}", key_reference);
+                    return make_status(SW_FILE_NOT_FOUND);
+                }
+            },
+            None => self.selected,
+        };
+        // RSA keys take whatever algorithm minidrivers send, as they always did;
+        // an EC key cannot sign for any other algorithm than its curve
+        let container = &self.containers[selected];
+        if let Some(&alg) = tlv_value(data, 0x80).and_then(|v| v.first())
+            && matches!(container.key, CardKey::Ec { .. })
+            && container.key_identifier() != Some(alg & 0x0F)
+        {
+            log::warn!(
+                "GIDS: MSE SET algorithm {:#04x} does not match the card key",
+                alg
+            );
+            return make_status(SW_INCORRECT_PARAMS);
+        }
+        self.selected = selected;
         make_status(SW_SUCCESS)
     }
 
@@ -429,13 +1007,11 @@ impl GidsEngine {
         if !self.pin_verified {
             return make_status(SW_SECURITY_STATUS_NOT_SATISFIED);
         }
-        let d = match self.d.as_ref() {
-            Some(d) => d,
-            None => return make_status(SW_SECURITY_STATUS_NOT_SATISFIED),
-        };
-        match self.rsa_pkcs1_sign(d, data) {
-            Ok(sig) => make_response(&sig, SW_SUCCESS),
-            Err(_) => make_status(SW_COMMAND_NOT_ALLOWED),
+        match self.containers[self.selected].sign(data) {
+            Some(Ok(sig)) => make_response(&sig, SW_SUCCESS),
+            Some(Err(_)) => make_status(SW_COMMAND_NOT_ALLOWED),
+            // Its password is not the PIN that was verified
+            None => make_status(SW_SECURITY_STATUS_NOT_SATISFIED),
         }
     }
 
@@ -484,67 +1060,134 @@ impl GidsEngine {
     // Data builders
     // ---------------------------------------------------------------------
 
-    /// The `7F 49` public-key DO (big-endian modulus + exponent).
-    fn pubkey_do(&self) -> Vec<u8> {
-        let mut modulus = self.n.to_bytes_be();
-        if modulus.len() < self.key_size {
-            let mut padded = vec![0u8; self.key_size - modulus.len()];
-            padded.extend_from_slice(&modulus);
-            modulus = padded;
+    /// The card application table (`DF 1F`): the reference card files plus the
+    /// certificate file of each container (`kxc00` -> DF 24, `ksc01` -> DF 25...).
+    fn cardapps_do(&self) -> Vec<u8> {
+        let mut content = CARDAPPS_BASE.clone();
+        for (index, container) in self.containers.iter().enumerate() {
+            content.extend_from_slice(&mf_record(
+                "mscp",
+                &container.cert_file_name(index),
+                0xDF00 | (FIRST_CERT_DO as u16 + index as u16),
+                0xA010,
+            ));
         }
-        let exp = self.e.to_bytes_be();
+        wrap_do(0xDF, 0x1F, &content)
+    }
+}
 
-        let mut content = Vec::with_capacity(4 + modulus.len() + 2 + exp.len());
-        content.extend_from_slice(&[0x81, 0x82, 0x01, 0x00]); // modulus tag + 256-byte length
-        content.extend_from_slice(&modulus);
-        content.push(0x82); // exponent tag
-        content.push(exp.len() as u8);
-        content.extend_from_slice(&exp);
+/// Card application table record (28 bytes): directory (9), file name (11), data
+/// object (LE u32) and file identifier (LE u32).
+fn mf_record(directory: &str, file: &str, data_object: u16, file_id: u16) -> Vec<u8> {
+    let mut record = Vec::with_capacity(28);
+    record.extend_from_slice(directory.as_bytes());
+    record.resize(9, 0);
+    record.extend_from_slice(file.as_bytes());
+    record.resize(20, 0);
+    record.extend_from_slice(&(data_object as u32).to_le_bytes());
+    record.extend_from_slice(&(file_id as u32).to_le_bytes());
+    record
+}
 
-        let mut do_ = Vec::with_capacity(5 + content.len());
-        do_.extend_from_slice(&[0x7F, 0x49, 0x82, 0x01, 0x09]); // 265-byte content
-        do_.extend_from_slice(&content);
-        do_
+/// Wrap `content` in a 2-byte tag DO with a BER length.
+fn wrap_do(tag_hi: u8, tag_lo: u8, content: &[u8]) -> Vec<u8> {
+    let mut do_ = Vec::with_capacity(5 + content.len());
+    do_.extend_from_slice(&[tag_hi, tag_lo]);
+    match content.len() {
+        len if len < 0x80 => do_.push(len as u8),
+        len if len < 0x100 => {
+            do_.extend_from_slice(&[0x81, len as u8]);
+        }
+        len => {
+            do_.extend_from_slice(&[0x82, (len >> 8) as u8, (len & 0xFF) as u8]);
+        }
     }
+    do_.extend_from_slice(content);
+    do_
+}
 
-    // ---------------------------------------------------------------------
-    // RSA
-    // ---------------------------------------------------------------------
-    fn rsa_raw(&self, d: &BigUint, value: &[u8]) -> Vec<u8> {
-        let v = BigUint::from_bytes_be(value);
-        let result = v.modpow(d, &self.n);
-        let mut bytes = result.to_bytes_be();
-        if bytes.len() < self.key_size {
-            let mut padded = vec![0u8; self.key_size - bytes.len()];
-            padded.extend_from_slice(&bytes);
-            bytes = padded;
+/// Value of the first simple TLV with `tag` in an MSE / GET DATA field. Walks
+/// into the constructed templates (`70`, `A4`, `A5`, `B6`...).
+fn tlv_value(data: &[u8], tag: u8) -> Option<&[u8]> {
+    let mut pos = 0;
+    while pos + 2 <= data.len() {
+        let (t, len) = (data[pos], data[pos + 1] as usize);
+        if t == tag {
+            return data.get(pos + 2..pos + 2 + len);
         }
-        bytes
+        // Constructed: look inside; primitive: skip the value
+        pos += if t & 0x20 != 0 { 2 } else { 2 + len };
     }
+    None
+}
 
-    fn rsa_pkcs1_sign(&self, d: &BigUint, data: &[u8]) -> Result<Vec<u8>, String> {
-        if data.len() + 11 > self.key_size {
-            return Err("Data too large".to_string());
+/// Fingerprints of the certificates whose keys use the card PIN.
+fn pin_fingerprints(containers: &[Container]) -> Vec<String> {
+    containers
+        .iter()
+        .filter(|c| c.uses_pin())
+        .map(|c| c.fingerprint.clone())
+        .collect()
+}
+
+/// GUID of an extra container, from its certificate (stable across sessions).
+fn derived_guid(cert_der: &[u8]) -> String {
+    let hash = sha2::Sha256::digest(cert_der);
+    let hex: String = hash[..16].iter().map(|b| format!("{b:02x}")).collect();
+    format!(
+        "tq-{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Parse a private key PEM. Unencrypted keys are returned whole; for an encrypted
+/// one the public half comes from the certificate and the PEM is returned to be
+/// decrypted by VERIFY.
+fn parse_private_key(
+    cert_der: &[u8],
+    key_pem: String,
+) -> Result<(CardKey, Option<String>), String> {
+    // Try the private key WITHOUT a password (unencrypted). If that fails the
+    // key is encrypted and its password will act as the card PIN.
+    let (key, key_pem) = if let Ok(key) = RsaPrivateKey::from_pkcs8_pem(&key_pem) {
+        let pkcs1 = key
+            .to_pkcs1_der()
+            .map_err(|e| format!("serialize key: {e}"))?;
+        let (n, e, d) = parse_rsa_pkcs1_components(pkcs1.as_bytes())
+            .ok_or_else(|| "parse key components".to_string())?;
+        (CardKey::Rsa { n, e, d: Some(d) }, None)
+    } else if let Some(signer) = EcSigner::from_pem(&key_pem) {
+        let key = CardKey::Ec {
+            curve: signer.curve(),
+            point: signer.public_point(),
+            signer: Some(signer),
+        };
+        (key, None)
+    } else {
+        let encrypted = pem::parse(&key_pem).is_ok_and(|p| p.tag() == "ENCRYPTED PRIVATE KEY");
+        if !encrypted {
+            return Err("unsupported private key (expected RSA or EC P-256/P-384)".to_string());
         }
-        let mut em = vec![0u8; self.key_size];
-        em[0] = 0x00;
-        em[1] = 0x01;
-        let ps_len = self.key_size - data.len() - 3;
-        em[2..2 + ps_len].fill(0xFF);
-        em[2 + ps_len] = 0x00;
-        em[3 + ps_len..].copy_from_slice(data);
-        Ok(self.rsa_raw(d, &em))
-    }
+        // Encrypted key: the public part must come from the certificate (it is
+        // served without PIN in the 7F49 during discovery).
+        (extract_public_from_cert(cert_der)?, Some(key_pem))
+    };
+    Ok((key, key_pem))
 }
 
-/// Extract the RSA public key (n, e) from an X.509 certificate DER. Used when the
-/// private key is encrypted: the public part must be served (7F49) before any PIN.
-fn extract_rsa_public_from_cert(cert_der: &[u8]) -> Result<(BigUint, BigUint), String> {
+/// Extract the public key (RSA n, e or the EC point) from an X.509 certificate DER.
+/// Used when the private key is encrypted: the public part must be served (7F49)
+/// before any PIN.
+fn extract_public_from_cert(cert_der: &[u8]) -> Result<CardKey, String> {
     const RSA_OID: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x01];
 
     // Walk the DER: certificate SEQUENCE -> tbsCertificate SEQUENCE, then scan its
     // top-level children (any TLV) for the subjectPublicKeyInfo SEQUENCE (the one
-    // containing the rsaEncryption OID).
+    // containing the rsaEncryption or id-ecPublicKey OID).
     let cert_seq = der_sequence(cert_der, 0).ok_or("cert: not a SEQUENCE")?;
     let tbs = der_sequence(cert_der, cert_seq.0).ok_or("cert: no tbsCertificate")?;
 
@@ -557,17 +1200,52 @@ fn extract_rsa_public_from_cert(cert_der: &[u8]) -> Result<(BigUint, BigUint), S
         if content_end > tbs.1 {
             break;
         }
+        let content = &cert_der[content_start..content_end];
+        if tag == 0x30 && content.windows(RSA_OID.len()).any(|w| w == RSA_OID) {
+            return parse_spki_rsa_public(content)
+                .map(|(n, e)| CardKey::Rsa { n, e, d: None })
+                .ok_or_else(|| "cert: no RSA key in SPKI".to_string());
+        }
         if tag == 0x30
-            && cert_der[content_start..content_end]
-                .windows(RSA_OID.len())
-                .any(|w| w == RSA_OID)
+            && content
+                .windows(EC_PUBLIC_KEY_OID.len())
+                .any(|w| w == EC_PUBLIC_KEY_OID)
         {
-            return parse_spki_rsa_public(&cert_der[content_start..content_end])
-                .ok_or_else(|| "cert: no RSA key in SPKI".to_string());
+            return parse_spki_ec_public(content)
+                .map(|(curve, point)| CardKey::Ec {
+                    curve,
+                    point,
+                    signer: None,
+                })
+                .ok_or_else(|| "cert: no P-256/P-384 key in SPKI".to_string());
         }
         pos = content_end;
     }
-    Err("cert: no RSA public key found".to_string())
+    Err("cert: no RSA or EC public key found".to_string())
+}
+
+/// Parse the subjectPublicKeyInfo content of an EC key: algorithm SEQUENCE (with
+/// the curve OID) + BIT STRING containing the uncompressed point.
+fn parse_spki_ec_public(spki: &[u8]) -> Option<(EcCurve, Vec<u8>)> {
+    let (alg_start, alg_end) = der_sequence(spki, 0)?;
+    let alg = &spki[alg_start..alg_end];
+    let has = |oid: &[u8]| alg.windows(oid.len()).any(|w| w == oid);
+    let curve = if has(P256_OID) {
+        EcCurve::P256
+    } else if has(P384_OID) {
+        EcCurve::P384
+    } else {
+        return None;
+    };
+    let bit_string = &spki[alg_end..];
+    if bit_string.first() != Some(&0x03) {
+        return None;
+    }
+    let (len, len_size) = read_ber_len(&bit_string[1..])?;
+    let content = bit_string.get(1 + len_size..1 + len_size + len)?;
+    let point = content.get(1..)?; // skip the unused-bits byte
+    let field_len = curve.bits() as usize / 8;
+    (point.len() == 1 + 2 * field_len && point[0] == 0x04).then(|| (curve, point.to_vec()))
 }
 
 /// Parse the subjectPublicKeyInfo content: algorithm SEQUENCE + BIT STRING
@@ -642,12 +1320,19 @@ fn read_ber_len(data: &[u8]) -> Option<(usize, usize)> {
 
 /// Find a known GIDS 2-byte tag inside a GET DATA APDU data field.
 fn find_gids_tag(data: &[u8]) -> Option<(u8, u8)> {
-    const TAGS: [(u8, u8); 8] = [
+    const TAGS: [(u8, u8); 15] = [
         (0xDF, 0x1F),
         (0xDF, 0x20),
         (0xDF, 0x22),
         (0xDF, 0x23),
         (0xDF, 0x24),
+        (0xDF, 0x25),
+        (0xDF, 0x26),
+        (0xDF, 0x27),
+        (0xDF, 0x28),
+        (0xDF, 0x29),
+        (0xDF, 0x2A),
+        (0xDF, 0x2B),
         (0x7F, 0x73),
         (0x7F, 0x49),
         (0x2F, 0x01),
@@ -9,15 +9,68 @@
 #[allow(clippy::module_inception)]
 mod tests {
     use num_bigint::BigUint;
+    use p256::ecdsa::signature::hazmat::PrehashVerifier;
+    use p256::elliptic_curve::sec1::ToEncodedPoint;
+    use p256::pkcs8::{DecodePublicKey, EncodePublicKey};
     use rsa::pkcs1::EncodeRsaPrivateKey;
     use rsa::pkcs8::{EncodePrivateKey, LineEnding};
+    use sha2::Digest;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex, OnceLock, Weak};
 
+    use crate::smartcard::emulated::EmulatedBackend;
     use crate::smartcard::emulated::gids_engine::{
-        GIDS_AID, GidsEngine, REFERENCE_CARDID, REFERENCE_GUID,
+        GIDS_AID, GidsEngine, KeySource, MAX_CONTAINERS, REFERENCE_CARDID, REFERENCE_GUID,
     };
     use crate::smartcard::emulated::helpers::{
         extract_apdu_data, parse_apdu_header, parse_rsa_pkcs1_components,
     };
+    use crate::smartcard::emulated::pin::{MemoryStore, PinPolicy, PinPrompt};
+    use crate::smartcard::emulated::signer::{Mechanism, Signer};
+
+    /// External signer holding the key in memory, optionally behind a PIN.
+    #[derive(Debug)]
+    enum TestKey {
+        Rsa(Box<rsa::RsaPrivateKey>),
+        P256(p256::ecdsa::SigningKey),
+    }
+
+    #[derive(Debug)]
+    struct TestSigner {
+        key: TestKey,
+        pin: Option<&'static str>,
+        logged_in: bool,
+    }
+
+    impl Signer for TestSigner {
+        fn uses_pin(&self) -> bool {
+            self.pin.is_some()
+        }
+
+        fn ready(&self) -> bool {
+            self.pin.is_none() || self.logged_in
+        }
+
+        fn login(&mut self, pin: &str) -> Result<bool, String> {
+            self.logged_in = self.pin == Some(pin);
+            Ok(self.logged_in)
+        }
+
+        fn sign(&self, mechanism: Mechanism, data: &[u8]) -> Result<Vec<u8>, String> {
+            use p256::ecdsa::signature::hazmat::PrehashSigner;
+            match (&self.key, mechanism) {
+                (TestKey::Rsa(key), Mechanism::RsaPkcs1) => key
+                    .sign(rsa::Pkcs1v15Sign::new_unprefixed(), data)
+                    .map_err(|e| e.to_string()),
+                (TestKey::P256(key), Mechanism::Ecdsa) => {
+                    let sig: p256::ecdsa::Signature =
+                        key.sign_prehash(data).map_err(|e| e.to_string())?;
+                    Ok(sig.to_bytes().to_vec())
+                }
+                _ => Err("wrong mechanism".to_string()),
+            }
+        }
+    }
 
     fn make_engine() -> (GidsEngine, BigUint, BigUint, BigUint) {
         let mut rng = rsa::rand_core::OsRng;
@@ -43,6 +96,12 @@ mod tests {
     /// is built as a minimal but valid X.509 DER containing the RSA public key, since
     /// the encrypted path extracts the public part from the certificate.
     fn make_encrypted_engine(password: &str) -> GidsEngine {
+        let (cert, key_pem) = make_encrypted_card(password);
+        GidsEngine::new(cert, key_pem).unwrap()
+    }
+
+    /// Certificate DER and encrypted key PEM of a card.
+    fn make_encrypted_card(password: &str) -> (Vec<u8>, String) {
         let mut rng = rsa::rand_core::OsRng;
         let key = rsa::RsaPrivateKey::new(&mut rng, 2048).unwrap();
         let pkcs1 = key.to_pkcs1_der().unwrap();
@@ -51,7 +110,7 @@ mod tests {
             .to_pkcs8_encrypted_pem(&mut rng, password, LineEnding::LF)
             .unwrap()
             .to_string();
-        GidsEngine::new(build_min_cert_der(&n, &e), key_pem).unwrap()
+        (build_min_cert_der(&n, &e), key_pem)
     }
 
     fn der_len(n: usize) -> Vec<u8> {
@@ -105,6 +164,81 @@ mod tests {
         der_seq(&tbs)
     }
 
+    /// Minimal X.509 certificate DER with an EC subjectPublicKeyInfo (already DER).
+    fn build_min_ec_cert_der(spki: &[u8]) -> Vec<u8> {
+        let version = vec![0xA0, 0x03, 0x02, 0x01, 0x02];
+        let serial = der_int(&BigUint::from(0x5566_7788u64));
+        let tbs = der_seq(&[version, serial, spki.to_vec()].concat());
+        der_seq(&tbs)
+    }
+
+    /// The subjectPublicKeyInfo of a certificate built by `build_min_ec_cert_der`
+    /// (the last child of the tbsCertificate).
+    fn cert_spki(cert: &[u8]) -> &[u8] {
+        fn tlv_len(data: &[u8]) -> (usize, usize) {
+            match data[1] {
+                l if l < 0x80 => (2, l as usize),
+                0x81 => (3, data[2] as usize),
+                _ => (4, ((data[2] as usize) << 8) | data[3] as usize),
+            }
+        }
+        let (hdr, _) = tlv_len(cert);
+        let tbs = &cert[hdr..];
+        let (tbs_hdr, tbs_len) = tlv_len(tbs);
+        let mut child = &tbs[tbs_hdr..tbs_hdr + tbs_len];
+        loop {
+            let (h, l) = tlv_len(child);
+            if h + l == child.len() {
+                return child;
+            }
+            child = &child[h + l..];
+        }
+    }
+
+    fn p256_engine() -> (GidsEngine, Vec<u8>) {
+        let key = p256::SecretKey::random(&mut rsa::rand_core::OsRng);
+        let spki = key.public_key().to_public_key_der().unwrap();
+        let cert = build_min_ec_cert_der(spki.as_bytes());
+        let key_pem = key.to_pkcs8_pem(LineEnding::LF).unwrap().to_string();
+        (GidsEngine::new(cert.clone(), key_pem).unwrap(), cert)
+    }
+
+    fn p384_engine() -> (GidsEngine, Vec<u8>) {
+        let key = p384::SecretKey::random(&mut rsa::rand_core::OsRng);
+        let spki = key.public_key().to_public_key_der().unwrap();
+        let cert = build_min_ec_cert_der(spki.as_bytes());
+        // SEC1 ("EC PRIVATE KEY") this time
+        let key_pem = key.to_sec1_pem(LineEnding::LF).unwrap().to_string();
+        (GidsEngine::new(cert.clone(), key_pem).unwrap(), cert)
+    }
+
+    /// Card with an RSA container (key exchange, default) and a P-256 one.
+    /// Returns the engine, the RSA public key and the EC certificate.
+    fn two_container_engine() -> (GidsEngine, BigUint, BigUint, Vec<u8>) {
+        let rsa_key = rsa::RsaPrivateKey::new(&mut rsa::rand_core::OsRng, 2048).unwrap();
+        let pkcs1 = rsa_key.to_pkcs1_der().unwrap();
+        let (n, e, _) = parse_rsa_pkcs1_components(pkcs1.as_bytes()).unwrap();
+        let rsa_pem = rsa_key.to_pkcs8_pem(LineEnding::LF).unwrap().to_string();
+
+        let ec_key = p256::SecretKey::random(&mut rsa::rand_core::OsRng);
+        let spki = ec_key.public_key().to_public_key_der().unwrap();
+        let ec_cert = build_min_ec_cert_der(spki.as_bytes());
+        let ec_pem = ec_key.to_pkcs8_pem(LineEnding::LF).unwrap().to_string();
+
+        let engine = GidsEngine::with_containers(vec![
+            (build_min_cert_der(&n, &e), rsa_pem.into()),
+            (ec_cert.clone(), ec_pem.into()),
+        ])
+        .unwrap();
+        (engine, n, e, ec_cert)
+    }
+
+    fn pso_sign_hash_apdu(hash: &[u8]) -> Vec<u8> {
+        let mut apdu = vec![0x00, 0x2A, 0x9E, 0x9A, hash.len() as u8];
+        apdu.extend_from_slice(hash);
+        apdu
+    }
+
     /// Read a full file through the engine (GET DATA + GET RESPONSE chaining).
     fn read_file(engine: &mut GidsEngine, p1: u8, p2: u8, tag: &[u8; 2]) -> Vec<u8> {
         let mut out = Vec::new();
@@ -375,17 +509,19 @@ mod tests {
         let key = rsa::RsaPrivateKey::new(&mut rng, 2048).unwrap();
         let key_pem = key.to_pkcs8_pem(LineEnding::LF).unwrap().to_string();
         let cert_pem = pem::Pem::new("CERTIFICATE", vec![0x30, 0x82, 0x01, 0x00]).to_string();
-        let backend = crate::smartcard::emulated::EmulatedBackend::from_spec(&format!(
-            "pem:{},{}",
-            cert_pem, key_pem
-        ));
+        let backend = crate::smartcard::emulated::EmulatedBackend::from_spec(
+            &format!("pem:{},{}", cert_pem, key_pem),
+            &crate::smartcard::PinPolicy::default(),
+        );
         assert!(backend.is_some());
     }
 
     #[test]
     fn from_spec_unsupported_prefix_is_none() {
-        let backend =
-            crate::smartcard::emulated::EmulatedBackend::from_spec("userdefined:whatever");
+        let backend = crate::smartcard::emulated::EmulatedBackend::from_spec(
+            "userdefined:whatever",
+            &crate::smartcard::PinPolicy::default(),
+        );
         assert!(backend.is_none());
     }
 
@@ -406,4 +542,544 @@ mod tests {
         assert!(data.windows(2).any(|w| w == [0x7F, 0x49]));
         assert_eq!(le, Some(256));
     }
+
+    #[test]
+    fn ec_p256_signature_verifies_against_certificate() {
+        let (mut engine, cert) = p256_engine();
+        let hash = sha2::Sha256::digest(b"smartcard logon");
+        let resp = engine.process_apdu(&pso_sign_hash_apdu(&hash));
+        assert_eq!(status(&resp), 0x9000);
+        // Raw r || s
+        assert_eq!(data(&resp).len(), 64);
+
+        let public = p256::PublicKey::from_public_key_der(cert_spki(&cert)).unwrap();
+        let verifier = p256::ecdsa::VerifyingKey::from(public);
+        let sig = p256::ecdsa::Signature::from_slice(data(&resp)).unwrap();
+        assert!(verifier.verify_prehash(&hash, &sig).is_ok());
+        let other = sha2::Sha256::digest(b"something else");
+        assert!(verifier.verify_prehash(&other, &sig).is_err());
+    }
+
+    #[test]
+    fn ec_p384_signature_verifies_against_certificate() {
+        let (mut engine, cert) = p384_engine();
+        let hash = sha2::Sha384::digest(b"smartcard logon");
+        let resp = engine.process_apdu(&pso_sign_hash_apdu(&hash));
+        assert_eq!(status(&resp), 0x9000);
+        assert_eq!(data(&resp).len(), 96);
+
+        let public = p384::PublicKey::from_public_key_der(cert_spki(&cert)).unwrap();
+        let verifier = p384::ecdsa::VerifyingKey::from(public);
+        let sig = p384::ecdsa::Signature::from_slice(data(&resp)).unwrap();
+        assert!(verifier.verify_prehash(&hash, &sig).is_ok());
+    }
+
+    #[test]
+    fn ec_7f49_is_the_certificate_point() {
+        let (mut engine, cert) = p256_engine();
+        let resp = engine.process_apdu(&get_data_7f49());
+        assert_eq!(status(&resp), 0x9000);
+        let d = data(&resp);
+        assert_eq!(&d[..5], &[0x7F, 0x49, 0x43, 0x86, 0x41]);
+        let public = p256::PublicKey::from_public_key_der(cert_spki(&cert)).unwrap();
+        let point = p256::EncodedPoint::from(public);
+        assert_eq!(&d[5..], point.as_bytes());
+
+        let (mut engine, _) = p384_engine();
+        let resp = engine.process_apdu(&get_data_7f49());
+        assert_eq!(&data(&resp)[..5], &[0x7F, 0x49, 0x63, 0x86, 0x61]);
+    }
+
+    #[test]
+    fn ec_container_is_a_signature_container() {
+        let (mut engine, _) = p384_engine();
+        // cmapfile: key size in the signature slot, no key exchange key
+        let resp = engine.process_apdu(&get_data_apdu(0xA0, 0x10, &[0xDF, 0x23]));
+        assert_eq!(status(&resp), 0x9000);
+        let d = data(&resp);
+        assert_eq!(&d[d.len() - 6..], &[0x03, 0x00, 0x80, 0x01, 0x00, 0x00]);
+        // The certificate file is ksc00
+        let resp = engine.process_apdu(&get_data_apdu(0xA0, 0x00, &[0xDF, 0x1F]));
+        let apps = data(&resp);
+        assert!(apps.windows(5).any(|w| w == b"ksc00"));
+        assert!(!apps.windows(5).any(|w| w == b"kxc00"));
+
+        // RSA stays as the reference card
+        let (mut engine, _, _, _) = make_engine();
+        let resp = engine.process_apdu(&get_data_apdu(0xA0, 0x10, &[0xDF, 0x23]));
+        let d = data(&resp);
+        assert_eq!(&d[d.len() - 6..], &[0x03, 0x00, 0x00, 0x00, 0x00, 0x08]);
+        let resp = engine.process_apdu(&get_data_apdu(0xA0, 0x00, &[0xDF, 0x1F]));
+        assert!(data(&resp).windows(5).any(|w| w == b"kxc00"));
+    }
+
+    #[test]
+    fn mse_set_checks_the_key_type() {
+        let (mut engine, _) = p256_engine();
+        let mse = |alg: u8| {
+            [
+                0x00, 0x22, 0x41, 0xB6, 0x06, 0x80, 0x01, alg, 0x84, 0x01, 0x81,
+            ]
+        };
+        assert_eq!(status(&engine.process_apdu(&mse(0x0C))), 0x9000);
+        // RSA 2048 algorithm on an EC card
+        assert_eq!(status(&engine.process_apdu(&mse(0x57))), 0x6A80);
+
+        // RSA cards stay lenient, whatever the algorithm
+        let (mut engine, _, _, _) = make_engine();
+        assert_eq!(status(&engine.process_apdu(&mse(0x0C))), 0x9000);
+        assert_eq!(status(&engine.process_apdu(&mse(0x56))), 0x9000);
+    }
+
+    #[test]
+    fn ec_encrypted_key_requires_password() {
+        let key = p256::SecretKey::random(&mut rsa::rand_core::OsRng);
+        let spki = key.public_key().to_public_key_der().unwrap();
+        let cert = build_min_ec_cert_der(spki.as_bytes());
+        let key_pem = key
+            .to_pkcs8_encrypted_pem(&mut rsa::rand_core::OsRng, "p4ss", LineEnding::LF)
+            .unwrap()
+            .to_string();
+        let mut engine = GidsEngine::new(cert.clone(), key_pem).unwrap();
+        // The public point is served before any PIN
+        let resp = engine.process_apdu(&get_data_7f49());
+        assert_eq!(&data(&resp)[..4], &[0x7F, 0x49, 0x43, 0x86]);
+
+        let hash = sha2::Sha256::digest(b"logon");
+        let resp = engine.process_apdu(&pso_sign_hash_apdu(&hash));
+        assert_eq!(status(&resp), 0x6982);
+        let resp = engine.process_apdu(&verify_apdu("wrong"));
+        assert_eq!(status(&resp), 0x63C2);
+        let resp = engine.process_apdu(&verify_apdu("p4ss"));
+        assert_eq!(status(&resp), 0x9000);
+
+        let resp = engine.process_apdu(&pso_sign_hash_apdu(&hash));
+        assert_eq!(status(&resp), 0x9000);
+        let public = p256::PublicKey::from_public_key_der(cert_spki(&cert)).unwrap();
+        let sig = p256::ecdsa::Signature::from_slice(data(&resp)).unwrap();
+        assert!(
+            p256::ecdsa::VerifyingKey::from(public)
+                .verify_prehash(&hash, &sig)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn from_spec_file_with_ec_key() {
+        let key = p256::SecretKey::random(&mut rsa::rand_core::OsRng);
+        let spki = key.public_key().to_public_key_der().unwrap();
+        let cert_pem =
+            pem::Pem::new("CERTIFICATE", build_min_ec_cert_der(spki.as_bytes())).to_string();
+        let key_pem = key.to_sec1_pem(LineEnding::LF).unwrap().to_string();
+        let path = std::env::temp_dir().join(format!("gids-ec-{}.pem", std::process::id()));
+        std::fs::write(&path, format!("{}{}", cert_pem, key_pem.as_str())).unwrap();
+        let backend = crate::smartcard::emulated::EmulatedBackend::from_spec(
+            &format!("file:{}", path.display()),
+            &crate::smartcard::PinPolicy::default(),
+        );
+        let _ = std::fs::remove_file(&path);
+        assert!(backend.is_some());
+    }
+
+    #[test]
+    fn unsupported_key_is_an_error() {
+        let key_pem = pem::Pem::new("PRIVATE KEY", vec![0x30, 0x03, 0x02, 0x01, 0x00]).to_string();
+        assert!(GidsEngine::new(vec![0x30, 0x00], key_pem).is_err());
+    }
+
+    #[test]
+    fn single_rsa_container_cardapps_is_the_reference_table() {
+        let (mut engine, _, _, _) = make_engine();
+        let resp = engine.process_apdu(&get_data_apdu(0xA0, 0x00, &[0xDF, 0x1F]));
+        assert_eq!(status(&resp), 0x9000);
+        let reference = concat!(
+            "DF1F81A901",
+            "6D736370000000000000000000000000000000000000000000A00000000000000000000000",
+            "636172646964000000000020DF000012A00000000000000000000000",
+            "636172646170707300000021DF000010A00000000000000000000000",
+            "636172646366000000000022DF000010A000006D7363700000000000",
+            "636D617066696C6500000023DF000010A000006D7363700000000000",
+            "6B7863303000000000000024DF000010A00000",
+        );
+        let hex: String = data(&resp).iter().map(|b| format!("{b:02X}")).collect();
+        assert_eq!(hex, reference);
+    }
+
+    #[test]
+    fn two_containers_in_cmapfile_and_cardapps() {
+        let (mut engine, _, _, _) = two_container_engine();
+        assert_eq!(engine.container_count(), 2);
+
+        let cmap = read_file(&mut engine, 0xA0, 0x10, &[0xDF, 0x23]);
+        assert_eq!(&cmap[..4], &[0xDF, 0x23, 0x81, 172]);
+        let (first, second) = cmap[4..].split_at(86);
+        let reference: Vec<u8> = REFERENCE_GUID
+            .encode_utf16()
+            .flat_map(|u| u.to_le_bytes())
+            .collect();
+        assert_eq!(&first[..reference.len()], &reference[..]);
+        // Default container, 2048-bit key exchange key
+        assert_eq!(&first[80..], &[0x03, 0x00, 0x00, 0x00, 0x00, 0x08]);
+        // Its own GUID, valid but not default, 256-bit signature key
+        assert_eq!(&second[..6], &[b't', 0, b'q', 0, b'-', 0]);
+        assert_ne!(&second[..reference.len()], &reference[..]);
+        assert_eq!(&second[80..], &[0x01, 0x00, 0x00, 0x01, 0x00, 0x00]);
+
+        let apps = read_file(&mut engine, 0xA0, 0x00, &[0xDF, 0x1F]);
+        assert_eq!(&apps[..3], &[0xDF, 0x1F, 0x81]);
+        assert_eq!(apps[3] as usize, apps.len() - 4);
+        let kxc = apps.windows(5).position(|w| w == b"kxc00").unwrap();
+        assert_eq!(&apps[kxc + 11..kxc + 15], &[0x24, 0xDF, 0x00, 0x00]);
+        let ksc = apps.windows(5).position(|w| w == b"ksc01").unwrap();
+        assert_eq!(&apps[ksc + 11..ksc + 15], &[0x25, 0xDF, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn second_container_certificate_and_public_key() {
+        let (mut engine, _, _, ec_cert) = two_container_engine();
+        let content = read_file(&mut engine, 0xA0, 0x10, &[0xDF, 0x25]);
+        assert_eq!(&content[..2], &[0xDF, 0x25]);
+        let offset = if content[2] == 0x81 { 4 } else { 3 };
+        let cert = &content[offset..];
+        assert_eq!(&cert[..2], &[0x01, 0x00]);
+        assert_eq!(
+            u16::from_le_bytes([cert[2], cert[3]]) as usize,
+            ec_cert.len()
+        );
+        let mut decoded = Vec::new();
+        std::io::Read::read_to_end(
+            &mut flate2::read::ZlibDecoder::new(&cert[4..]),
+            &mut decoded,
+        )
+        .unwrap();
+        assert_eq!(decoded, ec_cert);
+
+        let resp = engine.process_apdu(&get_data_apdu(0xA0, 0x10, &[0xDF, 0x26]));
+        assert_eq!(status(&resp), 0x6A88);
+
+        // 7F49 of key reference 82 is the EC point of the second certificate
+        let mut apdu = get_data_7f49();
+        apdu[9] = 0x82;
+        let resp = engine.process_apdu(&apdu);
+        assert_eq!(status(&resp), 0x9000);
+        let public = p256::PublicKey::from_public_key_der(cert_spki(&ec_cert)).unwrap();
+        assert_eq!(&data(&resp)[5..], public.to_encoded_point(false).as_bytes());
+        apdu[9] = 0x83;
+        assert_eq!(status(&engine.process_apdu(&apdu)), 0x6A88);
+    }
+
+    #[test]
+    fn mse_set_selects_the_container() {
+        let (mut engine, n, e, ec_cert) = two_container_engine();
+        let mse = |alg: u8, key: u8| {
+            [
+                0x00, 0x22, 0x41, 0xB6, 0x06, 0x80, 0x01, alg, 0x84, 0x01, key,
+            ]
+        };
+        // The RSA algorithm does not fit the second (EC) key, nor does 83 exist
+        assert_eq!(status(&engine.process_apdu(&mse(0x57, 0x82))), 0x6A80);
+        assert_eq!(status(&engine.process_apdu(&mse(0x0C, 0x83))), 0x6A88);
+
+        assert_eq!(status(&engine.process_apdu(&mse(0x0C, 0x82))), 0x9000);
+        let hash = sha2::Sha256::digest(b"document");
+        let resp = engine.process_apdu(&pso_sign_hash_apdu(&hash));
+        assert_eq!(status(&resp), 0x9000);
+        let public = p256::PublicKey::from_public_key_der(cert_spki(&ec_cert)).unwrap();
+        let sig = p256::ecdsa::Signature::from_slice(data(&resp)).unwrap();
+        assert!(
+            p256::ecdsa::VerifyingKey::from(public)
+                .verify_prehash(&hash, &sig)
+                .is_ok()
+        );
+
+        // Back to the first container
+        assert_eq!(status(&engine.process_apdu(&mse(0x57, 0x81))), 0x9000);
+        let resp = engine.process_apdu(&pso_sign_apdu());
+        assert_eq!(status(&resp), 0x9000);
+        let em = raw_rsa_public(data(&resp), &e, &n);
+        assert_eq!(&em[..2], &[0x00, 0x01]);
+    }
+
+    #[test]
+    fn container_info_blobs() {
+        let (engine, n, _, ec_cert) = two_container_engine();
+
+        // RSA: key exchange PUBLICKEYBLOB, as msclmd caches it (308 bytes)
+        let info = engine.container_info(0).unwrap();
+        assert_eq!(info.len(), 308);
+        assert_eq!(
+            &info[..16],
+            &[
+                0x00, 0x00, 0x01, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x24, 0x01,
+                0x00, 0x00
+            ]
+        );
+        assert_eq!(
+            &info[16..32],
+            &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x14, 0x01, 0, 0]
+        );
+        assert_eq!(
+            &info[32..40],
+            &[0x06, 0x02, 0x00, 0x00, 0x00, 0xA4, 0x00, 0x00]
+        );
+        assert_eq!(&info[40..44], b"RSA1");
+        assert_eq!(
+            &info[44..52],
+            &[0x00, 0x08, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00]
+        );
+        let mut modulus = n.to_bytes_be();
+        modulus.reverse();
+        assert_eq!(&info[52..], &modulus[..]);
+
+        // EC: signature BCRYPT_ECCKEY_BLOB ("ECS1", 32-byte coordinates)
+        let info = engine.container_info(1).unwrap();
+        assert_eq!(info.len(), 16 + 16 + 72);
+        assert_eq!(&info[12..16], &[88, 0, 0, 0]);
+        assert_eq!(&info[24..32], &[72, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&info[32..40], &[b'E', b'C', b'S', b'1', 32, 0, 0, 0]);
+        let public = p256::PublicKey::from_public_key_der(cert_spki(&ec_cert)).unwrap();
+        assert_eq!(&info[40..], &public.to_encoded_point(false).as_bytes()[1..]);
+
+        assert!(engine.container_info(2).is_none());
+    }
+
+    #[test]
+    fn container_count_limits() {
+        assert!(GidsEngine::with_containers(Vec::new()).is_err());
+        let pairs = (0..=MAX_CONTAINERS)
+            .map(|_| (vec![0x30, 0x00], String::new().into()))
+            .collect();
+        assert!(GidsEngine::with_containers(pairs).is_err());
+    }
+
+    #[test]
+    fn from_spec_list_of_containers() {
+        let ec_pair = || {
+            let key = p256::SecretKey::random(&mut rsa::rand_core::OsRng);
+            let spki = key.public_key().to_public_key_der().unwrap();
+            let cert_pem =
+                pem::Pem::new("CERTIFICATE", build_min_ec_cert_der(spki.as_bytes())).to_string();
+            (
+                cert_pem,
+                key.to_pkcs8_pem(LineEnding::LF).unwrap().to_string(),
+            )
+        };
+        // A file with two pairs (certificates first) plus an inline one
+        let (cert1, key1) = ec_pair();
+        let (cert2, key2) = ec_pair();
+        let (cert3, key3) = ec_pair();
+        let path = std::env::temp_dir().join(format!("gids-list-{}.pem", std::process::id()));
+        std::fs::write(&path, format!("{cert1}{cert2}{key1}{key2}")).unwrap();
+        let backend = crate::smartcard::emulated::EmulatedBackend::from_spec(
+            &format!("file:{}; pem:{},{}", path.display(), cert3, key3),
+            &crate::smartcard::PinPolicy::default(),
+        );
+        // A certificate without its key
+        std::fs::write(&path, format!("{cert1}{cert2}{key1}")).unwrap();
+        let unpaired = crate::smartcard::emulated::EmulatedBackend::from_spec(
+            &format!("file:{}", path.display()),
+            &crate::smartcard::PinPolicy::default(),
+        );
+        let _ = std::fs::remove_file(&path);
+
+        let backend = backend.unwrap();
+        assert_eq!(backend.engine.lock().unwrap().container_count(), 3);
+        assert!(unpaired.is_none());
+    }
+
+    #[test]
+    fn external_signer_signs_for_the_container() {
+        let key = rsa::RsaPrivateKey::new(&mut rsa::rand_core::OsRng, 2048).unwrap();
+        let pkcs1 = key.to_pkcs1_der().unwrap();
+        let (n, e, _) = parse_rsa_pkcs1_components(pkcs1.as_bytes()).unwrap();
+        let signer = TestSigner {
+            key: TestKey::Rsa(Box::new(key)),
+            pin: None,
+            logged_in: false,
+        };
+        let mut engine = GidsEngine::with_containers(vec![(
+            build_min_cert_der(&n, &e),
+            KeySource::Signer(Box::new(signer)),
+        )])
+        .unwrap();
+
+        // Public key from the certificate, no PIN needed
+        let pk = read_file(&mut engine, 0x3F, 0xFF, &[0x7F, 0x49]);
+        let mut modulus = n.to_bytes_be();
+        if modulus[0] == 0 {
+            modulus.remove(0);
+        }
+        assert!(pk.windows(modulus.len()).any(|w| w == modulus));
+        let resp = engine.process_apdu(&pso_sign_apdu());
+        assert_eq!(status(&resp), 0x9000);
+        let em = raw_rsa_public(data(&resp), &e, &n);
+        let digest_info = &pso_sign_apdu()[5..];
+        assert_eq!(&em[..2], &[0x00, 0x01]);
+        assert_eq!(&em[em.len() - digest_info.len()..], digest_info);
+    }
+
+    #[test]
+    fn external_signer_logs_in_with_the_card_pin() {
+        let key = p256::SecretKey::random(&mut rsa::rand_core::OsRng);
+        let spki = key.public_key().to_public_key_der().unwrap();
+        let cert = build_min_ec_cert_der(spki.as_bytes());
+        let signer = TestSigner {
+            key: TestKey::P256(key.into()),
+            pin: Some("1234"),
+            logged_in: false,
+        };
+        let mut engine =
+            GidsEngine::with_containers(vec![(cert.clone(), KeySource::Signer(Box::new(signer)))])
+                .unwrap();
+
+        let hash = sha2::Sha256::digest(b"token logon");
+        let resp = engine.process_apdu(&pso_sign_hash_apdu(&hash));
+        assert_eq!(status(&resp), 0x6982);
+        assert_eq!(status(&engine.process_apdu(&verify_apdu("0000"))), 0x63C2);
+        assert_eq!(status(&engine.process_apdu(&verify_apdu("1234"))), 0x9000);
+
+        let resp = engine.process_apdu(&pso_sign_hash_apdu(&hash));
+        assert_eq!(status(&resp), 0x9000);
+        let public = p256::PublicKey::from_public_key_der(cert_spki(&cert)).unwrap();
+        let sig = p256::ecdsa::Signature::from_slice(data(&resp)).unwrap();
+        assert!(
+            p256::ecdsa::VerifyingKey::from(public)
+                .verify_prehash(&hash, &sig)
+                .is_ok()
+        );
+    }
+
+    /// Answers the PIN prompt with a fixed PIN (or cancels), counting the asks.
+    struct TestPrompt {
+        pin: Option<&'static str>,
+        asked: Arc<AtomicUsize>,
+    }
+
+    impl PinPrompt for TestPrompt {
+        fn ask(&self, _retries_left: u8) -> Option<zeroize::Zeroizing<String>> {
+            self.asked.fetch_add(1, Ordering::SeqCst);
+            self.pin.map(|pin| zeroize::Zeroizing::new(pin.to_string()))
+        }
+    }
+
+    /// VERIFY without data: only the PIN status.
+    fn verify_status_apdu() -> Vec<u8> {
+        vec![0x00, 0x20, 0x00, 0x80]
+    }
+
+    #[test]
+    fn verify_without_data_reports_the_pin_status() {
+        let mut engine = make_encrypted_engine("p4ss");
+        assert_eq!(status(&engine.process_apdu(&verify_status_apdu())), 0x63C3);
+        assert_eq!(status(&engine.process_apdu(&verify_apdu("p4ss"))), 0x9000);
+        assert_eq!(status(&engine.process_apdu(&verify_status_apdu())), 0x9000);
+    }
+
+    #[test]
+    fn pin_failures_persist_and_block_the_card() {
+        let store = MemoryStore::default();
+        let policy = PinPolicy {
+            max_retries: 2,
+            ..Default::default()
+        };
+        let (cert, key_pem) = make_encrypted_card("p4ss");
+        let mut engine = GidsEngine::new(cert.clone(), key_pem.clone()).unwrap();
+        engine.set_pin_policy(policy.clone(), Box::new(store.clone()));
+        assert_eq!(status(&engine.process_apdu(&verify_apdu("wrong"))), 0x63C1);
+
+        // Same card, next session: one try left
+        let mut engine = GidsEngine::new(cert.clone(), key_pem.clone()).unwrap();
+        engine.set_pin_policy(policy.clone(), Box::new(store.clone()));
+        assert_eq!(status(&engine.process_apdu(&verify_status_apdu())), 0x63C1);
+        assert_eq!(status(&engine.process_apdu(&verify_apdu("wrong"))), 0x63C0);
+        // Blocked, even for the right PIN
+        assert_eq!(status(&engine.process_apdu(&verify_apdu("p4ss"))), 0x6983);
+
+        let mut engine = GidsEngine::new(cert, key_pem).unwrap();
+        engine.set_pin_policy(policy, Box::new(store));
+        assert_eq!(status(&engine.process_apdu(&verify_apdu("p4ss"))), 0x6983);
+    }
+
+    #[test]
+    fn pin_out_of_policy_is_a_wrong_pin() {
+        let mut engine = make_encrypted_engine("p4ss");
+        let policy = PinPolicy {
+            digits_only: true,
+            ..Default::default()
+        };
+        engine.set_pin_policy(policy, Box::new(MemoryStore::default()));
+        assert_eq!(status(&engine.process_apdu(&verify_apdu("p4ss"))), 0x63C2);
+    }
+
+    /// Card under a `local` policy, whose PIN comes from `prompt`.
+    fn local_pin_backend(prompt: impl PinPrompt + 'static) -> EmulatedBackend {
+        let mut engine = make_encrypted_engine("p4ss");
+        let policy = PinPolicy {
+            local: true,
+            ..Default::default()
+        };
+        engine.set_pin_policy(policy, Box::new(MemoryStore::default()));
+        EmulatedBackend {
+            engine: Mutex::new(engine),
+            prompt: Some(Box::new(prompt)),
+        }
+    }
+
+    #[test]
+    fn local_pin_is_asked_on_the_client() {
+        let asked = Arc::new(AtomicUsize::new(0));
+        let backend = local_pin_backend(TestPrompt {
+            pin: Some("p4ss"),
+            asked: asked.clone(),
+        });
+        let exchange = |apdu: &[u8]| status(&backend.exchange(apdu).unwrap());
+        // Whatever the server sends, the PIN comes from the prompt, once
+        assert_eq!(exchange(&verify_apdu("0000")), 0x9000);
+        assert_eq!(exchange(&verify_apdu("0000")), 0x9000);
+        assert_eq!(asked.load(Ordering::SeqCst), 1);
+        assert_eq!(exchange(&pso_sign_apdu()), 0x9000);
+
+        // Cancelled: not verified, and not a failure
+        let backend = local_pin_backend(TestPrompt {
+            pin: None,
+            asked: asked.clone(),
+        });
+        let exchange = |apdu: &[u8]| status(&backend.exchange(apdu).unwrap());
+        assert_eq!(exchange(&verify_apdu("p4ss")), 0x6982);
+        assert_eq!(exchange(&verify_status_apdu()), 0x63C3);
+    }
+
+    /// Checks, while it is asked, that the card can be used by other calls.
+    struct CheckingPrompt(Arc<OnceLock<Weak<EmulatedBackend>>>);
+
+    impl PinPrompt for CheckingPrompt {
+        fn ask(&self, _retries_left: u8) -> Option<zeroize::Zeroizing<String>> {
+            let backend = self.0.get()?.upgrade()?;
+            assert!(backend.engine.try_lock().is_ok());
+            Some(zeroize::Zeroizing::new("p4ss".to_string()))
+        }
+    }
+
+    #[test]
+    fn local_pin_is_asked_with_the_card_unlocked() {
+        let slot = Arc::new(OnceLock::new());
+        let backend = Arc::new(local_pin_backend(CheckingPrompt(slot.clone())));
+        slot.set(Arc::downgrade(&backend)).unwrap();
+        assert_eq!(
+            status(&backend.exchange(&verify_apdu("0000")).unwrap()),
+            0x9000
+        );
+    }
+
+    #[test]
+    fn local_policy_ignores_the_server_pin() {
+        let mut engine = make_encrypted_engine("p4ss");
+        let policy = PinPolicy {
+            local: true,
+            ..Default::default()
+        };
+        engine.set_pin_policy(policy, Box::new(MemoryStore::default()));
+        assert_eq!(status(&engine.process_apdu(&verify_apdu("p4ss"))), 0x6982);
+        assert_eq!(status(&engine.process_apdu(&verify_status_apdu())), 0x63C3);
+    }
 }
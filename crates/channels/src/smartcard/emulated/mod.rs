@@ -6,15 +6,21 @@
 //! Emulated smartcard backend module.
 //!
 //! Emulates a GIDS card so the Windows GIDS minidriver (msclmd) can drive it over
-//! the RDP smartcard redirect exactly like with the physical reference card.
+//! the RDP smartcard redirect exactly like with the physical reference card. The
+//! card can hold several containers (certificate + key pairs), and each key can
+//! be RSA or EC (P-256 / P-384).
 //!
 //! Sub-modules:
 //! - `gids_engine`: the GIDS APDU engine (SELECT, GET DATA, VERIFY, MSE SET, PSO)
 //! - `helpers`: TLV, APDU, DER parsing helpers (shared)
+//! - `signer`: external signers (PKCS#11, HTTP) for keys that are not on the client
+//! - `ephemeral`: per-session key pair whose certificate the broker issues
+//! - `pin`: PIN policy, retry counters kept between sessions, local PIN entry
 //! - `consts`/`euds_engine`/`euds_types`: legacy eUDS custom protocol (kept for
 //!   reuse in another project; not used by this backend)
 
 pub mod consts;
+mod ephemeral;
 #[allow(dead_code)]
 mod euds_engine;
 #[allow(dead_code)]
@@ -23,6 +29,8 @@ mod gids_engine;
 #[cfg(test)]
 mod gids_tests;
 mod helpers;
+mod pin;
+mod signer;
 #[cfg(test)]
 mod tests;
 
@@ -34,11 +42,17 @@ use rsa::RsaPrivateKey;
 use pcsc::ffi::DWORD;
 use rdp::integrations::smartcard::*;
 
-use self::gids_engine::{GIDS_ATR, GIDS_READER_NAME, GidsEngine};
+pub use self::ephemeral::EphemeralKey;
+pub(crate) use self::gids_engine::{GIDS_AID, GidsEngine};
+use self::gids_engine::{GIDS_ATR, GIDS_READER_NAME, KeySource};
+use self::pin::{LauncherPrompt, PinPrompt};
+pub use self::pin::{PinPolicy, PinRequest, clear_pin_failures, next_pin_request};
 use super::SmartcardBackend;
 
 pub(crate) struct EmulatedBackend {
     engine: Mutex<GidsEngine>,
+    /// Asks for the PIN of a `local` policy, never while `engine` is locked.
+    prompt: Option<Box<dyn PinPrompt>>,
 }
 
 impl std::fmt::Debug for EmulatedBackend {
@@ -48,49 +62,107 @@ impl std::fmt::Debug for EmulatedBackend {
 }
 
 impl EmulatedBackend {
-    pub fn from_pem(cert_pem: &str, key_pem: &str) -> Result<Self, String> {
-        let cert_der = pem::parse(cert_pem)
-            .map_err(|e| format!("cert PEM: {}", e))?
-            .into_contents();
+    pub fn from_pem(cert_pem: &str, key_pem: &str, policy: &PinPolicy) -> Result<Self, String> {
+        Self::from_pem_list(&[(cert_pem.to_string(), key_pem.to_string())], policy)
+    }
+
+    /// A card with one container per (certificate PEM, private key PEM) pair, the
+    /// first one being the default container.
+    pub fn from_pem_list(pairs: &[(String, String)], policy: &PinPolicy) -> Result<Self, String> {
+        let containers = pairs
+            .iter()
+            .map(|(cert_pem, key_pem)| pem_container(cert_pem, key_pem))
+            .collect::<Result<Vec<_>, String>>()?;
+        Self::with_containers(containers, policy)
+    }
+
+    /// The card, under the PIN policy of the session.
+    fn with_containers(
+        containers: Vec<(Vec<u8>, KeySource)>,
+        policy: &PinPolicy,
+    ) -> Result<Self, String> {
+        let mut engine = GidsEngine::with_containers(containers)?;
+        let prompt = policy.local.then(|| {
+            Box::new(LauncherPrompt {
+                policy: policy.clone(),
+            }) as Box<dyn PinPrompt>
+        });
+        engine.set_pin_policy(policy.clone(), policy.retry_store());
         Ok(EmulatedBackend {
-            engine: Mutex::new(GidsEngine::new(cert_der, key_pem.to_string())?),
+            engine: Mutex::new(engine),
+            prompt,
         })
     }
 
+    /// The card of an engine already set up, without PIN prompt.
+    pub(crate) fn from_engine(engine: GidsEngine) -> Self {
+        EmulatedBackend {
+            engine: Mutex::new(engine),
+            prompt: None,
+        }
+    }
+
     #[allow(dead_code)]
     pub fn from_der(cert_der: &[u8], key_pkcs8_der: &[u8]) -> Result<Self, String> {
         use rsa::pkcs8::{DecodePrivateKey, EncodePrivateKey, LineEnding};
         // DER path: the key must be unencrypted (no PEM password to decrypt on VERIFY).
-        let private_key =
-            RsaPrivateKey::from_pkcs8_der(key_pkcs8_der).map_err(|e| format!("key DER: {}", e))?;
-        let key_pem = private_key
-            .to_pkcs8_pem(LineEnding::LF)
-            .map_err(|e| format!("key PEM: {}", e))?
-            .to_string();
-        Ok(EmulatedBackend {
-            engine: Mutex::new(GidsEngine::new(cert_der.to_vec(), key_pem)?),
-        })
+        let key_pem = match RsaPrivateKey::from_pkcs8_der(key_pkcs8_der) {
+            Ok(private_key) => private_key
+                .to_pkcs8_pem(LineEnding::LF)
+                .map_err(|e| format!("key PEM: {}", e))?
+                .to_string(),
+            // EC keys (or anything else) go as they are, the engine tells
+            Err(_) => pem::Pem::new("PRIVATE KEY", key_pkcs8_der.to_vec()).to_string(),
+        };
+        Ok(Self::from_engine(GidsEngine::new(
+            cert_der.to_vec(),
+            key_pem,
+        )?))
     }
 
-    /// Load the emulated card from an `emulated_certs` spec:
-    /// - `file:<path>`  → a local PEM file (may contain cert + key blocks)
+    /// Load the emulated card from an `emulated_certs` spec, a `;` separated list
+    /// of entries (one or more containers each):
+    /// - `file:<path>`  → a local PEM file (cert + key blocks; several certificates
+    ///   pair with the keys in the same order)
     /// - `pem:<cert_pem>,<key_pem>` → the certificate and key as PEM strings
-    /// - `userdefined:` → reserved (not implemented yet)
-    pub fn from_spec(spec: &str) -> Option<Self> {
-        let (cert_pem, key_pem) = if let Some(path) = spec.strip_prefix("file:") {
-            let content = std::fs::read_to_string(path).ok()?;
-            extract_cert_and_key(&content)?
-        } else if let Some(rest) = spec.strip_prefix("pem:") {
-            let (cert, key) = rest.split_once(',')?;
-            (cert.to_string(), key.to_string())
-        } else {
-            log::error!(
-                "emulated_certs: unsupported spec prefix (expected file: or pem:) — got: {}",
-                spec
-            );
-            return None;
-        };
-        match Self::from_pem(&cert_pem, &key_pem) {
+    /// - `userdefined:<kind>,<options>` → the key stays in an external signer
+    ///   (PKCS#11 module or HTTP signing service, see `signer`)
+    /// - `ephemeral` → the session card issued by the broker (see `ephemeral`)
+    pub fn from_spec(spec: &str, policy: &PinPolicy) -> Option<Self> {
+        let mut containers = Vec::new();
+        for entry in spec.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let loaded = if let Some(path) = entry.strip_prefix("file:") {
+                let content = std::fs::read_to_string(path).ok()?;
+                extract_cert_and_key(&content)?
+                    .iter()
+                    .map(|(cert, key)| pem_container(cert, key))
+                    .collect::<Result<Vec<_>, _>>()
+            } else if let Some(rest) = entry.strip_prefix("pem:") {
+                let (cert, key) = rest.split_once(',')?;
+                pem_container(cert, key).map(|c| vec![c])
+            } else if let Some(rest) = entry.strip_prefix("userdefined:") {
+                signer::from_spec(rest)
+                    .map(|(cert_der, signer)| vec![(cert_der, KeySource::Signer(signer))])
+            } else if entry == "ephemeral" {
+                ephemeral::take_session_card()
+                    .map(|(cert_der, signer)| vec![(cert_der, KeySource::Signer(signer))])
+                    .ok_or_else(|| "no ephemeral card issued for the session".to_string())
+            } else {
+                log::error!(
+                    "emulated_certs: unsupported spec prefix (expected file:, pem:, userdefined: or ephemeral) — got: {}",
+                    entry
+                );
+                return None;
+            };
+            match loaded {
+                Ok(loaded) => containers.extend(loaded),
+                Err(e) => {
+                    log::error!("Failed to load emulated smartcard: {}", e);
+                    return None;
+                }
+            }
+        }
+        match Self::with_containers(containers, policy) {
             Ok(b) => Some(b),
             Err(e) => {
                 log::error!("Failed to load emulated smartcard: {}", e);
@@ -99,27 +171,27 @@ impl EmulatedBackend {
         }
     }
 
-    /// Load the emulated card from `UDS_SMARTCARD_KEYS="cert.pem;key.pem"`.
+    /// Load the emulated card from `UDS_SMARTCARD_KEYS="cert.pem;key.pem"` (or
+    /// `"cert1.pem;key1.pem;cert2.pem;key2.pem"` for several containers).
     /// The certificate is needed so msclmd can serve it (GET DATA DF24) and match
     /// the container key; the private key drives signing. If the key is encrypted,
     /// its password acts as the card PIN (asked by msclmd only when signing).
-    pub fn try_from_env() -> Option<Self> {
+    pub fn try_from_env(policy: &PinPolicy) -> Option<Self> {
         let spec = std::env::var("UDS_SMARTCARD_KEYS").ok()?;
-        let mut parts = spec.split(';');
-        let (cert_path, key_path) = (parts.next()?, parts.next()?);
-        if cert_path.is_empty() || key_path.is_empty() {
-            log::error!("UDS_SMARTCARD_KEYS must be \"cert.pem;key.pem\"");
+        let paths: Vec<&str> = spec.split(';').collect();
+        if !paths.len().is_multiple_of(2) || paths.iter().any(|p| p.is_empty()) {
+            log::error!("UDS_SMARTCARD_KEYS must be \"cert.pem;key.pem[;cert.pem;key.pem...]\"");
             return None;
         }
-        let cert_pem = std::fs::read_to_string(cert_path).ok()?;
-        let key_pem = std::fs::read_to_string(key_path).ok()?;
-        match Self::from_pem(&cert_pem, &key_pem) {
+        let mut pairs = Vec::with_capacity(paths.len() / 2);
+        for pair in paths.chunks(2) {
+            let cert_pem = std::fs::read_to_string(pair[0]).ok()?;
+            let key_pem = std::fs::read_to_string(pair[1]).ok()?;
+            pairs.push((cert_pem, key_pem));
+        }
+        match Self::from_pem_list(&pairs, policy) {
             Ok(b) => {
-                log::info!(
-                    "Emulated smartcard loaded: cert={}, key={}",
-                    cert_path,
-                    key_path
-                );
+                log::info!("Emulated smartcard loaded: {}", spec);
                 Some(b)
             }
             Err(e) => {
@@ -128,6 +200,19 @@ impl EmulatedBackend {
             }
         }
     }
+
+    /// Runs an APDU on the card. A PIN entered on the client is asked before
+    /// locking the card, so the rest of the calls go on while the user types it.
+    fn exchange(&self, apdu: &[u8]) -> Result<Vec<u8>, u32> {
+        let engine = || self.engine.lock().map_err(|_| SCARD_F_INTERNAL_ERROR);
+        // A statement of its own, so the lock is released before asking
+        let wanted = engine()?.local_pin_wanted(apdu);
+        if let (Some(prompt), Some(retries)) = (&self.prompt, wanted) {
+            let pin = prompt.ask(retries);
+            return Ok(engine()?.verify_local(pin));
+        }
+        Ok(engine()?.process_apdu(apdu))
+    }
 }
 
 impl SmartcardBackend for EmulatedBackend {
@@ -171,16 +256,10 @@ impl SmartcardBackend for EmulatedBackend {
         Ok(SCARD_PROTOCOL_T1)
     }
 
-    fn transmit(
-        &self,
-        _: &ScardHandle,
-        _: &ScardIORequest,
-        data: &[u8],
-    ) -> Result<TransmitResult, u32> {
-        let mut engine = self.engine.lock().map_err(|_| SCARD_F_INTERNAL_ERROR)?;
+    fn transmit(&self, _: &ScardHandle, data: &[u8]) -> Result<TransmitResult, u32> {
         Ok(TransmitResult {
             recv_pci: None,
-            recv_buffer: engine.process_apdu(data),
+            recv_buffer: self.exchange(data)?,
         })
     }
 
@@ -277,11 +356,14 @@ impl SmartcardBackend for EmulatedBackend {
         Ok(())
     }
 
-    fn get_container_info(&self, _: &ScardContext, _: u8) -> Result<Vec<u8>, u32> {
-        // The GIDS engine serves the container public key (7F 49) over TRANSMIT and
-        // msclmd builds ContainerInfo_XX itself (same flow as the physical card), so
-        // this stays as a MISS.
-        Err(SCARD_E_UNSUPPORTED_FEATURE)
+    fn get_container_info(&self, _: &ScardContext, container_index: u8) -> Result<Vec<u8>, u32> {
+        // The GIDS engine knows every container key, so ContainerInfo_XX is served
+        // from it (a cache HIT). msclmd can still read the keys over TRANSMIT (7F 49)
+        // like with the physical card; unknown containers stay a MISS.
+        let engine = self.engine.lock().map_err(|_| SCARD_F_INTERNAL_ERROR)?;
+        engine
+            .container_info(container_index as usize)
+            .ok_or(SCARD_E_UNSUPPORTED_FEATURE)
     }
 
     fn get_certificate(&self, _: &ScardContext) -> Result<Vec<u8>, u32> {
@@ -295,19 +377,36 @@ impl SmartcardBackend for EmulatedBackend {
     }
 }
 
-/// Extract the CERTIFICATE and PRIVATE KEY PEM blocks from a PEM bundle.
-fn extract_cert_and_key(content: &str) -> Option<(String, String)> {
+/// A container from its certificate and private key PEMs.
+fn pem_container(cert_pem: &str, key_pem: &str) -> Result<(Vec<u8>, KeySource), String> {
+    let cert_der = pem::parse(cert_pem)
+        .map_err(|e| format!("cert PEM: {}", e))?
+        .into_contents();
+    Ok((cert_der, KeySource::Pem(key_pem.to_string())))
+}
+
+/// Extract the CERTIFICATE and PRIVATE KEY PEM blocks from a PEM bundle, as
+/// (certificate, key) pairs: the n-th certificate goes with the n-th key.
+fn extract_cert_and_key(content: &str) -> Option<Vec<(String, String)>> {
     let blocks = pem::parse_many(content).ok()?;
-    let mut cert_pem = None;
-    let mut key_pem = None;
+    let mut certs = Vec::new();
+    let mut keys = Vec::new();
     for b in blocks {
         match b.tag() {
-            "CERTIFICATE" => cert_pem = Some(b.to_string()),
-            "PRIVATE KEY" | "ENCRYPTED PRIVATE KEY" | "RSA PRIVATE KEY" => {
-                key_pem = Some(b.to_string());
+            "CERTIFICATE" => certs.push(b.to_string()),
+            "PRIVATE KEY" | "ENCRYPTED PRIVATE KEY" | "RSA PRIVATE KEY" | "EC PRIVATE KEY" => {
+                keys.push(b.to_string());
             }
             _ => {}
         }
     }
-    Some((cert_pem?, key_pem?))
+    if certs.is_empty() || certs.len() != keys.len() {
+        log::error!(
+            "emulated_certs: {} certificates and {} private keys in the PEM file",
+            certs.len(),
+            keys.len()
+        );
+        return None;
+    }
+    Some(certs.into_iter().zip(keys).collect())
 }
@@ -0,0 +1,398 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! PIN policy of the emulated card.
+//!
+//! The card PIN is the key password (or the signer login). By default msclmd, on the
+//! server, asks for it and sends it in VERIFY. With a `local` policy the PIN that
+//! comes from the server is ignored: the launcher asks for it in its own popup (see
+//! [`next_pin_request`]) and the card checks it on the client, so the PIN never
+//! crosses the RDP channel.
+//!
+//! Either way, wrong PINs are counted per certificate, for the session or, with a
+//! `persistent` policy, in `AppData` so the counter survives it. After
+//! `max_retries` failures the card is blocked for `lockout_minutes` or, with 0,
+//! until its failures are cleared (a new session, or [`clear_pin_failures`]).
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use flume::{Receiver, Sender};
+use zeroize::Zeroizing;
+
+use shared::appdata::{AppData, PinFailures};
+
+/// No answer from the launcher in this time cancels the PIN entry.
+const PIN_PROMPT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Highest retry counter VERIFY can report (`63 CX`).
+const MAX_RETRIES_LIMIT: u8 = 15;
+
+const DEFAULT_LOCKOUT_MINUTES: u32 = 15;
+
+/// PIN rules of the emulated card, set by the session settings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PinPolicy {
+    /// The PIN is entered in the launcher, not on the server.
+    pub local: bool,
+    /// Wrong PINs before the card is blocked (1 to 15).
+    pub max_retries: u8,
+    pub min_length: usize,
+    /// 0 for no limit.
+    pub max_length: usize,
+    pub digits_only: bool,
+    /// Minutes a blocked card stays blocked, 0 until its failures are cleared.
+    pub lockout_minutes: u32,
+    /// Failures are kept in `AppData`, surviving the session.
+    pub persistent: bool,
+}
+
+impl Default for PinPolicy {
+    fn default() -> Self {
+        PinPolicy {
+            local: false,
+            max_retries: 3,
+            min_length: 0,
+            max_length: 0,
+            digits_only: false,
+            lockout_minutes: DEFAULT_LOCKOUT_MINUTES,
+            persistent: false,
+        }
+    }
+}
+
+impl PinPolicy {
+    /// Whether the PIN follows the policy, with the reason if not.
+    pub fn check(&self, pin: &str) -> Result<(), String> {
+        let len = pin.chars().count();
+        if len < self.min_length {
+            return Err(format!(
+                "The PIN must have at least {} characters",
+                self.min_length
+            ));
+        }
+        if self.max_length > 0 && len > self.max_length {
+            return Err(format!(
+                "The PIN must have at most {} characters",
+                self.max_length
+            ));
+        }
+        if self.digits_only && !pin.chars().all(|c| c.is_ascii_digit()) {
+            return Err("The PIN must have only digits".to_string());
+        }
+        Ok(())
+    }
+
+    fn max_retries(&self) -> u8 {
+        self.max_retries.clamp(1, MAX_RETRIES_LIMIT)
+    }
+
+    /// Where the failures of a card under this policy are kept.
+    pub(crate) fn retry_store(&self) -> Box<dyn RetryStore> {
+        if self.persistent {
+            Box::new(AppDataStore)
+        } else {
+            Box::new(MemoryStore::default())
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Failure counter
+// ---------------------------------------------------------------------------
+
+/// Where the wrong PINs of a card are kept between sessions.
+pub(crate) trait RetryStore: Send {
+    /// Failures of the card, from the fingerprints of its certificates.
+    fn load(&self, certs: &[String]) -> Option<PinFailures>;
+    /// Record the failures of the card, `None` to clear them.
+    fn save(&self, certs: &[String], failures: Option<PinFailures>);
+}
+
+/// Keeps the failures in `AppData`, one entry per certificate.
+pub(crate) struct AppDataStore;
+
+impl RetryStore for AppDataStore {
+    fn load(&self, certs: &[String]) -> Option<PinFailures> {
+        let app_data = AppData::load();
+        certs
+            .iter()
+            .filter_map(|cert| app_data.smartcard_pin_failures.get(cert))
+            .max_by_key(|failures| failures.count)
+            .copied()
+    }
+
+    fn save(&self, certs: &[String], failures: Option<PinFailures>) {
+        let mut app_data = AppData::load();
+        for cert in certs {
+            match failures {
+                Some(failures) => {
+                    app_data
+                        .smartcard_pin_failures
+                        .insert(cert.clone(), failures);
+                }
+                None => {
+                    app_data.smartcard_pin_failures.remove(cert);
+                }
+            }
+        }
+        app_data.save();
+    }
+}
+
+/// Unblocks every emulated card, forgetting the failures kept in `AppData`.
+pub fn clear_pin_failures() {
+    let mut app_data = AppData::load();
+    if !app_data.smartcard_pin_failures.is_empty() {
+        log::info!(
+            "Clearing the PIN failures of {} certificate(s)",
+            app_data.smartcard_pin_failures.len()
+        );
+        app_data.smartcard_pin_failures.clear();
+        app_data.save();
+    }
+}
+
+/// Failures kept only while the process runs (cards that are not persisted, tests).
+#[derive(Clone, Default)]
+pub(crate) struct MemoryStore(Arc<Mutex<HashMap<String, PinFailures>>>);
+
+impl RetryStore for MemoryStore {
+    fn load(&self, certs: &[String]) -> Option<PinFailures> {
+        let failures = self.0.lock().unwrap();
+        certs
+            .iter()
+            .filter_map(|cert| failures.get(cert))
+            .max_by_key(|failures| failures.count)
+            .copied()
+    }
+
+    fn save(&self, certs: &[String], failures: Option<PinFailures>) {
+        let mut stored = self.0.lock().unwrap();
+        for cert in certs {
+            match failures {
+                Some(failures) => stored.insert(cert.clone(), failures),
+                None => stored.remove(cert),
+            };
+        }
+    }
+}
+
+/// Retry counter of the card PIN, under a policy.
+pub(crate) struct PinCounter {
+    policy: PinPolicy,
+    /// Fingerprints of the certificates whose keys use the PIN.
+    certs: Vec<String>,
+    store: Box<dyn RetryStore>,
+    failures: PinFailures,
+}
+
+impl PinCounter {
+    pub fn new(policy: PinPolicy, certs: Vec<String>, store: Box<dyn RetryStore>) -> Self {
+        let failures = store.load(&certs).unwrap_or_default();
+        if failures.count > 0 {
+            log::info!(
+                "GIDS: {} wrong PIN(s) from previous sessions",
+                failures.count
+            );
+        }
+        PinCounter {
+            policy,
+            certs,
+            store,
+            failures,
+        }
+    }
+
+    pub fn policy(&self) -> &PinPolicy {
+        &self.policy
+    }
+
+    /// PIN tries left, 0 while the card is blocked.
+    pub fn remaining(&mut self) -> u8 {
+        let max = self.policy.max_retries();
+        if self.failures.count >= max && self.policy.lockout_minutes > 0 {
+            let blocked_for = now().saturating_sub(self.failures.last_failure);
+            if blocked_for >= self.policy.lockout_minutes as u64 * 60 {
+                log::info!("GIDS: PIN lockout expired");
+                self.succeeded();
+            }
+        }
+        max.saturating_sub(self.failures.count)
+    }
+
+    /// Count a wrong PIN, returns the tries left.
+    pub fn failed(&mut self) -> u8 {
+        self.failures = PinFailures {
+            count: self.failures.count.saturating_add(1),
+            last_failure: now(),
+        };
+        self.store.save(&self.certs, Some(self.failures));
+        self.policy
+            .max_retries()
+            .saturating_sub(self.failures.count)
+    }
+
+    pub fn succeeded(&mut self) {
+        if self.failures.count > 0 {
+            self.failures = PinFailures::default();
+            self.store.save(&self.certs, None);
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+// ---------------------------------------------------------------------------
+// Local PIN entry
+// ---------------------------------------------------------------------------
+
+/// Asks the user for the card PIN, on the client.
+pub(crate) trait PinPrompt: Send + Sync {
+    /// The PIN, `None` if the user cancelled.
+    fn ask(&self, retries_left: u8) -> Option<Zeroizing<String>>;
+}
+
+/// A PIN the emulated card waits for; the launcher shows it and answers.
+pub struct PinRequest {
+    pub retries_left: u8,
+    pub policy: PinPolicy,
+    response: Sender<Option<Zeroizing<String>>>,
+}
+
+impl std::fmt::Debug for PinRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PinRequest")
+            .field("retries_left", &self.retries_left)
+            .finish()
+    }
+}
+
+impl PinRequest {
+    /// Answer with the PIN, or `None` to cancel. Dropping the request cancels too.
+    pub fn answer(self, pin: Option<Zeroizing<String>>) {
+        let _ = self.response.send(pin);
+    }
+}
+
+static PIN_REQUESTS: LazyLock<(Sender<PinRequest>, Receiver<PinRequest>)> =
+    LazyLock::new(flume::unbounded);
+
+/// Next PIN the card asks for, to be shown by the launcher.
+pub fn next_pin_request() -> Option<PinRequest> {
+    PIN_REQUESTS.1.try_recv().ok()
+}
+
+/// Asks the launcher (see [`next_pin_request`]) and waits for its answer.
+pub(crate) struct LauncherPrompt {
+    pub policy: PinPolicy,
+}
+
+impl PinPrompt for LauncherPrompt {
+    fn ask(&self, retries_left: u8) -> Option<Zeroizing<String>> {
+        let (response, answer) = flume::bounded(1);
+        PIN_REQUESTS
+            .0
+            .send(PinRequest {
+                retries_left,
+                policy: self.policy.clone(),
+                response,
+            })
+            .ok()?;
+        answer.recv_timeout(PIN_PROMPT_TIMEOUT).ok().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counter(policy: PinPolicy, store: &MemoryStore) -> PinCounter {
+        PinCounter::new(policy, vec!["cert".to_string()], Box::new(store.clone()))
+    }
+
+    #[test]
+    fn policy_checks_the_pin() {
+        let policy = PinPolicy {
+            min_length: 4,
+            max_length: 8,
+            digits_only: true,
+            ..Default::default()
+        };
+        assert!(policy.check("1234").is_ok());
+        assert!(policy.check("123").is_err());
+        assert!(policy.check("123456789").is_err());
+        assert!(policy.check("12a4").is_err());
+        assert!(PinPolicy::default().check("").is_ok());
+    }
+
+    #[test]
+    fn failures_survive_the_session() {
+        let store = MemoryStore::default();
+        let mut pin = counter(PinPolicy::default(), &store);
+        assert_eq!(pin.remaining(), 3);
+        assert_eq!(pin.failed(), 2);
+
+        // Next session, same certificate
+        let mut pin = counter(PinPolicy::default(), &store);
+        assert_eq!(pin.remaining(), 2);
+        assert_eq!(pin.failed(), 1);
+        pin.succeeded();
+        assert_eq!(counter(PinPolicy::default(), &store).remaining(), 3);
+    }
+
+    #[test]
+    fn blocked_until_the_lockout_expires() {
+        let store = MemoryStore::default();
+        let policy = PinPolicy {
+            max_retries: 2,
+            lockout_minutes: 30,
+            ..Default::default()
+        };
+        let mut pin = counter(policy.clone(), &store);
+        pin.failed();
+        assert_eq!(pin.failed(), 0);
+        assert_eq!(pin.remaining(), 0);
+
+        // Blocked 31 minutes ago
+        let certs = ["cert".to_string()];
+        let failures = PinFailures {
+            count: 2,
+            last_failure: now() - 31 * 60,
+        };
+        store.save(&certs, Some(failures));
+        assert_eq!(counter(policy.clone(), &store).remaining(), 2);
+        assert!(store.load(&certs).is_none());
+
+        // The default lockout is finite too
+        let default = PinPolicy {
+            max_retries: 2,
+            ..Default::default()
+        };
+        let blocked_at = |last_failure| PinFailures {
+            count: 2,
+            last_failure,
+        };
+        store.save(&certs, Some(blocked_at(now())));
+        assert_eq!(counter(default.clone(), &store).remaining(), 0);
+        let expired = now() - DEFAULT_LOCKOUT_MINUTES as u64 * 60;
+        store.save(&certs, Some(blocked_at(expired)));
+        assert_eq!(counter(default, &store).remaining(), 2);
+
+        // Without lockout time, blocked for good
+        store.save(&certs, Some(failures));
+        let policy = PinPolicy {
+            lockout_minutes: 0,
+            ..policy
+        };
+        assert_eq!(counter(policy, &store).remaining(), 0);
+    }
+}
@@ -0,0 +1,148 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! HTTP signer: a signing service (e.g. next to a broker-side CA) holds the key.
+//!
+//! - `GET <url>/certificate` → the certificate, PEM.
+//! - `POST <url>/sign` with `{"mechanism": "rsa-pkcs1" | "ecdsa", "data": <base64>}`
+//!   → `{"signature": <base64>}` (PKCS#1 v1.5 signature of the DigestInfo in `data`,
+//!   or the `r || s` ECDSA signature of the hash).
+//!
+//! With a token, requests carry it as `Authorization: Bearer <token>`.
+
+use std::time::Duration;
+
+use base64::{Engine as _, engine::general_purpose};
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+
+use super::{Mechanism, Signer};
+
+/// msclmd waits for the PSO response, so do not hang the logon forever.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Serialize)]
+struct SignRequest<'a> {
+    mechanism: &'a str,
+    data: String,
+}
+
+#[derive(Deserialize)]
+struct SignResponse {
+    signature: String,
+}
+
+pub(crate) struct HttpSigner {
+    client: Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl std::fmt::Debug for HttpSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpSigner")
+            .field("url", &self.url)
+            .finish()
+    }
+}
+
+impl HttpSigner {
+    pub fn new(url: &str, token: Option<&str>) -> Result<Self, String> {
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| format!("HTTP signer client: {}", e))?;
+        Ok(HttpSigner {
+            client,
+            url: url.trim_end_matches('/').to_string(),
+            token: token.map(str::to_string),
+        })
+    }
+
+    /// The certificate of the service key (DER).
+    pub fn certificate(&self) -> Result<Vec<u8>, String> {
+        let pem = self
+            .authorized(self.client.get(format!("{}/certificate", self.url)))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.text())
+            .map_err(|e| format!("HTTP signer certificate: {}", e))?;
+        super::certificate_from_pem(&pem)
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+impl Signer for HttpSigner {
+    fn sign(&self, mechanism: Mechanism, data: &[u8]) -> Result<Vec<u8>, String> {
+        let request = SignRequest {
+            mechanism: mechanism.name(),
+            data: general_purpose::STANDARD.encode(data),
+        };
+        let response: SignResponse = self
+            .authorized(self.client.post(format!("{}/sign", self.url)))
+            .json(&request)
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.json())
+            .map_err(|e| format!("HTTP signer: {}", e))?;
+        general_purpose::STANDARD
+            .decode(response.signature)
+            .map_err(|e| format!("HTTP signer signature: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn certificate_and_signature_from_the_service() {
+        let mut server = mockito::Server::new();
+        let cert = pem::Pem::new("CERTIFICATE", vec![0x30, 0x03, 0x02, 0x01, 0x01]).to_string();
+        let cert_mock = server
+            .mock("GET", "/signer/certificate")
+            .match_header("authorization", "Bearer s3cret")
+            .with_body(cert)
+            .create();
+        let sign_mock = server
+            .mock("POST", "/signer/sign")
+            .match_header("authorization", "Bearer s3cret")
+            .match_body(mockito::Matcher::Json(serde_json::json!({
+                "mechanism": "ecdsa",
+                "data": general_purpose::STANDARD.encode([1u8, 2, 3]),
+            })))
+            .with_body(format!(
+                r#"{{"signature": "{}"}}"#,
+                general_purpose::STANDARD.encode([9u8; 64])
+            ))
+            .create();
+
+        let signer = HttpSigner::new(&format!("{}/signer/", server.url()), Some("s3cret")).unwrap();
+        assert_eq!(
+            signer.certificate().unwrap(),
+            vec![0x30, 0x03, 0x02, 0x01, 0x01]
+        );
+        assert_eq!(
+            signer.sign(Mechanism::Ecdsa, &[1, 2, 3]).unwrap(),
+            vec![9u8; 64]
+        );
+        cert_mock.assert();
+        sign_mock.assert();
+    }
+
+    #[test]
+    fn service_errors_fail_the_signature() {
+        let mut server = mockito::Server::new();
+        let _mock = server.mock("POST", "/sign").with_status(403).create();
+        let signer = HttpSigner::new(&server.url(), None).unwrap();
+        assert!(signer.sign(Mechanism::RsaPkcs1, &[0u8; 51]).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! External signers for the emulated card.
+//!
+//! With a `userdefined:` spec the container key is not loaded from a PEM: PSO hands
+//! the data to sign to a [`Signer`] instead — a PKCS#11 module (token, HSM, SoftHSM
+//! for tests) or an HTTP signing service — so the private key never reaches the
+//! client. The certificate comes from the signer too (or from a file), and the GIDS
+//! APDU layer is the same as with a local key.
+//!
+//! Spec: `userdefined:<kind>,<option>=<value>,...` (values cannot contain commas):
+//! - `pkcs11`: `module=<path>` (required), `token=<label>`, `label=<key label>` or
+//!   `id=<hex key id>`, `pin=<pin>` (without it the card PIN logs in the token) and
+//!   `cert=<PEM file>` (else the token certificate with the same label / id).
+//! - `http`: `url=<base url>` (required), `token=<bearer token>` and `cert=<PEM
+//!   file>` (else `GET <url>/certificate`).
+
+mod http;
+mod pkcs11;
+
+use std::collections::HashMap;
+
+pub(crate) use http::HttpSigner;
+pub(crate) use pkcs11::{KeySelector, Pkcs11Signer};
+
+/// Signature the card needs, from the type of the container key.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Mechanism {
+    /// RSA PKCS#1 v1.5 of a DigestInfo (the signer pads it), as `CKM_RSA_PKCS`.
+    RsaPkcs1,
+    /// ECDSA of a hash, as `r || s` (`CKM_ECDSA`).
+    Ecdsa,
+}
+
+impl Mechanism {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mechanism::RsaPkcs1 => "rsa-pkcs1",
+            Mechanism::Ecdsa => "ecdsa",
+        }
+    }
+}
+
+/// Holder of a container private key, outside of the emulated card.
+pub(crate) trait Signer: Send + std::fmt::Debug {
+    /// Whether the card PIN logs in the signer (checked by VERIFY).
+    fn uses_pin(&self) -> bool {
+        false
+    }
+
+    /// Whether it can sign now (logged in, if it uses the PIN).
+    fn ready(&self) -> bool {
+        true
+    }
+
+    /// Log in with the card PIN. `Ok(false)` when the PIN is wrong.
+    fn login(&mut self, _pin: &str) -> Result<bool, String> {
+        Ok(true)
+    }
+
+    /// Raw signature of the PSO data.
+    fn sign(&self, mechanism: Mechanism, data: &[u8]) -> Result<Vec<u8>, String>;
+}
+
+/// Open the signer of a `userdefined:` spec (without the prefix). Returns the
+/// certificate DER and the signer of its key.
+pub(crate) fn from_spec(spec: &str) -> Result<(Vec<u8>, Box<dyn Signer>), String> {
+    let (kind, options) = parse_options(spec)?;
+    let cert_file = options
+        .get("cert")
+        .map(|path| read_cert_file(path))
+        .transpose()?;
+    match kind {
+        "pkcs11" => {
+            let module = options
+                .get("module")
+                .ok_or("userdefined:pkcs11 needs module=<path>")?;
+            let selector = KeySelector {
+                label: options.get("label").map(|l| l.to_string()),
+                id: options.get("id").map(|id| hex_decode(id)).transpose()?,
+            };
+            let signer = Pkcs11Signer::open(
+                module,
+                options.get("token").copied(),
+                selector,
+                options.get("pin").copied(),
+            )?;
+            let cert = match cert_file {
+                Some(cert) => cert,
+                None => signer.certificate()?,
+            };
+            Ok((cert, Box::new(signer)))
+        }
+        "http" => {
+            let url = options
+                .get("url")
+                .ok_or("userdefined:http needs url=<url>")?;
+            let signer = HttpSigner::new(url, options.get("token").copied())?;
+            let cert = match cert_file {
+                Some(cert) => cert,
+                None => signer.certificate()?,
+            };
+            Ok((cert, Box::new(signer)))
+        }
+        _ => Err(format!(
+            "unsupported userdefined signer {:?} (expected pkcs11 or http)",
+            kind
+        )),
+    }
+}
+
+/// Split `<kind>,<option>=<value>,...`.
+fn parse_options(spec: &str) -> Result<(&str, HashMap<&str, &str>), String> {
+    let mut parts = spec.split(',').map(str::trim);
+    let kind = parts.next().unwrap_or_default();
+    let options = parts
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            p.split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| format!("userdefined: option without a value: {:?}", p))
+        })
+        .collect::<Result<HashMap<_, _>, _>>()?;
+    Ok((kind, options))
+}
+
+fn read_cert_file(path: &str) -> Result<Vec<u8>, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("cert {}: {}", path, e))?;
+    certificate_from_pem(&content)
+}
+
+/// DER of the first CERTIFICATE block of a PEM text.
+pub(crate) fn certificate_from_pem(content: &str) -> Result<Vec<u8>, String> {
+    pem::parse_many(content)
+        .map_err(|e| format!("cert PEM: {}", e))?
+        .into_iter()
+        .find(|b| b.tag() == "CERTIFICATE")
+        .map(|b| b.into_contents())
+        .ok_or_else(|| "no CERTIFICATE in the PEM".to_string())
+}
+
+fn hex_decode(hex: &str) -> Result<Vec<u8>, String> {
+    let hex = hex.trim_start_matches("0x");
+    if !hex.len().is_multiple_of(2) {
+        return Err(format!("invalid hex id {:?}", hex));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| format!("invalid hex id {:?}", hex))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_of_a_spec() {
+        let (kind, options) =
+            parse_options("pkcs11, module=/usr/lib/softhsm/libsofthsm2.so,label=card,id=0a1B")
+                .unwrap();
+        assert_eq!(kind, "pkcs11");
+        assert_eq!(options["module"], "/usr/lib/softhsm/libsofthsm2.so");
+        assert_eq!(options["label"], "card");
+        assert_eq!(hex_decode(options["id"]).unwrap(), vec![0x0A, 0x1B]);
+        assert!(parse_options("http,url").is_err());
+        assert!(hex_decode("abc").is_err());
+    }
+
+    #[test]
+    fn unknown_or_incomplete_specs() {
+        assert!(from_spec("whatever").is_err());
+        assert!(from_spec("pkcs11,label=card").is_err());
+        assert!(from_spec("http,token=abc").is_err());
+        // A module that cannot be loaded
+        assert!(from_spec("pkcs11,module=/nonexistent/libpkcs11.so").is_err());
+    }
+
+    #[test]
+    fn first_certificate_of_a_pem() {
+        let pem = format!(
+            "{}{}",
+            pem::Pem::new("PRIVATE KEY", vec![1, 2, 3]),
+            pem::Pem::new("CERTIFICATE", vec![0x30, 0x00])
+        );
+        assert_eq!(certificate_from_pem(&pem).unwrap(), vec![0x30, 0x00]);
+        assert!(certificate_from_pem("").is_err());
+    }
+}
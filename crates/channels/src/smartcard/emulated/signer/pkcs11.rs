@@ -0,0 +1,442 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! PKCS#11 signer: the container key lives in a token, loaded at runtime from its
+//! module (the `C_*` functions every module exports). One read-only session per
+//! signer; the key is found by label or id when opening it.
+
+use std::ffi::{c_ulong, c_void};
+use std::ptr;
+
+use super::{Mechanism, Signer};
+
+type CkUlong = c_ulong;
+type CkRv = CkUlong;
+
+const CKR_OK: CkRv = 0x000;
+const CKR_PIN_INCORRECT: CkRv = 0x0A0;
+const CKR_PIN_LEN_RANGE: CkRv = 0x0A2;
+const CKR_USER_ALREADY_LOGGED_IN: CkRv = 0x100;
+const CKR_CRYPTOKI_ALREADY_INITIALIZED: CkRv = 0x191;
+
+const CKF_SERIAL_SESSION: CkUlong = 0x04;
+const CKU_USER: CkUlong = 1;
+
+const CKA_CLASS: CkUlong = 0x000;
+const CKA_LABEL: CkUlong = 0x003;
+const CKA_VALUE: CkUlong = 0x011;
+const CKA_ID: CkUlong = 0x102;
+
+const CKO_CERTIFICATE: CkUlong = 1;
+const CKO_PRIVATE_KEY: CkUlong = 3;
+
+const CKM_RSA_PKCS: CkUlong = 0x0001;
+const CKM_ECDSA: CkUlong = 0x1041;
+
+/// Room for a CK_TOKEN_INFO (about 200 bytes on 64-bit targets); only its label,
+/// the first 32 bytes (blank padded), is used.
+const TOKEN_INFO_SIZE: usize = 512;
+
+// Cryptoki structures are packed on Windows.
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+struct Attribute {
+    kind: CkUlong,
+    value: *mut c_void,
+    len: CkUlong,
+}
+
+#[cfg_attr(windows, repr(C, packed))]
+#[cfg_attr(not(windows), repr(C))]
+struct CkMechanism {
+    mechanism: CkUlong,
+    parameter: *mut c_void,
+    parameter_len: CkUlong,
+}
+
+type InitializeFn = unsafe extern "C" fn(*mut c_void) -> CkRv;
+type FinalizeFn = unsafe extern "C" fn(*mut c_void) -> CkRv;
+type GetSlotListFn = unsafe extern "C" fn(u8, *mut CkUlong, *mut CkUlong) -> CkRv;
+type GetTokenInfoFn = unsafe extern "C" fn(CkUlong, *mut u8) -> CkRv;
+type OpenSessionFn =
+    unsafe extern "C" fn(CkUlong, CkUlong, *mut c_void, *mut c_void, *mut CkUlong) -> CkRv;
+type CloseSessionFn = unsafe extern "C" fn(CkUlong) -> CkRv;
+type LoginFn = unsafe extern "C" fn(CkUlong, CkUlong, *const u8, CkUlong) -> CkRv;
+type LogoutFn = unsafe extern "C" fn(CkUlong) -> CkRv;
+type FindObjectsInitFn = unsafe extern "C" fn(CkUlong, *mut Attribute, CkUlong) -> CkRv;
+type FindObjectsFn = unsafe extern "C" fn(CkUlong, *mut CkUlong, CkUlong, *mut CkUlong) -> CkRv;
+type FindObjectsFinalFn = unsafe extern "C" fn(CkUlong) -> CkRv;
+type GetAttributeValueFn = unsafe extern "C" fn(CkUlong, CkUlong, *mut Attribute, CkUlong) -> CkRv;
+type SignInitFn = unsafe extern "C" fn(CkUlong, *mut CkMechanism, CkUlong) -> CkRv;
+type SignFn = unsafe extern "C" fn(CkUlong, *const u8, CkUlong, *mut u8, *mut CkUlong) -> CkRv;
+
+struct Module {
+    initialize: InitializeFn,
+    finalize: FinalizeFn,
+    get_slot_list: GetSlotListFn,
+    get_token_info: GetTokenInfoFn,
+    open_session: OpenSessionFn,
+    close_session: CloseSessionFn,
+    login: LoginFn,
+    logout: LogoutFn,
+    find_objects_init: FindObjectsInitFn,
+    find_objects: FindObjectsFn,
+    find_objects_final: FindObjectsFinalFn,
+    get_attribute_value: GetAttributeValueFn,
+    sign_init: SignInitFn,
+    sign: SignFn,
+    // Keeps the symbols above valid
+    _lib: libloading::Library,
+}
+
+impl Module {
+    fn load(path: &str) -> Result<Self, String> {
+        let lib = unsafe { libloading::Library::new(path) }
+            .map_err(|e| format!("PKCS#11 module {}: {}", path, e))?;
+        unsafe {
+            macro_rules! symbol {
+                ($name:literal) => {
+                    *lib.get($name)
+                        .map_err(|e| format!("Missing PKCS#11 symbol {:?}: {e}", $name))?
+                };
+            }
+            Ok(Module {
+                initialize: symbol!(b"C_Initialize"),
+                finalize: symbol!(b"C_Finalize"),
+                get_slot_list: symbol!(b"C_GetSlotList"),
+                get_token_info: symbol!(b"C_GetTokenInfo"),
+                open_session: symbol!(b"C_OpenSession"),
+                close_session: symbol!(b"C_CloseSession"),
+                login: symbol!(b"C_Login"),
+                logout: symbol!(b"C_Logout"),
+                find_objects_init: symbol!(b"C_FindObjectsInit"),
+                find_objects: symbol!(b"C_FindObjects"),
+                find_objects_final: symbol!(b"C_FindObjectsFinal"),
+                get_attribute_value: symbol!(b"C_GetAttributeValue"),
+                sign_init: symbol!(b"C_SignInit"),
+                sign: symbol!(b"C_Sign"),
+                _lib: lib,
+            })
+        }
+    }
+}
+
+fn check(rv: CkRv, what: &str) -> Result<(), String> {
+    if rv == CKR_OK {
+        Ok(())
+    } else {
+        Err(format!("PKCS#11 {} failed: {:#x}", what, rv))
+    }
+}
+
+/// Key (and certificate) objects of the token to use. With neither, the first
+/// private key.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct KeySelector {
+    pub label: Option<String>,
+    pub id: Option<Vec<u8>>,
+}
+
+pub(crate) struct Pkcs11Signer {
+    module: Module,
+    session: CkUlong,
+    key: CkUlong,
+    selector: KeySelector,
+    /// The card PIN logs in the token (no PIN in the spec).
+    uses_pin: bool,
+    logged_in: bool,
+    /// We initialized the module, so we finalize it.
+    initialized: bool,
+}
+
+impl std::fmt::Debug for Pkcs11Signer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pkcs11Signer")
+            .field("selector", &self.selector)
+            .field("logged_in", &self.logged_in)
+            .finish()
+    }
+}
+
+impl Pkcs11Signer {
+    /// Open a session on the token (the one with the `token` label, or the first
+    /// present) and find the key. With a `pin` it logs in right away, else the
+    /// card PIN does.
+    pub fn open(
+        module_path: &str,
+        token: Option<&str>,
+        selector: KeySelector,
+        pin: Option<&str>,
+    ) -> Result<Self, String> {
+        let module = Module::load(module_path)?;
+        let rv = unsafe { (module.initialize)(ptr::null_mut()) };
+        if rv != CKR_CRYPTOKI_ALREADY_INITIALIZED {
+            check(rv, "C_Initialize")?;
+        }
+        let mut signer = Pkcs11Signer {
+            module,
+            session: 0,
+            key: 0,
+            selector,
+            uses_pin: pin.is_none(),
+            logged_in: false,
+            initialized: rv == CKR_OK,
+        };
+        let slot = signer.find_slot(token)?;
+        check(
+            unsafe {
+                (signer.module.open_session)(
+                    slot,
+                    CKF_SERIAL_SESSION,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                    &mut signer.session,
+                )
+            },
+            "C_OpenSession",
+        )?;
+        // Private keys are only visible once logged in
+        if let Some(pin) = pin
+            && !signer.login(pin)?
+        {
+            return Err("PKCS#11: wrong PIN".to_string());
+        }
+        log::info!(
+            "PKCS#11 signer opened: module={}, slot={}, {:?}",
+            module_path,
+            slot,
+            signer.selector
+        );
+        Ok(signer)
+    }
+
+    /// The token certificate of the selected key (`CKA_VALUE`, DER).
+    pub fn certificate(&self) -> Result<Vec<u8>, String> {
+        let cert = self
+            .find_object(CKO_CERTIFICATE)?
+            .ok_or_else(|| format!("PKCS#11: no certificate for {:?}", self.selector))?;
+        self.attribute(cert, CKA_VALUE)
+    }
+
+    fn find_slot(&self, token: Option<&str>) -> Result<CkUlong, String> {
+        let mut count: CkUlong = 0;
+        check(
+            unsafe { (self.module.get_slot_list)(1, ptr::null_mut(), &mut count) },
+            "C_GetSlotList",
+        )?;
+        let mut slots = vec![0 as CkUlong; count as usize];
+        check(
+            unsafe { (self.module.get_slot_list)(1, slots.as_mut_ptr(), &mut count) },
+            "C_GetSlotList",
+        )?;
+        slots.truncate(count as usize);
+        let Some(token) = token else {
+            return slots
+                .first()
+                .copied()
+                .ok_or_else(|| "PKCS#11: no token present".to_string());
+        };
+        for slot in slots {
+            let mut info = [0u8; TOKEN_INFO_SIZE];
+            if unsafe { (self.module.get_token_info)(slot, info.as_mut_ptr()) } != CKR_OK {
+                continue;
+            }
+            if String::from_utf8_lossy(&info[..32]).trim_end() == token {
+                return Ok(slot);
+            }
+        }
+        Err(format!("PKCS#11: token {:?} not found", token))
+    }
+
+    fn find_key(&self) -> Result<CkUlong, String> {
+        self.find_object(CKO_PRIVATE_KEY)?
+            .ok_or_else(|| format!("PKCS#11: no private key for {:?}", self.selector))
+    }
+
+    /// First object of `class` matching the selector.
+    fn find_object(&self, class: CkUlong) -> Result<Option<CkUlong>, String> {
+        let mut class = class;
+        let mut label = self.selector.label.clone().map(String::into_bytes);
+        let mut id = self.selector.id.clone();
+        let mut template = vec![Attribute {
+            kind: CKA_CLASS,
+            value: &mut class as *mut CkUlong as *mut c_void,
+            len: std::mem::size_of::<CkUlong>() as CkUlong,
+        }];
+        if let Some(label) = label.as_mut() {
+            template.push(Attribute {
+                kind: CKA_LABEL,
+                value: label.as_mut_ptr() as *mut c_void,
+                len: label.len() as CkUlong,
+            });
+        }
+        if let Some(id) = id.as_mut() {
+            template.push(Attribute {
+                kind: CKA_ID,
+                value: id.as_mut_ptr() as *mut c_void,
+                len: id.len() as CkUlong,
+            });
+        }
+        check(
+            unsafe {
+                (self.module.find_objects_init)(
+                    self.session,
+                    template.as_mut_ptr(),
+                    template.len() as CkUlong,
+                )
+            },
+            "C_FindObjectsInit",
+        )?;
+        let mut object: CkUlong = 0;
+        let mut found: CkUlong = 0;
+        let rv = unsafe { (self.module.find_objects)(self.session, &mut object, 1, &mut found) };
+        unsafe { (self.module.find_objects_final)(self.session) };
+        check(rv, "C_FindObjects")?;
+        Ok((found > 0).then_some(object))
+    }
+
+    fn attribute(&self, object: CkUlong, kind: CkUlong) -> Result<Vec<u8>, String> {
+        let mut attribute = Attribute {
+            kind,
+            value: ptr::null_mut(),
+            len: 0,
+        };
+        check(
+            unsafe { (self.module.get_attribute_value)(self.session, object, &mut attribute, 1) },
+            "C_GetAttributeValue",
+        )?;
+        let mut value = vec![0u8; attribute.len as usize];
+        attribute.value = value.as_mut_ptr() as *mut c_void;
+        check(
+            unsafe { (self.module.get_attribute_value)(self.session, object, &mut attribute, 1) },
+            "C_GetAttributeValue",
+        )?;
+        value.truncate(attribute.len as usize);
+        Ok(value)
+    }
+}
+
+impl Signer for Pkcs11Signer {
+    fn uses_pin(&self) -> bool {
+        self.uses_pin
+    }
+
+    fn ready(&self) -> bool {
+        self.logged_in
+    }
+
+    fn login(&mut self, pin: &str) -> Result<bool, String> {
+        // Log out first, so a wrong PIN is not taken while already logged in
+        if self.logged_in {
+            unsafe { (self.module.logout)(self.session) };
+            self.logged_in = false;
+        }
+        let rv = unsafe {
+            (self.module.login)(self.session, CKU_USER, pin.as_ptr(), pin.len() as CkUlong)
+        };
+        match rv {
+            CKR_OK | CKR_USER_ALREADY_LOGGED_IN => {}
+            CKR_PIN_INCORRECT | CKR_PIN_LEN_RANGE => return Ok(false),
+            rv => return Err(format!("PKCS#11 C_Login failed: {:#x}", rv)),
+        }
+        self.logged_in = true;
+        if self.key == 0 {
+            self.key = self.find_key()?;
+        }
+        Ok(true)
+    }
+
+    fn sign(&self, mechanism: Mechanism, data: &[u8]) -> Result<Vec<u8>, String> {
+        let mut mechanism = CkMechanism {
+            mechanism: match mechanism {
+                Mechanism::RsaPkcs1 => CKM_RSA_PKCS,
+                Mechanism::Ecdsa => CKM_ECDSA,
+            },
+            parameter: ptr::null_mut(),
+            parameter_len: 0,
+        };
+        check(
+            unsafe { (self.module.sign_init)(self.session, &mut mechanism, self.key) },
+            "C_SignInit",
+        )?;
+        // Size first (keeps the operation active), then the signature
+        let mut len: CkUlong = 0;
+        check(
+            unsafe {
+                (self.module.sign)(
+                    self.session,
+                    data.as_ptr(),
+                    data.len() as CkUlong,
+                    ptr::null_mut(),
+                    &mut len,
+                )
+            },
+            "C_Sign",
+        )?;
+        let mut signature = vec![0u8; len as usize];
+        check(
+            unsafe {
+                (self.module.sign)(
+                    self.session,
+                    data.as_ptr(),
+                    data.len() as CkUlong,
+                    signature.as_mut_ptr(),
+                    &mut len,
+                )
+            },
+            "C_Sign",
+        )?;
+        signature.truncate(len as usize);
+        Ok(signature)
+    }
+}
+
+impl Drop for Pkcs11Signer {
+    fn drop(&mut self) {
+        unsafe {
+            if self.session != 0 {
+                (self.module.close_session)(self.session);
+            }
+            if self.initialized {
+                (self.module.finalize)(ptr::null_mut());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Needs SoftHSM with a token holding an RSA key and its certificate, e.g.:
+    /// `softhsm2-util --init-token --free --label uds --pin 1234 --so-pin 0000`,
+    /// then `pkcs11-tool --module <module> --login --pin 1234 --keypairgen
+    /// --key-type rsa:2048 --label card` (and the certificate written with the same
+    /// label). `UDS_TEST_PKCS11_MODULE` is the module path.
+    #[test]
+    #[ignore]
+    fn test_manual_softhsm_sign() {
+        let module = std::env::var("UDS_TEST_PKCS11_MODULE")
+            .unwrap_or_else(|_| "/usr/lib/softhsm/libsofthsm2.so".to_string());
+        let selector = KeySelector {
+            label: Some("card".to_string()),
+            id: None,
+        };
+        let mut signer = Pkcs11Signer::open(&module, Some("uds"), selector, None).unwrap();
+        assert!(signer.uses_pin() && !signer.ready());
+        assert!(!signer.login("0000").unwrap());
+        assert!(signer.login("1234").unwrap());
+        let cert = signer.certificate().unwrap();
+        assert_eq!(cert[0], 0x30);
+        // SHA-256 DigestInfo of zeros
+        let mut digest_info = vec![
+            0x30, 0x31, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+            0x01, 0x05, 0x00, 0x04, 0x20,
+        ];
+        digest_info.extend_from_slice(&[0u8; 32]);
+        let signature = signer.sign(Mechanism::RsaPkcs1, &digest_info).unwrap();
+        assert_eq!(signature.len(), 256);
+    }
+}
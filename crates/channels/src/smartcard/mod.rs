@@ -13,14 +13,20 @@
 
 mod emulated;
 mod native;
+pub mod trace;
 
 use std::time::Duration;
 
 use pcsc::ffi::DWORD;
 use rdp::integrations::smartcard::*;
+use zeroize::{Zeroize, ZeroizeOnDrop};
 
 use emulated::EmulatedBackend;
+pub use emulated::{EphemeralKey, PinPolicy, PinRequest, clear_pin_failures, next_pin_request};
 use native::NativeBackend;
+pub use native::filter::ReaderFilter;
+use trace::recorder::RecordingBackend;
+use trace::replay::ReplayBackend;
 
 // ---------------------------------------------------------------------------
 // Internal Backend Trait
@@ -28,7 +34,7 @@ use native::NativeBackend;
 
 /// Internal backend trait — decouples `SmartcardHandle` from the actual
 /// SCard implementation (dummy vs pcsc-lite).
-trait SmartcardBackend: Send + Sync + std::fmt::Debug {
+pub(crate) trait SmartcardBackend: Send + Sync + std::fmt::Debug {
     fn establish_context(&self, scope: DWORD) -> Result<ScardContext, u32>;
     fn release_context(&self, ctx: &ScardContext) -> Result<(), u32>;
     fn is_valid_context(&self, ctx: &ScardContext) -> bool;
@@ -52,12 +58,8 @@ trait SmartcardBackend: Send + Sync + std::fmt::Debug {
         preferred_protocols: DWORD,
         initialization: DWORD,
     ) -> Result<u32, u32>;
-    fn transmit(
-        &self,
-        handle: &ScardHandle,
-        send_pci: &ScardIORequest,
-        data: &[u8],
-    ) -> Result<TransmitResult, u32>;
+    /// No `send_pci`: every backend answers with the protocol of the card.
+    fn transmit(&self, handle: &ScardHandle, data: &[u8]) -> Result<TransmitResult, u32>;
     fn control(
         &self,
         handle: &ScardHandle,
@@ -85,6 +87,38 @@ trait SmartcardBackend: Send + Sync + std::fmt::Debug {
 // SmartcardHandle
 // ---------------------------------------------------------------------------
 
+/// Emulated card spec (`file:...` / `pem:...` / `userdefined:...` / `ephemeral`).
+///
+/// It can hold private keys and PINs, so it is wiped on drop and its `Debug`
+/// shows only the kind of each entry. Used to log the settings of a session.
+#[derive(Clone, Default, PartialEq, Zeroize, ZeroizeOnDrop)]
+pub struct EmulatedSpec(pub String);
+
+impl std::fmt::Debug for EmulatedSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kinds: Vec<String> = self
+            .0
+            .split(';')
+            .map(|entry| match entry.split_once(':') {
+                Some((kind, _)) => format!("{}:***", kind.trim()),
+                None => entry.trim().to_string(),
+            })
+            .collect();
+        f.debug_tuple("EmulatedSpec")
+            .field(&kinds.join(";"))
+            .finish()
+    }
+}
+
+/// Smartcard options given by the RDP settings of the session.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SmartcardOptions {
+    /// PIN policy of the emulated card.
+    pub pin: PinPolicy,
+    /// Readers of the client that are redirected.
+    pub readers: ReaderFilter,
+}
+
 /// Smartcard integration handle.
 ///
 /// Wraps an internal backend (dummy by default, pcsc-lite later).
@@ -100,26 +134,61 @@ impl SmartcardHandle {
     /// real smartcard to redirect (emulated spec invalid / no PC/SC available).
     ///
     /// Priority:
-    /// 1. `emulated` spec (from the RDP settings) → emulated backend, or
+    /// 1. `UDS_SMARTCARD_REPLAY=<trace>` → the trace played back (see `trace`).
+    /// 2. `emulated` spec (from the RDP settings) → emulated backend, or
     ///    `None` (with a warning) if the spec is invalid.
-    /// 2. `UDS_SMARTCARD_EMULATED=1` + `UDS_SMARTCARD_KEYS` (dev helper).
-    /// 3. Native PC/SC backend (physical card), if available.
-    pub fn new(emulated: Option<String>) -> Option<Self> {
+    /// 3. `UDS_SMARTCARD_EMULATED=1` + `UDS_SMARTCARD_KEYS` (dev helper).
+    /// 4. Native PC/SC backend (physical card), if available.
+    ///
+    /// With `UDS_SMARTCARD_TRACE=<file>` the calls to the backend are traced.
+    pub fn new(emulated: Option<String>, options: SmartcardOptions) -> Option<Self> {
+        let backend = Self::backend(emulated, options)?;
+        let backend: Box<dyn SmartcardBackend> = match std::env::var("UDS_SMARTCARD_TRACE") {
+            Ok(path) if !path.is_empty() => match std::fs::File::create(&path) {
+                Ok(file) => {
+                    log::info!("smartcard: tracing to {}", path);
+                    let out = Box::new(std::io::LineWriter::new(file));
+                    Box::new(RecordingBackend::new(backend, out))
+                }
+                Err(e) => {
+                    log::warn!("smartcard: cannot trace to {}: {}", path, e);
+                    backend
+                }
+            },
+            _ => backend,
+        };
+        Some(SmartcardHandle { backend })
+    }
+
+    fn backend(
+        emulated: Option<String>,
+        options: SmartcardOptions,
+    ) -> Option<Box<dyn SmartcardBackend>> {
+        if let Ok(path) = std::env::var("UDS_SMARTCARD_REPLAY")
+            && !path.is_empty()
+        {
+            return match ReplayBackend::open(&path) {
+                Ok(replay) => {
+                    log::info!("smartcard: replaying {}", path);
+                    Some(Box::new(replay))
+                }
+                Err(e) => {
+                    log::warn!("smartcard: cannot replay, {}", e);
+                    None
+                }
+            };
+        }
         if let Some(spec) = emulated.as_deref() {
-            return EmulatedBackend::from_spec(spec).map(|b| SmartcardHandle {
-                backend: Box::new(b),
-            });
+            return EmulatedBackend::from_spec(spec, &options.pin)
+                .map(|b| Box::new(b) as Box<dyn SmartcardBackend>);
         }
         if std::env::var("UDS_SMARTCARD_EMULATED").as_deref() == Ok("1") {
-            return EmulatedBackend::try_from_env().map(|b| SmartcardHandle {
-                backend: Box::new(b),
-            });
+            return EmulatedBackend::try_from_env(&options.pin)
+                .map(|b| Box::new(b) as Box<dyn SmartcardBackend>);
         }
-        let native = NativeBackend::new();
+        let native = NativeBackend::new(options.readers);
         if native.is_available() {
-            Some(SmartcardHandle {
-                backend: Box::new(native),
-            })
+            Some(Box::new(native))
         } else {
             log::debug!("smartcard: no native PC/SC backend available, no smartcard");
             None
@@ -185,10 +254,10 @@ impl SmartcardIntegration for SmartcardHandle {
     fn transmit(
         &self,
         handle: &ScardHandle,
-        send_pci: &ScardIORequest,
+        _send_pci: &ScardIORequest,
         data: &[u8],
     ) -> Result<TransmitResult, u32> {
-        self.backend.transmit(handle, send_pci, data)
+        self.backend.transmit(handle, data)
     }
 
     fn control(
@@ -0,0 +1,36 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! Readers of the client that are redirected.
+//!
+//! Allow and deny lists of reader names (or parts of them, case insensitive), from
+//! the RDP settings of the session (see `SmartcardOptions`). With an
+//! allow list only the readers in it are redirected; the deny list removes readers
+//! (the built-in reader of a laptop, for example) and wins over the allow list.
+//! Hidden readers are not listed, cannot be connected to and look unknown in status
+//! changes. The `\\?PnP?\Notification` pseudo-reader always goes through.
+
+/// Pseudo-reader whose state changes when readers are plugged or unplugged.
+pub(crate) const PNP_NOTIFICATION: &str = r"\\?PnP?\Notification";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReaderFilter {
+    /// Only these readers, all of them if empty.
+    pub allow: Vec<String>,
+    pub deny: Vec<String>,
+}
+
+impl ReaderFilter {
+    /// Whether a reader is redirected.
+    pub fn allows(&self, reader: &str) -> bool {
+        if reader == PNP_NOTIFICATION {
+            return true;
+        }
+        let name = reader.to_lowercase();
+        let matches =
+            |pattern: &String| !pattern.is_empty() && name.contains(&pattern.to_lowercase());
+        (self.allow.is_empty() || self.allow.iter().any(matches)) && !self.deny.iter().any(matches)
+    }
+}
@@ -6,6 +6,9 @@
 use pcsc::ffi::{self, DWORD};
 use rdp::integrations::smartcard::consts::*;
 
+/// `SCARD_E_NO_READERS_AVAILABLE`: there is no reader in the system.
+pub(crate) const NO_READERS_AVAILABLE: u32 = 0x8010_002E;
+
 /// Build a `Cached_GeneralFile/...` value (16-byte CSP header + data).
 pub(crate) fn build_general_file_value(data: &[u8]) -> Vec<u8> {
     let mut out = Vec::with_capacity(16 + data.len());
@@ -64,6 +67,7 @@ pub(crate) fn pcsc_error_to_u32(err: pcsc::Error) -> u32 {
         pcsc::Error::RemovedCard => SCARD_W_REMOVED_CARD,
         pcsc::Error::UnsupportedFeature => SCARD_E_UNSUPPORTED_FEATURE,
         pcsc::Error::NoService => SCARD_E_NO_SERVICE,
+        pcsc::Error::NoReadersAvailable => NO_READERS_AVAILABLE,
         pcsc::Error::ServiceStopped => SCARD_E_SERVICE_STOPPED,
         _ => SCARD_F_UNKNOWN_ERROR,
     }
@@ -119,6 +123,16 @@ pub(crate) fn dword_to_state(bits: DWORD) -> pcsc::State {
     pcsc::State::from_bits_retain(bits)
 }
 
+/// Event state of a reader that is not there (unplugged, or not redirected), as
+/// PC/SC reports it.
+pub(crate) fn unknown_reader_state(current_state: u32) -> u32 {
+    if current_state & SCARD_STATE_UNKNOWN != 0 {
+        SCARD_STATE_UNKNOWN
+    } else {
+        SCARD_STATE_UNKNOWN | SCARD_STATE_CHANGED
+    }
+}
+
 pub(crate) fn pcsc_status_to_u32(status: pcsc::Status) -> u32 {
     let mut state = 0;
     if status.contains(pcsc::Status::SPECIFIC) {
@@ -3,7 +3,11 @@
 // All rights reserved.
 // Authors: Adolfo Gómez, dkmaster at dkmon dot com
 
+pub(crate) mod filter;
 pub(crate) mod helpers;
+pub(crate) mod system;
+#[cfg(test)]
+mod tests;
 pub(crate) mod types;
 
 use std::time::Duration;
@@ -12,28 +16,219 @@ use pcsc::ffi::DWORD;
 use rdp::integrations::smartcard::*;
 
 use super::SmartcardBackend;
+use filter::{PNP_NOTIFICATION, ReaderFilter};
 use helpers::*;
-use types::NativeRegistry;
+use system::{PcscCard, PcscContext, PcscService, ReaderWait, SystemPcsc};
+use types::{NativeCard, NativeRegistry};
 
 const MAX_CERTIFICATE_SIZE: usize = 1024 * 1024;
 const MAX_GET_RESPONSE_ROUNDS: usize = 4096;
 
-#[derive(Debug)]
+/// Longest wait of a status change (see `get_status_change`).
+const STATUS_CHANGE_STEP: Duration = Duration::from_millis(100);
+
+/// Status change errors of readers that were unplugged: pcsc-lite fails with
+/// `SCARD_E_NO_READERS_AVAILABLE` once the last one is gone, WinSCard may fail
+/// with the others.
+const READER_GONE: [u32; 3] = [
+    NO_READERS_AVAILABLE,
+    SCARD_E_UNKNOWN_READER,
+    SCARD_E_READER_UNAVAILABLE,
+];
+
 pub(crate) struct NativeBackend {
+    service: Box<dyn PcscService>,
+    filter: ReaderFilter,
     registry: NativeRegistry,
 }
 
+impl std::fmt::Debug for NativeBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeBackend")
+            .field("filter", &self.filter)
+            .field("registry", &self.registry)
+            .finish()
+    }
+}
+
 impl NativeBackend {
-    pub fn new() -> Self {
+    /// The PC/SC service of the system, with the readers of the session filter.
+    pub fn new(filter: ReaderFilter) -> Self {
+        Self::with_service(Box::new(SystemPcsc), filter)
+    }
+
+    pub(crate) fn with_service(service: Box<dyn PcscService>, filter: ReaderFilter) -> Self {
         NativeBackend {
+            service,
+            filter,
             registry: NativeRegistry::new(),
         }
     }
+
+    /// Status change of the readers, with the filtered out ones as unknown.
+    fn wait_status_change(
+        &self,
+        ctx: &ScardContext,
+        timeout: Duration,
+        mut waits: Vec<ReaderWait>,
+    ) -> Result<(Vec<ReaderWait>, u32), u32> {
+        let contexts = self.registry.contexts.read().unwrap();
+        let pcsc_ctx = contexts.get(&ctx.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
+
+        // Filtered out readers look unknown, without asking PC/SC about them
+        let (visible, hidden): (Vec<usize>, Vec<usize>) =
+            (0..waits.len()).partition(|&i| self.filter.allows(&waits[i].reader));
+        let mut changed = false;
+        for &i in &hidden {
+            waits[i].event_state = unknown_reader_state(waits[i].current_state);
+            changed |= waits[i].event_state & SCARD_STATE_CHANGED != 0;
+        }
+
+        // Mimic the FreeRDP reference channel: never block the IRP thread for more
+        // than a small step (100ms). The caller re-polls on SCARD_E_TIMEOUT.
+        let step = if changed {
+            Duration::ZERO
+        } else {
+            timeout.min(STATUS_CHANGE_STEP)
+        };
+        let mut states: Vec<ReaderWait> = visible.iter().map(|&i| waits[i].clone()).collect();
+        let result = if states.is_empty() {
+            std::thread::sleep(step);
+            Err(SCARD_E_TIMEOUT)
+        } else {
+            match pcsc_ctx.get_status_change(step, &mut states) {
+                // An unplugged reader is an event too (its card is removed), so
+                // the session notices it and can lock
+                Err(code) if READER_GONE.contains(&code) => {
+                    Self::readers_gone(pcsc_ctx.as_ref(), &mut states)?;
+                    if states
+                        .iter()
+                        .any(|state| state.event_state & SCARD_STATE_CHANGED != 0)
+                    {
+                        Ok(())
+                    } else {
+                        std::thread::sleep(step);
+                        Err(SCARD_E_TIMEOUT)
+                    }
+                }
+                result => result,
+            }
+        };
+        for (i, state) in visible.into_iter().zip(states) {
+            waits[i] = state;
+        }
+
+        let code = match result {
+            Ok(()) => SCARD_S_SUCCESS,
+            Err(SCARD_E_TIMEOUT) if changed => SCARD_S_SUCCESS,
+            Err(SCARD_E_TIMEOUT) => SCARD_E_TIMEOUT,
+            Err(e) => return Err(e),
+        };
+        for wait in waits.iter_mut() {
+            // A removed card leaves no ATR behind
+            if wait.event_state & SCARD_STATE_EMPTY != 0 {
+                wait.atr.clear();
+            }
+        }
+        Ok((waits, code))
+    }
+
+    /// Reader states after `SCARD_E_NO_READERS_AVAILABLE` (or the like): the
+    /// readers not in the system any more are unknown (their card removed), the
+    /// state of the others is read again, without waiting.
+    fn readers_gone(pcsc_ctx: &dyn PcscContext, waits: &mut [ReaderWait]) -> Result<(), u32> {
+        let present = pcsc_ctx.list_readers()?;
+        let mut others = Vec::new();
+        for (i, wait) in waits.iter_mut().enumerate() {
+            if wait.reader == PNP_NOTIFICATION || present.contains(&wait.reader) {
+                wait.event_state = wait.current_state & !SCARD_STATE_CHANGED;
+                others.push(i);
+            } else {
+                log::debug!("smartcard native: reader {:?} is gone", wait.reader);
+                wait.event_state = unknown_reader_state(wait.current_state);
+                wait.atr.clear();
+            }
+        }
+        if others.is_empty() {
+            return Ok(());
+        }
+        let mut states: Vec<ReaderWait> = others.iter().map(|&i| waits[i].clone()).collect();
+        match pcsc_ctx.get_status_change(Duration::ZERO, &mut states) {
+            Ok(()) => {}
+            Err(code) if code == SCARD_E_TIMEOUT || READER_GONE.contains(&code) => {}
+            Err(code) => return Err(code),
+        }
+        for (i, state) in others.into_iter().zip(states) {
+            waits[i] = state;
+        }
+        Ok(())
+    }
+}
+
+/// GET DATA DF24 (certificate file) — same APDU msclmd issues — with its GET
+/// RESPONSE chaining, as a `Cached_GeneralFile` value.
+fn read_certificate(card: &dyn PcscCard) -> Result<Vec<u8>, u32> {
+    let apdu = [0x00, 0xCB, 0xA0, 0x10, 0x04, 0x5C, 0x02, 0xDF, 0x24, 0x00];
+    let mut full = card.transmit(&apdu).inspect_err(|e| {
+        log::debug!(
+            "smartcard native: get_certificate DF24 transmit error: {:#X}",
+            e
+        );
+    })?;
+
+    // GET RESPONSE chaining (61 XX)
+    for _ in 0..MAX_GET_RESPONSE_ROUNDS {
+        let len = full.len();
+        if len < 2 {
+            break;
+        }
+        let (sw1, sw2) = (full[len - 2], full[len - 1]);
+        if sw1 != 0x61 {
+            break;
+        }
+        let remaining = sw2 as usize;
+        full.truncate(len - 2);
+        let get_resp = [
+            0x00,
+            0xC0,
+            0x00,
+            0x00,
+            if remaining == 0 { 0x00 } else { sw2 },
+        ];
+        let r = card.transmit(&get_resp).inspect_err(|e| {
+            log::debug!(
+                "smartcard native: get_certificate GET RESPONSE error: {:#X}",
+                e
+            );
+        })?;
+        if full.len().saturating_add(r.len()) > MAX_CERTIFICATE_SIZE {
+            return Err(SCARD_E_INSUFFICIENT_BUFFER);
+        }
+        full.extend_from_slice(&r);
+    }
+
+    if full.len() >= 2 && full[full.len() - 2] == 0x61 {
+        return Err(SCARD_E_UNSUPPORTED_FEATURE);
+    }
+
+    let content = match strip_do(&full) {
+        Some(c) => c.to_vec(),
+        None => {
+            log::debug!("smartcard native: get_certificate could not parse DF24 DO");
+            return Err(SCARD_E_UNSUPPORTED_FEATURE);
+        }
+    };
+    log::debug!(
+        "smartcard native: get_certificate content {} bytes (resp {} bytes)",
+        content.len(),
+        full.len()
+    );
+    Ok(build_general_file_value(&content))
 }
 
 impl SmartcardBackend for NativeBackend {
     fn establish_context(&self, _scope: DWORD) -> Result<ScardContext, u32> {
-        let pcsc_ctx = pcsc::Context::establish(pcsc::Scope::System).map_err(pcsc_error_to_u32)?;
+        let pcsc_ctx = self.service.establish()?;
         let sc_ctx = ScardContext::new();
         let mut contexts = self.registry.contexts.write().unwrap();
         contexts.insert(sc_ctx.raw(), pcsc_ctx);
@@ -41,11 +236,12 @@ impl SmartcardBackend for NativeBackend {
     }
 
     fn release_context(&self, ctx: &ScardContext) -> Result<(), u32> {
-        let card_id = self.registry.ctx_cards.write().unwrap().remove(&ctx.raw());
+        let card_ids = self.registry.ctx_cards.write().unwrap().remove(&ctx.raw());
 
-        if let Some(card_id) = card_id {
-            if let Some((card, _)) = self.registry.cards.write().unwrap().remove(&card_id) {
-                let _ = card.disconnect(pcsc::Disposition::LeaveCard);
+        let mut cards = self.registry.cards.write().unwrap();
+        for card_id in card_ids.unwrap_or_default() {
+            if let Some(native) = cards.remove(&card_id) {
+                let _ = native.card.disconnect(pcsc::ffi::SCARD_LEAVE_CARD);
             }
         }
 
@@ -66,21 +262,9 @@ impl SmartcardBackend for NativeBackend {
     ) -> Result<Vec<String>, u32> {
         let contexts = self.registry.contexts.read().unwrap();
         let pcsc_ctx = contexts.get(&ctx.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
-
-        let mut readers_buf = [0u8; 4096];
-        let readers = match pcsc_ctx.list_readers(&mut readers_buf) {
-            Ok(r) => r,
-            Err(pcsc::Error::NoReadersAvailable) => return Ok(Vec::new()),
-            Err(e) => return Err(pcsc_error_to_u32(e)),
-        };
-
-        let mut result = Vec::new();
-        for reader in readers {
-            if let Ok(s) = reader.to_str() {
-                result.push(s.to_string());
-            }
-        }
-        Ok(result)
+        let mut readers = pcsc_ctx.list_readers()?;
+        readers.retain(|reader| self.filter.allows(reader));
+        Ok(readers)
     }
 
     fn connect(
@@ -90,36 +274,38 @@ impl SmartcardBackend for NativeBackend {
         share_mode: DWORD,
         preferred_protocols: DWORD,
     ) -> Result<ConnectResult, u32> {
+        if !self.filter.allows(reader) {
+            log::debug!("smartcard native: reader {:?} is not redirected", reader);
+            return Err(SCARD_E_UNKNOWN_READER);
+        }
         let contexts = self.registry.contexts.read().unwrap();
         let pcsc_ctx = contexts.get(&ctx.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
 
-        let share = dword_to_share_mode(share_mode)?;
-        let protos = dword_to_protocols(preferred_protocols);
-
-        let reader_c = std::ffi::CString::new(reader).map_err(|_| SCARD_E_INVALID_PARAMETER)?;
-        let card = pcsc_ctx
-            .connect(&reader_c, share, protos)
-            .map_err(pcsc_error_to_u32)?;
-
-        let mut r_buf = [0u8; 256];
-        let mut a_buf = [0u8; 36];
-        let active_proto = match card.status2(&mut r_buf, &mut a_buf) {
-            Ok(status) => protocol_to_u32(status.protocol()),
-            Err(err) => {
-                let code = pcsc_error_to_u32(err);
-                let _ = card.disconnect(pcsc::Disposition::LeaveCard);
+        let card = pcsc_ctx.connect(reader, share_mode, preferred_protocols)?;
+        let active_proto = match card.status() {
+            Ok(status) => status.protocol,
+            Err(code) => {
+                let _ = card.disconnect(pcsc::ffi::SCARD_LEAVE_CARD);
                 return Err(code);
             }
         };
 
         let handle = ScardHandle::new(active_proto);
         let mut cards = self.registry.cards.write().unwrap();
-        cards.insert(handle.raw(), (card, reader.to_string()));
+        cards.insert(
+            handle.raw(),
+            NativeCard {
+                card,
+                reader: reader.to_string(),
+            },
+        );
         self.registry
             .ctx_cards
             .write()
             .unwrap()
-            .insert(ctx.raw(), handle.raw());
+            .entry(ctx.raw())
+            .or_default()
+            .push(handle.raw());
 
         Ok(ConnectResult {
             handle,
@@ -128,17 +314,12 @@ impl SmartcardBackend for NativeBackend {
     }
 
     fn disconnect(&self, handle: &ScardHandle, disposition: DWORD) -> Result<(), u32> {
-        let disp = dword_to_disposition(disposition)?;
         let mut cards = self.registry.cards.write().unwrap();
-        if let Some((card, _)) = cards.remove(&handle.raw()) {
-            self.registry
-                .ctx_cards
-                .write()
-                .unwrap()
-                .retain(|_, card_id| *card_id != handle.raw());
-            card.disconnect(disp)
-                .map_err(|(_, err)| pcsc_error_to_u32(err))?;
-            Ok(())
+        if let Some(native) = cards.remove(&handle.raw()) {
+            for card_ids in self.registry.ctx_cards.write().unwrap().values_mut() {
+                card_ids.retain(|card_id| *card_id != handle.raw());
+            }
+            native.card.disconnect(disposition)
         } else {
             Err(SCARD_E_INVALID_HANDLE)
         }
@@ -151,42 +332,20 @@ impl SmartcardBackend for NativeBackend {
         preferred_protocols: DWORD,
         initialization: DWORD,
     ) -> Result<u32, u32> {
-        let share = dword_to_share_mode(share_mode)?;
-        let protos = dword_to_protocols(preferred_protocols);
-        let init = dword_to_disposition(initialization)?;
-
         let mut cards = self.registry.cards.write().unwrap();
-        let (card, _) = cards.get_mut(&handle.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
-
-        card.reconnect(share, protos, init)
-            .map_err(pcsc_error_to_u32)?;
-
-        let mut r_buf = [0u8; 256];
-        let mut a_buf = [0u8; 36];
-        let new_proto = card
-            .status2(&mut r_buf, &mut a_buf)
-            .map(|status| protocol_to_u32(status.protocol()))
-            .map_err(pcsc_error_to_u32)?;
-        Ok(new_proto)
+        let native = cards.get_mut(&handle.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
+        native
+            .card
+            .reconnect(share_mode, preferred_protocols, initialization)
     }
 
-    fn transmit(
-        &self,
-        handle: &ScardHandle,
-        _send_pci: &ScardIORequest,
-        data: &[u8],
-    ) -> Result<TransmitResult, u32> {
+    fn transmit(&self, handle: &ScardHandle, data: &[u8]) -> Result<TransmitResult, u32> {
         let cards = self.registry.cards.read().unwrap();
-        let (card, _) = cards.get(&handle.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
-
-        let mut recv_buf = vec![0u8; SCARD_TRANSMIT_MAX];
-        let resp_slice = card
-            .transmit(data, &mut recv_buf)
-            .map_err(pcsc_error_to_u32)?;
+        let native = cards.get(&handle.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
 
         Ok(TransmitResult {
             recv_pci: None,
-            recv_buffer: resp_slice.to_vec(),
+            recv_buffer: native.card.transmit(data)?,
         })
     }
 
@@ -197,31 +356,20 @@ impl SmartcardBackend for NativeBackend {
         in_data: &[u8],
     ) -> Result<Vec<u8>, u32> {
         let cards = self.registry.cards.read().unwrap();
-        let (card, _) = cards.get(&handle.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
-
-        let mut out_buf = vec![0u8; 4096];
-        let resp_slice = card
-            .control(control_code, in_data, &mut out_buf)
-            .map_err(pcsc_error_to_u32)?;
-
-        Ok(resp_slice.to_vec())
+        let native = cards.get(&handle.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
+        native.card.control(control_code, in_data)
     }
 
     fn status(&self, handle: &ScardHandle) -> Result<ScardStatus, u32> {
         let cards = self.registry.cards.read().unwrap();
-        let (card, reader_name) = cards.get(&handle.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
-
-        let mut r_buf = [0u8; 256];
-        let mut a_buf = [0u8; 36];
-        let status = card
-            .status2(&mut r_buf, &mut a_buf)
-            .map_err(pcsc_error_to_u32)?;
+        let native = cards.get(&handle.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
+        let status = native.card.status()?;
 
         Ok(ScardStatus {
-            reader_names: vec![reader_name.clone()],
-            state: pcsc_status_to_u32(status.status()),
-            protocol: protocol_to_u32(status.protocol()),
-            atr: status.atr().to_vec(),
+            reader_names: vec![native.reader.clone()],
+            state: status.state,
+            protocol: status.protocol,
+            atr: status.atr,
         })
     }
 
@@ -231,51 +379,27 @@ impl SmartcardBackend for NativeBackend {
         timeout: Duration,
         reader_states: &[ReaderStateIn],
     ) -> Result<(Vec<ReaderStateOut>, u32), u32> {
-        let mut pcsc_states = Vec::new();
-        for rs in reader_states {
-            let cstr = std::ffi::CString::new(rs.reader_name.as_str())
-                .map_err(|_| SCARD_E_INVALID_PARAMETER)?;
-            let current_state = dword_to_state(rs.current_state.into());
-            pcsc_states.push(pcsc::ReaderState::new(cstr, current_state));
-        }
-
-        let contexts = self.registry.contexts.read().unwrap();
-        let pcsc_ctx = contexts.get(&ctx.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
-
-        // Mimic the FreeRDP reference channel: never block the IRP thread for more
-        // than a small step (100ms). The caller re-polls on SCARD_E_TIMEOUT.
-        let step = timeout.min(Duration::from_millis(100));
-        let gsc_result = pcsc_ctx.get_status_change(step, &mut pcsc_states);
-
-        let mut results = Vec::new();
-        for (i, rs) in reader_states.iter().enumerate() {
-            let out_state = &pcsc_states[i];
-            // Read the raw SCARD_READERSTATE: the pcsc crate's event_state()
-            // uses State::from_bits_truncate which drops the 0x0001_0000 bit
-            // that Windows sets in reader states (e.g. for \\?PnP?\Notification).
-            // The native FreeRDP channel passes these bits through verbatim, and
-            // the remote SCardSvr expects them.
-            let raw: &pcsc::ffi::SCARD_READERSTATE = unsafe { std::mem::transmute(out_state) };
-            let atr_len = (raw.cbAtr as usize).min(raw.rgbAtr.len());
-            results.push(ReaderStateOut {
-                reader_name: rs.reader_name.clone(),
-                current_state: rs.current_state,
-                event_state: raw.dwEventState as u32,
-                atr: raw.rgbAtr[..atr_len].to_vec(),
-            });
-        }
-
-        match gsc_result {
-            Ok(()) => Ok((results, SCARD_S_SUCCESS)),
-            Err(pcsc::Error::Timeout) => Ok((results, SCARD_E_TIMEOUT)),
-            Err(e) => Err(pcsc_error_to_u32(e)),
-        }
+        let waits = reader_states
+            .iter()
+            .map(|rs| ReaderWait::new(&rs.reader_name, rs.current_state))
+            .collect();
+        let (waits, code) = self.wait_status_change(ctx, timeout, waits)?;
+        let results = waits
+            .into_iter()
+            .map(|wait| ReaderStateOut {
+                reader_name: wait.reader,
+                current_state: wait.current_state,
+                event_state: wait.event_state,
+                atr: wait.atr,
+            })
+            .collect();
+        Ok((results, code))
     }
 
     fn cancel(&self, ctx: &ScardContext) -> Result<(), u32> {
         let contexts = self.registry.contexts.read().unwrap();
         let pcsc_ctx = contexts.get(&ctx.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
-        pcsc_ctx.cancel().map_err(pcsc_error_to_u32)
+        pcsc_ctx.cancel()
     }
 
     fn begin_transaction(&self, _handle: &ScardHandle) -> Result<(), u32> {
@@ -289,24 +413,15 @@ impl SmartcardBackend for NativeBackend {
     }
 
     fn get_attrib(&self, handle: &ScardHandle, attr_id: DWORD) -> Result<Vec<u8>, u32> {
-        let attribute = dword_to_attribute(attr_id)?;
         let cards = self.registry.cards.read().unwrap();
-        let (card, _) = cards.get(&handle.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
-
-        let mut buf = vec![0u8; 1024];
-        let slice = card
-            .get_attribute(attribute, &mut buf)
-            .map_err(pcsc_error_to_u32)?;
-        Ok(slice.to_vec())
+        let native = cards.get(&handle.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
+        native.card.get_attrib(attr_id)
     }
 
     fn set_attrib(&self, handle: &ScardHandle, attr_id: DWORD, data: &[u8]) -> Result<(), u32> {
-        let attribute = dword_to_attribute(attr_id)?;
         let cards = self.registry.cards.read().unwrap();
-        let (card, _) = cards.get(&handle.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
-        card.set_attribute(attribute, data)
-            .map_err(pcsc_error_to_u32)?;
-        Ok(())
+        let native = cards.get(&handle.raw()).ok_or(SCARD_E_INVALID_HANDLE)?;
+        native.card.set_attrib(attr_id, data)
     }
 
     fn get_container_info(
@@ -338,88 +453,29 @@ impl SmartcardBackend for NativeBackend {
         Err(SCARD_E_UNSUPPORTED_FEATURE)
     }
 
+    /// Certificate of the first card of the context that has one (the context may
+    /// hold cards of several readers).
     fn get_certificate(&self, ctx: &ScardContext) -> Result<Vec<u8>, u32> {
-        let handle_id = self
+        let card_ids = self
             .registry
             .ctx_cards
             .read()
             .unwrap()
             .get(&ctx.raw())
-            .copied()
-            .ok_or(SCARD_E_INVALID_HANDLE)?;
+            .cloned()
+            .unwrap_or_default();
         let cards = self.registry.cards.read().unwrap();
-        let (card, _) = cards.get(&handle_id).ok_or(SCARD_E_INVALID_HANDLE)?;
-
-        // GET DATA DF24 (certificate file) — same APDU msclmd issues.
-        let apdu = [0x00, 0xCB, 0xA0, 0x10, 0x04, 0x5C, 0x02, 0xDF, 0x24, 0x00];
-        let mut buf = vec![0u8; 4096];
-        let mut full = match card.transmit(&apdu, &mut buf) {
-            Ok(r) => r.to_vec(),
-            Err(e) => {
-                log::debug!(
-                    "smartcard native: get_certificate DF24 transmit error: {:?}",
-                    e
-                );
-                return Err(pcsc_error_to_u32(e));
-            }
-        };
-
-        // GET RESPONSE chaining (61 XX)
-        for _ in 0..MAX_GET_RESPONSE_ROUNDS {
-            let len = full.len();
-            if len < 2 {
-                break;
-            }
-            let (sw1, sw2) = (full[len - 2], full[len - 1]);
-            if sw1 != 0x61 {
+        let mut result = Err(SCARD_E_INVALID_HANDLE);
+        for native in card_ids.iter().filter_map(|id| cards.get(id)) {
+            result = read_certificate(native.card.as_ref());
+            if result.is_ok() {
                 break;
             }
-            let remaining = sw2 as usize;
-            full.truncate(len - 2);
-            let get_resp = [
-                0x00,
-                0xC0,
-                0x00,
-                0x00,
-                if remaining == 0 { 0x00 } else { sw2 },
-            ];
-            match card.transmit(&get_resp, &mut buf) {
-                Ok(r) => {
-                    if full.len().saturating_add(r.len()) > MAX_CERTIFICATE_SIZE {
-                        return Err(SCARD_E_INSUFFICIENT_BUFFER);
-                    }
-                    full.extend_from_slice(r);
-                }
-                Err(e) => {
-                    log::debug!(
-                        "smartcard native: get_certificate GET RESPONSE error: {:?}",
-                        e
-                    );
-                    return Err(pcsc_error_to_u32(e));
-                }
-            }
-        }
-
-        if full.len() >= 2 && full[full.len() - 2] == 0x61 {
-            return Err(SCARD_E_UNSUPPORTED_FEATURE);
         }
-
-        let content = match strip_do(&full) {
-            Some(c) => c.to_vec(),
-            None => {
-                log::debug!("smartcard native: get_certificate could not parse DF24 DO");
-                return Err(SCARD_E_UNSUPPORTED_FEATURE);
-            }
-        };
-        log::debug!(
-            "smartcard native: get_certificate content {} bytes (resp {} bytes)",
-            content.len(),
-            full.len()
-        );
-        Ok(build_general_file_value(&content))
+        result
     }
 
     fn is_available(&self) -> bool {
-        pcsc::Context::establish(pcsc::Scope::System).is_ok()
+        self.service.establish().is_ok()
     }
 }
@@ -0,0 +1,210 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! The PC/SC service under `NativeBackend`.
+//!
+//! The backend talks to the service through [`PcscService`], [`PcscContext`] and
+//! [`PcscCard`], with SCard error codes, so the tests can run it against a fake
+//! service with several readers and cards. [`SystemPcsc`] is the real one
+//! (pcsc-lite, WinSCard or the macOS PC/SC framework, through the `pcsc` crate).
+
+use std::ffi::CString;
+use std::time::Duration;
+
+use pcsc::ffi::DWORD;
+use rdp::integrations::smartcard::*;
+
+use super::helpers::*;
+
+/// State of a reader in a status change wait.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct ReaderWait {
+    pub reader: String,
+    pub current_state: u32,
+    /// Set by the wait.
+    pub event_state: u32,
+    pub atr: Vec<u8>,
+}
+
+impl ReaderWait {
+    pub fn new(reader: &str, current_state: u32) -> Self {
+        ReaderWait {
+            reader: reader.to_string(),
+            current_state,
+            event_state: 0,
+            atr: Vec::new(),
+        }
+    }
+}
+
+/// State, active protocol and ATR of a connected card.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct CardState {
+    pub state: u32,
+    pub protocol: u32,
+    pub atr: Vec<u8>,
+}
+
+pub(crate) trait PcscService: Send + Sync {
+    fn establish(&self) -> Result<Box<dyn PcscContext>, u32>;
+}
+
+pub(crate) trait PcscContext: Send + Sync {
+    /// Readers of the system, empty if there is none.
+    fn list_readers(&self) -> Result<Vec<String>, u32>;
+    fn connect(
+        &self,
+        reader: &str,
+        share_mode: DWORD,
+        preferred_protocols: DWORD,
+    ) -> Result<Box<dyn PcscCard>, u32>;
+    /// Wait for a change in the readers, `SCARD_E_TIMEOUT` if there was none.
+    fn get_status_change(&self, timeout: Duration, readers: &mut [ReaderWait]) -> Result<(), u32>;
+    fn cancel(&self) -> Result<(), u32>;
+}
+
+pub(crate) trait PcscCard: Send + Sync {
+    fn status(&self) -> Result<CardState, u32>;
+    fn transmit(&self, apdu: &[u8]) -> Result<Vec<u8>, u32>;
+    fn control(&self, control_code: DWORD, in_data: &[u8]) -> Result<Vec<u8>, u32>;
+    /// Returns the active protocol.
+    fn reconnect(
+        &mut self,
+        share_mode: DWORD,
+        preferred_protocols: DWORD,
+        initialization: DWORD,
+    ) -> Result<u32, u32>;
+    fn disconnect(self: Box<Self>, disposition: DWORD) -> Result<(), u32>;
+    fn get_attrib(&self, attr_id: DWORD) -> Result<Vec<u8>, u32>;
+    fn set_attrib(&self, attr_id: DWORD, data: &[u8]) -> Result<(), u32>;
+}
+
+/// The PC/SC service of the system.
+pub(crate) struct SystemPcsc;
+
+impl PcscService for SystemPcsc {
+    fn establish(&self) -> Result<Box<dyn PcscContext>, u32> {
+        let ctx = pcsc::Context::establish(pcsc::Scope::System).map_err(pcsc_error_to_u32)?;
+        Ok(Box::new(ctx))
+    }
+}
+
+impl PcscContext for pcsc::Context {
+    fn list_readers(&self) -> Result<Vec<String>, u32> {
+        let mut readers_buf = [0u8; 4096];
+        let readers = match pcsc::Context::list_readers(self, &mut readers_buf) {
+            Ok(r) => r,
+            Err(pcsc::Error::NoReadersAvailable) => return Ok(Vec::new()),
+            Err(e) => return Err(pcsc_error_to_u32(e)),
+        };
+        Ok(readers
+            .filter_map(|reader| reader.to_str().ok())
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn connect(
+        &self,
+        reader: &str,
+        share_mode: DWORD,
+        preferred_protocols: DWORD,
+    ) -> Result<Box<dyn PcscCard>, u32> {
+        let share = dword_to_share_mode(share_mode)?;
+        let protos = dword_to_protocols(preferred_protocols);
+        let reader_c = CString::new(reader).map_err(|_| SCARD_E_INVALID_PARAMETER)?;
+        let card =
+            pcsc::Context::connect(self, &reader_c, share, protos).map_err(pcsc_error_to_u32)?;
+        Ok(Box::new(card))
+    }
+
+    fn get_status_change(&self, timeout: Duration, readers: &mut [ReaderWait]) -> Result<(), u32> {
+        let mut pcsc_states = Vec::with_capacity(readers.len());
+        for rs in readers.iter() {
+            let cstr = CString::new(rs.reader.as_str()).map_err(|_| SCARD_E_INVALID_PARAMETER)?;
+            let current_state = dword_to_state(rs.current_state.into());
+            pcsc_states.push(pcsc::ReaderState::new(cstr, current_state));
+        }
+
+        let result = pcsc::Context::get_status_change(self, timeout, &mut pcsc_states);
+
+        for (rs, out_state) in readers.iter_mut().zip(&pcsc_states) {
+            // Read the raw SCARD_READERSTATE: the pcsc crate's event_state()
+            // uses State::from_bits_truncate which drops the 0x0001_0000 bit
+            // that Windows sets in reader states (e.g. for \\?PnP?\Notification).
+            // The native FreeRDP channel passes these bits through verbatim, and
+            // the remote SCardSvr expects them.
+            let raw: &pcsc::ffi::SCARD_READERSTATE = unsafe { std::mem::transmute(out_state) };
+            let atr_len = (raw.cbAtr as usize).min(raw.rgbAtr.len());
+            rs.event_state = raw.dwEventState as u32;
+            rs.atr = raw.rgbAtr[..atr_len].to_vec();
+        }
+        result.map_err(pcsc_error_to_u32)
+    }
+
+    fn cancel(&self) -> Result<(), u32> {
+        pcsc::Context::cancel(self).map_err(pcsc_error_to_u32)
+    }
+}
+
+impl PcscCard for pcsc::Card {
+    fn status(&self) -> Result<CardState, u32> {
+        let mut r_buf = [0u8; 256];
+        let mut a_buf = [0u8; 36];
+        let status = self
+            .status2(&mut r_buf, &mut a_buf)
+            .map_err(pcsc_error_to_u32)?;
+        Ok(CardState {
+            state: pcsc_status_to_u32(status.status()),
+            protocol: protocol_to_u32(status.protocol()),
+            atr: status.atr().to_vec(),
+        })
+    }
+
+    fn transmit(&self, apdu: &[u8]) -> Result<Vec<u8>, u32> {
+        let mut recv_buf = vec![0u8; SCARD_TRANSMIT_MAX];
+        pcsc::Card::transmit(self, apdu, &mut recv_buf)
+            .map(<[u8]>::to_vec)
+            .map_err(pcsc_error_to_u32)
+    }
+
+    fn control(&self, control_code: DWORD, in_data: &[u8]) -> Result<Vec<u8>, u32> {
+        let mut out_buf = vec![0u8; 4096];
+        pcsc::Card::control(self, control_code, in_data, &mut out_buf)
+            .map(<[u8]>::to_vec)
+            .map_err(pcsc_error_to_u32)
+    }
+
+    fn reconnect(
+        &mut self,
+        share_mode: DWORD,
+        preferred_protocols: DWORD,
+        initialization: DWORD,
+    ) -> Result<u32, u32> {
+        let share = dword_to_share_mode(share_mode)?;
+        let protos = dword_to_protocols(preferred_protocols);
+        let init = dword_to_disposition(initialization)?;
+        pcsc::Card::reconnect(self, share, protos, init).map_err(pcsc_error_to_u32)?;
+        PcscCard::status(self).map(|status| status.protocol)
+    }
+
+    fn disconnect(self: Box<Self>, disposition: DWORD) -> Result<(), u32> {
+        let disp = dword_to_disposition(disposition)?;
+        pcsc::Card::disconnect(*self, disp).map_err(|(_, err)| pcsc_error_to_u32(err))
+    }
+
+    fn get_attrib(&self, attr_id: DWORD) -> Result<Vec<u8>, u32> {
+        let attribute = dword_to_attribute(attr_id)?;
+        let mut buf = vec![0u8; 1024];
+        self.get_attribute(attribute, &mut buf)
+            .map(<[u8]>::to_vec)
+            .map_err(pcsc_error_to_u32)
+    }
+
+    fn set_attrib(&self, attr_id: DWORD, data: &[u8]) -> Result<(), u32> {
+        let attribute = dword_to_attribute(attr_id)?;
+        self.set_attribute(attribute, data)
+            .map_err(pcsc_error_to_u32)
+    }
+}
@@ -0,0 +1,467 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! Tests for the native backend, against an in-process fake PC/SC service.
+
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use pcsc::ffi::DWORD;
+    use rdp::integrations::smartcard::*;
+
+    use crate::smartcard::SmartcardBackend;
+    use crate::smartcard::native::NativeBackend;
+    use crate::smartcard::native::filter::{PNP_NOTIFICATION, ReaderFilter};
+    use crate::smartcard::native::helpers::NO_READERS_AVAILABLE;
+    use crate::smartcard::native::system::{
+        CardState, PcscCard, PcscContext, PcscService, ReaderWait,
+    };
+    use crate::smartcard::trace::SharedBuffer;
+    use crate::smartcard::trace::recorder::RecordingBackend;
+    use crate::smartcard::trace::replay::{ReplayBackend, check_responses};
+
+    const SHARE_SHARED: DWORD = 2;
+    const PROTOCOLS: DWORD = 3;
+    const LEAVE_CARD: DWORD = 0;
+
+    /// Readers of the fake service, with the ATR of the card in them.
+    #[derive(Default)]
+    struct FakeSystem {
+        readers: BTreeMap<String, Option<Vec<u8>>>,
+        /// Readers of the cards disconnected, in order.
+        disconnected: Vec<String>,
+    }
+
+    /// PC/SC service with the status change rules of pcsc-lite.
+    #[derive(Clone, Default)]
+    struct FakePcsc(Arc<Mutex<FakeSystem>>);
+
+    impl FakePcsc {
+        fn with_readers(readers: &[(&str, Option<&[u8]>)]) -> Self {
+            let fake = FakePcsc::default();
+            for (reader, atr) in readers {
+                fake.plug(reader, *atr);
+            }
+            fake
+        }
+
+        /// Plug a reader, or change its card.
+        fn plug(&self, reader: &str, atr: Option<&[u8]>) {
+            let mut system = self.0.lock().unwrap();
+            system
+                .readers
+                .insert(reader.to_string(), atr.map(<[u8]>::to_vec));
+        }
+
+        fn unplug(&self, reader: &str) {
+            self.0.lock().unwrap().readers.remove(reader);
+        }
+
+        fn disconnected(&self) -> Vec<String> {
+            self.0.lock().unwrap().disconnected.clone()
+        }
+
+        fn backend(&self, filter: ReaderFilter) -> NativeBackend {
+            NativeBackend::with_service(Box::new(self.clone()), filter)
+        }
+    }
+
+    impl PcscService for FakePcsc {
+        fn establish(&self) -> Result<Box<dyn PcscContext>, u32> {
+            Ok(Box::new(self.clone()))
+        }
+    }
+
+    impl PcscContext for FakePcsc {
+        fn list_readers(&self) -> Result<Vec<String>, u32> {
+            Ok(self.0.lock().unwrap().readers.keys().cloned().collect())
+        }
+
+        fn connect(&self, reader: &str, _: DWORD, _: DWORD) -> Result<Box<dyn PcscCard>, u32> {
+            match self.0.lock().unwrap().readers.get(reader) {
+                None => Err(SCARD_E_UNKNOWN_READER),
+                Some(None) => Err(SCARD_E_NO_SMARTCARD),
+                Some(Some(atr)) => Ok(Box::new(FakeCard {
+                    reader: reader.to_string(),
+                    atr: atr.clone(),
+                    system: self.clone(),
+                })),
+            }
+        }
+
+        fn get_status_change(&self, _: Duration, readers: &mut [ReaderWait]) -> Result<(), u32> {
+            let system = self.0.lock().unwrap();
+            if system.readers.is_empty() && !readers.iter().any(|r| r.reader == PNP_NOTIFICATION) {
+                return Err(NO_READERS_AVAILABLE);
+            }
+            let mut changed = false;
+            for wait in readers.iter_mut() {
+                let (state, atr) = match system.readers.get(&wait.reader) {
+                    _ if wait.reader == PNP_NOTIFICATION => {
+                        ((system.readers.len() as u32) << 16, None)
+                    }
+                    None => (SCARD_STATE_UNKNOWN, None),
+                    Some(None) => (SCARD_STATE_EMPTY, None),
+                    Some(Some(atr)) => (SCARD_STATE_PRESENT, Some(atr.clone())),
+                };
+                wait.event_state = state;
+                // pcsc-lite keeps the last ATR of an empty reader
+                if let Some(atr) = atr {
+                    wait.atr = atr;
+                }
+                if state != wait.current_state & !SCARD_STATE_CHANGED {
+                    wait.event_state |= SCARD_STATE_CHANGED;
+                    changed = true;
+                }
+            }
+            if changed {
+                Ok(())
+            } else {
+                Err(SCARD_E_TIMEOUT)
+            }
+        }
+
+        fn cancel(&self) -> Result<(), u32> {
+            Ok(())
+        }
+    }
+
+    /// A card answers every command with the name of its reader, and the
+    /// certificate (GET DATA DF24) is that name too.
+    struct FakeCard {
+        reader: String,
+        atr: Vec<u8>,
+        system: FakePcsc,
+    }
+
+    impl FakeCard {
+        fn inserted(&self) -> Result<(), u32> {
+            match self.system.0.lock().unwrap().readers.get(&self.reader) {
+                Some(Some(atr)) if *atr == self.atr => Ok(()),
+                _ => Err(SCARD_W_REMOVED_CARD),
+            }
+        }
+    }
+
+    impl PcscCard for FakeCard {
+        fn status(&self) -> Result<CardState, u32> {
+            self.inserted()?;
+            Ok(CardState {
+                state: SCARD_STATE_PRESENT,
+                protocol: SCARD_PROTOCOL_T1,
+                atr: self.atr.clone(),
+            })
+        }
+
+        fn transmit(&self, apdu: &[u8]) -> Result<Vec<u8>, u32> {
+            self.inserted()?;
+            let name = self.reader.as_bytes();
+            let mut response = match apdu {
+                [_, 0xCB, ..] => vec![0xDF, 0x24, name.len() as u8],
+                _ => Vec::new(),
+            };
+            response.extend_from_slice(name);
+            response.extend_from_slice(&[0x90, 0x00]);
+            Ok(response)
+        }
+
+        fn control(&self, _: DWORD, _: &[u8]) -> Result<Vec<u8>, u32> {
+            self.inserted().map(|()| Vec::new())
+        }
+
+        fn reconnect(&mut self, _: DWORD, _: DWORD, _: DWORD) -> Result<u32, u32> {
+            self.inserted().map(|()| SCARD_PROTOCOL_T1)
+        }
+
+        fn disconnect(self: Box<Self>, _: DWORD) -> Result<(), u32> {
+            let mut system = self.system.0.lock().unwrap();
+            system.disconnected.push(self.reader.clone());
+            Ok(())
+        }
+
+        fn get_attrib(&self, _: DWORD) -> Result<Vec<u8>, u32> {
+            self.inserted().map(|()| self.atr.clone())
+        }
+
+        fn set_attrib(&self, _: DWORD, _: &[u8]) -> Result<(), u32> {
+            self.inserted()
+        }
+    }
+
+    const ATR_A: &[u8] = &[0x3B, 0x8A, 0x01];
+    const ATR_B: &[u8] = &[0x3B, 0x8B, 0x02];
+
+    /// Certificate value of a fake card (see `FakeCard`).
+    fn certificate_of(reader: &str) -> Vec<u8> {
+        crate::smartcard::native::helpers::build_general_file_value(reader.as_bytes())
+    }
+
+    fn wait(
+        backend: &NativeBackend,
+        ctx: &ScardContext,
+        readers: &[(&str, u32)],
+    ) -> (Vec<ReaderWait>, u32) {
+        let waits = readers
+            .iter()
+            .map(|&(reader, state)| ReaderWait::new(reader, state))
+            .collect();
+        backend
+            .wait_status_change(ctx, Duration::from_millis(1), waits)
+            .unwrap()
+    }
+
+    #[test]
+    fn readers_are_filtered() {
+        let fake = FakePcsc::with_readers(&[
+            ("Integrated Smart Card Reader 00", Some(ATR_A)),
+            ("Yubico YubiKey CCID 01", Some(ATR_B)),
+            ("Generic USB Reader 02", None),
+        ]);
+        let list = |filter: ReaderFilter| {
+            let backend = fake.backend(filter);
+            let ctx = backend.establish_context(0).unwrap();
+            backend.list_readers(&ctx, None).unwrap()
+        };
+        assert_eq!(list(ReaderFilter::default()).len(), 3);
+        let deny = ReaderFilter {
+            deny: vec!["integrated".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            list(deny.clone()),
+            vec!["Generic USB Reader 02", "Yubico YubiKey CCID 01"]
+        );
+        let allow = ReaderFilter {
+            allow: vec!["YubiKey".to_string(), "Generic".to_string()],
+            deny: vec!["USB".to_string()],
+        };
+        assert_eq!(list(allow), vec!["Yubico YubiKey CCID 01"]);
+
+        // A hidden reader cannot be connected to, and looks unknown
+        let backend = fake.backend(deny);
+        let ctx = backend.establish_context(0).unwrap();
+        let connect = |reader| backend.connect(&ctx, reader, SHARE_SHARED, PROTOCOLS);
+        assert!(matches!(
+            connect("Integrated Smart Card Reader 00"),
+            Err(SCARD_E_UNKNOWN_READER)
+        ));
+        assert!(connect("Yubico YubiKey CCID 01").is_ok());
+        let (states, code) = wait(
+            &backend,
+            &ctx,
+            &[
+                ("Integrated Smart Card Reader 00", 0),
+                ("Yubico YubiKey CCID 01", SCARD_STATE_PRESENT),
+            ],
+        );
+        assert_eq!(code, SCARD_S_SUCCESS);
+        assert_eq!(
+            states[0].event_state,
+            SCARD_STATE_UNKNOWN | SCARD_STATE_CHANGED
+        );
+        assert_eq!(states[1].event_state, SCARD_STATE_PRESENT);
+        let (states, code) = wait(
+            &backend,
+            &ctx,
+            &[("Integrated Smart Card Reader 00", SCARD_STATE_UNKNOWN)],
+        );
+        assert_eq!(code, SCARD_E_TIMEOUT);
+        assert_eq!(states[0].event_state, SCARD_STATE_UNKNOWN);
+
+        // The PnP pseudo-reader is never filtered out
+        let only = fake.backend(ReaderFilter {
+            allow: vec!["nothing like it".to_string()],
+            ..Default::default()
+        });
+        let ctx = only.establish_context(0).unwrap();
+        let (states, _) = wait(&only, &ctx, &[(PNP_NOTIFICATION, 0)]);
+        assert_eq!(states[0].event_state, (3 << 16) | SCARD_STATE_CHANGED);
+    }
+
+    #[test]
+    fn card_insertion_and_removal_are_events() {
+        let reader = "Reader 0";
+        let fake = FakePcsc::with_readers(&[(reader, None)]);
+        let backend = fake.backend(ReaderFilter::default());
+        let ctx = backend.establish_context(0).unwrap();
+
+        let (states, code) = wait(&backend, &ctx, &[(reader, 0)]);
+        assert_eq!(code, SCARD_S_SUCCESS);
+        assert_eq!(
+            states[0].event_state,
+            SCARD_STATE_EMPTY | SCARD_STATE_CHANGED
+        );
+        let (_, code) = wait(&backend, &ctx, &[(reader, SCARD_STATE_EMPTY)]);
+        assert_eq!(code, SCARD_E_TIMEOUT);
+
+        // Inserted
+        fake.plug(reader, Some(ATR_A));
+        let (states, code) = wait(&backend, &ctx, &[(reader, SCARD_STATE_EMPTY)]);
+        assert_eq!(code, SCARD_S_SUCCESS);
+        assert_eq!(
+            states[0].event_state,
+            SCARD_STATE_PRESENT | SCARD_STATE_CHANGED
+        );
+        assert_eq!(states[0].atr, ATR_A);
+        let handle = backend
+            .connect(&ctx, reader, SHARE_SHARED, PROTOCOLS)
+            .unwrap()
+            .handle;
+        assert_eq!(backend.status(&handle).unwrap().atr, ATR_A);
+
+        // Removed: no ATR left, and the handle fails
+        fake.plug(reader, None);
+        let (states, code) = wait(&backend, &ctx, &[(reader, SCARD_STATE_PRESENT)]);
+        assert_eq!(code, SCARD_S_SUCCESS);
+        assert_eq!(
+            states[0].event_state,
+            SCARD_STATE_EMPTY | SCARD_STATE_CHANGED
+        );
+        assert!(states[0].atr.is_empty());
+        assert!(matches!(backend.status(&handle), Err(SCARD_W_REMOVED_CARD)));
+    }
+
+    #[test]
+    fn unplugged_readers_are_events() {
+        let fake = FakePcsc::with_readers(&[("Reader 0", Some(ATR_A)), ("Reader 1", Some(ATR_B))]);
+        let backend = fake.backend(ReaderFilter::default());
+        let ctx = backend.establish_context(0).unwrap();
+        let present = [
+            ("Reader 0", SCARD_STATE_PRESENT),
+            ("Reader 1", SCARD_STATE_PRESENT),
+        ];
+        let (_, code) = wait(&backend, &ctx, &present);
+        assert_eq!(code, SCARD_E_TIMEOUT);
+
+        fake.unplug("Reader 1");
+        let (states, code) = wait(&backend, &ctx, &present);
+        assert_eq!(code, SCARD_S_SUCCESS);
+        assert_eq!(states[0].event_state, SCARD_STATE_PRESENT);
+        assert_eq!(
+            states[1].event_state,
+            SCARD_STATE_UNKNOWN | SCARD_STATE_CHANGED
+        );
+
+        // The last one: pcsc-lite fails the wait, the card is removed all the same
+        fake.unplug("Reader 0");
+        let (states, code) = wait(&backend, &ctx, &present[..1]);
+        assert_eq!(code, SCARD_S_SUCCESS);
+        assert_eq!(
+            states[0].event_state,
+            SCARD_STATE_UNKNOWN | SCARD_STATE_CHANGED
+        );
+        assert!(states[0].atr.is_empty());
+        let (_, code) = wait(&backend, &ctx, &[("Reader 0", SCARD_STATE_UNKNOWN)]);
+        assert_eq!(code, SCARD_E_TIMEOUT);
+    }
+
+    #[test]
+    fn several_cards_per_context() {
+        let fake = FakePcsc::with_readers(&[("Reader 0", Some(ATR_A)), ("Reader 1", Some(ATR_B))]);
+        let backend = fake.backend(ReaderFilter::default());
+        let ctx = backend.establish_context(0).unwrap();
+        let other_ctx = backend.establish_context(0).unwrap();
+        let connect = |ctx, reader| {
+            backend
+                .connect(ctx, reader, SHARE_SHARED, PROTOCOLS)
+                .unwrap()
+                .handle
+        };
+        let first = connect(&ctx, "Reader 0");
+        let second = connect(&ctx, "Reader 1");
+        let other = connect(&other_ctx, "Reader 1");
+
+        assert_eq!(
+            backend.status(&first).unwrap().reader_names,
+            vec!["Reader 0"]
+        );
+        assert_eq!(backend.status(&second).unwrap().atr, ATR_B);
+        assert_eq!(
+            backend.get_certificate(&ctx).unwrap(),
+            certificate_of("Reader 0")
+        );
+
+        // The certificate of the card still there
+        backend.disconnect(&first, LEAVE_CARD).unwrap();
+        assert!(backend.status(&first).is_err());
+        assert_eq!(
+            backend.get_certificate(&ctx).unwrap(),
+            certificate_of("Reader 1")
+        );
+
+        // Releasing the context disconnects its cards, and only them
+        backend.release_context(&ctx).unwrap();
+        assert_eq!(fake.disconnected(), vec!["Reader 0", "Reader 1"]);
+        assert!(backend.status(&second).is_err());
+        assert!(backend.get_certificate(&ctx).is_err());
+        assert_eq!(
+            backend.status(&other).unwrap().reader_names,
+            vec!["Reader 1"]
+        );
+        assert!(!backend.is_valid_context(&ctx));
+        assert!(backend.is_valid_context(&other_ctx));
+    }
+
+    #[test]
+    fn traced_session_is_replayed_and_checked() {
+        let reader = "Reader 0";
+        let fake = FakePcsc::with_readers(&[(reader, Some(ATR_A))]);
+        let apdus = [
+            vec![0x00, 0xCB, 0x3F, 0xFF, 0x04, 0x5C, 0x02, 0xDF, 0x24, 0x00],
+            vec![0x00, 0x20, 0x00, 0x80, 0x04, b'1', b'2', b'3', b'4'],
+        ];
+
+        let out = SharedBuffer::default();
+        let recorder = RecordingBackend::new(
+            Box::new(fake.backend(ReaderFilter::default())),
+            Box::new(out.clone()),
+        );
+        let ctx = recorder.establish_context(0).unwrap();
+        let handle = recorder
+            .connect(&ctx, reader, SHARE_SHARED, PROTOCOLS)
+            .unwrap()
+            .handle;
+        let responses: Vec<Vec<u8>> = apdus
+            .iter()
+            .map(|apdu| recorder.transmit(&handle, apdu).unwrap().recv_buffer)
+            .collect();
+        let events = out.events();
+
+        // The trace answers as the card did
+        let replay = ReplayBackend::from_events(events.clone()).unwrap();
+        let replay_ctx = replay.establish_context(0).unwrap();
+        assert!(
+            replay
+                .connect(&replay_ctx, reader, SHARE_SHARED, PROTOCOLS)
+                .is_ok()
+        );
+        let replayed: Vec<Vec<u8>> = apdus
+            .iter()
+            .map(|apdu| replay.replay_transmit(apdu).unwrap())
+            .collect();
+        assert_eq!(replayed, responses);
+        assert!(replay.mismatches().is_empty());
+
+        // The backend still answers as traced, until the card changes
+        let backend = fake.backend(ReaderFilter::default());
+        let ctx = backend.establish_context(0).unwrap();
+        let handle = backend
+            .connect(&ctx, reader, SHARE_SHARED, PROTOCOLS)
+            .unwrap()
+            .handle;
+        let check = || {
+            check_responses(&events, Some("1234"), |apdu| {
+                backend.transmit(&handle, apdu).map(|r| r.recv_buffer)
+            })
+        };
+        assert!(check().is_empty(), "{:?}", check());
+        fake.plug(reader, Some(ATR_B));
+        assert_eq!(check().len(), 2);
+    }
+}
@@ -6,11 +6,20 @@
 use std::collections::HashMap;
 use std::sync::RwLock;
 
+use super::system::{PcscCard, PcscContext};
+
+/// A card connected through a context.
+pub(crate) struct NativeCard {
+    pub card: Box<dyn PcscCard>,
+    pub reader: String,
+}
+
 pub(crate) struct NativeRegistry {
-    pub contexts: RwLock<HashMap<u64, pcsc::Context>>,
-    pub cards: RwLock<HashMap<u64, (pcsc::Card, String)>>,
-    /// context id -> card handle id (the card connected in that context)
-    pub ctx_cards: RwLock<HashMap<u64, u64>>,
+    pub contexts: RwLock<HashMap<u64, Box<dyn PcscContext>>>,
+    pub cards: RwLock<HashMap<u64, NativeCard>>,
+    /// context id -> card handle ids (the cards connected in that context, in
+    /// connection order)
+    pub ctx_cards: RwLock<HashMap<u64, Vec<u64>>>,
 }
 
 impl NativeRegistry {
@@ -0,0 +1,268 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! Readable form of the traced GIDS APDUs, for the `scard-trace` tool.
+
+use super::{TraceCall, TraceEvent, from_hex, to_hex};
+use crate::smartcard::emulated::GIDS_AID;
+
+/// What a (traced, hex) command APDU does.
+pub fn describe_command(hex: &str) -> String {
+    let Ok(apdu) = from_hex(hex) else {
+        return "invalid APDU".to_string();
+    };
+    if apdu.len() < 4 || apdu[..4].iter().any(Option::is_none) {
+        return "invalid APDU".to_string();
+    }
+    let (cla, ins, p1, p2) = (
+        apdu[0].unwrap_or_default(),
+        apdu[1].unwrap_or_default(),
+        apdu[2].unwrap_or_default(),
+        apdu[3].unwrap_or_default(),
+    );
+    // Short APDU data (after Lc), masked bytes as 0
+    let data: Vec<u8> = match apdu.get(4) {
+        Some(Some(lc)) if apdu.len() > 5 => apdu[5..]
+            .iter()
+            .take(*lc as usize)
+            .map(|b| b.unwrap_or_default())
+            .collect(),
+        _ => Vec::new(),
+    };
+    let mut text = match ins {
+        0xA4 => {
+            let aid = &data[..];
+            if aid.starts_with(GIDS_AID) {
+                "SELECT GIDS application".to_string()
+            } else if p1 == 0x00 && data.len() == 2 {
+                format!("SELECT file {:02X}{:02X}", data[0], data[1])
+            } else {
+                format!("SELECT {}", to_hex(aid))
+            }
+        }
+        0xCB => format!("GET DATA {}", data_object(p1, p2, &data)),
+        0xDB => format!("PUT DATA {}", data_object(p1, p2, &data)),
+        0xC0 => "GET RESPONSE".to_string(),
+        0x20 if data.is_empty() => format!("VERIFY PIN {:02X}: status", p2),
+        0x20 => format!("VERIFY PIN {:02X}: {} bytes", p2, data.len()),
+        0x22 => match tlv(&data, 0x84) {
+            Some([key, ..]) => format!("MSE SET key {:02X}", key),
+            _ => "MSE SET".to_string(),
+        },
+        0x2A => match (p1, p2) {
+            (0x9E, 0x9A) | (0x9E, 0x80) => format!("PSO SIGN {} bytes", data.len()),
+            (0x80, 0x86) => format!("PSO DECIPHER {} bytes", data.len()),
+            _ => format!("PSO {:02X}{:02X}", p1, p2),
+        },
+        0x24 => format!("CHANGE REFERENCE DATA {:02X}", p2),
+        0x2C => format!("RESET RETRY COUNTER {:02X}", p2),
+        0x87 => "GENERAL AUTHENTICATE".to_string(),
+        0x47 => "GENERATE ASYMMETRIC KEY PAIR".to_string(),
+        0x84 => "GET CHALLENGE".to_string(),
+        _ => format!("INS {:02X} P1P2 {:02X}{:02X}", ins, p1, p2),
+    };
+    if cla & 0x10 != 0 {
+        text.push_str(" (chained)");
+    }
+    text
+}
+
+/// Data object of a GET/PUT DATA: the tag in P1P2, or in the `5C` tag list.
+fn data_object(p1: u8, p2: u8, data: &[u8]) -> String {
+    let tag = match tlv(data, 0x5C) {
+        Some(&[a, b]) => u16::from_be_bytes([a, b]),
+        Some(&[a]) => u16::from(a),
+        _ => u16::from_be_bytes([p1, p2]),
+    };
+    let name = match tag {
+        0xDF1F => "card applications".to_string(),
+        0xDF20 => "card id".to_string(),
+        0xDF21 => "PIN info".to_string(),
+        0xDF22 => "card configuration".to_string(),
+        0xDF23 => "container map".to_string(),
+        0xDF24..=0xDF2B => format!("certificate of container {}", tag - 0xDF24),
+        0x7F49 => "public key".to_string(),
+        0x7F73 => "key information".to_string(),
+        0x2F01 => "applet information".to_string(),
+        _ => return format!("{:04X}", tag),
+    };
+    format!("{:04X} ({})", tag, name)
+}
+
+/// Value of a first level tag (one byte tag, short length).
+fn tlv(data: &[u8], tag: u8) -> Option<&[u8]> {
+    let mut rest = data;
+    while let [t, len, tail @ ..] = rest {
+        let len = *len as usize;
+        if tail.len() < len {
+            return None;
+        }
+        if *t == tag {
+            return Some(&tail[..len]);
+        }
+        rest = &tail[len..];
+    }
+    None
+}
+
+/// What the status word of a (hex) response means.
+pub fn describe_response(hex: &str) -> String {
+    let Ok(response) = from_hex(hex) else {
+        return "invalid response".to_string();
+    };
+    let [.., Some(sw1), Some(sw2)] = response[..] else {
+        return "no status".to_string();
+    };
+    let data = response.len() - 2;
+    let status = match (sw1, sw2) {
+        (0x90, 0x00) => "OK".to_string(),
+        (0x61, n) => format!("{} more bytes", if n == 0 { 256 } else { n as usize }),
+        (0x63, n) if n & 0xF0 == 0xC0 => format!("wrong PIN, {} tries left", n & 0x0F),
+        (0x65, 0x81) => "memory failure".to_string(),
+        (0x67, 0x00) => "wrong length".to_string(),
+        (0x69, 0x82) => "security status not satisfied".to_string(),
+        (0x69, 0x83) => "PIN blocked".to_string(),
+        (0x69, 0x85) => "conditions of use not satisfied".to_string(),
+        (0x69, 0x86) => "command not allowed".to_string(),
+        (0x6A, 0x80) => "incorrect data".to_string(),
+        (0x6A, 0x82) => "file not found".to_string(),
+        (0x6A, 0x86) => "incorrect P1P2".to_string(),
+        (0x6A, 0x88) => "data not found".to_string(),
+        (0x6D, 0x00) => "instruction not supported".to_string(),
+        (0x6E, 0x00) => "class not supported".to_string(),
+        _ => "unknown status".to_string(),
+    };
+    match data {
+        0 => format!("{:02X}{:02X} {}", sw1, sw2, status),
+        _ => format!("{:02X}{:02X} {}, {} bytes", sw1, sw2, status, data),
+    }
+}
+
+/// Readable form of an event, its time relative to `start` (unix ms). A transmit
+/// takes two lines, the command and the response.
+pub fn describe_event(event: &TraceEvent, start: u64) -> String {
+    let time = event.time.saturating_sub(start) as f64 / 1000.0;
+    let call = match &event.call {
+        TraceCall::ListReaders { readers } => format!("list readers: {}", readers.join(", ")),
+        TraceCall::Connect {
+            reader,
+            handle,
+            protocol,
+        } => format!(
+            "connect {:?}: handle {:#X}, protocol {}",
+            reader, handle, protocol
+        ),
+        TraceCall::Disconnect { handle } => format!("disconnect {:#X}", handle),
+        TraceCall::Transmit {
+            command, response, ..
+        } => {
+            let mut text = format!("> {}  {}", command, describe_command(command));
+            if !response.is_empty() {
+                text.push_str(&format!(
+                    "\n           < {}  {}",
+                    response,
+                    describe_response(response)
+                ));
+            }
+            text
+        }
+        TraceCall::Control {
+            code,
+            input,
+            output,
+            ..
+        } => format!("control {:#X}: {} -> {}", code, input, output),
+        TraceCall::GetStatusChange { readers, code } => {
+            let states: Vec<String> = readers
+                .iter()
+                .map(|r| {
+                    format!(
+                        "{:?} {:#X} -> {:#X}",
+                        r.reader, r.current_state, r.event_state
+                    )
+                })
+                .collect();
+            format!("status change {:#X}: {}", code, states.join(", "))
+        }
+        TraceCall::GetAttrib { attr, value, .. } => format!("attribute {:#X}: {}", attr, value),
+        TraceCall::GetContainerInfo { index, value } => {
+            format!("container {} info: {} bytes", index, value.len() / 2)
+        }
+        TraceCall::GetCertificate { value } => format!("certificate: {} bytes", value.len() / 2),
+    };
+    match event.error {
+        Some(error) => format!("{:>9.3} {} (error {:#010X})", time, call, error),
+        None => format!("{:>9.3} {}", time, call),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gids_commands_are_named() {
+        assert_eq!(
+            describe_command("00A4040009A0000003974254465900"),
+            "SELECT GIDS application"
+        );
+        assert_eq!(
+            describe_command("00CB3FFF045C02DF2400"),
+            "GET DATA DF24 (certificate of container 0)"
+        );
+        assert_eq!(
+            describe_command("00CB3FFF045C02DF2300"),
+            "GET DATA DF23 (container map)"
+        );
+        assert_eq!(
+            describe_command("0020008004********"),
+            "VERIFY PIN 80: 4 bytes"
+        );
+        assert_eq!(describe_command("00200080"), "VERIFY PIN 80: status");
+        assert_eq!(describe_command("002241B606800157840181"), "MSE SET key 81");
+        assert_eq!(
+            describe_command("102A9E9A0400000000"),
+            "PSO SIGN 4 bytes (chained)"
+        );
+        assert_eq!(describe_command("00C0000000"), "GET RESPONSE");
+        assert_eq!(describe_command("00"), "invalid APDU");
+    }
+
+    #[test]
+    fn status_words_are_named() {
+        assert_eq!(describe_response("9000"), "9000 OK");
+        assert_eq!(describe_response("0102039000"), "9000 OK, 3 bytes");
+        assert_eq!(describe_response("63C2"), "63C2 wrong PIN, 2 tries left");
+        assert_eq!(describe_response("6100"), "6100 256 more bytes");
+        assert_eq!(describe_response("6983"), "6983 PIN blocked");
+        assert_eq!(describe_response(""), "no status");
+    }
+
+    #[test]
+    fn events_are_described() {
+        let event = TraceEvent {
+            time: 1_250,
+            call: TraceCall::Transmit {
+                handle: 1,
+                command: "00200080".to_string(),
+                response: "63C3".to_string(),
+            },
+            error: None,
+        };
+        assert_eq!(
+            describe_event(&event, 1_000),
+            "    0.250 > 00200080  VERIFY PIN 80: status\n           < 63C3  63C3 wrong PIN, 3 tries left"
+        );
+        let event = TraceEvent {
+            time: 1_000,
+            call: TraceCall::Disconnect { handle: 16 },
+            error: Some(0x8010_0003),
+        };
+        assert_eq!(
+            describe_event(&event, 1_000),
+            "    0.000 disconnect 0x10 (error 0x80100003)"
+        );
+    }
+}
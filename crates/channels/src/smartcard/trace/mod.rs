@@ -0,0 +1,285 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! Trace of the SCard calls of a session, to debug smartcard redirection without
+//! the FreeRDP wlog tags.
+//!
+//! With `UDS_SMARTCARD_TRACE=<file>` the session backend (emulated or native) is
+//! wrapped by a [`RecordingBackend`](recorder::RecordingBackend) that writes each
+//! call that reaches the card (readers, connect, transmit, control, status
+//! changes...) as a JSON line with its time. PIN bytes (VERIFY, CHANGE REFERENCE
+//! DATA, RESET RETRY COUNTER) are written as `**`. Status change polls that time
+//! out are not written.
+//!
+//! `UDS_SMARTCARD_REPLAY=<file>` plays a trace back as the card of the session
+//! (see `replay`), and the `scard-trace` tool prints it with the GIDS APDUs
+//! decoded.
+
+mod decode;
+pub(crate) mod recorder;
+pub(crate) mod replay;
+
+use std::io::{BufRead, BufReader};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use pcsc::ffi::DWORD;
+use serde::{Deserialize, Serialize};
+
+pub use decode::{describe_command, describe_event, describe_response};
+
+/// Instructions whose data is a PIN.
+const PIN_INS: [u8; 3] = [
+    0x20, // VERIFY
+    0x24, // CHANGE REFERENCE DATA
+    0x2C, // RESET RETRY COUNTER
+];
+
+/// Masked byte in a traced APDU.
+const MASKED: &str = "**";
+
+/// A call to the card and its result.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TraceEvent {
+    /// Unix time, milliseconds.
+    pub time: u64,
+    #[serde(flatten)]
+    pub call: TraceCall,
+    /// SCard error code, when the call failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<u32>,
+}
+
+/// Byte strings are uppercase hex.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "call", rename_all = "snake_case")]
+pub enum TraceCall {
+    ListReaders {
+        #[serde(default)]
+        readers: Vec<String>,
+    },
+    Connect {
+        reader: String,
+        #[serde(default)]
+        handle: u64,
+        #[serde(default)]
+        protocol: u32,
+    },
+    Disconnect {
+        handle: u64,
+    },
+    Transmit {
+        handle: u64,
+        command: String,
+        #[serde(default)]
+        response: String,
+    },
+    Control {
+        handle: u64,
+        code: DWORD,
+        input: String,
+        #[serde(default)]
+        output: String,
+    },
+    GetStatusChange {
+        readers: Vec<ReaderTrace>,
+        /// `SCARD_S_SUCCESS`, or the code of the call (e.g. cancelled).
+        code: u32,
+    },
+    GetAttrib {
+        handle: u64,
+        attr: DWORD,
+        #[serde(default)]
+        value: String,
+    },
+    GetContainerInfo {
+        index: u8,
+        #[serde(default)]
+        value: String,
+    },
+    GetCertificate {
+        #[serde(default)]
+        value: String,
+    },
+}
+
+/// A reader in a status change.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ReaderTrace {
+    pub reader: String,
+    pub current_state: u32,
+    pub event_state: u32,
+    pub atr: String,
+}
+
+impl TraceEvent {
+    pub fn now(call: TraceCall, error: Option<u32>) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default();
+        TraceEvent { time, call, error }
+    }
+}
+
+/// Read a trace file, one event per line (empty lines are skipped).
+pub fn read_trace(path: &str) -> Result<Vec<TraceEvent>, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("trace {}: {}", path, e))?;
+    parse_trace(BufReader::new(file))
+}
+
+pub(crate) fn parse_trace(reader: impl BufRead) -> Result<Vec<TraceEvent>, String> {
+    let mut events = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| format!("trace: {}", e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event =
+            serde_json::from_str(&line).map_err(|e| format!("trace line {}: {}", number + 1, e))?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+pub(crate) fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Bytes of a traced hex string, `None` for the masked ones.
+pub(crate) fn from_hex(hex: &str) -> Result<Vec<Option<u8>>, String> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(format!("odd hex length: {:?}", hex));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| match &hex[i..i + 2] {
+            MASKED => Ok(None),
+            byte => u8::from_str_radix(byte, 16)
+                .map(Some)
+                .map_err(|_| format!("invalid hex: {:?}", hex)),
+        })
+        .collect()
+}
+
+/// Whether an APDU is the traced one (masked bytes match anything).
+pub(crate) fn hex_matches(traced: &str, data: &[u8]) -> bool {
+    from_hex(traced).is_ok_and(|bytes| {
+        bytes.len() == data.len()
+            && bytes
+                .iter()
+                .zip(data)
+                .all(|(traced, byte)| traced.is_none_or(|t| t == *byte))
+    })
+}
+
+/// In-memory trace file, for tests.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct SharedBuffer(pub std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl SharedBuffer {
+    pub fn events(&self) -> Vec<TraceEvent> {
+        parse_trace(&self.0.lock().unwrap()[..]).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hex of a command APDU with its PIN bytes masked.
+pub(crate) fn masked_command(apdu: &[u8]) -> String {
+    if apdu.len() <= 5 || !PIN_INS.contains(&apdu[1]) {
+        return to_hex(apdu);
+    }
+    // Short APDU: the data is after Lc. Extended: after 00 and a two byte Lc
+    let (start, lc) = if apdu[4] == 0 && apdu.len() > 7 {
+        (7, u16::from_be_bytes([apdu[5], apdu[6]]) as usize)
+    } else {
+        (5, apdu[4] as usize)
+    };
+    let end = (start + lc).min(apdu.len());
+    let mut hex = to_hex(&apdu[..start]);
+    hex.push_str(&MASKED.repeat(end - start));
+    hex.push_str(&to_hex(&apdu[end..]));
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pin_bytes_are_masked() {
+        let verify = [0x00, 0x20, 0x00, 0x80, 0x04, b'1', b'2', b'3', b'4'];
+        let masked = masked_command(&verify);
+        assert_eq!(masked, "0020008004********");
+        assert!(!masked.contains("31323334"));
+        assert!(hex_matches(&masked, &verify));
+        assert!(hex_matches(
+            &masked,
+            &[0x00, 0x20, 0x00, 0x80, 0x04, 0, 0, 0, 0]
+        ));
+        assert!(!hex_matches(
+            &masked,
+            &[0x00, 0x20, 0x00, 0x80, 0x03, 0, 0, 0]
+        ));
+
+        // Extended length, with Le after the PIN
+        let extended = [
+            0x00, 0x20, 0x00, 0x80, 0x00, 0x00, 0x04, b'1', b'2', b'3', b'4', 0x00, 0x00,
+        ];
+        let masked = masked_command(&extended);
+        assert_eq!(masked, "00200080000004********0000");
+        assert!(hex_matches(&masked, &extended));
+
+        // PIN status query and other commands are kept
+        assert_eq!(masked_command(&[0x00, 0x20, 0x00, 0x80]), "00200080");
+        let get_data = [0x00, 0xCB, 0x3F, 0xFF, 0x04, 0x5C, 0x02, 0xDF, 0x24, 0x00];
+        assert_eq!(masked_command(&get_data), "00CB3FFF045C02DF2400");
+    }
+
+    #[test]
+    fn events_are_json_lines() {
+        let events = vec![
+            TraceEvent {
+                time: 1_000,
+                call: TraceCall::Transmit {
+                    handle: 7,
+                    command: "0020008004********".to_string(),
+                    response: "63C2".to_string(),
+                },
+                error: None,
+            },
+            TraceEvent {
+                time: 1_050,
+                call: TraceCall::Connect {
+                    reader: "Reader 0".to_string(),
+                    handle: 0,
+                    protocol: 0,
+                },
+                error: Some(0x8010_000C),
+            },
+        ];
+        let text: String = events
+            .iter()
+            .map(|e| serde_json::to_string(e).unwrap() + "\n")
+            .collect();
+        assert!(text.starts_with(r#"{"time":1000,"call":"transmit","handle":7,"#));
+        assert_eq!(parse_trace(text.as_bytes()).unwrap(), events);
+        assert!(parse_trace(&b"{\"time\": 1}\n"[..]).is_err());
+        assert_eq!(from_hex("0A**").unwrap(), vec![Some(0x0A), None]);
+        assert!(from_hex("0A0").is_err());
+    }
+}
@@ -0,0 +1,260 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! Backend wrapper that writes the trace of the calls to the card.
+
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use pcsc::ffi::DWORD;
+use rdp::integrations::smartcard::*;
+
+use super::{ReaderTrace, TraceCall, TraceEvent, masked_command, to_hex};
+use crate::smartcard::SmartcardBackend;
+
+pub(crate) struct RecordingBackend {
+    inner: Box<dyn SmartcardBackend>,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl std::fmt::Debug for RecordingBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RecordingBackend")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl RecordingBackend {
+    pub fn new(inner: Box<dyn SmartcardBackend>, out: Box<dyn Write + Send>) -> Self {
+        RecordingBackend {
+            inner,
+            out: Mutex::new(out),
+        }
+    }
+
+    /// Trace an APDU and the card response.
+    fn record_transmit(&self, handle: &ScardHandle, command: &[u8], response: Result<&[u8], u32>) {
+        self.record(
+            TraceCall::Transmit {
+                handle: handle.raw(),
+                command: masked_command(command),
+                response: response.map(to_hex).unwrap_or_default(),
+            },
+            &response,
+        );
+    }
+
+    fn record<T>(&self, call: TraceCall, result: &Result<T, u32>) {
+        let event = TraceEvent::now(call, result.as_ref().err().copied());
+        let Ok(line) = serde_json::to_string(&event) else {
+            return;
+        };
+        let mut out = self.out.lock().unwrap();
+        if let Err(e) = writeln!(out, "{}", line) {
+            log::warn!("smartcard trace: {}", e);
+        }
+    }
+}
+
+impl SmartcardBackend for RecordingBackend {
+    fn establish_context(&self, scope: DWORD) -> Result<ScardContext, u32> {
+        self.inner.establish_context(scope)
+    }
+
+    fn release_context(&self, ctx: &ScardContext) -> Result<(), u32> {
+        self.inner.release_context(ctx)
+    }
+
+    fn is_valid_context(&self, ctx: &ScardContext) -> bool {
+        self.inner.is_valid_context(ctx)
+    }
+
+    fn list_readers(
+        &self,
+        ctx: &ScardContext,
+        groups: Option<&[String]>,
+    ) -> Result<Vec<String>, u32> {
+        let result = self.inner.list_readers(ctx, groups);
+        let readers = result.as_ref().cloned().unwrap_or_default();
+        self.record(TraceCall::ListReaders { readers }, &result);
+        result
+    }
+
+    fn connect(
+        &self,
+        ctx: &ScardContext,
+        reader: &str,
+        share_mode: DWORD,
+        preferred_protocols: DWORD,
+    ) -> Result<ConnectResult, u32> {
+        let result = self
+            .inner
+            .connect(ctx, reader, share_mode, preferred_protocols);
+        let (handle, protocol) = result
+            .as_ref()
+            .map(|r| (r.handle.raw(), r.active_protocol))
+            .unwrap_or_default();
+        self.record(
+            TraceCall::Connect {
+                reader: reader.to_string(),
+                handle,
+                protocol,
+            },
+            &result,
+        );
+        result
+    }
+
+    fn disconnect(&self, handle: &ScardHandle, disposition: DWORD) -> Result<(), u32> {
+        let result = self.inner.disconnect(handle, disposition);
+        self.record(
+            TraceCall::Disconnect {
+                handle: handle.raw(),
+            },
+            &result,
+        );
+        result
+    }
+
+    fn reconnect(
+        &self,
+        handle: &ScardHandle,
+        share_mode: DWORD,
+        preferred_protocols: DWORD,
+        initialization: DWORD,
+    ) -> Result<u32, u32> {
+        self.inner
+            .reconnect(handle, share_mode, preferred_protocols, initialization)
+    }
+
+    fn transmit(&self, handle: &ScardHandle, data: &[u8]) -> Result<TransmitResult, u32> {
+        let result = self.inner.transmit(handle, data);
+        let response = result
+            .as_ref()
+            .map(|r| r.recv_buffer.as_slice())
+            .map_err(|e| *e);
+        self.record_transmit(handle, data, response);
+        result
+    }
+
+    fn control(
+        &self,
+        handle: &ScardHandle,
+        control_code: DWORD,
+        in_data: &[u8],
+    ) -> Result<Vec<u8>, u32> {
+        let result = self.inner.control(handle, control_code, in_data);
+        let output = result.as_deref().map(to_hex).unwrap_or_default();
+        self.record(
+            TraceCall::Control {
+                handle: handle.raw(),
+                code: control_code,
+                input: to_hex(in_data),
+                output,
+            },
+            &result,
+        );
+        result
+    }
+
+    fn status(&self, handle: &ScardHandle) -> Result<ScardStatus, u32> {
+        self.inner.status(handle)
+    }
+
+    fn get_status_change(
+        &self,
+        ctx: &ScardContext,
+        timeout: Duration,
+        reader_states: &[ReaderStateIn],
+    ) -> Result<(Vec<ReaderStateOut>, u32), u32> {
+        let result = self.inner.get_status_change(ctx, timeout, reader_states);
+        match &result {
+            // Polls without changes, every 100 ms
+            Ok((_, code)) if *code == SCARD_E_TIMEOUT => {}
+            Ok((states, code)) => {
+                let readers = states
+                    .iter()
+                    .map(|s| ReaderTrace {
+                        reader: s.reader_name.clone(),
+                        current_state: s.current_state,
+                        event_state: s.event_state,
+                        atr: to_hex(&s.atr),
+                    })
+                    .collect();
+                self.record(
+                    TraceCall::GetStatusChange {
+                        readers,
+                        code: *code,
+                    },
+                    &result,
+                );
+            }
+            Err(_) => self.record(
+                TraceCall::GetStatusChange {
+                    readers: Vec::new(),
+                    code: 0,
+                },
+                &result,
+            ),
+        }
+        result
+    }
+
+    fn cancel(&self, ctx: &ScardContext) -> Result<(), u32> {
+        self.inner.cancel(ctx)
+    }
+
+    fn begin_transaction(&self, handle: &ScardHandle) -> Result<(), u32> {
+        self.inner.begin_transaction(handle)
+    }
+
+    fn end_transaction(&self, handle: &ScardHandle, disposition: DWORD) -> Result<(), u32> {
+        self.inner.end_transaction(handle, disposition)
+    }
+
+    fn get_attrib(&self, handle: &ScardHandle, attr_id: DWORD) -> Result<Vec<u8>, u32> {
+        let result = self.inner.get_attrib(handle, attr_id);
+        let value = result.as_deref().map(to_hex).unwrap_or_default();
+        self.record(
+            TraceCall::GetAttrib {
+                handle: handle.raw(),
+                attr: attr_id,
+                value,
+            },
+            &result,
+        );
+        result
+    }
+
+    fn set_attrib(&self, handle: &ScardHandle, attr_id: DWORD, data: &[u8]) -> Result<(), u32> {
+        self.inner.set_attrib(handle, attr_id, data)
+    }
+
+    fn get_container_info(&self, ctx: &ScardContext, container_index: u8) -> Result<Vec<u8>, u32> {
+        let result = self.inner.get_container_info(ctx, container_index);
+        let value = result.as_deref().map(to_hex).unwrap_or_default();
+        self.record(
+            TraceCall::GetContainerInfo {
+                index: container_index,
+                value,
+            },
+            &result,
+        );
+        result
+    }
+
+    fn get_certificate(&self, ctx: &ScardContext) -> Result<Vec<u8>, u32> {
+        let result = self.inner.get_certificate(ctx);
+        let value = result.as_deref().map(to_hex).unwrap_or_default();
+        self.record(TraceCall::GetCertificate { value }, &result);
+        result
+    }
+
+    fn is_available(&self) -> bool {
+        self.inner.is_available()
+    }
+}
@@ -0,0 +1,615 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! Trace played back as the card.
+//!
+//! [`ReplayBackend`] answers transmit, control and status changes with the traced
+//! results, in order, so a session recorded with a physical card (or the emulated
+//! one) can be run again without it. Each command must be the traced one (PIN
+//! bytes aside): a different one fails with `SCARD_F_INTERNAL_ERROR` and is kept in
+//! [`ReplayBackend::mismatches`]. [`check_responses`] goes the other way, sending
+//! the traced commands to a card and comparing its responses with the traced ones.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use pcsc::ffi::DWORD;
+use rdp::integrations::smartcard::*;
+
+use super::{ReaderTrace, TraceCall, TraceEvent, from_hex, hex_matches, masked_command, to_hex};
+use crate::smartcard::SmartcardBackend;
+
+/// A traced call and its result (`Err` with the error code).
+struct Exchange {
+    request: String,
+    result: Result<Vec<u8>, u32>,
+}
+
+#[derive(Default)]
+struct ReplayState {
+    transmits: VecDeque<Exchange>,
+    controls: VecDeque<(DWORD, Exchange)>,
+    status_changes: VecDeque<(Vec<ReaderTrace>, u32)>,
+    /// Last state of each reader, once the status changes are replayed.
+    readers: HashMap<String, ReaderTrace>,
+    replayed: usize,
+    mismatches: Vec<String>,
+}
+
+pub(crate) struct ReplayBackend {
+    readers: Vec<String>,
+    protocols: HashMap<String, u32>,
+    attribs: HashMap<DWORD, Result<Vec<u8>, u32>>,
+    container_info: HashMap<u8, Result<Vec<u8>, u32>>,
+    certificate: Result<Vec<u8>, u32>,
+    state: Mutex<ReplayState>,
+}
+
+impl std::fmt::Debug for ReplayBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReplayBackend")
+            .field("readers", &self.readers)
+            .finish()
+    }
+}
+
+impl ReplayBackend {
+    pub fn open(path: &str) -> Result<Self, String> {
+        Self::from_events(super::read_trace(path)?)
+    }
+
+    pub fn from_events(events: Vec<TraceEvent>) -> Result<Self, String> {
+        let mut backend = ReplayBackend {
+            readers: Vec::new(),
+            protocols: HashMap::new(),
+            attribs: HashMap::new(),
+            container_info: HashMap::new(),
+            certificate: Err(SCARD_E_UNSUPPORTED_FEATURE),
+            state: Mutex::new(ReplayState::default()),
+        };
+        let state = backend.state.get_mut().unwrap();
+        for event in events {
+            let result = |value: &str| match event.error {
+                Some(error) => Ok(Err(error)),
+                None => bytes(value).map(Ok),
+            };
+            match &event.call {
+                TraceCall::ListReaders { readers } => {
+                    for reader in readers {
+                        if !backend.readers.contains(reader) {
+                            backend.readers.push(reader.clone());
+                        }
+                    }
+                }
+                TraceCall::Connect {
+                    reader, protocol, ..
+                } if event.error.is_none() => {
+                    backend.protocols.insert(reader.clone(), *protocol);
+                }
+                TraceCall::Transmit {
+                    command, response, ..
+                } => state.transmits.push_back(Exchange {
+                    request: command.clone(),
+                    result: result(response)?,
+                }),
+                TraceCall::Control {
+                    code,
+                    input,
+                    output,
+                    ..
+                } => state.controls.push_back((
+                    *code,
+                    Exchange {
+                        request: input.clone(),
+                        result: result(output)?,
+                    },
+                )),
+                TraceCall::GetStatusChange { readers, code } => {
+                    let code = event.error.unwrap_or(*code);
+                    state.status_changes.push_back((readers.clone(), code));
+                }
+                TraceCall::GetAttrib { attr, value, .. } => {
+                    backend.attribs.insert(*attr, result(value)?);
+                }
+                TraceCall::GetContainerInfo { index, value } => {
+                    backend.container_info.insert(*index, result(value)?);
+                }
+                TraceCall::GetCertificate { value } => backend.certificate = result(value)?,
+                _ => {}
+            }
+        }
+        Ok(backend)
+    }
+
+    /// Commands that were not the traced ones.
+    pub fn mismatches(&self) -> Vec<String> {
+        self.state.lock().unwrap().mismatches.clone()
+    }
+
+    /// Traced transmits not replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.state.lock().unwrap().transmits.len()
+    }
+
+    /// Traced response to a command.
+    pub(crate) fn replay_transmit(&self, data: &[u8]) -> Result<Vec<u8>, u32> {
+        let mut state = self.state.lock().unwrap();
+        state.replayed += 1;
+        let number = state.replayed;
+        let Some(exchange) = state.transmits.pop_front() else {
+            let mismatch = format!("transmit #{}: not traced: {}", number, masked_command(data));
+            state.mismatches.push(mismatch);
+            return Err(SCARD_E_NO_SMARTCARD);
+        };
+        if !hex_matches(&exchange.request, data) {
+            let mismatch = format!(
+                "transmit #{}: traced {}, got {}",
+                number,
+                exchange.request,
+                masked_command(data)
+            );
+            log::warn!("smartcard replay: {}", mismatch);
+            state.mismatches.push(mismatch);
+            return Err(SCARD_F_INTERNAL_ERROR);
+        }
+        exchange.result
+    }
+
+    /// Next traced status change of the readers (name, current state). Once
+    /// replayed, the readers stay in their last state.
+    pub(crate) fn replay_status_change(
+        &self,
+        timeout: Duration,
+        readers: &[(&str, u32)],
+    ) -> Result<(Vec<ReaderStateOut>, u32), u32> {
+        let mut state = self.state.lock().unwrap();
+        let code = match state.status_changes.pop_front() {
+            Some((traced, code)) => {
+                for reader in traced {
+                    state.readers.insert(reader.reader.clone(), reader);
+                }
+                code
+            }
+            None => {
+                // Nothing else happened in the trace
+                drop(state);
+                std::thread::sleep(timeout.min(Duration::from_millis(50)));
+                state = self.state.lock().unwrap();
+                SCARD_E_TIMEOUT
+            }
+        };
+        if code != SCARD_S_SUCCESS && code != SCARD_E_TIMEOUT {
+            return Err(code);
+        }
+        let results = readers
+            .iter()
+            .map(|&(name, current_state)| {
+                let (event_state, atr) = match state.readers.get(name) {
+                    Some(traced) if code == SCARD_E_TIMEOUT => {
+                        (traced.event_state & !SCARD_STATE_CHANGED, &traced.atr[..])
+                    }
+                    Some(traced) => (traced.event_state, &traced.atr[..]),
+                    None => (current_state & !SCARD_STATE_CHANGED, ""),
+                };
+                ReaderStateOut {
+                    reader_name: name.to_string(),
+                    current_state,
+                    event_state,
+                    atr: bytes(atr).unwrap_or_default(),
+                }
+            })
+            .collect();
+        Ok((results, code))
+    }
+
+    fn protocol(&self) -> u32 {
+        self.protocols
+            .values()
+            .next()
+            .copied()
+            .unwrap_or(SCARD_PROTOCOL_T1)
+    }
+
+    fn atr(&self, state: &ReplayState) -> Vec<u8> {
+        state
+            .readers
+            .values()
+            .find(|r| !r.atr.is_empty())
+            .and_then(|r| bytes(&r.atr).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Bytes of a traced value (values have no masked bytes).
+fn bytes(hex: &str) -> Result<Vec<u8>, String> {
+    from_hex(hex)?
+        .into_iter()
+        .map(|b| b.ok_or_else(|| format!("masked bytes in a traced value: {:?}", hex)))
+        .collect()
+}
+
+impl SmartcardBackend for ReplayBackend {
+    fn establish_context(&self, _scope: DWORD) -> Result<ScardContext, u32> {
+        Ok(ScardContext::new())
+    }
+
+    fn release_context(&self, _ctx: &ScardContext) -> Result<(), u32> {
+        Ok(())
+    }
+
+    fn is_valid_context(&self, _ctx: &ScardContext) -> bool {
+        true
+    }
+
+    fn list_readers(&self, _ctx: &ScardContext, _: Option<&[String]>) -> Result<Vec<String>, u32> {
+        Ok(self.readers.clone())
+    }
+
+    fn connect(
+        &self,
+        _ctx: &ScardContext,
+        reader: &str,
+        _: DWORD,
+        _: DWORD,
+    ) -> Result<ConnectResult, u32> {
+        let protocol = *self.protocols.get(reader).ok_or(SCARD_E_UNKNOWN_READER)?;
+        Ok(ConnectResult {
+            handle: ScardHandle::new(protocol),
+            active_protocol: protocol,
+        })
+    }
+
+    fn disconnect(&self, _handle: &ScardHandle, _disposition: DWORD) -> Result<(), u32> {
+        Ok(())
+    }
+
+    fn reconnect(&self, _: &ScardHandle, _: DWORD, _: DWORD, _: DWORD) -> Result<u32, u32> {
+        Ok(self.protocol())
+    }
+
+    fn transmit(&self, _: &ScardHandle, data: &[u8]) -> Result<TransmitResult, u32> {
+        self.replay_transmit(data)
+            .map(|recv_buffer| TransmitResult {
+                recv_pci: None,
+                recv_buffer,
+            })
+    }
+
+    fn control(
+        &self,
+        _: &ScardHandle,
+        control_code: DWORD,
+        in_data: &[u8],
+    ) -> Result<Vec<u8>, u32> {
+        let mut state = self.state.lock().unwrap();
+        match state.controls.pop_front() {
+            Some((code, exchange))
+                if code == control_code && hex_matches(&exchange.request, in_data) =>
+            {
+                exchange.result
+            }
+            _ => {
+                let mismatch = format!("control {:#X}: not traced here", control_code);
+                state.mismatches.push(mismatch);
+                Err(SCARD_E_UNSUPPORTED_FEATURE)
+            }
+        }
+    }
+
+    fn status(&self, _: &ScardHandle) -> Result<ScardStatus, u32> {
+        let state = self.state.lock().unwrap();
+        Ok(ScardStatus {
+            reader_names: self.readers.iter().take(1).cloned().collect(),
+            state: SCARD_STATE_PRESENT,
+            protocol: self.protocol(),
+            atr: self.atr(&state),
+        })
+    }
+
+    fn get_status_change(
+        &self,
+        _: &ScardContext,
+        timeout: Duration,
+        reader_states: &[ReaderStateIn],
+    ) -> Result<(Vec<ReaderStateOut>, u32), u32> {
+        let readers: Vec<(&str, u32)> = reader_states
+            .iter()
+            .map(|rs| (rs.reader_name.as_str(), rs.current_state))
+            .collect();
+        self.replay_status_change(timeout, &readers)
+    }
+
+    fn cancel(&self, _: &ScardContext) -> Result<(), u32> {
+        Ok(())
+    }
+
+    fn begin_transaction(&self, _: &ScardHandle) -> Result<(), u32> {
+        Ok(())
+    }
+
+    fn end_transaction(&self, _: &ScardHandle, _: DWORD) -> Result<(), u32> {
+        Ok(())
+    }
+
+    fn get_attrib(&self, _: &ScardHandle, attr_id: DWORD) -> Result<Vec<u8>, u32> {
+        self.attribs
+            .get(&attr_id)
+            .cloned()
+            .unwrap_or(Err(SCARD_E_UNSUPPORTED_FEATURE))
+    }
+
+    fn set_attrib(&self, _: &ScardHandle, _: DWORD, _: &[u8]) -> Result<(), u32> {
+        Ok(())
+    }
+
+    fn get_container_info(&self, _: &ScardContext, container_index: u8) -> Result<Vec<u8>, u32> {
+        self.container_info
+            .get(&container_index)
+            .cloned()
+            .unwrap_or(Err(SCARD_E_UNSUPPORTED_FEATURE))
+    }
+
+    fn get_certificate(&self, _: &ScardContext) -> Result<Vec<u8>, u32> {
+        self.certificate.clone()
+    }
+
+    fn is_available(&self) -> bool {
+        true
+    }
+}
+
+/// Send the traced commands to a card and compare its responses with the traced
+/// ones. Masked PIN data is replaced by `pin`. Returns the differences.
+pub(crate) fn check_responses(
+    events: &[TraceEvent],
+    pin: Option<&str>,
+    mut card: impl FnMut(&[u8]) -> Result<Vec<u8>, u32>,
+) -> Vec<String> {
+    let mut differences = Vec::new();
+    let transmits = events.iter().filter_map(|e| match &e.call {
+        TraceCall::Transmit {
+            command, response, ..
+        } => Some((command, response, e.error)),
+        _ => None,
+    });
+    for (number, (command, response, error)) in transmits.enumerate() {
+        let apdu = match unmask(command, pin) {
+            Ok(apdu) => apdu,
+            Err(e) => {
+                differences.push(format!("transmit #{}: {}", number + 1, e));
+                continue;
+            }
+        };
+        let traced = match error {
+            Some(error) => Err(error),
+            None => bytes(response).map_err(|_| SCARD_F_INTERNAL_ERROR),
+        };
+        let got = card(&apdu);
+        if got != traced {
+            differences.push(format!(
+                "transmit #{} {}: traced {}, got {}",
+                number + 1,
+                command,
+                result_hex(&traced),
+                result_hex(&got)
+            ));
+        }
+    }
+    differences
+}
+
+/// The APDU of a traced command, with `pin` as the masked data.
+fn unmask(command: &str, pin: Option<&str>) -> Result<Vec<u8>, String> {
+    let traced = from_hex(command)?;
+    if traced.iter().all(Option::is_some) {
+        return Ok(traced.into_iter().flatten().collect());
+    }
+    let pin = pin.ok_or("masked PIN and no PIN to replay it")?;
+    let header: Vec<u8> = traced.iter().take(4).flatten().copied().collect();
+    let trailer: Vec<u8> = traced
+        .iter()
+        .skip(5)
+        .skip_while(|b| b.is_none())
+        .flatten()
+        .copied()
+        .collect();
+    Ok([&header[..], &[pin.len() as u8], pin.as_bytes(), &trailer].concat())
+}
+
+fn result_hex(result: &Result<Vec<u8>, u32>) -> String {
+    match result {
+        Ok(data) => to_hex(data),
+        Err(error) => format!("error {:#010X}", error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smartcard::emulated::{EmulatedBackend, GidsEngine};
+    use crate::smartcard::trace::SharedBuffer;
+    use crate::smartcard::trace::recorder::RecordingBackend;
+
+    /// A card whose PIN is the key password.
+    struct Card {
+        /// Only the public key, as the engine reads it.
+        cert_der: Vec<u8>,
+        key_pem: String,
+    }
+
+    impl Card {
+        fn new(password: &str) -> Self {
+            use rsa::pkcs8::{EncodePublicKey, LineEnding};
+            fn seq(content: &[u8]) -> Vec<u8> {
+                let len = content.len();
+                [&[0x30, 0x82, (len >> 8) as u8, len as u8][..], content].concat()
+            }
+            let mut rng = rsa::rand_core::OsRng;
+            let key = rsa::RsaPrivateKey::new(&mut rng, 2048).unwrap();
+            let spki = key.to_public_key().to_public_key_der().unwrap();
+            Card {
+                cert_der: seq(&seq(spki.as_bytes())),
+                key_pem: key
+                    .to_pkcs8_encrypted_pem(&mut rng, password, LineEnding::LF)
+                    .unwrap()
+                    .to_string(),
+            }
+        }
+
+        fn engine(&self) -> GidsEngine {
+            GidsEngine::new(self.cert_der.clone(), self.key_pem.clone()).unwrap()
+        }
+    }
+
+    /// The APDUs of a logon as msclmd sends them: select, certificate, PIN, signature.
+    fn logon_apdus(pin: &str) -> Vec<Vec<u8>> {
+        let mut select = vec![0x00, 0xA4, 0x04, 0x00, 0x09];
+        select.extend_from_slice(&[0xA0, 0x00, 0x00, 0x03, 0x97, 0x42, 0x54, 0x46, 0x59, 0x00]);
+        let mut verify = vec![0x00, 0x20, 0x00, 0x80, pin.len() as u8];
+        verify.extend_from_slice(pin.as_bytes());
+        let mut pso = vec![0x00, 0x2A, 0x9E, 0x9A, 0x33];
+        pso.extend_from_slice(&[0x30, 0x31, 0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01]);
+        pso.extend_from_slice(&[0x65, 0x03, 0x04, 0x02, 0x01, 0x05, 0x00, 0x04, 0x20]);
+        pso.extend_from_slice(&[0x5A; 32]);
+        pso.push(0x00);
+        vec![
+            select,
+            vec![0x00, 0xCB, 0x3F, 0xFF, 0x04, 0x5C, 0x02, 0xDF, 0x24, 0x00],
+            verify,
+            vec![
+                0x00, 0x22, 0x41, 0xB6, 0x06, 0x80, 0x01, 0x57, 0x84, 0x01, 0x81,
+            ],
+            pso,
+        ]
+    }
+
+    /// Trace of a logon with the PIN, and the responses of the card.
+    fn record_logon(card: &Card, pin: &str) -> (Vec<Vec<u8>>, Vec<TraceEvent>) {
+        let out = SharedBuffer::default();
+        let card = EmulatedBackend::from_engine(card.engine());
+        let recorder = RecordingBackend::new(Box::new(card), Box::new(out.clone()));
+        let ctx = recorder.establish_context(0).unwrap();
+        let reader = recorder.list_readers(&ctx, None).unwrap().remove(0);
+        let handle = recorder.connect(&ctx, &reader, 2, 2).unwrap().handle;
+        let responses = logon_apdus(pin)
+            .iter()
+            .map(|apdu| recorder.transmit(&handle, apdu).unwrap().recv_buffer)
+            .collect();
+        (responses, out.events())
+    }
+
+    #[test]
+    fn recorded_logon_masks_the_pin() {
+        let card = Card::new("1234");
+        let (responses, events) = record_logon(&card, "1234");
+        assert_eq!(responses[2], vec![0x90, 0x00]);
+        assert_eq!(responses[4].len(), 256 + 2);
+        assert!(matches!(events[0].call, TraceCall::ListReaders { .. }));
+        assert!(matches!(events[1].call, TraceCall::Connect { .. }));
+        let commands: Vec<&String> = events
+            .iter()
+            .filter_map(|e| match &e.call {
+                TraceCall::Transmit { command, .. } => Some(command),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(commands.len(), 5);
+        assert_eq!(commands[2], "0020008004********");
+    }
+
+    #[test]
+    fn replay_answers_as_the_traced_card() {
+        let card = Card::new("1234");
+        let (responses, events) = record_logon(&card, "1234");
+
+        // Any PIN goes, the traced one is masked
+        let replay = ReplayBackend::from_events(events.clone()).unwrap();
+        let ctx = replay.establish_context(0).unwrap();
+        let reader = replay.list_readers(&ctx, None).unwrap().remove(0);
+        assert!(replay.connect(&ctx, &reader, 2, 2).is_ok());
+        let replayed: Vec<Vec<u8>> = logon_apdus("9999")
+            .iter()
+            .map(|apdu| replay.replay_transmit(apdu).unwrap())
+            .collect();
+        assert_eq!(replayed, responses);
+        assert!(replay.mismatches().is_empty());
+        assert_eq!(replay.remaining(), 0);
+        assert_eq!(
+            replay.replay_transmit(&[0x00, 0xC0, 0, 0, 0]),
+            Err(SCARD_E_NO_SMARTCARD)
+        );
+
+        // Another command fails
+        let replay = ReplayBackend::from_events(events).unwrap();
+        assert_eq!(
+            replay.replay_transmit(&[0x00, 0xC0, 0, 0, 0]),
+            Err(SCARD_F_INTERNAL_ERROR)
+        );
+        assert_eq!(replay.mismatches().len(), 1);
+        assert!(replay.connect(&ctx, "Other reader", 2, 2).is_err());
+    }
+
+    #[test]
+    fn gids_engine_still_answers_as_traced() {
+        let card = Card::new("1234");
+        let (_, events) = record_logon(&card, "1234");
+
+        // RSA PKCS#1 v1.5 signatures are deterministic, so the whole logon matches
+        let mut engine = card.engine();
+        let differences =
+            check_responses(&events, Some("1234"), |apdu| Ok(engine.process_apdu(apdu)));
+        assert!(differences.is_empty(), "{:?}", differences);
+
+        // A wrong PIN is a difference (and the signature after it)
+        let mut engine = card.engine();
+        let differences =
+            check_responses(&events, Some("0000"), |apdu| Ok(engine.process_apdu(apdu)));
+        assert_eq!(differences.len(), 2, "{:?}", differences);
+        assert!(differences[0].starts_with("transmit #3 0020008004********: traced 9000"));
+        assert!(differences[1].starts_with("transmit #5 "));
+
+        // Without the PIN, the VERIFY cannot be sent
+        let differences =
+            check_responses(&events, None, |apdu| Ok(card.engine().process_apdu(apdu)));
+        assert!(differences.iter().any(|d| d.starts_with("transmit #3: ")));
+    }
+
+    #[test]
+    fn card_removal_is_replayed() {
+        let present = SCARD_STATE_PRESENT | SCARD_STATE_CHANGED;
+        let empty = SCARD_STATE_EMPTY | SCARD_STATE_CHANGED;
+        let status = |event_state, atr: &str| TraceEvent {
+            time: 0,
+            call: TraceCall::GetStatusChange {
+                readers: vec![ReaderTrace {
+                    reader: "Reader 0".to_string(),
+                    current_state: 0,
+                    event_state,
+                    atr: atr.to_string(),
+                }],
+                code: SCARD_S_SUCCESS,
+            },
+            error: None,
+        };
+        let replay =
+            ReplayBackend::from_events(vec![status(present, "3B00"), status(empty, "")]).unwrap();
+        let wait = || {
+            replay
+                .replay_status_change(Duration::from_millis(1), &[("Reader 0", 0), ("Other", 0)])
+                .unwrap()
+        };
+        let (out, code) = wait();
+        assert_eq!(code, SCARD_S_SUCCESS);
+        assert_eq!(out[0].event_state, present);
+        assert_eq!(out[0].atr, vec![0x3B, 0x00]);
+        assert_eq!(out[1].event_state, 0);
+        assert_eq!(wait().0[0].event_state, empty);
+        // Nothing else: the last state, unchanged
+        let (out, code) = wait();
+        assert_eq!(code, SCARD_E_TIMEOUT);
+        assert_eq!(out[0].event_state, SCARD_STATE_EMPTY);
+    }
+}
@@ -10,12 +10,57 @@ use std::time::Duration;
 use flume::{Receiver, Sender};
 use shared::log;
 
+use crate::webcam::devices::{CameraSelection, CameraWatcher, is_mock_forced};
 use crate::webcam::encoders::{self, MjpegEncoder, RawEncoder, VideoEncoder, Yuy2Encoder};
+use crate::webcam::rate_control::{RateController, RateWindow, SentCounter};
+use crate::webcam::screen::{FrameSource, ScreenCapture, selected_source};
 use crate::webcam::{
-    StreamState, WEBCAM_QUALITY, WebcamCommand, WebcamFrame, WebcamMode,
-    calculate_scaled_dimensions, generate_mock_frame, init_real_camera, resize_rgb,
+    Privacy, StreamState, WEBCAM_QUALITY, WebcamCommand, WebcamFrame, WebcamMode, blur_background,
+    calculate_scaled_dimensions, generate_mock_frame, generate_paused_frame, init_real_camera,
+    reduce_detail, resize_rgb,
 };
 
+/// How often the rate control looks at the stream
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Where the frames come from
+enum Source {
+    Camera(nokhwa::Camera),
+    Screen(ScreenCapture),
+}
+
+impl Source {
+    fn name(&self) -> String {
+        match self {
+            Source::Camera(cam) => cam.info().human_name(),
+            Source::Screen(screen) => screen.name(),
+        }
+    }
+
+    /// Next frame, RGB24, with its size
+    fn frame(&mut self, state: &StreamState) -> anyhow::Result<(Vec<u8>, u32, u32)> {
+        match self {
+            Source::Camera(cam) => {
+                log::trace!("Calling cam.frame()...");
+                let frame = cam.frame()?;
+                log::trace!("cam.frame() returned Ok");
+                let img = frame.decode_image::<nokhwa::pixel_format::RgbFormat>()?;
+                let (w, h) = (img.width(), img.height());
+                Ok((img.into_raw(), w, h))
+            }
+            Source::Screen(screen) => Ok((
+                screen.frame(state.width, state.height)?,
+                state.width,
+                state.height,
+            )),
+        }
+    }
+
+    fn is_screen(&self) -> bool {
+        matches!(self, Source::Screen(_))
+    }
+}
+
 /// Holds all channels and state shared with the [`WebcamHandle`](crate::webcam::WebcamHandle).
 pub(crate) struct CaptureLoop {
     cmd_rx: Receiver<WebcamCommand>,
@@ -24,9 +69,16 @@ pub(crate) struct CaptureLoop {
     frame_tx_cb: Arc<Mutex<Option<Sender<WebcamFrame>>>>,
     samples_req: Arc<Mutex<u32>>,
     active_chan: Arc<Mutex<Option<usize>>>,
+    active_camera: Arc<Mutex<Option<String>>>,
+    sent: Arc<SentCounter>,
+    privacy: Arc<Privacy>,
+    selection: Arc<CameraSelection>,
+    // Frame source requested by the RDP settings
+    source: Option<String>,
 }
 
 impl CaptureLoop {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         cmd_rx: Receiver<WebcamCommand>,
         frame_out: Arc<Mutex<Option<Vec<u8>>>>,
@@ -34,6 +86,11 @@ impl CaptureLoop {
         frame_tx_cb: Arc<Mutex<Option<Sender<WebcamFrame>>>>,
         samples_req: Arc<Mutex<u32>>,
         active_chan: Arc<Mutex<Option<usize>>>,
+        active_camera: Arc<Mutex<Option<String>>>,
+        sent: Arc<SentCounter>,
+        privacy: Arc<Privacy>,
+        selection: Arc<CameraSelection>,
+        source: Option<String>,
     ) -> Self {
         Self {
             cmd_rx,
@@ -42,9 +99,50 @@ impl CaptureLoop {
             frame_tx_cb,
             samples_req,
             active_chan,
+            active_camera,
+            sent,
+            privacy,
+            selection,
+            source,
         }
     }
 
+    /// Opens the selected camera, or the screen or window being shared. `None`
+    /// (use the mock) if it cannot be opened.
+    fn open_camera(&self, width: u32, height: u32, fps: u32) -> Option<Source> {
+        let source = match selected_source(self.source.as_deref()) {
+            FrameSource::Camera => match init_real_camera(&self.selection, width, height, fps) {
+                Ok(cam) => {
+                    log::debug!("Real camera initialized successfully");
+                    Some(Source::Camera(cam))
+                }
+                Err(e) => {
+                    log::warn!(
+                        "Real camera initialization failed, falling back to mock: {}",
+                        e
+                    );
+                    None
+                }
+            },
+            shared => match ScreenCapture::open(&shared) {
+                Ok(screen) => Some(Source::Screen(screen)),
+                Err(e) => {
+                    log::warn!("Screen sharing failed, falling back to mock: {}", e);
+                    None
+                }
+            },
+        };
+        *self.active_camera.lock().unwrap() = source.as_ref().map(Source::name);
+        source
+    }
+
+    fn close_camera(&self, camera: &mut Option<Source>) {
+        if let Some(Source::Camera(mut cam)) = camera.take() {
+            let _ = cam.stop_stream();
+        }
+        *self.active_camera.lock().unwrap() = None;
+    }
+
     /// Spawns a thread that runs the capture loop until a `Close` command is
     /// received. Consumes `self` so the channels are dropped when the thread exits.
     pub(crate) fn run(self) {
@@ -56,8 +154,15 @@ impl CaptureLoop {
             let mut stream_start_time = std::time::Instant::now();
             let mut encoder: Box<dyn VideoEncoder> = Box::new(RawEncoder);
             let mut current_mode: Option<WebcamMode> = None;
-            let mut camera: Option<nokhwa::Camera> = None;
+            let mut camera: Option<Source> = None;
             let mut is_mock = false;
+            let mut watcher = CameraWatcher::new();
+            let mut rate = RateController::new(true);
+            let mut rate_window = RateWindow::default();
+            let mut rate_start = std::time::Instant::now();
+            let mut sent_base = self.sent.get();
+            // The camera is closed while paused, and opened again on resume
+            let mut paused = false;
 
             loop {
                 // Non-blocking query of commands
@@ -66,6 +171,10 @@ impl CaptureLoop {
                         WebcamCommand::StartStream { width, height, fps } => {
                             log::debug!("Webcam: StartStream {width}x{height} @ {fps}fps");
                             current_mode = None;
+                            rate = RateController::new(true);
+                            rate_window = RateWindow::default();
+                            rate_start = std::time::Instant::now();
+                            sent_base = self.sent.get();
                             frame_count = 0;
                             bytes_count = 0;
                             last_report = std::time::Instant::now();
@@ -78,30 +187,17 @@ impl CaptureLoop {
                                 color_offset: 0,
                             });
 
-                            let force_mock = std::env::var("UDSLAUNCHER_CAM_MOCK")
-                                .map(|v| v == "1" || v.to_lowercase() == "true")
-                                .unwrap_or(false);
-                            if force_mock {
+                            self.close_camera(&mut camera);
+                            self.privacy.set_streaming(true);
+                            paused = self.privacy.is_paused();
+                            if is_mock_forced() {
                                 log::info!("Mock Webcam forced by environment variable");
                                 is_mock = true;
-                                camera = None;
-                            } else {
-                                match init_real_camera(width, height, fps) {
-                                    Ok(cam) => {
-                                        camera = Some(cam);
-                                        is_mock = false;
-                                        log::debug!("Real camera initialized successfully");
-                                    }
-                                    Err(e) => {
-                                        log::warn!(
-                                            "Real camera initialization failed, falling back to mock: {}",
-                                            e
-                                        );
-                                        camera = None;
-                                        is_mock = true;
-                                    }
-                                }
+                            } else if !paused {
+                                camera = self.open_camera(width, height, fps);
+                                is_mock = camera.is_none();
                             }
+                            watcher = CameraWatcher::new();
                         }
                         WebcamCommand::SetFormat {
                             format: _,
@@ -111,6 +207,10 @@ impl CaptureLoop {
                         } => {
                             log::debug!("Webcam: SetFormat {width}x{height} @ {fps}fps");
                             current_mode = None;
+                            rate = RateController::new(true);
+                            rate_window = RateWindow::default();
+                            rate_start = std::time::Instant::now();
+                            sent_base = self.sent.get();
                             let mut needs_restart = true;
                             if let Some(ref mut s) = state {
                                 if s.width == width
@@ -129,67 +229,74 @@ impl CaptureLoop {
                                 }
                             }
 
-                            if !is_mock && needs_restart {
-                                if let Some(ref mut cam) = camera {
-                                    let _ = cam.stop_stream();
-                                }
-                                match init_real_camera(width, height, fps) {
-                                    Ok(cam) => {
-                                        camera = Some(cam);
-                                        is_mock = false;
-                                    }
-                                    Err(e) => {
-                                        log::warn!(
-                                            "Failed to re-initialize camera for new format, falling back to mock: {e}"
-                                        );
-                                        camera = None;
-                                        is_mock = true;
-                                    }
-                                }
+                            if !is_mock && !paused && needs_restart {
+                                self.close_camera(&mut camera);
+                                camera = self.open_camera(width, height, fps);
+                                is_mock = camera.is_none();
+                            }
+                        }
+                        WebcamCommand::SwitchCamera { camera: wanted } => {
+                            log::debug!("Webcam: SwitchCamera {wanted}");
+                            self.selection.switch_to(&wanted);
+                            // Moves now if streaming, otherwise used on the next StartStream
+                            if let Some(ref s) = state
+                                && !is_mock_forced()
+                                && !paused
+                            {
+                                self.close_camera(&mut camera);
+                                camera = self.open_camera(s.width, s.height, s.fps);
+                                is_mock = camera.is_none();
                             }
                         }
                         WebcamCommand::StopStream => {
                             log::debug!("Webcam: StopStream");
-                            if let Some(mut cam) = camera.take() {
-                                let _ = cam.stop_stream();
-                            }
+                            self.close_camera(&mut camera);
+                            self.privacy.set_streaming(false);
                             state = None;
                             *self.frame_out.lock().unwrap() = None;
                         }
                         WebcamCommand::Close => {
                             log::debug!("Webcam: Close");
-                            if let Some(mut cam) = camera.take() {
-                                let _ = cam.stop_stream();
-                            }
+                            self.close_camera(&mut camera);
+                            self.privacy.set_streaming(false);
                             return;
                         }
                     }
                 }
 
                 if let Some(ref mut s) = state {
+                    if self.privacy.is_paused() != paused {
+                        paused = !paused;
+                        self.close_camera(&mut camera);
+                        if !paused && !is_mock_forced() {
+                            camera = self.open_camera(s.width, s.height, s.fps);
+                            is_mock = camera.is_none();
+                        }
+                    }
+
+                    // Hot-plug: current camera unplugged, or the wanted one plugged.
+                    // Nothing to follow while sharing the screen.
+                    let current = self.active_camera.lock().unwrap().clone();
+                    if !paused
+                        && !camera.as_ref().is_some_and(Source::is_screen)
+                        && watcher.needs_switch(&self.selection, current.as_deref())
+                    {
+                        self.close_camera(&mut camera);
+                        camera = self.open_camera(s.width, s.height, s.fps);
+                        is_mock = camera.is_none();
+                    }
+
                     log::trace!(
                         "Webcam capture loop iteration: frame_count = {}",
                         frame_count
                     );
-                    let (rgb, src_w, src_h) = if is_mock {
+                    let (rgb, src_w, src_h) = if paused {
+                        (generate_paused_frame(s), s.width, s.height)
+                    } else if is_mock {
                         (generate_mock_frame(s), s.width, s.height)
-                    } else if let Some(ref mut cam) = camera {
-                        log::trace!("Calling cam.frame()...");
-                        match cam.frame() {
-                            Ok(frame) => {
-                                log::trace!("cam.frame() returned Ok");
-                                match frame.decode_image::<nokhwa::pixel_format::RgbFormat>() {
-                                    Ok(img) => {
-                                        let w = img.width();
-                                        let h = img.height();
-                                        (img.into_raw(), w, h)
-                                    }
-                                    Err(e) => {
-                                        log::error!("Failed to decode camera frame: {e}");
-                                        (generate_mock_frame(s), s.width, s.height)
-                                    }
-                                }
-                            }
+                    } else if let Some(ref mut source) = camera {
+                        match source.frame(s) {
+                            Ok(frame) => frame,
                             Err(e) => {
                                 log::error!("Failed to capture camera frame: {e}");
                                 (generate_mock_frame(s), s.width, s.height)
@@ -199,8 +306,19 @@ impl CaptureLoop {
                         (generate_mock_frame(s), s.width, s.height)
                     };
 
+                    let quality = WEBCAM_QUALITY.load(std::sync::atomic::Ordering::Relaxed);
+                    let target = rate.target(s.fps, quality);
+
                     let (dst_w, dst_h) = calculate_scaled_dimensions(s.width, s.height);
-                    let rgb_scaled = resize_rgb(&rgb, src_w, src_h, dst_w, dst_h);
+                    let mut rgb_scaled = resize_rgb(&rgb, src_w, src_h, dst_w, dst_h);
+                    if !paused && self.privacy.is_blurred() {
+                        rgb_scaled = blur_background(&rgb_scaled, dst_w, dst_h);
+                    }
+                    if target.detail < 100 {
+                        rgb_scaled = reduce_detail(&rgb_scaled, dst_w, dst_h, target.detail);
+                    }
+                    // What the server gets, for the local preview
+                    self.privacy.update_preview(&rgb_scaled, dst_w, dst_h);
 
                     let mode_val = *self.cam_mode.lock().unwrap();
                     if current_mode != Some(mode_val) {
@@ -218,15 +336,23 @@ impl CaptureLoop {
                             },
                             WebcamMode::Raw => Box::new(RawEncoder),
                         };
-                        let q = WEBCAM_QUALITY.load(std::sync::atomic::Ordering::Relaxed);
-                        let _ = encoder.init(dst_w, dst_h, s.fps, q);
+                        let _ = encoder.init(dst_w, dst_h, target.fps, quality);
+                        if target.quality < quality
+                            && let Err(e) = encoder.set_quality(target.quality)
+                        {
+                            log::warn!("Webcam encoder quality not set: {e}");
+                        }
+                        let compressed = matches!(mode_val, WebcamMode::MJPEG | WebcamMode::H264);
+                        if rate.compressed() != compressed {
+                            rate = RateController::new(compressed);
+                        }
                         current_mode = Some(mode_val);
                         log::info!(
                             "Webcam encoder initialized: Mode={:?}, Resolution={}x{}, FPS={}",
                             mode_val,
                             dst_w,
                             dst_h,
-                            s.fps
+                            target.fps
                         );
                     }
 
@@ -238,22 +364,52 @@ impl CaptureLoop {
                         }
                     };
 
-                    *self.frame_out.lock().unwrap() = Some(output.clone());
-                    frame_count += 1;
-                    bytes_count += output.len() as u64;
+                    // Empty when the encoder skips the frame (H264 rate control)
+                    if !output.is_empty() {
+                        *self.frame_out.lock().unwrap() = Some(output.clone());
+                        frame_count += 1;
+                        bytes_count += output.len() as u64;
+                        rate_window.produced_frames += 1;
+                        rate_window.produced_bytes += output.len() as u64;
 
-                    let mut reqs = self.samples_req.lock().unwrap();
-                    if *reqs > 0
-                        && let (Some(chan), Some(tx)) = (
-                            *self.active_chan.lock().unwrap(),
-                            self.frame_tx_cb.lock().unwrap().as_ref(),
-                        )
-                    {
-                        *reqs -= 1;
-                        let _ = tx.send(WebcamFrame {
-                            data: output,
-                            channel_ptr: chan,
-                        });
+                        let mut reqs = self.samples_req.lock().unwrap();
+                        if *reqs > 0
+                            && let (Some(chan), Some(tx)) = (
+                                *self.active_chan.lock().unwrap(),
+                                self.frame_tx_cb.lock().unwrap().as_ref(),
+                            )
+                        {
+                            *reqs -= 1;
+                            self.sent.add(output.len());
+                            let _ = tx.send(WebcamFrame {
+                                data: output,
+                                channel_ptr: chan,
+                            });
+                        }
+                    }
+
+                    // Frames the server did not ask for are the congestion signal
+                    let rate_elapsed = rate_start.elapsed();
+                    if rate_elapsed >= RATE_WINDOW {
+                        let sent_now = self.sent.get();
+                        rate_window.seconds = rate_elapsed.as_secs_f64();
+                        rate_window.sent_frames = sent_now.0 - sent_base.0;
+                        rate_window.sent_bytes = sent_now.1 - sent_base.1;
+                        if rate.evaluate(&rate_window) {
+                            let new_target = rate.target(s.fps, quality);
+                            log::info!("Webcam {}", rate);
+                            if new_target.fps != target.fps {
+                                // Encoders are set up for a frame rate, start again
+                                current_mode = None;
+                            } else if new_target.quality != target.quality
+                                && let Err(e) = encoder.set_quality(new_target.quality)
+                            {
+                                log::warn!("Webcam encoder quality not set: {e}");
+                            }
+                        }
+                        rate_window = RateWindow::default();
+                        rate_start = std::time::Instant::now();
+                        sent_base = sent_now;
                     }
 
                     let elapsed_total = stream_start_time.elapsed().as_secs();
@@ -281,13 +437,14 @@ impl CaptureLoop {
                                 None => "None",
                             };
                             log::debug!(
-                                "Webcam [{}]: {} frames in {:.1}s (~{:.1} fps), {:.0} bytes/s, {:.0} bytes/frame",
+                                "Webcam [{}]: {} frames in {:.1}s (~{:.1} fps), {:.0} bytes/s, {:.0} bytes/frame, {}",
                                 mode_name,
                                 frame_count,
                                 duration,
                                 fps,
                                 bytes_per_sec,
                                 bytes_per_frame,
+                                rate,
                             );
                         }
                         frame_count = 0;
@@ -295,7 +452,7 @@ impl CaptureLoop {
                         last_report = std::time::Instant::now();
                     }
 
-                    let interval = Duration::from_secs_f64(1.0 / s.fps.max(1) as f64);
+                    let interval = Duration::from_secs_f64(1.0 / target.fps.max(1) as f64);
                     thread::sleep(interval);
                 } else {
                     thread::sleep(Duration::from_millis(50));
@@ -0,0 +1,294 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+// Camera enumeration, selection and hot-plug detection.
+// The camera comes from (first found): a switch requested during the session,
+// the env var or the RDP settings of the session. With none of them (or if the
+// wanted one is not plugged) the first camera is used.
+use std::sync::RwLock;
+use std::time::Instant;
+
+use nokhwa::utils::{
+    ApiBackend, CameraFormat, CameraIndex, FrameFormat, RequestedFormat, RequestedFormatType,
+    Resolution,
+};
+use shared::log;
+
+use crate::audio::devices::{DEVICE_CHECK_INTERVAL, match_name};
+
+pub const CAM_DEVICE_ENV: &str = "UDSLAUNCHER_CAM_DEVICE";
+pub const CAM_MOCK_ENV: &str = "UDSLAUNCHER_CAM_MOCK";
+
+pub const MOCK_CAMERA_NAME: &str = "Mock Camera";
+
+/// A camera on the system, with the formats it can capture
+#[derive(Debug, Clone)]
+pub struct CameraDevice {
+    pub index: CameraIndex,
+    pub name: String,
+    pub formats: Vec<CameraFormat>,
+}
+
+/// Camera wanted by a session, shared by its handle and its capture loop
+#[derive(Debug, Default)]
+pub struct CameraSelection {
+    session: Option<String>,          // From the RDP settings
+    switched: RwLock<Option<String>>, // Chosen while the session runs
+}
+
+pub fn is_mock_forced() -> bool {
+    std::env::var(CAM_MOCK_ENV)
+        .map(|v| v == "1" || v.to_lowercase() == "true")
+        .unwrap_or(false)
+}
+
+fn select_from(
+    switched: Option<String>,
+    env: Option<String>,
+    session: Option<String>,
+) -> Option<String> {
+    switched
+        .or(env)
+        .or(session)
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty() && !v.eq_ignore_ascii_case("default"))
+}
+
+impl CameraSelection {
+    /// `camera` is the one requested by the RDP settings, if any
+    pub fn new(camera: Option<String>) -> Self {
+        log::debug!("Session camera: {:?}", camera);
+        CameraSelection {
+            session: camera,
+            switched: RwLock::new(None),
+        }
+    }
+
+    pub(crate) fn switch_to(&self, camera: &str) {
+        *self.switched.write().unwrap() = Some(camera.to_string());
+    }
+
+    /// Index or (part of) the name of the wanted camera, `None` for the first one
+    pub fn wanted(&self) -> Option<String> {
+        select_from(
+            self.switched.read().unwrap().clone(),
+            std::env::var(CAM_DEVICE_ENV).ok(),
+            self.session.clone(),
+        )
+    }
+}
+
+/// Cameras on the system (index and name), without opening them
+pub fn camera_names() -> Vec<(CameraIndex, String)> {
+    if is_mock_forced() {
+        return vec![(CameraIndex::Index(0), MOCK_CAMERA_NAME.to_string())];
+    }
+    match nokhwa::query(ApiBackend::Auto) {
+        Ok(devices) => devices
+            .into_iter()
+            .map(|d| (d.index().clone(), d.human_name()))
+            .collect(),
+        Err(e) => {
+            log::warn!("Cannot enumerate cameras: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+pub(crate) fn mock_formats() -> Vec<CameraFormat> {
+    vec![
+        CameraFormat::new(Resolution::new(640, 480), FrameFormat::MJPEG, 30),
+        CameraFormat::new(Resolution::new(640, 480), FrameFormat::YUYV, 30),
+        CameraFormat::new(Resolution::new(1280, 720), FrameFormat::MJPEG, 30),
+        CameraFormat::new(Resolution::new(1920, 1080), FrameFormat::MJPEG, 30),
+    ]
+}
+
+/// Formats of a camera. Needs to open it, so a camera in use (even by us) may
+/// return none.
+pub(crate) fn query_formats(index: &CameraIndex) -> Vec<CameraFormat> {
+    if is_mock_forced() {
+        return mock_formats();
+    }
+    let requested =
+        RequestedFormat::new::<nokhwa::pixel_format::RgbFormat>(RequestedFormatType::None);
+    match nokhwa::Camera::new(index.clone(), requested) {
+        Ok(mut cam) => cam.compatible_camera_formats().unwrap_or_else(|e| {
+            log::debug!("Cannot query formats of camera {}: {}", index, e);
+            Vec::new()
+        }),
+        Err(e) => {
+            log::debug!("Cannot open camera {}: {}", index, e);
+            Vec::new()
+        }
+    }
+}
+
+/// Cameras on the system with their supported formats, for settings UIs
+pub fn list_cameras() -> Vec<CameraDevice> {
+    camera_names()
+        .into_iter()
+        .map(|(index, name)| CameraDevice {
+            formats: query_formats(&index),
+            index,
+            name,
+        })
+        .collect()
+}
+
+fn select_index(names: &[String], selection: Option<&str>) -> Option<usize> {
+    if names.is_empty() {
+        return None;
+    }
+    match selection.and_then(|wanted| match_name(names, wanted)) {
+        Some(idx) => Some(idx),
+        None => {
+            if let Some(wanted) = selection {
+                log::warn!(
+                    "Camera '{}' not found (available: {:?}), using the first one",
+                    wanted,
+                    names
+                );
+            }
+            Some(0)
+        }
+    }
+}
+
+/// Camera to use now, following the selection
+pub fn selected_camera(selection: &CameraSelection) -> Option<(CameraIndex, String)> {
+    let mut cameras = camera_names();
+    let names: Vec<String> = cameras.iter().map(|(_, n)| n.clone()).collect();
+    select_index(&names, selection.wanted().as_deref()).map(|idx| cameras.swap_remove(idx))
+}
+
+/// Camera after `current` on the list, wrapping around
+pub(crate) fn next_camera_name(names: &[String], current: Option<&str>) -> Option<String> {
+    let pos = current.and_then(|c| names.iter().position(|n| n == c));
+    let next = match pos {
+        Some(p) => (p + 1) % names.len(),
+        None => 0,
+    };
+    names.get(next).cloned()
+}
+
+// Whether the capture must move from `current` to another camera
+fn should_switch(selection: Option<&str>, current: Option<&str>, available: &[String]) -> bool {
+    if available.is_empty() {
+        return false; // Nothing to move to, keep what we have (or the mock)
+    }
+    let Some(current) = current else {
+        return true; // A camera has been plugged
+    };
+    if !available.iter().any(|n| n == current) {
+        return true; // Unplugged
+    }
+    // The wanted one (is back) and we are not on it
+    selection
+        .and_then(|wanted| match_name(available, wanted))
+        .is_some_and(|idx| available[idx] != current)
+}
+
+/// Checks from time to time, while streaming, whether the camera has been
+/// unplugged or the wanted one plugged.
+pub struct CameraWatcher {
+    last_check: Instant,
+}
+
+impl CameraWatcher {
+    pub fn new() -> Self {
+        CameraWatcher {
+            last_check: Instant::now(),
+        }
+    }
+
+    pub fn needs_switch(&mut self, selection: &CameraSelection, current: Option<&str>) -> bool {
+        if self.last_check.elapsed() < DEVICE_CHECK_INTERVAL || is_mock_forced() {
+            return false;
+        }
+        self.last_check = Instant::now();
+        let available: Vec<String> = camera_names().into_iter().map(|(_, n)| n).collect();
+        let switch = should_switch(selection.wanted().as_deref(), current, &available);
+        if switch {
+            log::info!(
+                "Camera change (current={:?}, available={:?})",
+                current,
+                available
+            );
+        }
+        switch
+    }
+}
+
+impl Default for CameraWatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn selection_priority() {
+        let s = |v: &str| Some(v.to_string());
+        assert_eq!(select_from(s("B"), s("E"), s("S")), s("B"));
+        assert_eq!(select_from(None, s("E"), s("S")), s("E"));
+        assert_eq!(select_from(None, None, s(" S ")), s("S"));
+        assert_eq!(select_from(None, None, s("default")), None);
+        assert_eq!(select_from(None, s(""), None), None);
+    }
+
+    #[test]
+    fn select_camera() {
+        let list = names(&["Integrated Camera", "Logitech BRIO", "OBS Virtual Camera"]);
+        assert_eq!(select_index(&list, None), Some(0));
+        assert_eq!(select_index(&list, Some("brio")), Some(1));
+        assert_eq!(select_index(&list, Some("2")), Some(2));
+        assert_eq!(select_index(&list, Some("Missing")), Some(0));
+        assert_eq!(select_index(&[], Some("brio")), None);
+    }
+
+    #[test]
+    fn next_camera() {
+        let list = names(&["A", "B", "C"]);
+        assert_eq!(next_camera_name(&list, Some("A")).as_deref(), Some("B"));
+        assert_eq!(next_camera_name(&list, Some("C")).as_deref(), Some("A"));
+        assert_eq!(next_camera_name(&list, None).as_deref(), Some("A"));
+        assert_eq!(next_camera_name(&list, Some("Gone")).as_deref(), Some("A"));
+        assert_eq!(next_camera_name(&[], Some("A")), None);
+    }
+
+    #[test]
+    fn switch_on_hotplug() {
+        let list = names(&["Integrated Camera", "Logitech BRIO"]);
+        // Nothing changed
+        assert!(!should_switch(None, Some("Integrated Camera"), &list));
+        assert!(!should_switch(Some("brio"), Some("Logitech BRIO"), &list));
+        // Unplugged
+        assert!(should_switch(None, Some("USB Camera"), &list));
+        // Wanted one plugged again
+        assert!(should_switch(
+            Some("brio"),
+            Some("Integrated Camera"),
+            &list
+        ));
+        // Wanted one missing, stay
+        assert!(!should_switch(
+            Some("USB"),
+            Some("Integrated Camera"),
+            &list
+        ));
+        // First camera plugged while on the mock
+        assert!(should_switch(None, None, &list));
+        // All gone, keep going (the capture falls back to the mock)
+        assert!(!should_switch(None, Some("Integrated Camera"), &[]));
+    }
+}
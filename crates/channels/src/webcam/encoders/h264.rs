@@ -15,6 +15,7 @@ pub struct H264Encoder {
     width: u32,
     height: u32,
     fps: u32,
+    quality: u32, // Of the session, runs without rate control
     y_plane: Vec<u8>,
     u_plane: Vec<u8>,
     v_plane: Vec<u8>,
@@ -34,6 +35,7 @@ impl H264Encoder {
             width: 0,
             height: 0,
             fps: 0,
+            quality: 0,
             y_plane: Vec::new(),
             u_plane: Vec::new(),
             v_plane: Vec::new(),
@@ -42,6 +44,46 @@ impl H264Encoder {
             sps_pps: Vec::new(),
         })
     }
+
+    fn configure(&mut self, quality: u32) -> Result<()> {
+        let encoder = self.encoder.as_mut().context("Encoder is not created")?;
+
+        // Prepare configuration using the safe EncoderConfig builder
+        let q = quality.clamp(1, 100);
+        let base_bitrate = (self.width * self.height * self.fps * 2 / 10) as f64;
+        let target_bitrate = (base_bitrate * (q as f64 / 100.0)) as i32;
+        // At the session quality there is no rate control, below it (rate control
+        // of the stream lowering it) the bitrate is enforced
+        let rc_mode = if q >= self.quality {
+            -1 // RC_OFF_MODE
+        } else {
+            1 // RC_BITRATE_MODE
+        };
+
+        let config = EncoderConfig::new(self.width, self.height, self.fps as f32)
+            .with_bitrate(target_bitrate)
+            .with_rc_mode(rc_mode);
+
+        encoder
+            .initialize(&config)
+            .context("OpenH264 initialize failed")?;
+
+        // SAFETY: The option values are correctly typed for each option ID.
+        unsafe {
+            // ENCODER_OPTION_DATAFORMAT = 0, videoFormatI420 = 23
+            let mut video_format = 23i32;
+            let _ = encoder.set_option(0, &mut video_format as *mut i32 as *mut c_void);
+
+            // ENCODER_OPTION_TRACE_LEVEL = 19
+            let mut log_level = 2i32;
+            let _ = encoder.set_option(19, &mut log_level as *mut i32 as *mut c_void);
+
+            // ENCODER_OPTION_TRACE_CALLBACK = 20
+            let mut callback: openh264::WelsTraceCallback = openh264_trace_callback;
+            let _ = encoder.set_option(20, &mut callback as *mut _ as *mut c_void);
+        }
+        Ok(())
+    }
 }
 
 // No need for a custom Drop — `Encoder` handles uninitialize + destroy automatically.
@@ -67,37 +109,12 @@ impl VideoEncoder for H264Encoder {
         self.u_plane = vec![128u8; uv_len];
         self.v_plane = vec![128u8; uv_len];
 
-        // Prepare configuration using the safe EncoderConfig builder
-        let q = if quality == 0 {
+        self.quality = if quality == 0 {
             80
         } else {
             quality.clamp(1, 100)
         };
-        let base_bitrate = (width * height * fps * 2 / 10) as f64;
-        let target_bitrate = (base_bitrate * (q as f64 / 100.0)) as i32;
-
-        let config = EncoderConfig::new(width, height, fps as f32)
-            .with_bitrate(target_bitrate)
-            .with_rc_mode(-1); // RC_OFF_MODE
-
-        encoder
-            .initialize(&config)
-            .context("OpenH264 initialize failed")?;
-
-        // SAFETY: The option values are correctly typed for each option ID.
-        unsafe {
-            // ENCODER_OPTION_DATAFORMAT = 0, videoFormatI420 = 23
-            let mut video_format = 23i32;
-            let _ = encoder.set_option(0, &mut video_format as *mut i32 as *mut c_void);
-
-            // ENCODER_OPTION_TRACE_LEVEL = 19
-            let mut log_level = 2i32;
-            let _ = encoder.set_option(19, &mut log_level as *mut i32 as *mut c_void);
-
-            // ENCODER_OPTION_TRACE_CALLBACK = 20
-            let mut callback: openh264::WelsTraceCallback = openh264_trace_callback;
-            let _ = encoder.set_option(20, &mut callback as *mut _ as *mut c_void);
-        }
+        self.configure(self.quality)?;
 
         log::info!(
             "OpenH264 initialized successfully: {}x{} @ {}fps",
@@ -312,6 +329,22 @@ impl VideoEncoder for H264Encoder {
             Ok(filtered_data)
         }
     }
+
+    fn set_quality(&mut self, quality: u32) -> Result<()> {
+        if self.width == 0 {
+            return Ok(()); // Not initialized yet, init will get it
+        }
+        let encoder = self.encoder.as_mut().context("Encoder is not created")?;
+        encoder
+            .uninitialize()
+            .context("OpenH264 uninitialize failed")?;
+        // New parameter sets, so start again with an IDR
+        self.has_sent_idr = false;
+        self.sps_pps.clear();
+        self.configure(quality)?;
+        log::debug!("OpenH264 quality set to {}", quality);
+        Ok(())
+    }
 }
 
 unsafe extern "C" fn openh264_trace_callback(
@@ -0,0 +1,465 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+// Baseline JPEG encoder in pure Rust, for MJPEG when turbojpeg is not there (built
+// without it, or failed to start). YCbCr 4:2:0 with the quantization and Huffman
+// tables of the JPEG standard (Annex K), and the libjpeg quality scaling.
+use anyhow::{Result, bail};
+
+use super::VideoEncoder;
+
+// Natural (row major) index of each zig-zag position
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+// Natural order
+const STD_LUMA_QUANT: [u16; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61, 12, 12, 14, 19, 26, 58, 60, 55, 14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62, 18, 22, 37, 56, 68, 109, 103, 77, 24, 35, 55, 64, 81, 104, 113,
+    92, 49, 64, 78, 87, 103, 121, 120, 101, 72, 92, 95, 98, 112, 100, 103, 99,
+];
+const STD_CHROMA_QUANT: [u16; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99, 18, 21, 26, 66, 99, 99, 99, 99, 24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+// Huffman tables: number of codes of each length (1..=16), then the symbols
+const DC_LUMA_BITS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const DC_CHROMA_BITS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const DC_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+
+const AC_LUMA_BITS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d];
+const AC_LUMA_VALUES: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+    0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5,
+    0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+const AC_CHROMA_BITS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+const AC_CHROMA_VALUES: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0,
+    0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26,
+    0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5,
+    0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3,
+    0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda,
+    0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+/// Code and length of each symbol
+struct HuffmanTable {
+    codes: [(u16, u8); 256],
+}
+
+impl HuffmanTable {
+    // Canonical codes, as in Annex C
+    fn new(bits: &[u8; 16], values: &[u8]) -> Self {
+        let mut codes = [(0u16, 0u8); 256];
+        let mut code = 0u16;
+        let mut k = 0;
+        for (len, &count) in bits.iter().enumerate() {
+            for _ in 0..count {
+                codes[values[k] as usize] = (code, len as u8 + 1);
+                code += 1;
+                k += 1;
+            }
+            code <<= 1;
+        }
+        HuffmanTable { codes }
+    }
+}
+
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn new(out: Vec<u8>) -> Self {
+        BitWriter {
+            out,
+            acc: 0,
+            bits: 0,
+        }
+    }
+
+    fn put(&mut self, value: u16, len: u8) {
+        self.acc = (self.acc << len) | (value as u32 & ((1 << len) - 1));
+        self.bits += len as u32;
+        while self.bits >= 8 {
+            let byte = (self.acc >> (self.bits - 8)) as u8;
+            self.out.push(byte);
+            // Byte stuffing, 0xFF on the data would read as a marker
+            if byte == 0xFF {
+                self.out.push(0);
+            }
+            self.bits -= 8;
+        }
+        self.acc &= (1 << self.bits) - 1;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        // Pad with ones
+        if self.bits > 0 {
+            let pad = 8 - self.bits as u8;
+            self.put((1 << pad) - 1, pad);
+        }
+        self.out
+    }
+}
+
+/// Quantization table in natural order for `quality` (1..=100), libjpeg scaling
+fn scale_quant(table: &[u16; 64], quality: u32) -> [u16; 64] {
+    let quality = quality.clamp(1, 100);
+    let scale = if quality < 50 {
+        5000 / quality
+    } else {
+        200 - quality * 2
+    };
+    table.map(|q| ((q as u32 * scale + 50) / 100).clamp(1, 255) as u16)
+}
+
+// Basis of the 8 point DCT, with the normalization of the standard
+fn dct_table() -> [[f32; 8]; 8] {
+    let mut table = [[0.0f32; 8]; 8];
+    for (u, row) in table.iter_mut().enumerate() {
+        let c = if u == 0 {
+            std::f32::consts::FRAC_1_SQRT_2
+        } else {
+            1.0
+        };
+        for (x, v) in row.iter_mut().enumerate() {
+            *v = c / 2.0 * ((2 * x + 1) as f32 * u as f32 * std::f32::consts::PI / 16.0).cos();
+        }
+    }
+    table
+}
+
+// Number of bits of the magnitude, and the bits themselves (one's complement
+// for negatives)
+fn magnitude(value: i32) -> (u8, u16) {
+    let abs = value.unsigned_abs();
+    let size = (32 - abs.leading_zeros()) as u8;
+    let bits = if value < 0 {
+        (value - 1) as u16 & ((1 << size) - 1) as u16
+    } else {
+        value as u16
+    };
+    (size, bits)
+}
+
+pub struct JpegEncoder {
+    width: u32,
+    height: u32,
+    luma_quant: [u16; 64],
+    chroma_quant: [u16; 64],
+    dct: [[f32; 8]; 8],
+    dc_luma: HuffmanTable,
+    ac_luma: HuffmanTable,
+    dc_chroma: HuffmanTable,
+    ac_chroma: HuffmanTable,
+}
+
+impl JpegEncoder {
+    pub fn new() -> Self {
+        let mut encoder = JpegEncoder {
+            width: 0,
+            height: 0,
+            luma_quant: STD_LUMA_QUANT,
+            chroma_quant: STD_CHROMA_QUANT,
+            dct: dct_table(),
+            dc_luma: HuffmanTable::new(&DC_LUMA_BITS, &DC_VALUES),
+            ac_luma: HuffmanTable::new(&AC_LUMA_BITS, &AC_LUMA_VALUES),
+            dc_chroma: HuffmanTable::new(&DC_CHROMA_BITS, &DC_VALUES),
+            ac_chroma: HuffmanTable::new(&AC_CHROMA_BITS, &AC_CHROMA_VALUES),
+        };
+        encoder.apply_quality(80);
+        encoder
+    }
+
+    fn apply_quality(&mut self, quality: u32) {
+        self.luma_quant = scale_quant(&STD_LUMA_QUANT, quality);
+        self.chroma_quant = scale_quant(&STD_CHROMA_QUANT, quality);
+    }
+
+    fn write_headers(&self, out: &mut Vec<u8>) {
+        let mut segment = |marker: u8, body: &[u8]| {
+            out.extend_from_slice(&[0xFF, marker]);
+            out.extend_from_slice(&(body.len() as u16 + 2).to_be_bytes());
+            out.extend_from_slice(body);
+        };
+        // JFIF, 1:1 aspect
+        segment(0xE0, b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0");
+        for (id, table) in [(0u8, &self.luma_quant), (1, &self.chroma_quant)] {
+            let mut body = vec![id];
+            body.extend(ZIGZAG.iter().map(|&n| table[n] as u8));
+            segment(0xDB, &body);
+        }
+        // Baseline, 8 bits. Y 2x2 with table 0, Cb and Cr 1x1 with table 1.
+        let mut sof = vec![8];
+        sof.extend_from_slice(&(self.height as u16).to_be_bytes());
+        sof.extend_from_slice(&(self.width as u16).to_be_bytes());
+        sof.extend_from_slice(&[3, 1, 0x22, 0, 2, 0x11, 1, 3, 0x11, 1]);
+        segment(0xC0, &sof);
+        let tables: [(u8, &[u8; 16], &[u8]); 4] = [
+            (0x00, &DC_LUMA_BITS, &DC_VALUES),
+            (0x10, &AC_LUMA_BITS, &AC_LUMA_VALUES),
+            (0x01, &DC_CHROMA_BITS, &DC_VALUES),
+            (0x11, &AC_CHROMA_BITS, &AC_CHROMA_VALUES),
+        ];
+        for (class_id, bits, values) in tables {
+            let mut body = vec![class_id];
+            body.extend_from_slice(bits);
+            body.extend_from_slice(values);
+            segment(0xC4, &body);
+        }
+        segment(0xDA, &[3, 1, 0x00, 2, 0x11, 3, 0x11, 0, 63, 0]);
+    }
+
+    // Forward DCT and quantization of a level shifted block, zig-zag order out
+    fn transform(&self, block: &[f32; 64], quant: &[u16; 64]) -> [i32; 64] {
+        let mut rows = [0.0f32; 64];
+        for y in 0..8 {
+            for u in 0..8 {
+                rows[y * 8 + u] = (0..8).map(|x| self.dct[u][x] * block[y * 8 + x]).sum();
+            }
+        }
+        let mut out = [0i32; 64];
+        for (k, &n) in ZIGZAG.iter().enumerate() {
+            let (v, u) = (n / 8, n % 8);
+            let coef: f32 = (0..8).map(|y| self.dct[v][y] * rows[y * 8 + u]).sum();
+            // Baseline Huffman tables code AC values up to 10 bits
+            let limit = if k == 0 { 2047 } else { 1023 };
+            out[k] = ((coef / quant[n] as f32).round() as i32).clamp(-limit, limit);
+        }
+        out
+    }
+
+    fn encode_block(
+        writer: &mut BitWriter,
+        coefs: &[i32; 64],
+        prev_dc: &mut i32,
+        dc: &HuffmanTable,
+        ac: &HuffmanTable,
+    ) {
+        let (size, bits) = magnitude(coefs[0] - *prev_dc);
+        *prev_dc = coefs[0];
+        let (code, len) = dc.codes[size as usize];
+        writer.put(code, len);
+        writer.put(bits, size);
+
+        let mut run = 0;
+        for &coef in &coefs[1..] {
+            if coef == 0 {
+                run += 1;
+                continue;
+            }
+            while run > 15 {
+                let (code, len) = ac.codes[0xF0]; // ZRL, 16 zeros
+                writer.put(code, len);
+                run -= 16;
+            }
+            let (size, bits) = magnitude(coef);
+            let (code, len) = ac.codes[(run << 4) | size as usize];
+            writer.put(code, len);
+            writer.put(bits, size);
+            run = 0;
+        }
+        if run > 0 {
+            let (code, len) = ac.codes[0x00]; // EOB
+            writer.put(code, len);
+        }
+    }
+
+    /// Encodes an RGB24 frame of the configured size
+    pub fn encode_rgb(&self, rgb: &[u8]) -> Result<Vec<u8>> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        if width == 0 || height == 0 || width > u16::MAX as usize || height > u16::MAX as usize {
+            bail!("Invalid JPEG dimensions {}x{}", width, height);
+        }
+        if rgb.len() < width * height * 3 {
+            bail!("RGB buffer is too small for the configured dimensions");
+        }
+
+        let mut out = Vec::with_capacity(width * height / 4);
+        out.extend_from_slice(&[0xFF, 0xD8]);
+        self.write_headers(&mut out);
+        let mut writer = BitWriter::new(out);
+
+        let mut prev = [0i32; 3];
+        let mut y_blocks = [[0.0f32; 64]; 4];
+        let mut cb = [0.0f32; 64];
+        let mut cr = [0.0f32; 64];
+        for mcu_y in (0..height).step_by(16) {
+            for mcu_x in (0..width).step_by(16) {
+                cb.fill(0.0);
+                cr.fill(0.0);
+                for dy in 0..16 {
+                    // Edges repeat the last row / column
+                    let y = (mcu_y + dy).min(height - 1);
+                    for dx in 0..16 {
+                        let x = (mcu_x + dx).min(width - 1);
+                        let idx = (y * width + x) * 3;
+                        let (r, g, b) = (rgb[idx] as f32, rgb[idx + 1] as f32, rgb[idx + 2] as f32);
+                        let block = (dy / 8) * 2 + dx / 8;
+                        y_blocks[block][(dy % 8) * 8 + dx % 8] =
+                            0.299 * r + 0.587 * g + 0.114 * b - 128.0;
+                        // 2x2 average for the chroma
+                        let c = (dy / 2) * 8 + dx / 2;
+                        cb[c] += (-0.168_736 * r - 0.331_264 * g + 0.5 * b) / 4.0;
+                        cr[c] += (0.5 * r - 0.418_688 * g - 0.081_312 * b) / 4.0;
+                    }
+                }
+                for block in &y_blocks {
+                    let coefs = self.transform(block, &self.luma_quant);
+                    Self::encode_block(
+                        &mut writer,
+                        &coefs,
+                        &mut prev[0],
+                        &self.dc_luma,
+                        &self.ac_luma,
+                    );
+                }
+                for (block, prev_dc) in [&cb, &cr].into_iter().zip(&mut prev[1..]) {
+                    let coefs = self.transform(block, &self.chroma_quant);
+                    Self::encode_block(
+                        &mut writer,
+                        &coefs,
+                        prev_dc,
+                        &self.dc_chroma,
+                        &self.ac_chroma,
+                    );
+                }
+            }
+        }
+
+        let mut out = writer.finish();
+        out.extend_from_slice(&[0xFF, 0xD9]);
+        Ok(out)
+    }
+}
+
+impl Default for JpegEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VideoEncoder for JpegEncoder {
+    fn init(&mut self, width: u32, height: u32, _fps: u32, quality: u32) -> Result<()> {
+        self.width = width;
+        self.height = height;
+        self.apply_quality(quality);
+        Ok(())
+    }
+
+    fn encode(&mut self, rgb: &[u8]) -> Result<Vec<u8>> {
+        self.encode_rgb(rgb)
+    }
+
+    fn set_quality(&mut self, quality: u32) -> Result<()> {
+        self.apply_quality(quality);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Smooth gradients with a moving square, like a camera would send
+    fn test_frame(width: u32, height: u32) -> Vec<u8> {
+        let mut rgb = Vec::with_capacity((width * height * 3) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let inside = (20..60).contains(&x) && (10..40).contains(&y);
+                if inside {
+                    rgb.extend_from_slice(&[240, 240, 240]);
+                } else {
+                    rgb.extend_from_slice(&[
+                        (x * 255 / width) as u8,
+                        (y * 255 / height) as u8,
+                        128,
+                    ]);
+                }
+            }
+        }
+        rgb
+    }
+
+    fn encode(width: u32, height: u32, quality: u32) -> (Vec<u8>, Vec<u8>) {
+        let rgb = test_frame(width, height);
+        let mut encoder = JpegEncoder::new();
+        encoder.init(width, height, 15, quality).unwrap();
+        (rgb.clone(), encoder.encode(&rgb).unwrap())
+    }
+
+    fn psnr(a: &[u8], b: &[u8]) -> f64 {
+        let mse = a
+            .iter()
+            .zip(b)
+            .map(|(&x, &y)| (x as f64 - y as f64).powi(2))
+            .sum::<f64>()
+            / a.len() as f64;
+        10.0 * (255.0 * 255.0 / mse.max(1e-10)).log10()
+    }
+
+    #[test]
+    fn jpeg_decodes_back() {
+        // Sizes multiple of the MCU and not
+        for (width, height) in [(64, 48), (100, 75), (33, 17)] {
+            let (rgb, jpeg) = encode(width, height, 85);
+            let decoded = image::load_from_memory_with_format(&jpeg, image::ImageFormat::Jpeg)
+                .unwrap()
+                .to_rgb8();
+            assert_eq!(decoded.dimensions(), (width, height));
+            let psnr = psnr(&rgb, decoded.as_raw());
+            assert!(psnr > 30.0, "{}x{}: PSNR {:.1} dB", width, height, psnr);
+        }
+    }
+
+    #[test]
+    fn jpeg_quality_changes_size() {
+        let (_, low) = encode(320, 240, 20);
+        let (_, high) = encode(320, 240, 95);
+        assert!(
+            low.len() < high.len() / 2,
+            "{} vs {}",
+            low.len(),
+            high.len()
+        );
+        assert_eq!(low[..2], [0xFF, 0xD8]);
+        assert_eq!(low[low.len() - 2..], [0xFF, 0xD9]);
+    }
+
+    #[test]
+    fn jpeg_tables() {
+        assert_eq!(scale_quant(&STD_LUMA_QUANT, 50), STD_LUMA_QUANT);
+        assert!(scale_quant(&STD_LUMA_QUANT, 100).iter().all(|&q| q == 1));
+        assert_eq!(magnitude(0), (0, 0));
+        assert_eq!(magnitude(5), (3, 0b101));
+        assert_eq!(magnitude(-5), (3, 0b010));
+        // First DC luma codes: 00 (2 bits) for 0, 010 for 1
+        let dc = HuffmanTable::new(&DC_LUMA_BITS, &DC_VALUES);
+        assert_eq!(dc.codes[0], (0b00, 2));
+        assert_eq!(dc.codes[1], (0b010, 3));
+    }
+}
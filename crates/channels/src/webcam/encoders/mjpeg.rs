@@ -3,15 +3,23 @@
 // All rights reserved.
 // Authors: Adolfo Gómez, dkmaster at dkmon dot com
 
-use super::VideoEncoder;
-use anyhow::{Result, bail};
+// MJPEG through turbojpeg when built with it and it starts, else (or if a frame
+// fails on it) the pure Rust encoder.
+use super::{JpegEncoder, VideoEncoder};
+use anyhow::Result;
+#[cfg(feature = "turbojpeg")]
+use anyhow::bail;
+#[cfg(feature = "turbojpeg")]
 use shared::log;
+#[cfg(feature = "turbojpeg")]
 use turbojpeg::{Image, OutputBuf, PixelFormat};
 
 pub struct MjpegEncoder {
     width: u32,
     height: u32,
+    #[cfg(feature = "turbojpeg")]
     compressor: Option<turbojpeg::Compressor>,
+    fallback: JpegEncoder,
 }
 
 impl MjpegEncoder {
@@ -19,9 +27,51 @@ impl MjpegEncoder {
         Self {
             width: 0,
             height: 0,
+            #[cfg(feature = "turbojpeg")]
             compressor: None,
+            fallback: JpegEncoder::new(),
         }
     }
+
+    #[cfg(feature = "turbojpeg")]
+    fn init_turbojpeg(&mut self, quality: u32) {
+        if self.compressor.is_none() {
+            match turbojpeg::Compressor::new() {
+                Ok(c) => self.compressor = Some(c),
+                Err(e) => {
+                    log::warn!("Failed to create turbojpeg compressor, using the Rust encoder: {e}")
+                }
+            }
+        }
+        if let Some(ref mut c) = self.compressor {
+            let _ = c.set_quality(quality as i32);
+        }
+    }
+
+    #[cfg(feature = "turbojpeg")]
+    fn compress(&mut self, rgb: &[u8]) -> Option<Vec<u8>> {
+        let compressor = self.compressor.as_mut()?;
+        let image = Image {
+            pixels: rgb,
+            width: self.width as usize,
+            height: self.height as usize,
+            pitch: (self.width * 3) as usize,
+            format: PixelFormat::RGB,
+        };
+        let mut output = OutputBuf::new_owned();
+        match compressor.compress(image, &mut output) {
+            Ok(_) => Some(output.to_vec()).filter(|jpeg| !jpeg.is_empty()),
+            Err(e) => {
+                log::error!("MJPEG compression failed: {e}");
+                None
+            }
+        }
+    }
+
+    #[cfg(not(feature = "turbojpeg"))]
+    fn compress(&mut self, _rgb: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
 }
 
 impl Default for MjpegEncoder {
@@ -31,45 +81,28 @@ impl Default for MjpegEncoder {
 }
 
 impl VideoEncoder for MjpegEncoder {
-    fn init(&mut self, width: u32, height: u32, _fps: u32, quality: u32) -> Result<()> {
+    fn init(&mut self, width: u32, height: u32, fps: u32, quality: u32) -> Result<()> {
         self.width = width;
         self.height = height;
-        if self.compressor.is_none() {
-            match turbojpeg::Compressor::new() {
-                Ok(mut c) => {
-                    let _ = c.set_quality(quality as i32);
-                    self.compressor = Some(c);
-                }
-                Err(e) => bail!("Failed to create turbojpeg compressor: {e}"),
-            }
-        } else if let Some(ref mut c) = self.compressor {
-            let _ = c.set_quality(quality as i32);
-        }
-        Ok(())
+        #[cfg(feature = "turbojpeg")]
+        self.init_turbojpeg(quality);
+        self.fallback.init(width, height, fps, quality)
     }
 
     fn encode(&mut self, rgb: &[u8]) -> Result<Vec<u8>> {
-        if let Some(ref mut compressor) = self.compressor {
-            let image = Image {
-                pixels: rgb,
-                width: self.width as usize,
-                height: self.height as usize,
-                pitch: (self.width * 3) as usize,
-                format: PixelFormat::RGB,
-            };
-            let mut output = OutputBuf::new_owned();
-            match compressor.compress(image, &mut output) {
-                Ok(_) => {
-                    let jpeg = output.to_vec();
-                    if !jpeg.is_empty() {
-                        return Ok(jpeg);
-                    }
-                }
-                Err(e) => {
-                    log::error!("MJPEG compression failed: {e}");
-                }
-            }
+        if let Some(jpeg) = self.compress(rgb) {
+            return Ok(jpeg);
+        }
+        self.fallback.encode(rgb)
+    }
+
+    fn set_quality(&mut self, quality: u32) -> Result<()> {
+        #[cfg(feature = "turbojpeg")]
+        if let Some(ref mut c) = self.compressor
+            && let Err(e) = c.set_quality(quality as i32)
+        {
+            bail!("Failed to set the MJPEG quality to {quality}: {e}");
         }
-        Ok(rgb.to_vec()) // Fallback
+        self.fallback.set_quality(quality)
     }
 }
@@ -8,6 +8,11 @@ use anyhow::Result;
 pub trait VideoEncoder: Send {
     fn init(&mut self, width: u32, height: u32, fps: u32, quality: u32) -> Result<()>;
     fn encode(&mut self, rgb: &[u8]) -> Result<Vec<u8>>;
+    /// Changes the quality of an initialized encoder, used by the rate control.
+    /// Encoders whose output does not depend on it ignore it.
+    fn set_quality(&mut self, _quality: u32) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub struct RawEncoder;
@@ -23,9 +28,11 @@ impl VideoEncoder for RawEncoder {
 }
 
 mod h264;
+mod jpeg;
 mod mjpeg;
 mod yuy2;
 
 pub use h264::H264Encoder;
+pub use jpeg::JpegEncoder;
 pub use mjpeg::MjpegEncoder;
 pub use yuy2::Yuy2Encoder;
@@ -34,3 +34,28 @@ pub fn generate_mock_frame(s: &mut StreamState) -> Vec<u8> {
     }
     rgb
 }
+
+/// Placeholder sent while the camera is paused: a dark frame with a crossed out
+/// circle, so the other side sees the camera is off on purpose.
+pub fn generate_paused_frame(s: &StreamState) -> Vec<u8> {
+    let (w, h) = (s.width as i64, s.height as i64);
+    let mut rgb = vec![24u8; (w * h * 3) as usize];
+    let (cx, cy) = (w / 2, h / 2);
+    let radius = w.min(h) / 5;
+    let thickness = (radius / 6).max(1);
+
+    for y in 0..h {
+        for x in 0..w {
+            let (dx, dy) = (x - cx, y - cy);
+            let dist = ((dx * dx + dy * dy) as f64).sqrt() as i64;
+            let ring = (dist - radius).abs() <= thickness / 2;
+            // Diagonal, inside the circle
+            let slash = dist < radius && (dx - dy).abs() <= thickness;
+            if ring || slash {
+                let idx = ((y * w + x) * 3) as usize;
+                rgb[idx..idx + 3].copy_from_slice(&[160, 160, 160]);
+            }
+        }
+    }
+    rgb
+}
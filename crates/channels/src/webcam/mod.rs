@@ -5,24 +5,31 @@
 
 use anyhow::{Context, Result};
 use capture_loop::CaptureLoop;
+use rate_control::SentCounter;
 use std::sync::atomic::{AtomicU32, Ordering};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, LazyLock, Mutex};
 
 use flume::{Sender, unbounded};
-use nokhwa::{
-    nokhwa_initialize,
-    utils::{CameraFormat, FrameFormat, Resolution},
-};
+use nokhwa::{nokhwa_initialize, utils::CameraFormat};
 use shared::log;
 
 mod capture_loop;
+pub mod devices;
 mod encoders;
 mod mock;
 pub mod openh264;
+mod privacy;
+mod rate_control;
+mod screen;
 
-pub use encoders::{MjpegEncoder, RawEncoder, VideoEncoder, Yuy2Encoder};
-pub use mock::{StreamState, generate_mock_frame};
+pub use devices::{CameraDevice, CameraSelection, list_cameras};
+pub use encoders::{JpegEncoder, MjpegEncoder, RawEncoder, VideoEncoder, Yuy2Encoder};
+pub use mock::{StreamState, generate_mock_frame, generate_paused_frame};
 pub use openh264::h264_available;
+pub use privacy::{PreviewFrame, Privacy, PrivacySettings, blur_background};
+pub use screen::{
+    CAM_SOURCE_ENV, FrameSource, Region, ScreenCapture, WindowMatch, parse_source, selected_source,
+};
 
 pub use rdp::integrations::webcam::{WebcamFrame, WebcamIntegration, WebcamMode};
 
@@ -32,51 +39,62 @@ pub static WEBCAM_FPS: AtomicU32 = AtomicU32::new(15);
 pub static WEBCAM_MAX_WIDTH: AtomicU32 = AtomicU32::new(0);
 pub static WEBCAM_MAX_HEIGHT: AtomicU32 = AtomicU32::new(0);
 
-static CAMERA_DIMENSIONS: std::sync::OnceLock<Option<(u32, u32)>> = std::sync::OnceLock::new();
+// Dimensions of the last camera asked for, re-queried when the camera changes
+static CAMERA_DIMENSIONS: LazyLock<Mutex<Option<(String, (u32, u32))>>> =
+    LazyLock::new(|| Mutex::new(None));
 
-pub fn get_camera_dimensions() -> Option<(u32, u32)> {
-    *CAMERA_DIMENSIONS.get_or_init(|| {
-        let force_mock = std::env::var("UDSLAUNCHER_CAM_MOCK")
-            .map(|v| v == "1" || v.to_lowercase() == "true")
-            .unwrap_or(false);
-        if force_mock {
-            return Some((640, 480));
-        }
+pub fn get_camera_dimensions(selection: &CameraSelection) -> Option<(u32, u32)> {
+    if devices::is_mock_forced() {
+        return Some((640, 480));
+    }
 
-        nokhwa_initialize(|_| {});
+    nokhwa_initialize(|_| {});
 
-        // Check if there are any cameras on the system
-        let devices = nokhwa::query(nokhwa::utils::ApiBackend::Auto)
-            .ok()
-            .unwrap_or_default();
-        if devices.is_empty() {
-            log::warn!("No cameras detected on the system via query.");
-            return None;
-        }
+    // Check if there are any cameras on the system
+    let Some((index, name)) = devices::selected_camera(selection) else {
+        log::warn!("No cameras detected on the system via query.");
+        return None;
+    };
 
-        let index = select_camera_index();
-        let requested_none = nokhwa::utils::RequestedFormat::new::<nokhwa::pixel_format::RgbFormat>(
-            nokhwa::utils::RequestedFormatType::None,
-        );
-        if let Ok(mut cam) = nokhwa::Camera::new(index, requested_none)
-            && let Ok(formats) = cam.compatible_camera_formats()
-        {
-            let best_format = formats.iter().max_by_key(|f| f.width() * f.height());
-            if let Some(format) = best_format {
-                log::info!(
-                    "Proactively detected camera dimensions: {}x{}",
-                    format.width(),
-                    format.height()
-                );
-                return Some((format.width(), format.height()));
-            }
+    let mut cached = CAMERA_DIMENSIONS.lock().unwrap();
+    if let Some((cached_name, dims)) = cached.as_ref()
+        && *cached_name == name
+    {
+        return Some(*dims);
+    }
+
+    let best_format = devices::query_formats(&index)
+        .into_iter()
+        .max_by_key(|f| f.width() * f.height());
+    let dims = match best_format {
+        Some(format) => {
+            log::info!(
+                "Proactively detected camera dimensions for {}: {}x{}",
+                name,
+                format.width(),
+                format.height()
+            );
+            (format.width(), format.height())
+        }
+        None => {
+            // If we have devices but failed to open or query formats (e.g. camera in use),
+            // fallback to default dimensions to keep redirection enabled.
+            log::warn!("Camera detected but failed to query dimensions. Falling back to 640x480.");
+            (640, 480)
         }
+    };
+    *cached = Some((name, dims));
+    Some(dims)
+}
 
-        // If we have devices but failed to open or query formats (e.g. camera in use),
-        // fallback to default dimensions to keep redirection enabled.
-        log::warn!("Camera detected but failed to query dimensions. Falling back to 640x480.");
-        Some((640, 480))
-    })
+/// Webcam options given by the RDP settings of the session
+#[derive(Debug, Clone, Default)]
+pub struct WebcamOptions {
+    /// Index or (part of) the name of the camera
+    pub camera: Option<String>,
+    /// Where the frames come from, see [`parse_source`]
+    pub source: Option<String>,
+    pub privacy: PrivacySettings,
 }
 
 pub enum WebcamCommand {
@@ -92,6 +110,11 @@ pub enum WebcamCommand {
         height: u32,
         fps: u32,
     },
+    /// Moves the capture to another camera (index or name). The stream keeps its
+    /// format, frames are scaled to it, so the channel is not renegotiated.
+    SwitchCamera {
+        camera: String,
+    },
     StopStream,
     Close,
 }
@@ -104,28 +127,24 @@ pub struct WebcamHandle {
     pub frame_tx: Arc<Mutex<Option<Sender<WebcamFrame>>>>,
     pub samples_requested: Arc<Mutex<u32>>,
     pub active_channel: Arc<Mutex<Option<usize>>>,
+    /// Name of the camera being captured, `None` when not streaming or on the mock
+    pub active_camera: Arc<Mutex<Option<String>>>,
+    /// Preview, pause and blur, shared with the UI
+    pub privacy: Arc<Privacy>,
+    // Frames taken by the server, for the rate control
+    sent: Arc<SentCounter>,
+    selection: Arc<CameraSelection>,
 }
 
-fn select_camera_index() -> nokhwa::utils::CameraIndex {
-    if let Ok(val) = std::env::var("UDSLAUNCHER_CAM_DEVICE") {
-        if let Ok(idx) = val.parse::<u32>() {
-            return nokhwa::utils::CameraIndex::Index(idx);
-        }
-        // Query devices
-        if let Ok(devices) = nokhwa::query(nokhwa::utils::ApiBackend::Auto) {
-            let val_lower = val.to_lowercase();
-            for camera in devices {
-                if camera.human_name().to_lowercase().contains(&val_lower) {
-                    return camera.index().clone();
-                }
-            }
-        }
-    }
-    nokhwa::utils::CameraIndex::Index(0)
-}
-
-pub(crate) fn init_real_camera(width: u32, height: u32, fps: u32) -> Result<nokhwa::Camera> {
-    let index = select_camera_index();
+/// Opens the selected camera with the format closest to the requested one
+pub(crate) fn init_real_camera(
+    selection: &CameraSelection,
+    width: u32,
+    height: u32,
+    fps: u32,
+) -> Result<nokhwa::Camera> {
+    let (index, name) = devices::selected_camera(selection).context("No cameras found")?;
+    log::info!("Opening camera {} ({})", name, index);
 
     let requested_none = nokhwa::utils::RequestedFormat::new::<nokhwa::pixel_format::RgbFormat>(
         nokhwa::utils::RequestedFormatType::None,
@@ -159,11 +178,11 @@ pub(crate) fn init_real_camera(width: u32, height: u32, fps: u32) -> Result<nokh
 // Capture loop lives in capture_loop.rs (CaptureLoop::run)
 
 impl WebcamHandle {
-    pub fn new() -> Self {
-        Self::with_mode(WebcamMode::Raw)
+    pub fn new(options: WebcamOptions) -> Self {
+        Self::with_mode(options, WebcamMode::Raw)
     }
 
-    pub fn with_mode(mode: WebcamMode) -> Self {
+    pub fn with_mode(options: WebcamOptions, mode: WebcamMode) -> Self {
         nokhwa_initialize(|_| {});
 
         let (cmd_tx, cmd_rx) = unbounded::<WebcamCommand>();
@@ -172,6 +191,10 @@ impl WebcamHandle {
         let frame_tx = Arc::new(Mutex::new(None::<Sender<WebcamFrame>>));
         let samples_requested = Arc::new(Mutex::new(0u32));
         let active_channel = Arc::new(Mutex::new(None::<usize>));
+        let active_camera = Arc::new(Mutex::new(None::<String>));
+        let sent = Arc::new(SentCounter::default());
+        let privacy = Arc::new(Privacy::new(options.privacy));
+        let selection = Arc::new(CameraSelection::new(options.camera));
 
         // Creates the capture loop thread that will handle webcam streaming and commands.
         CaptureLoop::new(
@@ -181,6 +204,11 @@ impl WebcamHandle {
             frame_tx.clone(),
             samples_requested.clone(),
             active_channel.clone(),
+            active_camera.clone(),
+            sent.clone(),
+            privacy.clone(),
+            selection.clone(),
+            options.source,
         )
         .run();
 
@@ -191,12 +219,42 @@ impl WebcamHandle {
             frame_tx,
             samples_requested,
             active_channel,
+            active_camera,
+            privacy,
+            sent,
+            selection,
         }
     }
 
     pub fn close(&self) {
         let _ = self.cmd_tx.send(WebcamCommand::Close);
     }
+
+    pub fn current_camera(&self) -> Option<String> {
+        self.active_camera.lock().unwrap().clone()
+    }
+
+    /// Uses another camera from now on, also mid-stream
+    pub fn switch_camera(&self, camera: &str) {
+        log::info!("Switching camera to {}", camera);
+        let _ = self.cmd_tx.send(WebcamCommand::SwitchCamera {
+            camera: camera.to_string(),
+        });
+    }
+
+    /// Switches to the next camera on the system. Returns its name, if any.
+    pub fn next_camera(&self) -> Option<String> {
+        let names: Vec<String> = devices::camera_names()
+            .into_iter()
+            .map(|(_, n)| n)
+            .collect();
+        let current = self.current_camera();
+        let next = devices::next_camera_name(&names, current.as_deref())?;
+        if Some(&next) != current.as_ref() {
+            self.switch_camera(&next);
+        }
+        Some(next)
+    }
 }
 
 impl WebcamIntegration for WebcamHandle {
@@ -205,7 +263,7 @@ impl WebcamIntegration for WebcamHandle {
     }
 
     fn get_camera_dimensions(&self) -> (u32, u32) {
-        self::get_camera_dimensions().unwrap_or((0, 0))
+        self::get_camera_dimensions(&self.selection).unwrap_or((0, 0))
     }
 
     fn get_max_dimensions(&self) -> (u32, u32) {
@@ -263,6 +321,7 @@ impl WebcamIntegration for WebcamHandle {
             )
         {
             *reqs -= 1;
+            self.sent.add(frame.len());
             let _ = tx.send(WebcamFrame {
                 data: frame.to_vec(),
                 channel_ptr,
@@ -282,18 +341,10 @@ impl WebcamIntegration for WebcamHandle {
     }
 
     fn get_device_name(&self) -> String {
-        let force_mock = std::env::var("UDSLAUNCHER_CAM_MOCK")
-            .map(|v| v == "1" || v.to_lowercase() == "true")
-            .unwrap_or(false);
-        if force_mock {
-            return "Mock Camera".to_string();
-        }
-        if let Ok(devices) = nokhwa::query(nokhwa::utils::ApiBackend::Auto) {
-            if devices.is_empty() {
-                return "Mock Camera".to_string();
-            }
-        } else {
-            return "Mock Camera".to_string();
+        // The server sees a single camera whatever the local one is, so switching
+        // does not need a new channel
+        if devices::is_mock_forced() || devices::camera_names().is_empty() {
+            return devices::MOCK_CAMERA_NAME.to_string();
         }
         "UDS Camera".to_string()
     }
@@ -301,7 +352,7 @@ impl WebcamIntegration for WebcamHandle {
 
 impl Default for WebcamHandle {
     fn default() -> Self {
-        Self::new()
+        Self::new(WebcamOptions::default())
     }
 }
 
@@ -357,39 +408,25 @@ pub(crate) fn resize_rgb(src: &[u8], src_w: u32, src_h: u32, dst_w: u32, dst_h:
     dst
 }
 
-/// Query compatible formats from the default camera without keeping it open.
+/// Lowers the detail of a frame to `percent` of its resolution, keeping its size
+pub(crate) fn reduce_detail(src: &[u8], w: u32, h: u32, percent: u32) -> Vec<u8> {
+    let low_w = (w * percent / 100).max(2);
+    let low_h = (h * percent / 100).max(2);
+    let low = resize_rgb(src, w, h, low_w, low_h);
+    resize_rgb(&low, low_w, low_h, w, h)
+}
+
+/// Query compatible formats from the selected camera without keeping it open.
 /// Returns all (format, resolution, fps) tuples the hardware supports.
-pub fn compatible_formats() -> Vec<CameraFormat> {
-    let force_mock = std::env::var("UDSLAUNCHER_CAM_MOCK")
-        .map(|v| v == "1" || v.to_lowercase() == "true")
-        .unwrap_or(false);
-    if force_mock {
-        return vec![
-            CameraFormat::new(Resolution::new(640, 480), FrameFormat::MJPEG, 30),
-            CameraFormat::new(Resolution::new(640, 480), FrameFormat::YUYV, 30),
-            CameraFormat::new(Resolution::new(1280, 720), FrameFormat::MJPEG, 30),
-            CameraFormat::new(Resolution::new(1920, 1080), FrameFormat::MJPEG, 30),
-        ];
-    }
-
-    let index = select_camera_index();
-    let requested = nokhwa::utils::RequestedFormat::new::<nokhwa::pixel_format::RgbFormat>(
-        nokhwa::utils::RequestedFormatType::None,
-    );
-    if let Ok(mut camera) = nokhwa::Camera::new(index, requested)
-        && let Ok(formats) = camera.compatible_camera_formats()
-        && !formats.is_empty()
-    {
-        return formats;
+pub fn compatible_formats(selection: &CameraSelection) -> Vec<CameraFormat> {
+    let formats = devices::selected_camera(selection)
+        .map(|(index, _)| devices::query_formats(&index))
+        .unwrap_or_default();
+    if formats.is_empty() {
+        // Return a mock set of formats if query fails
+        return devices::mock_formats();
     }
-
-    // Return a mock set of formats if query fails
-    vec![
-        CameraFormat::new(Resolution::new(640, 480), FrameFormat::MJPEG, 30),
-        CameraFormat::new(Resolution::new(640, 480), FrameFormat::YUYV, 30),
-        CameraFormat::new(Resolution::new(1280, 720), FrameFormat::MJPEG, 30),
-        CameraFormat::new(Resolution::new(1920, 1080), FrameFormat::MJPEG, 30),
-    ]
+    formats
 }
 
 #[cfg(test)]
@@ -399,14 +436,14 @@ mod tests {
 
     #[test]
     fn webcam_handle_creation() {
-        let handle = WebcamHandle::new();
+        let handle = WebcamHandle::default();
         assert!(handle.latest_frame.lock().unwrap().is_none());
         handle.close();
     }
 
     #[test]
     fn webcam_capture_frame() {
-        let handle = WebcamHandle::new();
+        let handle = WebcamHandle::default();
         let _rx = handle.start_stream(320, 240, 5);
         std::thread::sleep(Duration::from_millis(500));
         let frame = handle.latest_frame.lock().unwrap().clone();
@@ -419,7 +456,7 @@ mod tests {
 
     #[test]
     fn webcam_stop_clears_frame() {
-        let handle = WebcamHandle::new();
+        let handle = WebcamHandle::default();
         let _rx = handle.start_stream(320, 240, 5);
         std::thread::sleep(Duration::from_millis(500));
         handle.stop_stream();
@@ -471,12 +508,24 @@ mod tests {
         assert_eq!(dst, vec![1u8, 2, 3]); // nearest neighbor maps top-left pixel
     }
 
+    #[test]
+    fn test_reduce_detail() {
+        // 4x2 RGB image, each pixel a different value
+        let src: Vec<u8> = (0..24).collect();
+        let dst = reduce_detail(&src, 4, 2, 50);
+        assert_eq!(dst.len(), src.len());
+        // Down to 2x2 and back, pixels go in pairs
+        assert_eq!(dst[0..3], dst[3..6]);
+        assert_eq!(dst[6..9], dst[9..12]);
+        assert_eq!(reduce_detail(&src, 4, 2, 100), src);
+    }
+
     #[test]
     fn test_get_camera_dimensions() {
         unsafe {
             std::env::set_var("UDSLAUNCHER_CAM_MOCK", "true");
         }
-        let dims = get_camera_dimensions();
+        let dims = get_camera_dimensions(&CameraSelection::default());
         assert_eq!(dims, Some((640, 480)));
         unsafe {
             std::env::remove_var("UDSLAUNCHER_CAM_MOCK");
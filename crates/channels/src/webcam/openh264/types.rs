@@ -280,6 +280,19 @@ impl Encoder {
         }
     }
 
+    /// Uninitializes the encoder, so it can be initialized again with new parameters.
+    pub fn uninitialize(&mut self) -> Result<(), Error> {
+        // SAFETY: The vtable function is valid as long as the library is loaded.
+        unsafe {
+            let ret = (self.vtbl().uninitialize)(self.ptr.as_ptr());
+            if ret == 0 {
+                Ok(())
+            } else {
+                Err(Error::ParamError)
+            }
+        }
+    }
+
     /// Initializes the encoder with extended parameters.
     ///
     /// # Safety
@@ -0,0 +1,295 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+// Privacy controls of the webcam: a local preview of what is being sent, the
+// pause (the camera is closed and a placeholder goes to the server instead) and
+// a simple background blur.
+// The blur does not know where the person is: it keeps sharp an ellipse on the
+// center of the frame, where someone in a call usually is, and blurs the rest.
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use shared::log;
+
+use super::resize_rgb;
+
+/// Width of the frames kept for the preview
+pub const PREVIEW_MAX_WIDTH: u32 = 240;
+
+// Sharp ellipse, as fractions of the frame, and where the feathering starts
+const FOCUS_CENTER: (f32, f32) = (0.5, 0.55);
+const FOCUS_RADIUS: (f32, f32) = (0.3, 0.5);
+const FOCUS_FEATHER: f32 = 0.75;
+
+/// Privacy options of the session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrivacySettings {
+    pub preview: bool,
+    pub blur: bool,
+}
+
+impl Default for PrivacySettings {
+    fn default() -> Self {
+        PrivacySettings {
+            preview: true,
+            blur: false,
+        }
+    }
+}
+
+/// Frame shown on the local preview, RGB24
+#[derive(Debug, Clone)]
+pub struct PreviewFrame {
+    pub rgb: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// Grows with each frame, to know if there is a new one
+    pub seq: u64,
+}
+
+/// State shared between the capture loop and the UI
+#[derive(Debug)]
+pub struct Privacy {
+    paused: AtomicBool,
+    blur: AtomicBool,
+    preview: AtomicBool,
+    streaming: AtomicBool,
+    seq: AtomicU64,
+    frame: Mutex<Option<PreviewFrame>>,
+}
+
+impl Privacy {
+    pub fn new(settings: PrivacySettings) -> Self {
+        Privacy {
+            paused: AtomicBool::new(false),
+            blur: AtomicBool::new(settings.blur),
+            preview: AtomicBool::new(settings.preview),
+            streaming: AtomicBool::new(false),
+            seq: AtomicU64::new(0),
+            frame: Mutex::new(None),
+        }
+    }
+
+    /// Whether the server is taking frames (the camera is in use)
+    pub fn is_streaming(&self) -> bool {
+        self.streaming.load(Ordering::Relaxed)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Pauses or resumes the camera. Returns whether it is paused now.
+    pub fn toggle_pause(&self) -> bool {
+        let paused = !self.paused.fetch_xor(true, Ordering::Relaxed);
+        log::info!("Webcam {}", if paused { "paused" } else { "resumed" });
+        paused
+    }
+
+    pub fn is_blurred(&self) -> bool {
+        self.blur.load(Ordering::Relaxed)
+    }
+
+    pub fn toggle_blur(&self) -> bool {
+        let blur = !self.blur.fetch_xor(true, Ordering::Relaxed);
+        log::info!("Webcam background blur {}", if blur { "on" } else { "off" });
+        blur
+    }
+
+    pub fn is_preview_enabled(&self) -> bool {
+        self.preview.load(Ordering::Relaxed)
+    }
+
+    pub fn toggle_preview(&self) -> bool {
+        !self.preview.fetch_xor(true, Ordering::Relaxed)
+    }
+
+    /// Last frame sent, if the preview is enabled and the camera in use
+    pub fn preview_frame(&self) -> Option<PreviewFrame> {
+        if !self.is_preview_enabled() || !self.is_streaming() {
+            return None;
+        }
+        self.frame.lock().unwrap().clone()
+    }
+
+    /// Sequence of the last preview frame, to poll for new ones cheaply
+    pub fn preview_seq(&self) -> u64 {
+        self.seq.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_streaming(&self, streaming: bool) {
+        self.streaming.store(streaming, Ordering::Relaxed);
+        if !streaming {
+            *self.frame.lock().unwrap() = None;
+            self.seq.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Keeps a small copy of the frame going to the encoder
+    pub(crate) fn update_preview(&self, rgb: &[u8], width: u32, height: u32) {
+        if !self.is_preview_enabled() || width == 0 || height == 0 {
+            return;
+        }
+        let (pw, ph) = if width > PREVIEW_MAX_WIDTH {
+            (
+                PREVIEW_MAX_WIDTH,
+                (height * PREVIEW_MAX_WIDTH / width).max(1),
+            )
+        } else {
+            (width, height)
+        };
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed) + 1;
+        *self.frame.lock().unwrap() = Some(PreviewFrame {
+            rgb: resize_rgb(rgb, width, height, pw, ph),
+            width: pw,
+            height: ph,
+            seq,
+        });
+    }
+}
+
+impl Default for Privacy {
+    fn default() -> Self {
+        Self::new(PrivacySettings::default())
+    }
+}
+
+// Box blur of `radius` on each row (horizontal) or column, with a running sum
+fn box_blur_pass(src: &[u8], dst: &mut [u8], w: usize, h: usize, radius: usize, horizontal: bool) {
+    let (lines, len) = if horizontal { (h, w) } else { (w, h) };
+    let index = |line: usize, pos: usize| {
+        if horizontal {
+            (line * w + pos) * 3
+        } else {
+            (pos * w + line) * 3
+        }
+    };
+    for line in 0..lines {
+        for c in 0..3 {
+            // Edges repeat the first and last pixels
+            let at = |pos: isize| src[index(line, pos.clamp(0, len as isize - 1) as usize) + c];
+            let r = radius as isize;
+            let mut sum: u32 = (-r..=r).map(|p| at(p) as u32).sum();
+            let count = 2 * radius as u32 + 1;
+            for pos in 0..len {
+                dst[index(line, pos) + c] = (sum / count) as u8;
+                let pos = pos as isize;
+                sum += at(pos + r + 1) as u32;
+                sum -= at(pos - r) as u32;
+            }
+        }
+    }
+}
+
+/// Blurs all but an ellipse on the center of an RGB24 frame
+pub fn blur_background(rgb: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let (w, h) = (width as usize, height as usize);
+    if w < 4 || h < 4 || rgb.len() < w * h * 3 {
+        return rgb.to_vec();
+    }
+    let radius = (w / 48).max(2);
+    let mut blurred = rgb.to_vec();
+    let mut tmp = vec![0u8; w * h * 3];
+    // Twice, close enough to a gaussian
+    for _ in 0..2 {
+        box_blur_pass(&blurred, &mut tmp, w, h, radius, true);
+        box_blur_pass(&tmp, &mut blurred, w, h, radius, false);
+    }
+
+    let (cx, cy) = (FOCUS_CENTER.0 * w as f32, FOCUS_CENTER.1 * h as f32);
+    let (rx, ry) = (FOCUS_RADIUS.0 * w as f32, FOCUS_RADIUS.1 * h as f32);
+    for y in 0..h {
+        let dy = (y as f32 - cy) / ry;
+        for x in 0..w {
+            let dx = (x as f32 - cx) / rx;
+            let dist = (dx * dx + dy * dy).sqrt();
+            // 1 keeps the frame, 0 takes the blurred one
+            let keep = ((1.0 - dist) / (1.0 - FOCUS_FEATHER)).clamp(0.0, 1.0);
+            if keep >= 1.0 {
+                let idx = (y * w + x) * 3;
+                blurred[idx..idx + 3].copy_from_slice(&rgb[idx..idx + 3]);
+            } else if keep > 0.0 {
+                let idx = (y * w + x) * 3;
+                for c in idx..idx + 3 {
+                    blurred[c] = (rgb[c] as f32 * keep + blurred[c] as f32 * (1.0 - keep)) as u8;
+                }
+            }
+        }
+    }
+    blurred
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Black and white pixels, as sharp as it gets
+    fn checkerboard(width: u32, height: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let v = if (i % width + i / width).is_multiple_of(2) {
+                    0
+                } else {
+                    255
+                };
+                [v, v, v]
+            })
+            .collect()
+    }
+
+    fn contrast(rgb: &[u8], width: u32, x: u32, y: u32) -> i32 {
+        let at = |x: u32, y: u32| rgb[((y * width + x) * 3) as usize] as i32;
+        (at(x, y) - at(x + 1, y)).abs()
+    }
+
+    #[test]
+    fn blur_keeps_the_center() {
+        let (w, h) = (96, 64);
+        let src = checkerboard(w, h);
+        let out = blur_background(&src, w, h);
+        assert_eq!(out.len(), src.len());
+        // Center untouched, corners flattened
+        assert_eq!(contrast(&out, w, w / 2, h / 2), 255);
+        assert!(contrast(&out, w, 0, 0) < 40);
+        assert!(contrast(&out, w, w - 2, h - 1) < 40);
+        // Too small to blur
+        assert_eq!(blur_background(&src[..12], 2, 2), src[..12]);
+    }
+
+    #[test]
+    fn preview_follows_stream() {
+        let privacy = Privacy::default();
+        let frame = checkerboard(640, 480);
+        privacy.update_preview(&frame, 640, 480);
+        // Not streaming, nothing to show
+        assert!(privacy.preview_frame().is_none());
+
+        privacy.set_streaming(true);
+        privacy.update_preview(&frame, 640, 480);
+        let preview = privacy.preview_frame().unwrap();
+        assert_eq!((preview.width, preview.height), (PREVIEW_MAX_WIDTH, 180));
+        assert_eq!(preview.rgb.len(), (PREVIEW_MAX_WIDTH * 180 * 3) as usize);
+        assert_eq!(preview.seq, privacy.preview_seq());
+
+        assert!(!privacy.toggle_preview());
+        assert!(privacy.preview_frame().is_none());
+        assert!(privacy.toggle_preview());
+
+        privacy.set_streaming(false);
+        assert!(privacy.preview_frame().is_none());
+        assert!(privacy.preview_seq() > preview.seq);
+    }
+
+    #[test]
+    fn toggles() {
+        let privacy = Privacy::default();
+        assert!(!privacy.is_paused());
+        assert!(privacy.toggle_pause());
+        assert!(privacy.is_paused());
+        assert!(!privacy.toggle_pause());
+        let blur = privacy.is_blurred();
+        assert_eq!(privacy.toggle_blur(), !blur);
+    }
+}
@@ -0,0 +1,377 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+// Adaptive rate control of the webcam stream.
+// The server asks for each frame (samples_requested), so the frames it takes
+// against the frames we produce tell if the link keeps up. When it does not, we
+// go down a ladder of quality, fps and detail, always below the session limits,
+// as far as the measured throughput needs. Going back up is done one step at a
+// time, waiting longer each time a step up fails.
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Below this fraction of frames taken by the server the link is congested
+const CONGESTED_DELIVERY: f64 = 0.8;
+/// Above this one, the link is fine
+const GOOD_DELIVERY: f64 = 0.95;
+/// Room left below the measured throughput when going down
+const THROUGHPUT_MARGIN: f64 = 0.9;
+/// Good windows in a row before trying a step up
+const RECOVER_WINDOWS: u32 = 3;
+const MAX_RECOVER_WINDOWS: u32 = 32;
+/// A congestion this soon (windows) after a step up means the step up failed
+const PROBE_WINDOWS: u32 = 2;
+
+const MIN_QUALITY: u32 = 20;
+const MIN_FPS: u32 = 2;
+
+/// Percentages of the session quality, fps and detail (resolution)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Step {
+    quality: u32,
+    fps: u32,
+    detail: u32,
+}
+
+const fn step(quality: u32, fps: u32, detail: u32) -> Step {
+    Step {
+        quality,
+        fps,
+        detail,
+    }
+}
+
+// Quality first, it is the cheapest to lose, then fps and detail
+const COMPRESSED_LADDER: [Step; 8] = [
+    step(100, 100, 100),
+    step(75, 100, 100),
+    step(55, 100, 100),
+    step(55, 75, 100),
+    step(45, 75, 85),
+    step(45, 50, 75),
+    step(35, 50, 50),
+    step(35, 33, 50),
+];
+
+// The size of uncompressed frames does not depend on the content, only fps helps
+const UNCOMPRESSED_LADDER: [Step; 4] = [
+    step(100, 100, 100),
+    step(100, 75, 100),
+    step(100, 50, 100),
+    step(100, 33, 100),
+];
+
+impl Step {
+    /// Relative cost in bytes per second. Rough, but enough to pick the step.
+    fn cost(&self, compressed: bool) -> f64 {
+        let fps = self.fps as f64 / 100.0;
+        if !compressed {
+            return fps;
+        }
+        let detail = self.detail as f64 / 100.0;
+        // Encoded size goes down slower than the quality
+        let quality = 0.3 + 0.7 * self.quality as f64 / 100.0;
+        fps * detail * detail * quality
+    }
+}
+
+/// What the capture loop must use now
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateTarget {
+    pub fps: u32,
+    pub quality: u32,
+    /// Percent of the stream resolution kept. Frames keep the negotiated size
+    /// (the server would need a new media type otherwise), the detail goes down.
+    pub detail: u32,
+}
+
+/// Frames and bytes taken by the server, counted where they are sent
+#[derive(Debug, Default)]
+pub struct SentCounter {
+    frames: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl SentCounter {
+    pub fn add(&self, bytes: usize) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> (u64, u64) {
+        (
+            self.frames.load(Ordering::Relaxed),
+            self.bytes.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// What happened during an evaluation window
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RateWindow {
+    pub seconds: f64,
+    pub produced_frames: u64,
+    pub produced_bytes: u64,
+    pub sent_frames: u64,
+    pub sent_bytes: u64,
+}
+
+impl RateWindow {
+    fn delivery(&self) -> f64 {
+        self.sent_frames as f64 / self.produced_frames as f64
+    }
+}
+
+pub struct RateController {
+    ladder: &'static [Step],
+    compressed: bool,
+    level: usize,
+    good_windows: u32,
+    recover_after: u32,
+    windows_since_up: Option<u32>,
+    // Last measures, for the report
+    delivery: f64,
+    throughput: f64,
+    demand: f64,
+}
+
+impl RateController {
+    pub fn new(compressed: bool) -> Self {
+        RateController {
+            ladder: if compressed {
+                &COMPRESSED_LADDER
+            } else {
+                &UNCOMPRESSED_LADDER
+            },
+            compressed,
+            level: 0,
+            good_windows: 0,
+            recover_after: RECOVER_WINDOWS,
+            windows_since_up: None,
+            delivery: 1.0,
+            throughput: 0.0,
+            demand: 0.0,
+        }
+    }
+
+    pub fn compressed(&self) -> bool {
+        self.compressed
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /// Target for a stream of `fps` at the session `quality`
+    pub fn target(&self, fps: u32, quality: u32) -> RateTarget {
+        let step = self.ladder[self.level];
+        let scale = |value: u32, percent: u32, min: u32| {
+            if percent >= 100 {
+                value
+            } else {
+                (value * percent / 100).max(min.min(value))
+            }
+        };
+        RateTarget {
+            fps: scale(fps, step.fps, MIN_FPS),
+            quality: scale(quality, step.quality, MIN_QUALITY),
+            detail: step.detail,
+        }
+    }
+
+    /// Looks at a window and moves on the ladder. Returns whether the target changed.
+    pub fn evaluate(&mut self, window: &RateWindow) -> bool {
+        if window.produced_frames == 0 || window.seconds <= 0.0 {
+            return false;
+        }
+        self.delivery = window.delivery();
+        self.throughput = window.sent_bytes as f64 / window.seconds;
+        self.demand = window.produced_bytes as f64 / window.seconds;
+        self.windows_since_up = self.windows_since_up.map(|w| w + 1);
+
+        if self.delivery < CONGESTED_DELIVERY {
+            self.good_windows = 0;
+            if self.windows_since_up.is_some_and(|w| w <= PROBE_WINDOWS) {
+                self.recover_after = (self.recover_after * 2).min(MAX_RECOVER_WINDOWS);
+            }
+            self.windows_since_up = None;
+            return self.step_down();
+        }
+        if self.delivery >= GOOD_DELIVERY {
+            self.good_windows += 1;
+            if self.good_windows >= self.recover_after && self.level > 0 {
+                self.good_windows = 0;
+                self.level -= 1;
+                self.windows_since_up = Some(0);
+                if self.level == 0 {
+                    self.recover_after = RECOVER_WINDOWS;
+                }
+                return true;
+            }
+        } else {
+            self.good_windows = 0;
+        }
+        false
+    }
+
+    // Down to the first step whose expected demand fits in the throughput
+    fn step_down(&mut self) -> bool {
+        let last = self.ladder.len() - 1;
+        if self.level == last {
+            return false;
+        }
+        let current = self.ladder[self.level].cost(self.compressed);
+        let budget = self.throughput * THROUGHPUT_MARGIN;
+        let mut level = self.level + 1;
+        while level < last
+            && self.demand * self.ladder[level].cost(self.compressed) / current > budget
+        {
+            level += 1;
+        }
+        self.level = level;
+        true
+    }
+}
+
+impl fmt::Display for RateController {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let step = self.ladder[self.level];
+        write!(
+            f,
+            "rate level {}/{} (quality {}%, fps {}%, detail {}%), delivered {:.0}%, {:.0} of {:.0} bytes/s sent",
+            self.level,
+            self.ladder.len() - 1,
+            step.quality,
+            step.fps,
+            step.detail,
+            self.delivery * 100.0,
+            self.throughput,
+            self.demand
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One second at 15 fps of 10000 byte frames, the server taking `sent` of them
+    fn window(sent: u64, frame_bytes: u64) -> RateWindow {
+        RateWindow {
+            seconds: 1.0,
+            produced_frames: 15,
+            produced_bytes: 15 * frame_bytes,
+            sent_frames: sent,
+            sent_bytes: sent * frame_bytes,
+        }
+    }
+
+    #[test]
+    fn full_rate_when_link_keeps_up() {
+        let mut rc = RateController::new(true);
+        for _ in 0..10 {
+            assert!(!rc.evaluate(&window(15, 10000)));
+        }
+        assert_eq!(
+            rc.target(15, 80),
+            RateTarget {
+                fps: 15,
+                quality: 80,
+                detail: 100
+            }
+        );
+    }
+
+    // The level is the first one that fits in what got through
+    fn assert_fits(rc: &RateController, delivered: f64) {
+        let budget = delivered * THROUGHPUT_MARGIN;
+        let level = rc.level();
+        assert!(COMPRESSED_LADDER[level].cost(true) <= budget);
+        assert!(COMPRESSED_LADDER[level - 1].cost(true) > budget);
+    }
+
+    #[test]
+    fn goes_down_as_far_as_needed() {
+        let mut rc = RateController::new(true);
+        assert!(rc.evaluate(&window(11, 10000)));
+        assert_fits(&rc, 11.0 / 15.0);
+
+        let mut rc = RateController::new(true);
+        assert!(rc.evaluate(&window(5, 10000)));
+        assert_fits(&rc, 5.0 / 15.0);
+
+        // Nothing gets through: bottom of the ladder, and stays there
+        let mut rc = RateController::new(true);
+        assert!(rc.evaluate(&window(0, 10000)));
+        assert_eq!(rc.level(), COMPRESSED_LADDER.len() - 1);
+        assert!(!rc.evaluate(&window(0, 10000)));
+    }
+
+    #[test]
+    fn target_within_limits() {
+        let mut rc = RateController::new(true);
+        rc.evaluate(&window(0, 10000));
+        let target = rc.target(15, 80);
+        assert!(target.fps < 15 && target.fps >= MIN_FPS);
+        assert!(target.quality < 80 && target.quality >= MIN_QUALITY);
+        assert!(target.detail < 100);
+        // Never above what the session allows, even if that is below the minimums
+        let target = rc.target(1, 10);
+        assert_eq!((target.fps, target.quality), (1, 10));
+    }
+
+    #[test]
+    fn uncompressed_only_lowers_fps() {
+        let mut rc = RateController::new(false);
+        assert!(rc.evaluate(&window(10, 100000)));
+        let target = rc.target(30, 80);
+        assert_eq!((target.quality, target.detail), (80, 100));
+        assert_eq!(target.fps, 15);
+    }
+
+    #[test]
+    fn recovers_step_by_step() {
+        let mut rc = RateController::new(true);
+        rc.evaluate(&window(0, 10000));
+        let bottom = rc.level();
+        let mut windows = 0;
+        while rc.level() > 0 {
+            rc.evaluate(&window(15, 10000));
+            windows += 1;
+            assert!(windows < 100);
+        }
+        assert_eq!(windows, bottom as u32 * RECOVER_WINDOWS);
+    }
+
+    #[test]
+    fn failed_step_up_waits_longer() {
+        let mut rc = RateController::new(true);
+        rc.evaluate(&window(11, 10000));
+        let level = rc.level();
+        for _ in 0..RECOVER_WINDOWS {
+            rc.evaluate(&window(15, 10000));
+        }
+        assert_eq!(rc.level(), level - 1);
+        // Congested again right after going up
+        rc.evaluate(&window(11, 10000));
+        let level = rc.level();
+        for _ in 0..RECOVER_WINDOWS {
+            rc.evaluate(&window(15, 10000));
+        }
+        assert_eq!(rc.level(), level);
+        for _ in 0..RECOVER_WINDOWS {
+            rc.evaluate(&window(15, 10000));
+        }
+        assert_eq!(rc.level(), level - 1);
+    }
+
+    #[test]
+    fn sent_counter() {
+        let counter = SentCounter::default();
+        counter.add(100);
+        counter.add(50);
+        assert_eq!(counter.get(), (2, 150));
+    }
+}
@@ -0,0 +1,776 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+// Screen and window sharing as the webcam: instead of a camera, the frames come
+// from the local screen (or a part of it) or from a window, so a local application
+// can be shown in a call running inside the remote desktop.
+// Only X11 for now, through libX11 loaded at runtime. There is no PipeWire /
+// screen cast portal backend, so on Wayland only XWayland windows can be shared,
+// and without XWayland nothing. Where it cannot be opened the loop sends the mock
+// pattern, as with a missing camera.
+use anyhow::Result;
+use shared::log;
+
+use super::resize_rgb;
+
+pub const CAM_SOURCE_ENV: &str = "UDSLAUNCHER_CAM_SOURCE";
+
+/// Part of the screen, in screen pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// How the shared window is found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowMatch {
+    Id(u64),
+    /// Part of the title, case insensitive
+    Title(String),
+}
+
+/// Where the webcam frames come from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameSource {
+    Camera,
+    /// The whole screen, or a region of it
+    Screen(Option<Region>),
+    Window(WindowMatch),
+}
+
+/// Parses `camera`, `screen`, `screen:x,y,width,height`, `window:<title>` or
+/// `window:0x<id>`. `None` if not valid.
+pub fn parse_source(value: &str) -> Option<FrameSource> {
+    let value = value.trim();
+    let (kind, arg) = match value.split_once(':') {
+        Some((kind, arg)) => (kind, Some(arg.trim())),
+        None => (value, None),
+    };
+    match (kind.to_lowercase().as_str(), arg) {
+        ("" | "camera", None) => Some(FrameSource::Camera),
+        ("screen", None) => Some(FrameSource::Screen(None)),
+        ("screen", Some(region)) => {
+            let parts: Vec<&str> = region.split(',').map(str::trim).collect();
+            let [x, y, width, height] = parts.as_slice() else {
+                return None;
+            };
+            let region = Region {
+                x: x.parse().ok()?,
+                y: y.parse().ok()?,
+                width: width.parse().ok()?,
+                height: height.parse().ok()?,
+            };
+            (region.width > 0 && region.height > 0).then_some(FrameSource::Screen(Some(region)))
+        }
+        ("window", Some(window)) if !window.is_empty() => {
+            let id = window
+                .strip_prefix("0x")
+                .or_else(|| window.strip_prefix("0X"))
+                .and_then(|hex| u64::from_str_radix(hex, 16).ok());
+            Some(FrameSource::Window(match id {
+                Some(id) => WindowMatch::Id(id),
+                None => WindowMatch::Title(window.to_string()),
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// Source of the frames: the env var or `session`, the one given by the RDP
+/// settings of the session. The camera if none (or not valid).
+pub fn selected_source(session: Option<&str>) -> FrameSource {
+    let value = std::env::var(CAM_SOURCE_ENV)
+        .ok()
+        .or_else(|| session.map(str::to_string));
+    match value {
+        Some(value) => parse_source(&value).unwrap_or_else(|| {
+            log::warn!("Invalid webcam source {:?}, using the camera", value);
+            FrameSource::Camera
+        }),
+        None => FrameSource::Camera,
+    }
+}
+
+/// Scales keeping the aspect ratio and centers on black bars
+pub(crate) fn letterbox(rgb: &[u8], width: u32, height: u32, dst_w: u32, dst_h: u32) -> Vec<u8> {
+    if width == 0 || height == 0 || dst_w == 0 || dst_h == 0 {
+        return vec![0; (dst_w * dst_h * 3) as usize];
+    }
+    if width == dst_w && height == dst_h {
+        return rgb.to_vec();
+    }
+    // Fits the width or the height, whichever is smaller
+    let (fit_w, fit_h) = if width as u64 * dst_h as u64 > height as u64 * dst_w as u64 {
+        (
+            dst_w,
+            ((height as u64 * dst_w as u64) / width as u64).max(1) as u32,
+        )
+    } else {
+        (
+            ((width as u64 * dst_h as u64) / height as u64).max(1) as u32,
+            dst_h,
+        )
+    };
+    let scaled = resize_rgb(rgb, width, height, fit_w, fit_h);
+    let (off_x, off_y) = ((dst_w - fit_w) / 2, (dst_h - fit_h) / 2);
+    let mut out = vec![0u8; (dst_w * dst_h * 3) as usize];
+    let row_len = (fit_w * 3) as usize;
+    for y in 0..fit_h {
+        let src = (y * fit_w * 3) as usize;
+        let dst = (((y + off_y) * dst_w + off_x) * 3) as usize;
+        out[dst..dst + row_len].copy_from_slice(&scaled[src..src + row_len]);
+    }
+    out
+}
+
+/// Captures the screen, a region or a window, as frames of the stream size
+pub struct ScreenCapture {
+    grabber: platform::Grabber,
+}
+
+impl ScreenCapture {
+    pub fn open(source: &FrameSource) -> Result<Self> {
+        let grabber = platform::Grabber::open(source)?;
+        log::info!("Sharing {} as the webcam", grabber.name());
+        Ok(ScreenCapture { grabber })
+    }
+
+    /// Name shown as the active camera
+    pub fn name(&self) -> String {
+        self.grabber.name()
+    }
+
+    /// Next frame, RGB24 of `width` x `height`
+    pub fn frame(&mut self, width: u32, height: u32) -> Result<Vec<u8>> {
+        let (rgb, src_w, src_h) = self.grabber.grab()?;
+        Ok(letterbox(&rgb, src_w, src_h, width, height))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use anyhow::{Result, bail};
+
+    use super::FrameSource;
+
+    pub struct Grabber;
+
+    impl Grabber {
+        pub fn open(_source: &FrameSource) -> Result<Self> {
+            bail!("Screen sharing as the webcam is only supported on Linux with X11")
+        }
+
+        pub fn name(&self) -> String {
+            String::new()
+        }
+
+        pub fn grab(&mut self) -> Result<(Vec<u8>, u32, u32)> {
+            bail!("Screen sharing as the webcam is only supported on X11")
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::ffi::{CStr, c_char, c_int, c_uint, c_ulong, c_void};
+    use std::ptr;
+    use std::sync::atomic::{AtomicPtr, Ordering};
+    use std::sync::{LazyLock, Mutex};
+
+    use anyhow::{Context, Result, anyhow, bail};
+    use shared::log;
+
+    use super::{FrameSource, Region, WindowMatch};
+
+    const Z_PIXMAP: c_int = 2;
+    const ALL_PLANES: c_ulong = !0;
+    const LSB_FIRST: c_int = 0;
+
+    type Window = c_ulong;
+
+    #[repr(C)]
+    struct Display {
+        _private: [u8; 0],
+    }
+
+    // Same layout as in Xlib, not all fields are used
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct XErrorEvent {
+        kind: c_int,
+        display: *mut Display,
+        resource_id: c_ulong,
+        serial: c_ulong,
+        error_code: u8,
+        request_code: u8,
+        minor_code: u8,
+    }
+
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct XImageFuncs {
+        create_image: *const c_void,
+        destroy_image: unsafe extern "C" fn(*mut XImage) -> c_int,
+        get_pixel: *const c_void,
+        put_pixel: *const c_void,
+        sub_image: *const c_void,
+        add_pixel: *const c_void,
+    }
+
+    #[repr(C)]
+    #[allow(dead_code)]
+    struct XImage {
+        width: c_int,
+        height: c_int,
+        xoffset: c_int,
+        format: c_int,
+        data: *mut c_char,
+        byte_order: c_int,
+        bitmap_unit: c_int,
+        bitmap_bit_order: c_int,
+        bitmap_pad: c_int,
+        depth: c_int,
+        bytes_per_line: c_int,
+        bits_per_pixel: c_int,
+        red_mask: c_ulong,
+        green_mask: c_ulong,
+        blue_mask: c_ulong,
+        obdata: *mut c_char,
+        f: XImageFuncs,
+    }
+
+    type ErrorHandler = unsafe extern "C" fn(*mut Display, *mut XErrorEvent) -> c_int;
+
+    type OpenDisplayFn = unsafe extern "C" fn(*const c_char) -> *mut Display;
+    type CloseDisplayFn = unsafe extern "C" fn(*mut Display) -> c_int;
+    type DefaultRootWindowFn = unsafe extern "C" fn(*mut Display) -> Window;
+    type GetGeometryFn = unsafe extern "C" fn(
+        *mut Display,
+        Window,
+        *mut Window,
+        *mut c_int,
+        *mut c_int,
+        *mut c_uint,
+        *mut c_uint,
+        *mut c_uint,
+        *mut c_uint,
+    ) -> c_int;
+    type TranslateCoordinatesFn = unsafe extern "C" fn(
+        *mut Display,
+        Window,
+        Window,
+        c_int,
+        c_int,
+        *mut c_int,
+        *mut c_int,
+        *mut Window,
+    ) -> c_int;
+    type GetImageFn = unsafe extern "C" fn(
+        *mut Display,
+        Window,
+        c_int,
+        c_int,
+        c_uint,
+        c_uint,
+        c_ulong,
+        c_int,
+    ) -> *mut XImage;
+    type QueryTreeFn = unsafe extern "C" fn(
+        *mut Display,
+        Window,
+        *mut Window,
+        *mut Window,
+        *mut *mut Window,
+        *mut c_uint,
+    ) -> c_int;
+    type FetchNameFn = unsafe extern "C" fn(*mut Display, Window, *mut *mut c_char) -> c_int;
+    type FreeFn = unsafe extern "C" fn(*mut c_void) -> c_int;
+    type SetErrorHandlerFn = unsafe extern "C" fn(Option<ErrorHandler>) -> Option<ErrorHandler>;
+
+    struct X11Library {
+        open_display: OpenDisplayFn,
+        close_display: CloseDisplayFn,
+        default_root_window: DefaultRootWindowFn,
+        get_geometry: GetGeometryFn,
+        translate_coordinates: TranslateCoordinatesFn,
+        get_image: GetImageFn,
+        query_tree: QueryTreeFn,
+        fetch_name: FetchNameFn,
+        free: FreeFn,
+        set_error_handler: SetErrorHandlerFn,
+        // Keeps the symbols above valid
+        _lib: libloading::Library,
+    }
+
+    static X11: LazyLock<Option<X11Library>> = LazyLock::new(|| match load_library() {
+        Ok(lib) => Some(lib),
+        Err(e) => {
+            log::warn!("X11 library failed to load, no screen sharing: {e}");
+            None
+        }
+    });
+
+    fn load_library() -> Result<X11Library> {
+        let lib = ["libX11.so.6", "libX11.so"]
+            .iter()
+            .find_map(|name| unsafe { libloading::Library::new(name) }.ok())
+            .ok_or_else(|| anyhow!("Could not find or load libX11"))?;
+        unsafe {
+            macro_rules! symbol {
+                ($name:literal) => {
+                    *lib.get($name)
+                        .map_err(|e| anyhow!("Missing X11 symbol {:?}: {e}", $name))?
+                };
+            }
+            Ok(X11Library {
+                open_display: symbol!(b"XOpenDisplay"),
+                close_display: symbol!(b"XCloseDisplay"),
+                default_root_window: symbol!(b"XDefaultRootWindow"),
+                get_geometry: symbol!(b"XGetGeometry"),
+                translate_coordinates: symbol!(b"XTranslateCoordinates"),
+                get_image: symbol!(b"XGetImage"),
+                query_tree: symbol!(b"XQueryTree"),
+                fetch_name: symbol!(b"XFetchName"),
+                free: symbol!(b"XFree"),
+                set_error_handler: symbol!(b"XSetErrorHandler"),
+                _lib: lib,
+            })
+        }
+    }
+
+    // Xlib's default error handler exits the process, and a shared window can be
+    // closed at any time. The handler is only installed while our requests run (see
+    // `ErrorScope`): errors on our connection are logged, the rest go to the handler
+    // that was there (the one of the window toolkit, if any).
+    static CAPTURE_DISPLAY: AtomicPtr<Display> = AtomicPtr::new(ptr::null_mut());
+    static PREVIOUS_HANDLER: Mutex<Option<ErrorHandler>> = Mutex::new(None);
+
+    unsafe extern "C" fn error_handler(display: *mut Display, event: *mut XErrorEvent) -> c_int {
+        if display == CAPTURE_DISPLAY.load(Ordering::Relaxed) {
+            let code = unsafe { event.as_ref() }.map_or(0, |e| e.error_code);
+            log::debug!("X11 error {} while sharing the screen", code);
+            return 0;
+        }
+        let previous = *PREVIOUS_HANDLER.lock().unwrap();
+        match previous {
+            Some(previous) => unsafe { previous(display, event) },
+            None => 0,
+        }
+    }
+
+    /// Our error handler, from creation until dropped. The requests made meanwhile
+    /// are all round trips, so their errors arrive before it is removed.
+    struct ErrorScope<'a> {
+        x11: &'a X11Library,
+        previous: Option<ErrorHandler>,
+    }
+
+    impl<'a> ErrorScope<'a> {
+        fn enter(x11: &'a X11Library, display: *mut Display) -> Self {
+            CAPTURE_DISPLAY.store(display, Ordering::Relaxed);
+            let previous = unsafe { (x11.set_error_handler)(Some(error_handler)) };
+            *PREVIOUS_HANDLER.lock().unwrap() = previous;
+            ErrorScope { x11, previous }
+        }
+    }
+
+    impl Drop for ErrorScope<'_> {
+        fn drop(&mut self) {
+            unsafe {
+                (self.x11.set_error_handler)(self.previous);
+            }
+            CAPTURE_DISPLAY.store(ptr::null_mut(), Ordering::Relaxed);
+        }
+    }
+
+    // Why the X display can't be used. There is no Wayland backend (the screen cast
+    // portal), so a Wayland session without XWayland can't share anything.
+    fn no_display() -> anyhow::Error {
+        if std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            anyhow!(
+                "Screen sharing as the webcam only works on X11, and this Wayland session has no \
+                 XWayland display (the screen cast portal is not supported)"
+            )
+        } else {
+            anyhow!("Cannot open the X display, screen sharing as the webcam needs X11")
+        }
+    }
+
+    /// What is captured, found again on each frame (windows move and resize)
+    enum Target {
+        Screen(Option<Region>),
+        Window { id: Window, title: String },
+    }
+
+    pub struct Grabber {
+        display: *mut Display,
+        root: Window,
+        target: Target,
+    }
+
+    // The display connection is only used from the capture thread
+    unsafe impl Send for Grabber {}
+
+    impl Grabber {
+        pub fn open(source: &FrameSource) -> Result<Self> {
+            let x11 = X11.as_ref().context(
+                "libX11 is not available, screen sharing as the webcam only works on X11",
+            )?;
+            let display = unsafe { (x11.open_display)(ptr::null()) };
+            if display.is_null() {
+                return Err(no_display());
+            }
+            if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+                log::warn!("Wayland session, only XWayland windows can be shared");
+            }
+            let _errors = ErrorScope::enter(x11, display);
+            let root = unsafe { (x11.default_root_window)(display) };
+            let mut grabber = Grabber {
+                display,
+                root,
+                target: Target::Screen(None),
+            };
+            grabber.target = match source {
+                FrameSource::Screen(region) => Target::Screen(*region),
+                FrameSource::Window(wanted) => {
+                    let (id, title) = grabber
+                        .find_window(root, wanted)
+                        .with_context(|| format!("Window {:?} not found", wanted))?;
+                    Target::Window { id, title }
+                }
+                FrameSource::Camera => bail!("The camera is not a screen source"),
+            };
+            Ok(grabber)
+        }
+
+        pub fn name(&self) -> String {
+            match &self.target {
+                Target::Screen(None) => "Screen".to_string(),
+                Target::Screen(Some(r)) => {
+                    format!("Screen {}x{}+{}+{}", r.width, r.height, r.x, r.y)
+                }
+                Target::Window { title, .. } => format!("Window \"{}\"", title),
+            }
+        }
+
+        fn window_name(&self, window: Window) -> Option<String> {
+            let x11 = X11.as_ref()?;
+            let mut name: *mut c_char = ptr::null_mut();
+            unsafe {
+                if (x11.fetch_name)(self.display, window, &mut name) == 0 || name.is_null() {
+                    return None;
+                }
+                let value = CStr::from_ptr(name).to_string_lossy().into_owned();
+                (x11.free)(name as *mut c_void);
+                Some(value)
+            }
+        }
+
+        fn children(&self, window: Window) -> Vec<Window> {
+            let Some(x11) = X11.as_ref() else {
+                return Vec::new();
+            };
+            let (mut root, mut parent) = (0, 0);
+            let mut children: *mut Window = ptr::null_mut();
+            let mut count: c_uint = 0;
+            unsafe {
+                if (x11.query_tree)(
+                    self.display,
+                    window,
+                    &mut root,
+                    &mut parent,
+                    &mut children,
+                    &mut count,
+                ) == 0
+                {
+                    return Vec::new();
+                }
+                if children.is_null() {
+                    return Vec::new();
+                }
+                let list = std::slice::from_raw_parts(children, count as usize).to_vec();
+                (x11.free)(children as *mut c_void);
+                list
+            }
+        }
+
+        // Depth first, top-most windows first (the tree lists them last)
+        fn find_window(&self, window: Window, wanted: &WindowMatch) -> Option<(Window, String)> {
+            for child in self.children(window).into_iter().rev() {
+                let name = self.window_name(child);
+                let found = match wanted {
+                    // c_ulong is 32 bits on some targets
+                    #[allow(clippy::unnecessary_cast)]
+                    WindowMatch::Id(id) => child as u64 == *id,
+                    WindowMatch::Title(title) => name
+                        .as_ref()
+                        .is_some_and(|n| n.to_lowercase().contains(&title.to_lowercase())),
+                };
+                if found {
+                    return Some((child, name.unwrap_or_else(|| format!("{:#x}", child))));
+                }
+                if let Some(found) = self.find_window(child, wanted) {
+                    return Some(found);
+                }
+            }
+            None
+        }
+
+        // Position and size on the screen of a window
+        fn geometry(&self, window: Window) -> Option<Region> {
+            let x11 = X11.as_ref()?;
+            let mut root = 0;
+            let (mut x, mut y) = (0, 0);
+            let (mut width, mut height, mut border, mut depth) = (0, 0, 0, 0);
+            let mut child = 0;
+            unsafe {
+                if (x11.get_geometry)(
+                    self.display,
+                    window,
+                    &mut root,
+                    &mut x,
+                    &mut y,
+                    &mut width,
+                    &mut height,
+                    &mut border,
+                    &mut depth,
+                ) == 0
+                {
+                    return None;
+                }
+                if window != self.root
+                    && (x11.translate_coordinates)(
+                        self.display,
+                        window,
+                        self.root,
+                        0,
+                        0,
+                        &mut x,
+                        &mut y,
+                        &mut child,
+                    ) == 0
+                {
+                    return None;
+                }
+            }
+            Some(Region {
+                x,
+                y,
+                width,
+                height,
+            })
+        }
+
+        /// Grabs the target from the root window, RGB24. Windows are taken as they
+        /// show on the screen, with whatever covers them.
+        pub fn grab(&mut self) -> Result<(Vec<u8>, u32, u32)> {
+            let x11 = X11.as_ref().context("X11 is not available")?;
+            let _errors = ErrorScope::enter(x11, self.display);
+            let screen = self
+                .geometry(self.root)
+                .context("Cannot get the screen size")?;
+            let wanted = match &self.target {
+                Target::Screen(None) => screen,
+                Target::Screen(Some(region)) => *region,
+                Target::Window { id, title } => self
+                    .geometry(*id)
+                    .with_context(|| format!("Window \"{}\" is gone", title))?,
+            };
+            let area = clip(wanted, screen).context("The shared area is off the screen")?;
+
+            let image = unsafe {
+                (x11.get_image)(
+                    self.display,
+                    self.root,
+                    area.x,
+                    area.y,
+                    area.width,
+                    area.height,
+                    ALL_PLANES,
+                    Z_PIXMAP,
+                )
+            };
+            if image.is_null() {
+                bail!("XGetImage failed");
+            }
+            let result = unsafe { image_to_rgb(&*image) };
+            unsafe {
+                ((*image).f.destroy_image)(image);
+            }
+            result.map(|rgb| (rgb, area.width, area.height))
+        }
+    }
+
+    impl Drop for Grabber {
+        fn drop(&mut self) {
+            if let Some(x11) = X11.as_ref() {
+                unsafe {
+                    (x11.close_display)(self.display);
+                }
+            }
+        }
+    }
+
+    // Part of `area` inside `screen`, `None` if nothing
+    fn clip(area: Region, screen: Region) -> Option<Region> {
+        let x0 = area.x.max(0);
+        let y0 = area.y.max(0);
+        let x1 = (area.x + area.width as i32).min(screen.width as i32);
+        let y1 = (area.y + area.height as i32).min(screen.height as i32);
+        (x1 > x0 && y1 > y0).then(|| Region {
+            x: x0,
+            y: y0,
+            width: (x1 - x0) as u32,
+            height: (y1 - y0) as u32,
+        })
+    }
+
+    // Pixel value to 8 bits with a channel mask
+    fn channel(pixel: u32, mask: u32) -> u8 {
+        if mask == 0 {
+            return 0;
+        }
+        let value = (pixel & mask) >> mask.trailing_zeros();
+        let bits = (mask >> mask.trailing_zeros()).count_ones();
+        if bits >= 8 {
+            (value >> (bits - 8)) as u8
+        } else {
+            ((value * 255) / ((1 << bits) - 1)) as u8
+        }
+    }
+
+    unsafe fn image_to_rgb(image: &XImage) -> Result<Vec<u8>> {
+        if image.bits_per_pixel != 32 || image.data.is_null() {
+            bail!(
+                "Unsupported screen format: {} bits per pixel",
+                image.bits_per_pixel
+            );
+        }
+        let (width, height) = (image.width as usize, image.height as usize);
+        let stride = image.bytes_per_line as usize;
+        let data = unsafe { std::slice::from_raw_parts(image.data as *const u8, stride * height) };
+        let masks = (
+            image.red_mask as u32,
+            image.green_mask as u32,
+            image.blue_mask as u32,
+        );
+        let mut rgb = Vec::with_capacity(width * height * 3);
+        for row in data.chunks_exact(stride) {
+            for px in row[..width * 4].chunks_exact(4) {
+                let bytes = [px[0], px[1], px[2], px[3]];
+                let pixel = if image.byte_order == LSB_FIRST {
+                    u32::from_le_bytes(bytes)
+                } else {
+                    u32::from_be_bytes(bytes)
+                };
+                rgb.extend_from_slice(&[
+                    channel(pixel, masks.0),
+                    channel(pixel, masks.1),
+                    channel(pixel, masks.2),
+                ]);
+            }
+        }
+        Ok(rgb)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn clip_to_screen() {
+            let screen = Region {
+                x: 0,
+                y: 0,
+                width: 1920,
+                height: 1080,
+            };
+            let area = Region {
+                x: -10,
+                y: 1000,
+                width: 100,
+                height: 200,
+            };
+            let clipped = clip(area, screen).unwrap();
+            assert_eq!((clipped.x, clipped.y), (0, 1000));
+            assert_eq!((clipped.width, clipped.height), (90, 80));
+            let off = Region { x: 2000, ..area };
+            assert!(clip(off, screen).is_none());
+        }
+
+        #[test]
+        fn channel_masks() {
+            let pixel = 0x00_12_34_56;
+            assert_eq!(channel(pixel, 0xff0000), 0x12);
+            assert_eq!(channel(pixel, 0x00ff00), 0x34);
+            assert_eq!(channel(pixel, 0x0000ff), 0x56);
+            // 5 bits of red at full goes to 255
+            assert_eq!(channel(0xf800, 0xf800), 255);
+        }
+
+        // Needs an X display
+        #[test]
+        #[ignore]
+        fn test_manual_grab_screen() {
+            let mut grabber = Grabber::open(&FrameSource::Screen(None)).unwrap();
+            let (rgb, width, height) = grabber.grab().unwrap();
+            assert_eq!(rgb.len(), (width * height * 3) as usize);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sources() {
+        assert_eq!(parse_source("camera"), Some(FrameSource::Camera));
+        assert_eq!(parse_source(" Screen "), Some(FrameSource::Screen(None)));
+        assert_eq!(
+            parse_source("screen:10, -20,640,480"),
+            Some(FrameSource::Screen(Some(Region {
+                x: 10,
+                y: -20,
+                width: 640,
+                height: 480
+            })))
+        );
+        assert_eq!(parse_source("screen:1,2,3"), None);
+        assert_eq!(parse_source("screen:0,0,0,480"), None);
+        assert_eq!(
+            parse_source("window:0x1a00003"),
+            Some(FrameSource::Window(WindowMatch::Id(0x1a00003)))
+        );
+        assert_eq!(
+            parse_source("window:Slides - Impress"),
+            Some(FrameSource::Window(WindowMatch::Title(
+                "Slides - Impress".to_string()
+            )))
+        );
+        assert_eq!(parse_source("window:"), None);
+        assert_eq!(parse_source("desktop"), None);
+    }
+
+    #[test]
+    fn letterbox_keeps_aspect() {
+        // 4x1 white into 4x4: a 4x1 band in the middle
+        let rgb = vec![255u8; 4 * 3];
+        let out = letterbox(&rgb, 4, 1, 4, 4);
+        assert_eq!(out.len(), 4 * 4 * 3);
+        let row = |y: usize| &out[y * 12..(y + 1) * 12];
+        assert!(row(0).iter().all(|&v| v == 0));
+        assert!(row(1).iter().all(|&v| v == 255));
+        assert!(row(2).iter().all(|&v| v == 0));
+        // Tall into wide: bars on the sides
+        let rgb = vec![255u8; 2 * 4 * 3];
+        let out = letterbox(&rgb, 2, 4, 8, 4);
+        assert_eq!(&out[..3], &[0, 0, 0]);
+        assert_eq!(&out[4 * 3..4 * 3 + 3], &[255, 255, 255]);
+    }
+}
@@ -34,6 +34,9 @@ pub trait BrokerApi: Send + Sync {
     ) -> Result<types::Script, types::Error>;
     async fn send_log(&self, ticket: &str, log: &str) -> Result<()>;
     async fn request_rdp_sign(&self, ticket: &str, rdp: &str) -> Result<String>;
+    /// Certificate (PEM) issued by the broker for a PKCS#10 request (PEM), valid for
+    /// the session of the ticket only.
+    async fn request_smartcard_certificate(&self, ticket: &str, csr: &str) -> Result<String>;
 }
 
 pub struct UdsBrokerApi {
@@ -140,6 +143,7 @@ impl BrokerApi for UdsBrokerApi {
             kem_kyber_key: &general_purpose::STANDARD.encode(self.public_key),
             hostname: &self.hostname,
             version: consts::UDS_CLIENT_VERSION,
+            cached_modules: super::modules::cached(),
         };
 
         let response = self
@@ -193,6 +197,26 @@ impl BrokerApi for UdsBrokerApi {
             .into_result()?;
         Ok(signed_rdp)
     }
+
+    async fn request_smartcard_certificate(&self, ticket: &str, csr: &str) -> Result<String> {
+        log::debug!(
+            "Sending smartcard certificate request to broker at {}",
+            self.broker_url
+        );
+        let cert_request = types::SmartcardCertRequest { csr };
+        let response = self
+            .client
+            .put(format!("{}/{}/smartcard_cert", self.broker_url, ticket))
+            .headers(self.headers())
+            .json(&cert_request)
+            .send()
+            .await?;
+        let certificate = response
+            .json::<types::BrokerResponse<String>>()
+            .await?
+            .into_result()?;
+        Ok(certificate)
+    }
 }
 
 // OnceLock to store api instance, so we can use it across the app without passing it around
@@ -233,3 +233,65 @@ async fn test_request_rdp_sign() {
     let signed_rdp = response.unwrap();
     assert_eq!(signed_rdp, expected_signed_rdp);
 }
+
+#[tokio::test]
+async fn test_request_smartcard_certificate() {
+    log::setup_logging("debug", log::LogType::Test);
+    let (mut server, api) = setup_server_and_api().await;
+
+    let csr = "-----BEGIN CERTIFICATE REQUEST-----\nMIIB\n-----END CERTIFICATE REQUEST-----\n";
+    let expected_cert = "-----BEGIN CERTIFICATE-----\nMIIC\n-----END CERTIFICATE-----\n";
+
+    let result = types::BrokerResponse::<String> {
+        result: Some(expected_cert.to_string()),
+        error: None,
+    };
+
+    let _m = server
+        .mock(
+            "PUT",
+            mockito::Matcher::Regex(format!(r"/{}/smartcard_cert", TICKET_ID)),
+        )
+        .match_header("content-type", "application/json")
+        .match_body(mockito::Matcher::Json(serde_json::json!({ "csr": csr })))
+        .with_body(serde_json::to_string(&result).unwrap())
+        .with_status(200)
+        .create_async()
+        .await;
+
+    let response = api.request_smartcard_certificate(TICKET_ID, csr).await;
+    assert!(
+        response.is_ok(),
+        "Request smartcard certificate failed: {:?}",
+        response
+    );
+    assert_eq!(response.unwrap(), expected_cert);
+}
+
+#[tokio::test]
+async fn test_request_smartcard_certificate_denied() {
+    log::setup_logging("debug", log::LogType::Test);
+    let (mut server, api) = setup_server_and_api().await;
+
+    let result = types::BrokerResponse::<String> {
+        result: None,
+        error: Some(types::Error {
+            message: "Smartcard certificates not allowed".to_string(),
+            is_retryable: false,
+            percent: 0,
+        }),
+    };
+
+    let _m = server
+        .mock(
+            "PUT",
+            mockito::Matcher::Regex(format!(r"/{}/smartcard_cert", TICKET_ID)),
+        )
+        .with_body(serde_json::to_string(&result).unwrap())
+        .with_status(200)
+        .create_async()
+        .await;
+
+    let response = api.request_smartcard_certificate(TICKET_ID, "csr").await;
+    assert!(response.is_err());
+}
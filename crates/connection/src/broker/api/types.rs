@@ -166,6 +166,8 @@ pub struct TicketReqBody<'a> {
     pub kem_kyber_key: &'a str,
     pub hostname: &'a str,
     pub version: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cached_modules: Vec<String>, // name@version of library modules already cached
 }
 
 #[derive(Debug, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
@@ -179,6 +181,34 @@ pub struct Script {
     pub params: String, // from codecs.encode(codecs.encode(json.dumps(self.parameters).encode(), 'bz2'), 'base64').decode()
     pub log: Log,
     pub shared_secret: Option<SharedSecret>, // provided by the broker for cryptographic operations
+    #[serde(default)]
+    pub modules: Vec<ScriptModule>, // Library modules the script can import by name
+}
+
+// Library module importable from scripts. The code can be omitted by the broker if the
+// client reported this name@version as cached (see broker::modules)
+#[derive(Debug, Clone, Serialize, Deserialize, Zeroize)]
+pub struct ScriptModule {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub code: Option<String>, // Same encoding as Script::script
+    pub signature: String, // base64-encoded signature of `signed_message`
+}
+
+impl ScriptModule {
+    pub fn decoded_code(&self) -> Result<Option<String>> {
+        self.code
+            .as_ref()
+            .map(|code| -> Result<String> { Ok(String::from_utf8(Script::decode_value(code)?)?) })
+            .transpose()
+    }
+
+    /// What the signature covers: `name@version`, a newline and the decoded code, so
+    /// signed code can't be served as another module or version
+    pub fn signed_message(&self, code: &str) -> Vec<u8> {
+        format!("{}@{}\n{}", self.name, self.version, code).into_bytes()
+    }
 }
 
 impl Script {
@@ -225,6 +255,11 @@ pub struct RdpSignRequest<'a> {
     pub rdp: &'a str,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SmartcardCertRequest<'a> {
+    pub csr: &'a str,
+}
+
 // Test helper to get a sample Script
 #[cfg(test)]
 pub fn get_test_script() -> Script {
@@ -321,6 +356,7 @@ pub fn get_test_script() -> Script {
             ticket: Some("dummy_ticket".to_string()),
         },
         shared_secret: None,
+        modules: Vec::new(),
     }
 }
 
@@ -4,6 +4,7 @@
 // Authors: Adolfo Gómez, dkmaster at dkmon dot com
 
 pub mod api;
+pub mod modules;
 pub mod ticket;
 
 #[cfg(test)]
@@ -0,0 +1,335 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+// Library modules for scripts (see ScriptModule).
+// Modules are cached by name and version, so the broker can omit the code of the
+// ones we already have. Code is always verified against the signature sent by the
+// broker, cached or not. The signature covers the name and version too (see
+// ScriptModule::signed_message).
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use directories_next::ProjectDirs;
+
+use shared::log;
+
+use super::api::types::ScriptModule;
+
+const CACHE_FOLDER: &str = "modules";
+const MODULE_EXTENSION: &str = ".js";
+
+// name: segments separated by "/", version: single segment. Segments use [A-Za-z0-9._-]
+// and cannot start with a dot, so they are safe as path components.
+fn valid_segment(segment: &str) -> bool {
+    !segment.is_empty()
+        && !segment.starts_with('.')
+        && segment
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+pub fn valid_name(name: &str) -> bool {
+    name.split('/').all(valid_segment)
+}
+
+// Checks a base64 signature of a message, crypt::verify_signature but on tests
+type Verifier = fn(&[u8], &str) -> Result<()>;
+
+fn check_signature(module: &ScriptModule, code: &str, verify: Verifier) -> Result<()> {
+    verify(&module.signed_message(code), &module.signature).map_err(|e| {
+        anyhow::anyhow!(
+            "Invalid signature for module {}@{}: {}",
+            module.name,
+            module.version,
+            e
+        )
+    })
+}
+
+// Verified code of a module sent with its code, for when there is no cache
+fn resolve_uncached(module: &ScriptModule, verify: Verifier) -> Result<String> {
+    let code = module.decoded_code()?.ok_or_else(|| {
+        anyhow::anyhow!(
+            "Module {}@{} not sent and there is no module cache",
+            module.name,
+            module.version
+        )
+    })?;
+    check_signature(module, &code, verify)?;
+    Ok(code)
+}
+
+pub struct ModuleCache {
+    root: PathBuf,
+    verify: Verifier,
+}
+
+impl ModuleCache {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            verify: crypt::verify_signature,
+        }
+    }
+
+    /// Cache on the user cache folder of the launcher
+    pub fn open() -> Option<Self> {
+        ProjectDirs::from("org", "openuds", "launcher")
+            .map(|dirs| Self::new(&dirs.cache_dir().join(CACHE_FOLDER)))
+    }
+
+    fn module_path(&self, name: &str, version: &str) -> Result<PathBuf> {
+        if !valid_name(name) || !valid_segment(version) {
+            return Err(anyhow::anyhow!(
+                "Invalid module name or version: {}@{}",
+                name,
+                version
+            ));
+        }
+        let mut path = self.root.clone();
+        path.extend(name.split('/'));
+        Ok(path.join(format!("{}{}", version, MODULE_EXTENSION)))
+    }
+
+    /// name@version of every cached module
+    pub fn list(&self) -> Vec<String> {
+        fn walk(dir: &Path, prefix: &str, found: &mut Vec<String>) {
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                return;
+            };
+            for entry in entries.flatten() {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let path = entry.path();
+                if path.is_dir() {
+                    let prefix = if prefix.is_empty() {
+                        file_name
+                    } else {
+                        format!("{}/{}", prefix, file_name)
+                    };
+                    walk(&path, &prefix, found);
+                } else if let Some(version) = file_name.strip_suffix(MODULE_EXTENSION)
+                    && !prefix.is_empty()
+                {
+                    found.push(format!("{}@{}", prefix, version));
+                }
+            }
+        }
+        let mut found = Vec::new();
+        walk(&self.root, "", &mut found);
+        found.sort();
+        found
+    }
+
+    /// Returns the verified code of the module, storing it if it was not cached
+    pub fn resolve(&self, module: &ScriptModule) -> Result<String> {
+        let path = self.module_path(&module.name, &module.version)?;
+        let code = match module.decoded_code()? {
+            Some(code) => code,
+            None => std::fs::read_to_string(&path).map_err(|e| {
+                anyhow::anyhow!(
+                    "Module {}@{} not sent and not cached: {}",
+                    module.name,
+                    module.version,
+                    e
+                )
+            })?,
+        };
+
+        check_signature(module, &code, self.verify).inspect_err(|_| {
+            // A bad cached copy is useless, remove it so next request gets the code
+            if module.code.is_none() {
+                std::fs::remove_file(&path).ok();
+            }
+        })?;
+
+        if module.code.is_some() && !path.exists() {
+            log::debug!(
+                "Caching module {}@{} on {:?}",
+                module.name,
+                module.version,
+                path
+            );
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).ok();
+            }
+            if let Err(e) = std::fs::write(&path, &code) {
+                // Not fatal, we have the code
+                log::warn!("Could not cache module {}: {}", module.name, e);
+            }
+        }
+
+        Ok(code)
+    }
+}
+
+/// Cached modules, to be reported to the broker
+pub fn cached() -> Vec<String> {
+    ModuleCache::open().map(|c| c.list()).unwrap_or_default()
+}
+
+/// Resolves all modules of a script to (name, verified code). Without a cache
+/// folder only the modules sent with their code can be resolved.
+pub fn resolve_all(modules: &[ScriptModule]) -> Result<Vec<(String, String)>> {
+    if modules.is_empty() {
+        return Ok(Vec::new());
+    }
+    let cache = ModuleCache::open();
+    if cache.is_none() {
+        log::warn!("Cannot locate the module cache folder, modules will not be cached");
+    }
+    modules
+        .iter()
+        .map(|m| {
+            let code = match &cache {
+                Some(cache) => cache.resolve(m)?,
+                None => resolve_uncached(m, crypt::verify_signature)?,
+            };
+            Ok((m.name.clone(), code))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::api::types::get_test_script;
+
+    // Signatures of the tests are the signed message itself
+    fn fake_verify(message: &[u8], signature: &str) -> Result<()> {
+        if message == signature.as_bytes() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("Signature verification failed"))
+        }
+    }
+
+    fn signed_module(name: &str, version: &str, with_code: bool) -> ScriptModule {
+        let mut module = ScriptModule {
+            name: name.to_string(),
+            version: version.to_string(),
+            code: Some(get_test_script().script),
+            signature: String::new(),
+        };
+        let code = module.decoded_code().unwrap().unwrap_or_default();
+        module.signature = String::from_utf8(module.signed_message(&code)).unwrap();
+        if !with_code {
+            module.code = None;
+        }
+        module
+    }
+
+    fn temp_cache() -> (ModuleCache, PathBuf) {
+        let root = std::env::temp_dir().join(format!("uds-modules-test-{}", rand::random::<u64>()));
+        let cache = ModuleCache {
+            verify: fake_verify,
+            ..ModuleCache::new(&root)
+        };
+        (cache, root)
+    }
+
+    #[test]
+    fn test_names() {
+        assert!(valid_name("tunnel"));
+        assert!(valid_name("uds/tunnel-helpers_2"));
+        assert!(!valid_name("../etc"));
+        assert!(!valid_name("uds//tunnel"));
+        assert!(!valid_name("uds/.hidden"));
+        assert!(!valid_name("c:\\windows"));
+        assert!(!valid_name(""));
+    }
+
+    #[test]
+    fn test_resolve_caches_and_reuses() -> Result<()> {
+        let (cache, root) = temp_cache();
+        assert!(cache.list().is_empty());
+
+        // Not cached, no code
+        assert!(
+            cache
+                .resolve(&signed_module("uds/tunnel", "1.0", false))
+                .is_err()
+        );
+
+        let code = cache.resolve(&signed_module("uds/tunnel", "1.0", true))?;
+        assert_eq!(cache.list(), vec!["uds/tunnel@1.0".to_string()]);
+
+        // Now it comes from cache
+        assert_eq!(
+            cache.resolve(&signed_module("uds/tunnel", "1.0", false))?,
+            code
+        );
+        // But only for the pinned version
+        assert!(
+            cache
+                .resolve(&signed_module("uds/tunnel", "1.1", false))
+                .is_err()
+        );
+
+        std::fs::remove_dir_all(root).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_rejects_bad_signatures() -> Result<()> {
+        let (cache, root) = temp_cache();
+        let mut module = signed_module("tunnel", "2", true);
+        module.signature.push(' ');
+        assert!(cache.resolve(&module).is_err());
+        assert!(
+            cache.list().is_empty(),
+            "Unverified code must not be cached"
+        );
+
+        // Tampered cache
+        cache.resolve(&signed_module("tunnel", "2", true))?;
+        std::fs::write(cache.module_path("tunnel", "2")?, "export const x = 1;")?;
+        assert!(cache.resolve(&signed_module("tunnel", "2", false)).is_err());
+        assert!(cache.list().is_empty(), "Tampered module must be removed");
+
+        std::fs::remove_dir_all(root).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_signature_covers_name_and_version() -> Result<()> {
+        let (cache, root) = temp_cache();
+        let signed = signed_module("uds/tunnel", "1.0", true);
+        for (name, version) in [("uds/tunnel", "0.9"), ("uds/evil", "1.0")] {
+            let renamed = ScriptModule {
+                name: name.to_string(),
+                version: version.to_string(),
+                ..signed.clone()
+            };
+            assert!(cache.resolve(&renamed).is_err(), "{}@{}", name, version);
+        }
+        assert!(cache.list().is_empty());
+
+        // A signature of the code alone, as scripts are signed, is not enough
+        let script = get_test_script();
+        let code_only = ScriptModule {
+            name: "uds/tunnel".to_string(),
+            version: "1.0".to_string(),
+            code: Some(script.script.clone()),
+            signature: script.signature.clone(),
+        };
+        let code = code_only.decoded_code()?.unwrap_or_default();
+        assert!(crypt::verify_signature(code.as_bytes(), &code_only.signature).is_ok());
+        assert!(ModuleCache::new(&root).resolve(&code_only).is_err());
+
+        std::fs::remove_dir_all(root).ok();
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_uncached() -> Result<()> {
+        let code = resolve_uncached(&signed_module("tunnel", "1", true), fake_verify)?;
+        assert!(!code.is_empty());
+        assert!(resolve_uncached(&signed_module("tunnel", "1", false), fake_verify).is_err());
+        let mut module = signed_module("tunnel", "1", true);
+        module.version = "2".to_string();
+        assert!(resolve_uncached(&module, fake_verify).is_err());
+        Ok(())
+    }
+}
@@ -16,6 +16,22 @@ pub fn load_png_rgba(png_bytes: &[u8]) -> (Vec<u8>, u32, u32) {
     (rgba.into_raw(), w, h)
 }
 
+/// Widen RGBA pixel data repeating its column `at` `extra` times, to stretch a flat
+/// part of an image without scaling the rest.
+pub fn widen_rgba(rgba: &[u8], w: u32, at: u32, extra: u32) -> Vec<u8> {
+    let row = w as usize * 4;
+    let at = at as usize * 4;
+    let mut widened = Vec::with_capacity(rgba.len() / row * (row + extra as usize * 4));
+    for line in rgba.chunks_exact(row) {
+        widened.extend_from_slice(&line[..at]);
+        for _ in 0..extra {
+            widened.extend_from_slice(&line[at..at + 4]);
+        }
+        widened.extend_from_slice(&line[at..]);
+    }
+    widened
+}
+
 #[allow(dead_code)]
 /// Generate a glass-style rounded rectangle RGBA buffer.
 /// `color` = [r, g, b, a] for the fill.
@@ -80,3 +96,21 @@ fn corner_alpha(x: u32, y: u32, w: u32, h: u32, r: u32) -> f32 {
         1.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widen_repeats_column() {
+        // 3x2 image, one byte value per pixel
+        let px = |v: u8| [v; 4];
+        let rgba: Vec<u8> = [1, 2, 3, 4, 5, 6].iter().flat_map(|v| px(*v)).collect();
+        let widened = widen_rgba(&rgba, 3, 1, 2);
+        let expected: Vec<u8> = [1, 2, 2, 2, 3, 4, 5, 5, 5, 6]
+            .iter()
+            .flat_map(|v| px(*v))
+            .collect();
+        assert_eq!(widened, expected);
+    }
+}
@@ -12,7 +12,9 @@ use winit::keyboard::PhysicalKey;
 use shared::log;
 
 use super::{AppHandler, RawKey};
+use crate::keymap::HotkeyAction;
 use crate::monitor;
+use crate::pointer::{self, PointerEvent};
 use crate::windows::rdp_window::RdpMode;
 
 impl AppHandler {
@@ -24,46 +26,23 @@ impl AppHandler {
             return false;
         };
 
-        // Track Alt
-        match code {
-            winit::keyboard::KeyCode::AltLeft | winit::keyboard::KeyCode::AltRight => {
-                self.alt_held = key_ev.state.is_pressed();
-            }
-            _ => {}
-        }
+        // Track the modifiers of the escape chord
+        self.modifiers.update(code, key_ev.state.is_pressed());
 
         if !self.processing_events.load(Ordering::Relaxed) {
             return false;
         }
 
         // Hotkeys
-        if self.alt_held && key_ev.state.is_pressed() && !key_ev.repeat {
-            let is_rail = self
+        if key_ev.state.is_pressed()
+            && !key_ev.repeat
+            && let Some(action) = self
                 .rdp
                 .as_ref()
-                .is_some_and(|s| matches!(s.mode, RdpMode::Rail(_)));
-            match code {
-                winit::keyboard::KeyCode::Enter if !is_rail => {
-                    log::debug!("Alt+Enter → fullscreen");
-                    self.toggle_fullscreen();
-                    return true;
-                }
-                winit::keyboard::KeyCode::KeyF if !is_rail => {
-                    if let Some(ref s) = self.rdp
-                        && let RdpMode::Desktop { ref fps, .. } = s.mode
-                    {
-                        fps.toggle();
-                    }
-                    return true;
-                }
-                winit::keyboard::KeyCode::F4 => {
-                    log::debug!("Alt+F4 → exit");
-                    self.stop.trigger();
-                    el.exit();
-                    return true;
-                }
-                _ => {}
-            }
+                .and_then(|s| s.hotkeys.action(code, &self.modifiers))
+            && self.run_hotkey(el, action)
+        {
+            return true;
         }
 
         let raw = RawKey {
@@ -75,11 +54,68 @@ impl AppHandler {
         true
     }
 
+    /// Run a hotkey action, false if it does not apply and the key goes to the session.
+    fn run_hotkey(&mut self, el: &ActiveEventLoop, action: HotkeyAction) -> bool {
+        let Some(ref s) = self.rdp else {
+            return false;
+        };
+        let is_rail = matches!(s.mode, RdpMode::Rail(_));
+        match action {
+            HotkeyAction::Fullscreen if !is_rail => {
+                log::debug!("Hotkey → fullscreen");
+                self.toggle_fullscreen();
+            }
+            HotkeyAction::Fps if !is_rail => {
+                if let RdpMode::Desktop { ref fps, .. } = s.mode {
+                    fps.toggle();
+                }
+            }
+            HotkeyAction::Mute => {
+                log::debug!("Hotkey → mute");
+                s.volume.toggle_mute();
+                s.window.window.request_redraw();
+            }
+            HotkeyAction::VolumeUp | HotkeyAction::VolumeDown => {
+                s.volume.step(action == HotkeyAction::VolumeUp);
+                s.window.window.request_redraw();
+            }
+            // Only while the camera is in use, the key goes to the session otherwise
+            HotkeyAction::Camera if s.webcam.current_camera().is_some() => {
+                let camera = s.webcam.next_camera();
+                log::debug!("Hotkey → camera {:?}", camera);
+            }
+            // Webcam privacy, also only while the camera is in use
+            HotkeyAction::Pause | HotkeyAction::Preview | HotkeyAction::Blur
+                if s.webcam.privacy.is_streaming() =>
+            {
+                let privacy = &s.webcam.privacy;
+                let on = match action {
+                    HotkeyAction::Pause => privacy.toggle_pause(),
+                    HotkeyAction::Preview => privacy.toggle_preview(),
+                    _ => privacy.toggle_blur(),
+                };
+                log::debug!("Hotkey {:?} → webcam privacy {}", action, on);
+                s.window.window.request_redraw();
+            }
+            HotkeyAction::CtrlAltDel => s.send_ctrl_alt_del(),
+            HotkeyAction::Exit => {
+                log::debug!("Hotkey → exit");
+                self.stop.trigger();
+                el.exit();
+            }
+            _ => return false,
+        }
+        true
+    }
+
     pub(crate) fn toggle_fullscreen(&mut self) {
         let Some(s) = &mut self.rdp else { return };
+        // A multi-monitor session window always spans its monitors
         if let RdpMode::Desktop {
             ref full_screen,
             ref mut last_windowed_size,
+            ref mut grab,
+            layout: None,
             ..
         } = s.mode
         {
@@ -107,6 +143,9 @@ impl AppHandler {
                     *last_windowed_size = Some((w, h));
                 }
             }
+            if let Some(grab) = grab {
+                grab.update(s.window.window.has_focus());
+            }
         }
     }
 
@@ -156,29 +195,28 @@ impl AppHandler {
                     s.request_screen_resize();
                 }
             }
+            WindowEvent::Focused(focused) => {
+                if let RdpMode::Desktop {
+                    grab: Some(ref mut grab),
+                    ..
+                } = s.mode
+                {
+                    grab.update(*focused);
+                }
+            }
             WindowEvent::CursorMoved { position, .. } => {
                 self.last_pointer = Some(*position);
                 s.cursor.x = position.x as f32;
                 s.cursor.y = position.y as f32;
                 s.window.window.request_redraw();
-                let gdi_w = unsafe { (*s.gdi).width as u32 };
-                let gdi_h = unsafe { (*s.gdi).height as u32 };
                 let phys_w = s.window.window.inner_size().width;
-                let phys_h = s.window.window.inner_size().height;
-                let x = ((position.x * gdi_w as f64) / phys_w as f64)
-                    .round()
-                    .clamp(0.0, (gdi_w - 1) as f64) as u16;
-                let y = ((position.y * gdi_h as f64) / phys_h as f64)
-                    .round()
-                    .clamp(0.0, (gdi_h - 1) as f64) as u16;
-                let _ = s.command_tx.send(rdp::messaging::RdpCommand::Input(
-                    rdp::messaging::InputEvent::Mouse {
-                        flags: rdp::sys::PTR_FLAGS_MOVE as u16,
-                        x,
-                        y,
-                    },
-                ));
-                rdp::Rdp::set_command_event(&s.command_event);
+                let (x, y) = s.pointer_scaler().point(position.x, position.y);
+                s.send_pointer(&[PointerEvent {
+                    flags: rdp::sys::PTR_FLAGS_MOVE as u16,
+                    x,
+                    y,
+                    extended: false,
+                }]);
                 // Pinbar
                 if let RdpMode::Desktop {
                     ref mut pinbar,
@@ -211,6 +249,30 @@ impl AppHandler {
                     && *button == winit::event::MouseButton::Left
                 {
                     let px = pos.x as f32;
+                    if pinbar.btn_vol_down_x.contains(&px) || pinbar.btn_vol_up_x.contains(&px) {
+                        s.volume.step(pinbar.btn_vol_up_x.contains(&px));
+                        s.window.window.request_redraw();
+                        return true;
+                    }
+                    if pinbar.btn_pause_x.contains(&px) {
+                        s.webcam.privacy.toggle_pause();
+                        s.window.window.request_redraw();
+                        return true;
+                    }
+                    if pinbar.btn_cam_x.contains(&px) {
+                        let camera = s.webcam.next_camera();
+                        log::debug!("Pinbar → camera {:?}", camera);
+                        return true;
+                    }
+                    if pinbar.btn_mute_x.contains(&px) {
+                        s.volume.toggle_mute();
+                        s.window.window.request_redraw();
+                        return true;
+                    }
+                    if pinbar.btn_cad_x.contains(&px) {
+                        s.send_ctrl_alt_del();
+                        return true;
+                    }
                     if pinbar.btn_fs_x.contains(&px) {
                         self.toggle_fullscreen();
                         return true;
@@ -221,66 +283,14 @@ impl AppHandler {
                 }
 
                 if let Some(pos) = self.last_pointer {
-                    let gdi_w = unsafe { (*s.gdi).width as u32 };
-                    let gdi_h = unsafe { (*s.gdi).height as u32 };
-                    let phys_w = s.window.window.inner_size().width;
-                    let phys_h = s.window.window.inner_size().height;
-                    let x = ((pos.x * gdi_w as f64) / phys_w as f64)
-                        .round()
-                        .clamp(0.0, (gdi_w - 1) as f64) as u16;
-                    let y = ((pos.y * gdi_h as f64) / phys_h as f64)
-                        .round()
-                        .clamp(0.0, (gdi_h - 1) as f64) as u16;
-                    let flags = match *button {
-                        winit::event::MouseButton::Left => rdp::sys::PTR_FLAGS_BUTTON1,
-                        winit::event::MouseButton::Right => rdp::sys::PTR_FLAGS_BUTTON2,
-                        winit::event::MouseButton::Middle => rdp::sys::PTR_FLAGS_BUTTON3,
-                        _ => 0,
-                    } as u16;
-                    if flags != 0 {
-                        let f = flags
-                            | if btn.is_pressed() {
-                                rdp::sys::PTR_FLAGS_DOWN as u16
-                            } else {
-                                0
-                            };
-                        let _ = s.command_tx.send(rdp::messaging::RdpCommand::Input(
-                            rdp::messaging::InputEvent::Mouse { flags: f, x, y },
-                        ));
-                        rdp::Rdp::set_command_event(&s.command_event);
+                    let (x, y) = s.pointer_scaler().point(pos.x, pos.y);
+                    if let Some(event) = pointer::button(*button, btn.is_pressed(), x, y) {
+                        s.send_pointer(&[event]);
                     }
                 }
             }
             WindowEvent::MouseWheel { delta, .. } => {
-                let dy = match delta {
-                    winit::event::MouseScrollDelta::LineDelta(_, y) => *y as i32,
-                    winit::event::MouseScrollDelta::PixelDelta(pos) => pos.y as i32,
-                };
-                let mut wd = (dy as f32 * 120.0) as i32;
-                let flags = (rdp::sys::PTR_FLAGS_WHEEL as u16)
-                    | if wd < 0 {
-                        wd = -wd;
-                        rdp::sys::PTR_FLAGS_WHEEL_NEGATIVE as u16
-                    } else {
-                        0
-                    };
-                while wd > 0 {
-                    let step: u16 = if wd > 0xFF { 0xFF } else { (wd & 0xFF) as u16 };
-                    wd -= step as i32;
-                    let cflags = if flags & (rdp::sys::PTR_FLAGS_WHEEL_NEGATIVE as u16) != 0 {
-                        flags | (0x100 - step)
-                    } else {
-                        flags | step
-                    };
-                    let _ = s.command_tx.send(rdp::messaging::RdpCommand::Input(
-                        rdp::messaging::InputEvent::Mouse {
-                            flags: cflags,
-                            x: 0,
-                            y: 0,
-                        },
-                    ));
-                    rdp::Rdp::set_command_event(&s.command_event);
-                }
+                s.send_pointer(&pointer::wheel(delta));
             }
             _ => {}
         }
@@ -359,6 +369,8 @@ impl AppHandler {
         };
         let cmd_tx = state.command_tx.clone();
         let cmd_ev = state.command_event;
+        let gdi = state.gdi;
+        let gdi_lock = state.gdi_lock.clone();
 
         if let WindowEvent::MouseInput { state: btn, .. } = &event {
             self.rail_button_down = if btn.is_pressed() {
@@ -444,18 +456,6 @@ impl AppHandler {
                 if let Some(pos) = self.last_pointer
                     && let Some(rw) = rail.windows.get(&rail_id)
                 {
-                    let bm = match button {
-                        winit::event::MouseButton::Left => rdp::sys::PTR_FLAGS_BUTTON1,
-                        winit::event::MouseButton::Right => rdp::sys::PTR_FLAGS_BUTTON2,
-                        winit::event::MouseButton::Middle => rdp::sys::PTR_FLAGS_BUTTON3,
-                        _ => return,
-                    } as u16;
-                    let f = bm
-                        | if btn.is_pressed() {
-                            rdp::sys::PTR_FLAGS_DOWN as u16
-                        } else {
-                            0
-                        };
                     let sf = state.coords_scale;
                     let (gx, gy) = monitor::phys_2_logic(
                         (
@@ -468,8 +468,11 @@ impl AppHandler {
                     let dh = state.desktop_size.1.saturating_sub(1) as i32;
                     let gx = gx.clamp(0, dw) as u16;
                     let gy = gy.clamp(0, dh) as u16;
+                    let Some(click) = pointer::button(button, btn.is_pressed(), gx, gy) else {
+                        return;
+                    };
                     log::trace!(
-                        "RAIL[{rail_id}] MouseClick → flags={f} x={gx} y={gy} (phys=({:.0},{:.0}) sf={sf} rect=({},{})+{}x{})",
+                        "RAIL[{rail_id}] MouseClick → {click:?} (phys=({:.0},{:.0}) sf={sf} rect=({},{})+{}x{})",
                         pos.x,
                         pos.y,
                         rw.rect.x,
@@ -477,46 +480,19 @@ impl AppHandler {
                         rw.rect.w,
                         rw.rect.h
                     );
-                    let _ = cmd_tx.send(rdp::messaging::RdpCommand::Input(
-                        rdp::messaging::InputEvent::Mouse {
-                            flags: f,
-                            x: gx,
-                            y: gy,
-                        },
-                    ));
-                    rdp::Rdp::set_command_event(&cmd_ev);
+                    if click.extended {
+                        pointer::send_extended(gdi, &gdi_lock, &click);
+                    } else {
+                        let _ = cmd_tx.send(rdp::messaging::RdpCommand::Input(click.into()));
+                        rdp::Rdp::set_command_event(&cmd_ev);
+                    }
                 }
             }
             WindowEvent::MouseWheel { delta, .. } => {
-                let dy = match delta {
-                    winit::event::MouseScrollDelta::LineDelta(_, y) => y as i32,
-                    winit::event::MouseScrollDelta::PixelDelta(pos) => pos.y as i32,
-                };
-                let mut wd = (dy as f32 * 120.0) as i32;
-                let flags = (rdp::sys::PTR_FLAGS_WHEEL as u16)
-                    | if wd < 0 {
-                        wd = -wd;
-                        rdp::sys::PTR_FLAGS_WHEEL_NEGATIVE as u16
-                    } else {
-                        0
-                    };
-                while wd > 0 {
-                    let step: u16 = if wd > 0xFF { 0xFF } else { (wd & 0xFF) as u16 };
-                    wd -= step as i32;
-                    let cflags = if flags & (rdp::sys::PTR_FLAGS_WHEEL_NEGATIVE as u16) != 0 {
-                        flags | (0x100 - step)
-                    } else {
-                        flags | step
-                    };
-                    let _ = cmd_tx.send(rdp::messaging::RdpCommand::Input(
-                        rdp::messaging::InputEvent::Mouse {
-                            flags: cflags,
-                            x: 0,
-                            y: 0,
-                        },
-                    ));
-                    rdp::Rdp::set_command_event(&cmd_ev);
+                for event in pointer::wheel(&delta) {
+                    let _ = cmd_tx.send(rdp::messaging::RdpCommand::Input(event.into()));
                 }
+                rdp::Rdp::set_command_event(&cmd_ev);
             }
             _ => {}
         }
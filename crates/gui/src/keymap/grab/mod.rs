@@ -0,0 +1,196 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! Keyboard grab: OS shortcuts (Alt+Tab, the Super key, Ctrl+Esc...) go to the
+//! session instead of acting on the local desktop.
+//!
+//! Windows uses a low level keyboard hook, X11 an active keyboard grab on the
+//! session window. Wayland and macOS have no grab: a warning is logged and those
+//! shortcuts stay local. Hotkeys and the modifiers of the escape chord are never
+//! grabbed. Ctrl+Alt+Del cannot be grabbed anywhere, it has its own hotkey and
+//! pinbar button.
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::Result;
+use flume::Sender;
+use shared::log;
+use winit::keyboard::KeyCode;
+
+use super::hotkeys::{Hotkeys, Modifiers};
+use crate::RawKey;
+
+#[cfg(windows)]
+mod windows;
+#[cfg(target_os = "linux")]
+mod x11;
+
+#[cfg(test)]
+mod tests;
+
+/// When the keyboard is grabbed, always with the session window focused.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GrabMode {
+    Never,
+    #[default]
+    Fullscreen,
+    Focused,
+}
+
+impl std::str::FromStr for GrabMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "never" => Ok(GrabMode::Never),
+            "fullscreen" => Ok(GrabMode::Fullscreen),
+            "focused" => Ok(GrabMode::Focused),
+            _ => anyhow::bail!("invalid keyboard grab '{}'", s),
+        }
+    }
+}
+
+/// Key combinations the local OS would act upon.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub fn is_system_shortcut(code: KeyCode, held: &Modifiers) -> bool {
+    match code {
+        KeyCode::SuperLeft | KeyCode::SuperRight => true,
+        KeyCode::Tab | KeyCode::F4 | KeyCode::Space => held.alt,
+        KeyCode::Escape => held.alt || held.ctrl,
+        _ => false,
+    }
+}
+
+/// Decides which keys seen by the OS hook are grabbed, and forwards them.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) struct Grabber {
+    mode: GrabMode,
+    full_screen: Arc<AtomicBool>,
+    processing: Arc<AtomicBool>,
+    keys_tx: Sender<RawKey>,
+    hotkeys: Hotkeys,
+    held: Modifiers,
+    grabbed: Vec<KeyCode>,
+}
+
+#[cfg_attr(not(windows), allow(dead_code))]
+impl Grabber {
+    pub fn new(
+        mode: GrabMode,
+        full_screen: Arc<AtomicBool>,
+        processing: Arc<AtomicBool>,
+        keys_tx: Sender<RawKey>,
+        hotkeys: Hotkeys,
+    ) -> Self {
+        Grabber {
+            mode,
+            full_screen,
+            processing,
+            keys_tx,
+            hotkeys,
+            held: Modifiers::default(),
+            grabbed: Vec::new(),
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.processing.load(Ordering::Relaxed)
+            && match self.mode {
+                GrabMode::Never => false,
+                GrabMode::Fullscreen => self.full_screen.load(Ordering::Relaxed),
+                GrabMode::Focused => true,
+            }
+    }
+
+    /// A key event, `focused` if the session window has the keyboard. True if the
+    /// key is grabbed: sent to the session and hidden from the OS.
+    pub fn key(&mut self, code: KeyCode, pressed: bool, focused: bool) -> bool {
+        self.held.update(code, pressed);
+        // Releases of grabbed keys are grabbed too, whatever happened meanwhile
+        if !pressed {
+            let Some(pos) = self.grabbed.iter().position(|c| *c == code) else {
+                return false;
+            };
+            self.grabbed.remove(pos);
+            self.forward(code, false, false);
+            return true;
+        }
+        if !focused || !self.is_active() || !is_system_shortcut(code, &self.held) {
+            return false;
+        }
+        let is_chord_modifier =
+            Modifiers::of(code).is_some_and(|m| self.hotkeys.chord.contains(&m));
+        if is_chord_modifier || self.hotkeys.action(code, &self.held).is_some() {
+            return false;
+        }
+        let repeat = self.grabbed.contains(&code);
+        if !repeat {
+            self.grabbed.push(code);
+        }
+        self.forward(code, true, repeat);
+        true
+    }
+
+    fn forward(&self, code: KeyCode, pressed: bool, repeat: bool) {
+        log::trace!("Grabbed key {:?} pressed={}", code, pressed);
+        let _ = self.keys_tx.send(RawKey {
+            keycode: code,
+            pressed,
+            repeat,
+        });
+    }
+}
+
+/// Keyboard grab of a session window, released when dropped.
+pub struct KeyboardGrab {
+    #[cfg(windows)]
+    _hook: windows::Hook,
+    #[cfg(target_os = "linux")]
+    x11: x11::Grab,
+}
+
+impl KeyboardGrab {
+    /// Grab the keyboard for `window`, None if `mode` is `Never` or the platform
+    /// cannot grab it.
+    pub fn new(
+        window: &winit::window::Window,
+        mode: GrabMode,
+        full_screen: Arc<AtomicBool>,
+        processing: Arc<AtomicBool>,
+        keys_tx: Sender<RawKey>,
+        hotkeys: Hotkeys,
+    ) -> Option<Self> {
+        if mode == GrabMode::Never {
+            return None;
+        }
+        let grabber = Grabber::new(mode, full_screen, processing, keys_tx, hotkeys);
+        #[cfg(windows)]
+        {
+            windows::Hook::install(window, grabber).map(|hook| KeyboardGrab { _hook: hook })
+        }
+        #[cfg(target_os = "linux")]
+        {
+            x11::Grab::install(window, grabber).map(|x11| KeyboardGrab { x11 })
+        }
+        #[cfg(not(any(windows, target_os = "linux")))]
+        {
+            let _ = (window, grabber);
+            log::warn!("Keyboard grab is not supported on this platform, OS shortcuts stay local");
+            None
+        }
+    }
+
+    /// The session window gained or lost the focus, or went in or out of full
+    /// screen. The Windows hook checks it on each key by itself.
+    pub fn update(&mut self, focused: bool) {
+        #[cfg(target_os = "linux")]
+        self.x11.update(focused);
+        #[cfg(not(target_os = "linux"))]
+        let _ = focused;
+    }
+}
@@ -0,0 +1,120 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use flume::{Receiver, unbounded};
+use winit::keyboard::KeyCode;
+
+use super::*;
+
+struct Fixture {
+    grabber: Grabber,
+    full_screen: Arc<AtomicBool>,
+    keys_rx: Receiver<RawKey>,
+}
+
+fn fixture(mode: GrabMode, hotkeys: Hotkeys) -> Fixture {
+    let (keys_tx, keys_rx) = unbounded();
+    let full_screen = Arc::new(AtomicBool::new(true));
+    Fixture {
+        grabber: Grabber::new(
+            mode,
+            full_screen.clone(),
+            Arc::new(AtomicBool::new(true)),
+            keys_tx,
+            hotkeys,
+        ),
+        full_screen,
+        keys_rx,
+    }
+}
+
+impl Fixture {
+    fn forwarded(&self) -> Vec<(KeyCode, bool, bool)> {
+        self.keys_rx
+            .try_iter()
+            .map(|k| (k.keycode, k.pressed, k.repeat))
+            .collect()
+    }
+}
+
+#[test]
+fn grab_mode_parse() {
+    assert_eq!("Focused".parse::<GrabMode>().unwrap(), GrabMode::Focused);
+    assert_eq!("never".parse::<GrabMode>().unwrap(), GrabMode::Never);
+    assert!("always".parse::<GrabMode>().is_err());
+}
+
+#[test]
+fn alt_tab_is_grabbed() {
+    let mut f = fixture(GrabMode::Fullscreen, Hotkeys::default());
+    // Alt goes through the window as usual
+    assert!(!f.grabber.key(KeyCode::AltLeft, true, true));
+    assert!(f.grabber.key(KeyCode::Tab, true, true));
+    assert!(f.grabber.key(KeyCode::Tab, true, true));
+    assert!(!f.grabber.key(KeyCode::AltLeft, false, true));
+    // Released after Alt, still grabbed
+    assert!(f.grabber.key(KeyCode::Tab, false, true));
+    assert_eq!(
+        f.forwarded(),
+        vec![
+            (KeyCode::Tab, true, false),
+            (KeyCode::Tab, true, true),
+            (KeyCode::Tab, false, false)
+        ]
+    );
+    // Plain Tab is not a shortcut
+    assert!(!f.grabber.key(KeyCode::Tab, true, true));
+}
+
+#[test]
+fn super_and_ctrl_esc() {
+    let mut f = fixture(GrabMode::Focused, Hotkeys::default());
+    f.full_screen.store(false, Ordering::Relaxed);
+    assert!(f.grabber.key(KeyCode::SuperLeft, true, true));
+    assert!(f.grabber.key(KeyCode::SuperLeft, false, true));
+    assert!(!f.grabber.key(KeyCode::ControlLeft, true, true));
+    assert!(f.grabber.key(KeyCode::Escape, true, true));
+    assert_eq!(f.forwarded().len(), 3);
+}
+
+#[test]
+fn not_grabbed() {
+    // Windowed session
+    let mut f = fixture(GrabMode::Fullscreen, Hotkeys::default());
+    f.full_screen.store(false, Ordering::Relaxed);
+    assert!(!f.grabber.key(KeyCode::SuperLeft, true, true));
+
+    // Not focused
+    let mut f = fixture(GrabMode::Focused, Hotkeys::default());
+    assert!(!f.grabber.key(KeyCode::SuperLeft, true, false));
+
+    let mut f = fixture(GrabMode::Never, Hotkeys::default());
+    assert!(!f.grabber.key(KeyCode::SuperLeft, true, true));
+    assert!(f.forwarded().is_empty());
+}
+
+#[test]
+fn hotkeys_stay_local() {
+    // Alt+F4 is the exit hotkey with the default chord
+    let mut f = fixture(GrabMode::Fullscreen, Hotkeys::default());
+    f.grabber.key(KeyCode::AltLeft, true, true);
+    assert!(!f.grabber.key(KeyCode::F4, true, true));
+
+    // But goes to the session with another one
+    let hotkeys = Hotkeys::new(Some("ctrl+alt"), &[]).unwrap();
+    let mut f = fixture(GrabMode::Fullscreen, hotkeys);
+    f.grabber.key(KeyCode::AltLeft, true, true);
+    assert!(f.grabber.key(KeyCode::F4, true, true));
+
+    // A chord with Super keeps it local
+    let hotkeys = Hotkeys::new(Some("super"), &[]).unwrap();
+    let mut f = fixture(GrabMode::Fullscreen, hotkeys);
+    assert!(!f.grabber.key(KeyCode::SuperLeft, true, true));
+}
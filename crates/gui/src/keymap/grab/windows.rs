@@ -0,0 +1,98 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+use std::sync::Mutex;
+
+use shared::log;
+use windows::Win32::{
+    Foundation::{LPARAM, LRESULT, WPARAM},
+    System::LibraryLoader::GetModuleHandleW,
+    UI::WindowsAndMessaging::{
+        CallNextHookEx, GetForegroundWindow, HC_ACTION, HHOOK, KBDLLHOOKSTRUCT, LLKHF_EXTENDED,
+        LLKHF_UP, SetWindowsHookExW, UnhookWindowsHookEx, WH_KEYBOARD_LL,
+    },
+};
+use winit::keyboard::PhysicalKey;
+use winit::platform::scancode::PhysicalKeyExtScancode;
+use winit::raw_window_handle::{HasWindowHandle, RawWindowHandle};
+
+use super::Grabber;
+
+struct HookState {
+    grabber: Grabber,
+    hwnd: isize,
+}
+
+/// The hook procedure has no context, and there is one session window at most.
+static HOOK_STATE: Mutex<Option<HookState>> = Mutex::new(None);
+
+/// Low level keyboard hook, runs on the GUI thread (the one that installs it).
+pub(super) struct Hook(isize);
+
+impl Hook {
+    pub fn install(window: &winit::window::Window, grabber: Grabber) -> Option<Self> {
+        let hwnd = match window.window_handle().map(|h| h.as_raw()) {
+            Ok(RawWindowHandle::Win32(handle)) => handle.hwnd.get(),
+            _ => return None,
+        };
+        *HOOK_STATE.lock().unwrap() = Some(HookState { grabber, hwnd });
+
+        let hook = unsafe {
+            GetModuleHandleW(None).and_then(|module| {
+                SetWindowsHookExW(WH_KEYBOARD_LL, Some(hook_proc), Some(module.into()), 0)
+            })
+        };
+        match hook {
+            Ok(hook) => {
+                log::debug!("Keyboard grab hook installed");
+                Some(Hook(hook.0 as isize))
+            }
+            Err(e) => {
+                log::warn!("Keyboard grab hook could not be installed: {}", e);
+                HOOK_STATE.lock().unwrap().take();
+                None
+            }
+        }
+    }
+}
+
+impl Drop for Hook {
+    fn drop(&mut self) {
+        if let Err(e) = unsafe { UnhookWindowsHookEx(HHOOK(self.0 as *mut _)) } {
+            log::warn!("Keyboard grab hook could not be removed: {}", e);
+        }
+        HOOK_STATE.lock().unwrap().take();
+    }
+}
+
+unsafe extern "system" fn hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code == HC_ACTION as i32 {
+        // SAFETY: for WH_KEYBOARD_LL hooks, lparam points to a KBDLLHOOKSTRUCT
+        let event = unsafe { &*(lparam.0 as *const KBDLLHOOKSTRUCT) };
+        if grab(event) {
+            return LRESULT(1);
+        }
+    }
+    unsafe { CallNextHookEx(None, code, wparam, lparam) }
+}
+
+fn grab(event: &KBDLLHOOKSTRUCT) -> bool {
+    // Extended keys as winit expects them, 0xE0 prefixed
+    let extended = (event.flags & LLKHF_EXTENDED).0 != 0;
+    let scancode = event.scanCode | if extended { 0xE000 } else { 0 };
+    let PhysicalKey::Code(code) = PhysicalKey::from_scancode(scancode) else {
+        return false;
+    };
+    let pressed = (event.flags & LLKHF_UP).0 == 0;
+    // Never panic here, it would cross the FFI boundary
+    let Ok(mut state) = HOOK_STATE.lock() else {
+        return false;
+    };
+    let Some(state) = state.as_mut() else {
+        return false;
+    };
+    let focused = unsafe { GetForegroundWindow() }.0 as isize == state.hwnd;
+    state.grabber.key(code, pressed, focused)
+}
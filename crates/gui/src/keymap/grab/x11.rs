@@ -0,0 +1,153 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+use std::{
+    ffi::{c_int, c_ulong, c_void},
+    sync::LazyLock,
+};
+
+use anyhow::{Result, anyhow};
+use shared::log;
+use winit::raw_window_handle::{
+    HasDisplayHandle, HasWindowHandle, RawDisplayHandle, RawWindowHandle,
+};
+
+use super::Grabber;
+
+type Display = c_void;
+type GrabKeyboardFn =
+    unsafe extern "C" fn(*mut Display, c_ulong, c_int, c_int, c_int, c_ulong) -> c_int;
+type UngrabKeyboardFn = unsafe extern "C" fn(*mut Display, c_ulong) -> c_int;
+type FlushFn = unsafe extern "C" fn(*mut Display) -> c_int;
+
+const TRUE: c_int = 1;
+const GRAB_MODE_ASYNC: c_int = 1;
+const GRAB_SUCCESS: c_int = 0;
+const CURRENT_TIME: c_ulong = 0;
+
+struct X11Library {
+    grab_keyboard: GrabKeyboardFn,
+    ungrab_keyboard: UngrabKeyboardFn,
+    flush: FlushFn,
+    // Keeps the symbols above valid
+    _lib: libloading::Library,
+}
+
+static X11: LazyLock<Option<X11Library>> = LazyLock::new(|| match load_library() {
+    Ok(lib) => Some(lib),
+    Err(e) => {
+        log::warn!("X11 library failed to load, no keyboard grab: {e}");
+        None
+    }
+});
+
+fn load_library() -> Result<X11Library> {
+    let lib = ["libX11.so.6", "libX11.so"]
+        .iter()
+        .find_map(|name| unsafe { libloading::Library::new(name) }.ok())
+        .ok_or_else(|| anyhow!("Could not find or load libX11"))?;
+    unsafe {
+        macro_rules! symbol {
+            ($name:literal) => {
+                *lib.get($name)
+                    .map_err(|e| anyhow!("Missing X11 symbol {:?}: {e}", $name))?
+            };
+        }
+        Ok(X11Library {
+            grab_keyboard: symbol!(b"XGrabKeyboard"),
+            ungrab_keyboard: symbol!(b"XUngrabKeyboard"),
+            flush: symbol!(b"XFlush"),
+            _lib: lib,
+        })
+    }
+}
+
+/// Active X keyboard grab on the session window while the grab mode asks for it.
+/// The window manager then gets no keys, and winit delivers them all to the window
+/// (hotkeys included, so they keep working).
+///
+/// Uses the display connection of winit, only from the GUI thread.
+pub(super) struct Grab {
+    x11: &'static X11Library,
+    display: *mut Display,
+    window: c_ulong,
+    grabber: Grabber,
+    grabbed: bool,
+}
+
+impl Grab {
+    pub fn install(window: &winit::window::Window, grabber: Grabber) -> Option<Self> {
+        let display = match window.display_handle().map(|h| h.as_raw()) {
+            Ok(RawDisplayHandle::Xlib(handle)) => handle.display?.as_ptr(),
+            Ok(RawDisplayHandle::Wayland(_)) => {
+                log::warn!("Keyboard grab is not supported on Wayland, OS shortcuts stay local");
+                return None;
+            }
+            _ => {
+                log::warn!(
+                    "Keyboard grab is not supported on this display, OS shortcuts stay local"
+                );
+                return None;
+            }
+        };
+        let window = match window.window_handle().map(|h| h.as_raw()) {
+            Ok(RawWindowHandle::Xlib(handle)) => handle.window,
+            _ => return None,
+        };
+        Some(Grab {
+            x11: X11.as_ref()?,
+            display,
+            window,
+            grabber,
+            grabbed: false,
+        })
+    }
+
+    /// Grab or release the keyboard, as the mode asks with the window `focused` or not.
+    pub fn update(&mut self, focused: bool) {
+        let active = focused && self.grabber.is_active();
+        if active == self.grabbed {
+            return;
+        }
+        if active {
+            let status = unsafe {
+                (self.x11.grab_keyboard)(
+                    self.display,
+                    self.window,
+                    // Keys still reach the other windows of the client
+                    TRUE,
+                    GRAB_MODE_ASYNC,
+                    GRAB_MODE_ASYNC,
+                    CURRENT_TIME,
+                )
+            };
+            if status != GRAB_SUCCESS {
+                // Another client has it (a menu, a screen locker), tried again on next update
+                log::debug!("Keyboard grab failed, status {}", status);
+                return;
+            }
+            log::debug!("Keyboard grabbed");
+        } else {
+            self.ungrab();
+            log::debug!("Keyboard grab released");
+        }
+        self.grabbed = active;
+    }
+
+    fn ungrab(&self) {
+        unsafe {
+            (self.x11.ungrab_keyboard)(self.display, CURRENT_TIME);
+            (self.x11.flush)(self.display);
+        }
+    }
+}
+
+impl Drop for Grab {
+    fn drop(&mut self) {
+        if self.grabbed {
+            self.ungrab();
+        }
+    }
+}
@@ -0,0 +1,276 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! Local hotkeys of a desktop session.
+//!
+//! A hotkey is the escape chord (Alt by default) held down plus the key of an
+//! action. Both the chord and the keys come from the RDP settings (see
+//! `KeyboardSettings`); keys that are not hotkeys go to the session.
+
+use anyhow::Result;
+use winit::keyboard::KeyCode;
+
+use super::winit_keys::key_from_name;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HotkeyAction {
+    Fullscreen,
+    Fps,
+    Mute,
+    VolumeUp,
+    VolumeDown,
+    Camera,
+    Pause,
+    Preview,
+    Blur,
+    CtrlAltDel,
+    Exit,
+}
+
+/// Actions with their settings name and default key.
+const DEFAULT_KEYS: &[(HotkeyAction, &str, KeyCode)] = &[
+    (HotkeyAction::Fullscreen, "fullscreen", KeyCode::Enter),
+    (HotkeyAction::Fps, "fps", KeyCode::KeyF),
+    (HotkeyAction::Mute, "mute", KeyCode::KeyM),
+    (HotkeyAction::VolumeUp, "volume_up", KeyCode::PageUp),
+    (HotkeyAction::VolumeDown, "volume_down", KeyCode::PageDown),
+    (HotkeyAction::Camera, "camera", KeyCode::KeyC),
+    (HotkeyAction::Pause, "pause", KeyCode::KeyP),
+    (HotkeyAction::Preview, "preview", KeyCode::KeyV),
+    (HotkeyAction::Blur, "blur", KeyCode::KeyB),
+    (HotkeyAction::CtrlAltDel, "ctrl_alt_del", KeyCode::End),
+    (HotkeyAction::Exit, "exit", KeyCode::F4),
+];
+
+/// Modifier keys, the held ones or the ones of a chord.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub meta: bool,
+}
+
+impl Modifiers {
+    pub const ALT: Modifiers = Modifiers {
+        ctrl: false,
+        alt: true,
+        shift: false,
+        meta: false,
+    };
+
+    /// The modifier of a key, None if it is not one.
+    pub fn of(code: KeyCode) -> Option<Self> {
+        let mut modifiers = Modifiers::default();
+        modifiers.update(code, true).then_some(modifiers)
+    }
+
+    /// Track a modifier key, false if `code` is not one.
+    pub fn update(&mut self, code: KeyCode, pressed: bool) -> bool {
+        let flag = match code {
+            KeyCode::ControlLeft | KeyCode::ControlRight => &mut self.ctrl,
+            KeyCode::AltLeft | KeyCode::AltRight => &mut self.alt,
+            KeyCode::ShiftLeft | KeyCode::ShiftRight => &mut self.shift,
+            KeyCode::SuperLeft | KeyCode::SuperRight => &mut self.meta,
+            _ => return false,
+        };
+        *flag = pressed;
+        true
+    }
+
+    /// Every modifier of `other` is in these ones.
+    pub fn contains(&self, other: &Modifiers) -> bool {
+        (self.ctrl || !other.ctrl)
+            && (self.alt || !other.alt)
+            && (self.shift || !other.shift)
+            && (self.meta || !other.meta)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Modifiers::default()
+    }
+
+    /// Parse a chord such as "alt", "ctrl+alt" or "super+shift".
+    pub fn parse(chord: &str) -> Result<Self> {
+        let mut modifiers = Modifiers::default();
+        for name in chord.split('+').map(|n| n.trim().to_lowercase()) {
+            match name.as_str() {
+                "ctrl" | "control" => modifiers.ctrl = true,
+                "alt" => modifiers.alt = true,
+                "shift" => modifiers.shift = true,
+                "super" | "meta" | "win" | "cmd" => modifiers.meta = true,
+                _ => anyhow::bail!("invalid modifier '{}' in escape chord '{}'", name, chord),
+            }
+        }
+        if modifiers.is_empty() {
+            anyhow::bail!("empty escape chord");
+        }
+        Ok(modifiers)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hotkeys {
+    pub chord: Modifiers,
+    keys: Vec<(HotkeyAction, KeyCode)>,
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Hotkeys {
+            chord: Modifiers::ALT,
+            keys: DEFAULT_KEYS
+                .iter()
+                .map(|(action, _, key)| (*action, *key))
+                .collect(),
+        }
+    }
+}
+
+impl Hotkeys {
+    /// Hotkeys with another escape chord and keys, as (action name, key name) pairs.
+    /// Missing actions keep their default key, and "none" unbinds one.
+    pub fn new(chord: Option<&str>, keys: &[(&str, &str)]) -> Result<Self> {
+        let mut hotkeys = Hotkeys::default();
+        if let Some(chord) = chord {
+            hotkeys.chord = Modifiers::parse(chord)?;
+        }
+        for (name, key) in keys {
+            let Some((action, _, _)) = DEFAULT_KEYS.iter().find(|(_, n, _)| n == name) else {
+                anyhow::bail!("unknown hotkey action '{}'", name);
+            };
+            hotkeys.keys.retain(|(a, _)| a != action);
+            if key.eq_ignore_ascii_case("none") {
+                continue;
+            }
+            let code = key_from_name(key)
+                .filter(|code| Modifiers::of(*code).is_none())
+                .ok_or_else(|| anyhow::anyhow!("invalid key '{}' for hotkey '{}'", key, name))?;
+            hotkeys.keys.push((*action, code));
+        }
+        // Checked once all of them are set, so keys can be swapped
+        for (i, (action, code)) in hotkeys.keys.iter().enumerate() {
+            if let Some((other, _)) = hotkeys.keys[i + 1..].iter().find(|(_, c)| c == code) {
+                anyhow::bail!(
+                    "key {:?} bound to both {:?} and {:?} hotkeys",
+                    code,
+                    action,
+                    other
+                );
+            }
+        }
+        Ok(hotkeys)
+    }
+
+    /// Action of `code` pressed with the `held` modifiers, if it is a hotkey.
+    pub fn action(&self, code: KeyCode, held: &Modifiers) -> Option<HotkeyAction> {
+        if !held.contains(&self.chord) {
+            return None;
+        }
+        self.keys
+            .iter()
+            .find(|(_, c)| *c == code)
+            .map(|(action, _)| *action)
+    }
+
+    /// Key of an action, None if it is unbound.
+    pub fn key(&self, action: HotkeyAction) -> Option<KeyCode> {
+        self.keys
+            .iter()
+            .find(|(a, _)| *a == action)
+            .map(|(_, code)| *code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CTRL_ALT: Modifiers = Modifiers {
+        ctrl: true,
+        alt: true,
+        shift: false,
+        meta: false,
+    };
+
+    #[test]
+    fn default_hotkeys() {
+        let hotkeys = Hotkeys::default();
+        assert_eq!(
+            hotkeys.action(KeyCode::Enter, &Modifiers::ALT),
+            Some(HotkeyAction::Fullscreen)
+        );
+        assert_eq!(
+            hotkeys.action(KeyCode::F4, &CTRL_ALT),
+            Some(HotkeyAction::Exit)
+        );
+        assert_eq!(hotkeys.action(KeyCode::Enter, &Modifiers::default()), None);
+        assert_eq!(hotkeys.action(KeyCode::KeyQ, &Modifiers::ALT), None);
+    }
+
+    #[test]
+    fn chord() {
+        let hotkeys = Hotkeys::new(Some("Ctrl+Alt"), &[]).unwrap();
+        assert_eq!(hotkeys.chord, CTRL_ALT);
+        // Alt+F4 goes to the session now
+        assert_eq!(hotkeys.action(KeyCode::F4, &Modifiers::ALT), None);
+        assert_eq!(
+            hotkeys.action(KeyCode::F4, &CTRL_ALT),
+            Some(HotkeyAction::Exit)
+        );
+        assert!(Modifiers::parse("super + shift").unwrap().meta);
+        assert!(Modifiers::parse("").is_err());
+        assert!(Modifiers::parse("alt+hyper").is_err());
+    }
+
+    #[test]
+    fn remap() {
+        let hotkeys = Hotkeys::new(
+            None,
+            &[("fullscreen", "F"), ("fps", "Enter"), ("exit", "none")],
+        )
+        .unwrap();
+        assert_eq!(hotkeys.key(HotkeyAction::Fullscreen), Some(KeyCode::KeyF));
+        assert_eq!(hotkeys.key(HotkeyAction::Fps), Some(KeyCode::Enter));
+        assert_eq!(hotkeys.key(HotkeyAction::Exit), None);
+        assert_eq!(hotkeys.action(KeyCode::F4, &Modifiers::ALT), None);
+        assert_eq!(
+            hotkeys.action(KeyCode::KeyF, &Modifiers::ALT),
+            Some(HotkeyAction::Fullscreen)
+        );
+        assert_eq!(
+            Hotkeys::new(None, &[("ctrl_alt_del", "delete")])
+                .unwrap()
+                .key(HotkeyAction::CtrlAltDel),
+            Some(KeyCode::Delete)
+        );
+    }
+
+    #[test]
+    fn remap_errors() {
+        // Already the mute key
+        assert!(Hotkeys::new(None, &[("fps", "M")]).is_err());
+        assert!(Hotkeys::new(None, &[("fps", "NoSuchKey")]).is_err());
+        assert!(Hotkeys::new(None, &[("fps", "ShiftLeft")]).is_err());
+        assert!(Hotkeys::new(None, &[("reboot", "R")]).is_err());
+    }
+
+    #[test]
+    fn modifiers_tracking() {
+        let mut held = Modifiers::default();
+        assert!(held.update(KeyCode::ControlRight, true));
+        assert!(held.update(KeyCode::AltLeft, true));
+        assert!(!held.update(KeyCode::KeyA, true));
+        assert_eq!(held, CTRL_ALT);
+        assert!(held.contains(&Modifiers::ALT));
+        held.update(KeyCode::AltLeft, false);
+        assert!(!held.contains(&Modifiers::ALT));
+        assert_eq!(
+            Modifiers::of(KeyCode::SuperLeft).map(|m| m.meta),
+            Some(true)
+        );
+        assert_eq!(Modifiers::of(KeyCode::Tab), None);
+    }
+}
@@ -5,10 +5,22 @@
 
 use winit::keyboard::KeyCode;
 
+mod grab;
+mod hotkeys;
 mod scancodes;
 mod winit_keys;
 
+pub use grab::{GrabMode, KeyboardGrab};
+pub use hotkeys::{HotkeyAction, Hotkeys, Modifiers};
 pub use scancodes::RdpScanCode;
+pub use winit_keys::key_from_name;
+
+/// Keyboard settings of a desktop session.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KeyboardSettings {
+    pub grab: GrabMode,
+    pub hotkeys: Hotkeys,
+}
 
 impl RdpScanCode {
     pub fn get_from_key(key: Option<&KeyCode>) -> Option<Self> {
@@ -157,3 +157,19 @@ impl RdpScanCode {
         WININIT_SCANCODE_MAP.get(key_code).copied()
     }
 }
+
+/// Key by its winit name, case insensitive ("Enter", "PageUp", "F4"...). Letters and
+/// digits may skip their "Key" / "Digit" prefix ("M", "1").
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    let name = name.trim();
+    _SCANCODE_LIST.iter().map(|(key, _)| *key).find(|key| {
+        let key_name = format!("{:?}", key);
+        [
+            name.to_string(),
+            format!("Key{name}"),
+            format!("Digit{name}"),
+        ]
+        .iter()
+        .any(|n| n.eq_ignore_ascii_case(&key_name))
+    })
+}
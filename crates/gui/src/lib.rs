@@ -30,8 +30,11 @@ pub mod ipc;
 mod wgpu_render;
 
 mod input;
+mod pointer;
 mod session;
 
+pub use monitor::MonitorSelection;
+
 use types::{AppState, GuiMessage, ReturnCode};
 use windows::about::AboutState;
 use windows::popup::PopupState;
@@ -72,7 +75,7 @@ pub struct AppHandler {
     processing_events: Arc<AtomicBool>,
     stop: Trigger,
     fps_limit: u32,
-    alt_held: bool,
+    modifiers: keymap::Modifiers,
     last_pointer: Option<winit::dpi::PhysicalPosition<f64>>,
     rail_button_down: Option<u32>,
     rail_ipc: Option<crate::ipc::IpcListener>,
@@ -107,7 +110,7 @@ pub fn run_gui(
         processing_events,
         stop,
         fps_limit: fps_limit.unwrap_or(60),
-        alt_held: false,
+        modifiers: keymap::Modifiers::default(),
         last_pointer: None,
         rail_button_down: None,
         rail_ipc: None,
@@ -173,6 +176,15 @@ impl ApplicationHandler for AppHandler {
     }
 
     fn window_event(&mut self, el: &ActiveEventLoop, wid: WindowId, event: WindowEvent) {
+        // The PIN popup keeps its keys, they must not reach the session
+        if let WindowEvent::KeyboardInput { event: key_ev, .. } = &event
+            && self.windows.get(&wid) == Some(&WindowKind::Popup)
+            && self.popup.as_ref().is_some_and(|p| p.is_pin())
+        {
+            self.handle_pin_key(key_ev);
+            return;
+        }
+
         // Keyboard — global, not tied to a specific window
         if matches!(&event, WindowEvent::KeyboardInput { .. }) && self.handle_keyboard(el, &event) {
             return;
@@ -255,6 +267,7 @@ impl ApplicationHandler for AppHandler {
         self.process_gui_messages(el);
         self.process_rdp_updates(el);
         self.process_rail_actions(el);
+        self.process_pin_requests(el);
 
         let has_active_animations = self.progress.is_some() || self.about.is_some();
         let rdp_active = self.rdp.is_some();
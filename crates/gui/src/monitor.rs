@@ -5,6 +5,7 @@
 
 use std::sync::{LazyLock, OnceLock};
 
+use shared::log;
 use winit::{event_loop::ActiveEventLoop, monitor::MonitorHandle};
 
 #[allow(dead_code)]
@@ -15,16 +16,26 @@ pub struct MonitorInfo {
     pub size: (u32, u32),
     pub position: (i32, i32),
     pub scale: f64,
+    pub primary: bool,
 }
 
 /// Global monitor list, populated once in RdpAppProxy::resumed.
 static MONITORS: OnceLock<Vec<MonitorInfo>> = OnceLock::new();
 
+/// Whether winit runs on Wayland, where a window cannot be placed on a monitor.
+static WAYLAND: OnceLock<bool> = OnceLock::new();
+
 /// Populate the global monitor list from winit's ActiveEventLoop.
 /// Called once from RdpAppProxy::resumed.
 /// On Wayland, winit uses zxdg_output_v1 where available (wlroots, KDE, GNOME).
 /// Falls back to empty if the compositor restricts screen info.
 pub fn populate(event_loop: &ActiveEventLoop) {
+    #[cfg(target_os = "linux")]
+    {
+        use winit::platform::wayland::ActiveEventLoopExtWayland;
+        let _ = WAYLAND.set(event_loop.is_wayland());
+    }
+    let primary = event_loop.primary_monitor();
     let _ = MONITORS.set(
         event_loop
             .available_monitors()
@@ -34,6 +45,7 @@ pub fn populate(event_loop: &ActiveEventLoop) {
                 size: (m.size().width, m.size().height),
                 position: (m.position().x, m.position().y),
                 scale: m.scale_factor(),
+                primary: primary.as_ref() == Some(&m),
             })
             .collect(),
     );
@@ -85,6 +97,198 @@ pub fn phys_2_logic(physical: (i32, i32), sf: f64) -> (i32, i32) {
     )
 }
 
+/// Monitors a desktop session is shown on, from the RDP settings.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum MonitorSelection {
+    /// A single window on the first monitor, fullscreen or not
+    #[default]
+    Single,
+    /// Every local monitor
+    All,
+    /// The monitors with these indices, unknown ones are ignored
+    Indices(Vec<usize>),
+}
+
+/// A monitor of a multi-monitor session.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayoutMonitor {
+    pub index: usize,
+    /// Area of the session desktop (GDI pixels) shown on this monitor
+    pub position: (i32, i32),
+    pub size: (u32, u32),
+    pub scale: f64,
+    pub primary: bool,
+}
+
+/// Session desktop spanning several monitors, shown in a single borderless window
+/// that covers the rectangle enclosing them.
+///
+/// The server gets no monitor definitions, just one desktop the size of that
+/// rectangle, so the monitors must fill it and share their scale factor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitorLayout {
+    pub monitors: Vec<LayoutMonitor>,
+    /// Physical position and size of the spanning window
+    pub position: (i32, i32),
+    pub size: (u32, u32),
+    /// GDI pixels → physical pixels
+    pub coords_scale: f64,
+}
+
+impl MonitorLayout {
+    /// Compute the layout of the selected monitors, None for a single window session
+    /// (also, with a warning, when they cannot be spanned).
+    ///
+    /// With the local scaler the desktop is laid out in GDI pixels at the scale of
+    /// the monitors. Without it it is laid out in physical pixels and the server
+    /// renders it at that scale.
+    pub fn new(
+        monitors: &[MonitorInfo],
+        selection: &MonitorSelection,
+        use_local_scaler: bool,
+    ) -> Option<Self> {
+        let selected: Vec<&MonitorInfo> = match selection {
+            MonitorSelection::Single => return None,
+            MonitorSelection::All => monitors.iter().collect(),
+            MonitorSelection::Indices(indices) => {
+                let mut selected: Vec<&MonitorInfo> = Vec::new();
+                for &index in indices {
+                    match monitors.iter().find(|m| m.index == index) {
+                        Some(m) if !selected.iter().any(|s| s.index == index) => selected.push(m),
+                        Some(_) => {}
+                        None => log::warn!("Monitor {index} not found, ignored"),
+                    }
+                }
+                selected
+            }
+        };
+        if selected.is_empty() {
+            return None;
+        }
+        if let Err(e) = spannable(&selected) {
+            log::warn!("Cannot span the selected monitors, {e}; using a single window");
+            return None;
+        }
+
+        let left = selected.iter().map(|m| m.position.0).min()?;
+        let top = selected.iter().map(|m| m.position.1).min()?;
+        let right = selected
+            .iter()
+            .map(|m| m.position.0 + m.size.0 as i32)
+            .max()?;
+        let bottom = selected
+            .iter()
+            .map(|m| m.position.1 + m.size.1 as i32)
+            .max()?;
+        let coords_scale = if use_local_scaler {
+            selected
+                .iter()
+                .map(|m| m.scale)
+                .fold(f64::INFINITY, f64::min)
+        } else {
+            1.0
+        };
+        // The local primary monitor, or the first selected one if it is not there
+        let primary = selected.iter().position(|m| m.primary).unwrap_or_default();
+
+        let monitors = selected
+            .iter()
+            .enumerate()
+            .map(|(i, m)| {
+                // Edges are converted one by one so adjacent monitors stay adjacent
+                let (x, y) = phys_2_logic((m.position.0 - left, m.position.1 - top), coords_scale);
+                let (r, b) = phys_2_logic(
+                    (
+                        m.position.0 + m.size.0 as i32 - left,
+                        m.position.1 + m.size.1 as i32 - top,
+                    ),
+                    coords_scale,
+                );
+                LayoutMonitor {
+                    index: m.index,
+                    position: (x, y),
+                    size: ((r - x) as u32, (b - y) as u32),
+                    scale: if use_local_scaler { 1.0 } else { m.scale },
+                    primary: i == primary,
+                }
+            })
+            .collect();
+
+        Some(MonitorLayout {
+            monitors,
+            position: (left, top),
+            size: ((right - left) as u32, (bottom - top) as u32),
+            coords_scale,
+        })
+    }
+
+    /// Size of the session desktop, in GDI pixels.
+    pub fn desktop_size(&self) -> (u32, u32) {
+        self.monitors.iter().fold((0, 0), |(w, h), m| {
+            (
+                w.max(m.position.0 as u32 + m.size.0),
+                h.max(m.position.1 as u32 + m.size.1),
+            )
+        })
+    }
+
+    pub fn primary(&self) -> &LayoutMonitor {
+        self.monitors
+            .iter()
+            .find(|m| m.primary)
+            .unwrap_or(&self.monitors[0])
+    }
+}
+
+/// Why the monitors cannot be shown as one desktop: they must share their scale
+/// factor and fill the rectangle enclosing them, without gaps or overlaps.
+fn spannable(monitors: &[&MonitorInfo]) -> Result<(), &'static str> {
+    fn edges(m: &MonitorInfo) -> (i64, i64, i64, i64) {
+        let (x, y) = (m.position.0 as i64, m.position.1 as i64);
+        (x, y, x + m.size.0 as i64, y + m.size.1 as i64)
+    }
+    let scale = monitors[0].scale;
+    if monitors
+        .iter()
+        .any(|m| (m.scale - scale).abs() > f64::EPSILON)
+    {
+        return Err("they have different scale factors");
+    }
+    for (i, a) in monitors.iter().enumerate() {
+        let (al, at, ar, ab) = edges(a);
+        for b in &monitors[i + 1..] {
+            let (bl, bt, br, bb) = edges(b);
+            if al < br && bl < ar && at < bb && bt < ab {
+                return Err("they overlap");
+            }
+        }
+    }
+    let (mut left, mut top, mut right, mut bottom) = edges(monitors[0]);
+    let mut area = 0;
+    for m in monitors {
+        let (l, t, r, b) = edges(m);
+        (left, top, right, bottom) = (left.min(l), top.min(t), right.max(r), bottom.max(b));
+        area += (r - l) * (b - t);
+    }
+    if area != (right - left) * (bottom - top) {
+        return Err("they do not fill the rectangle enclosing them");
+    }
+    Ok(())
+}
+
+/// Layout of the monitors requested for the session, None for a single window.
+pub fn session_layout(
+    selection: &MonitorSelection,
+    use_local_scaler: bool,
+) -> Option<MonitorLayout> {
+    if *selection != MonitorSelection::Single && WAYLAND.get().copied().unwrap_or_default() {
+        log::warn!("Spanning monitors is not supported on Wayland, using a single window");
+        return None;
+    }
+    let monitors = MONITORS.get()?;
+    MonitorLayout::new(monitors, selection, use_local_scaler)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -133,4 +337,140 @@ mod tests {
         assert!((back.0 - phys.0).abs() <= 1);
         assert!((back.1 - phys.1).abs() <= 1);
     }
+
+    fn monitor(index: usize, position: (i32, i32), size: (u32, u32), scale: f64) -> MonitorInfo {
+        MonitorInfo {
+            index,
+            size,
+            position,
+            scale,
+            primary: index == 0,
+        }
+    }
+
+    #[test]
+    fn layout_single_is_none() {
+        let monitors = [monitor(0, (0, 0), (1920, 1080), 1.0)];
+        assert!(MonitorLayout::new(&monitors, &MonitorSelection::Single, false).is_none());
+    }
+
+    #[test]
+    fn layout_all_side_by_side() {
+        let monitors = [
+            monitor(0, (0, 0), (1920, 1080), 1.0),
+            monitor(1, (1920, 0), (1280, 1080), 1.0),
+        ];
+        let layout = MonitorLayout::new(&monitors, &MonitorSelection::All, false).unwrap();
+        assert_eq!(layout.position, (0, 0));
+        assert_eq!(layout.size, (3200, 1080));
+        assert_eq!(layout.desktop_size(), (3200, 1080));
+        assert_eq!(layout.coords_scale, 1.0);
+        assert_eq!(layout.monitors[1].position, (1920, 0));
+        assert_eq!(layout.monitors[1].size, (1280, 1080));
+        assert_eq!(layout.primary().index, 0);
+    }
+
+    #[test]
+    fn layout_primary_relative() {
+        // Secondary monitor on the left of the primary one
+        let monitors = [
+            monitor(0, (0, 0), (1920, 1080), 1.0),
+            monitor(1, (-1920, 0), (1920, 1080), 1.0),
+        ];
+        let layout = MonitorLayout::new(&monitors, &MonitorSelection::All, false).unwrap();
+        assert_eq!(layout.position, (-1920, 0));
+        assert_eq!(layout.monitors[0].position.0, 1920);
+        assert_eq!(layout.monitors[1].position, (0, 0));
+        assert_eq!(layout.primary().index, 0);
+    }
+
+    #[test]
+    fn layout_indices() {
+        let monitors = [
+            monitor(0, (0, 0), (1920, 1080), 1.0),
+            monitor(1, (1920, 0), (1920, 1080), 1.0),
+            monitor(2, (3840, 0), (1920, 1080), 1.0),
+        ];
+        let selection = MonitorSelection::Indices(vec![2, 1, 1, 7]);
+        let layout = MonitorLayout::new(&monitors, &selection, false).unwrap();
+        let indices: Vec<usize> = layout.monitors.iter().map(|m| m.index).collect();
+        assert_eq!(indices, vec![2, 1]);
+        assert_eq!(layout.position, (1920, 0));
+        assert_eq!(layout.size, (3840, 1080));
+        // Local primary not selected, the first one listed is
+        assert_eq!(layout.primary().index, 2);
+        assert_eq!(layout.primary().position.0, 1920);
+
+        let unknown = MonitorSelection::Indices(vec![5]);
+        assert!(MonitorLayout::new(&monitors, &unknown, false).is_none());
+    }
+
+    #[test]
+    fn layout_mixed_dpi() {
+        let monitors = [
+            monitor(0, (0, 0), (3840, 2160), 2.0),
+            monitor(1, (3840, 0), (1920, 1080), 1.0),
+        ];
+        assert!(MonitorLayout::new(&monitors, &MonitorSelection::All, false).is_none());
+        assert!(MonitorLayout::new(&monitors, &MonitorSelection::All, true).is_none());
+
+        // Same scale, rendered by the server at that scale or by the local scaler
+        let monitors = [
+            monitor(0, (0, 0), (3840, 2160), 2.0),
+            monitor(1, (3840, 0), (3840, 2160), 2.0),
+        ];
+        let layout = MonitorLayout::new(&monitors, &MonitorSelection::All, false).unwrap();
+        assert_eq!(layout.desktop_size(), (7680, 2160));
+        assert_eq!(layout.primary().scale, 2.0);
+        let layout = MonitorLayout::new(&monitors, &MonitorSelection::All, true).unwrap();
+        assert_eq!(layout.coords_scale, 2.0);
+        assert_eq!(layout.desktop_size(), (3840, 1080));
+        assert_eq!(layout.primary().scale, 1.0);
+    }
+
+    #[test]
+    fn layout_not_contiguous() {
+        // Different heights leave a gap under the second monitor
+        let monitors = [
+            monitor(0, (0, 0), (1920, 1080), 1.0),
+            monitor(1, (1920, 0), (1280, 1024), 1.0),
+        ];
+        assert!(MonitorLayout::new(&monitors, &MonitorSelection::All, false).is_none());
+
+        let apart = [
+            monitor(0, (0, 0), (1920, 1080), 1.0),
+            monitor(1, (3840, 0), (1920, 1080), 1.0),
+        ];
+        assert!(MonitorLayout::new(&apart, &MonitorSelection::All, false).is_none());
+
+        // Mirrored monitors
+        let overlapping = [
+            monitor(0, (0, 0), (1920, 1080), 1.0),
+            monitor(1, (0, 0), (1920, 1080), 1.0),
+        ];
+        assert!(MonitorLayout::new(&overlapping, &MonitorSelection::All, false).is_none());
+
+        // The three of them fill it, the outer ones alone do not
+        let monitors = [
+            monitor(0, (0, 0), (1920, 1080), 1.0),
+            monitor(1, (1920, 0), (1920, 1080), 1.0),
+            monitor(2, (3840, 0), (1920, 1080), 1.0),
+        ];
+        assert!(MonitorLayout::new(&monitors, &MonitorSelection::All, false).is_some());
+        let outer = MonitorSelection::Indices(vec![0, 2]);
+        assert!(MonitorLayout::new(&monitors, &outer, false).is_none());
+    }
+
+    #[test]
+    fn layout_local_scaler_keeps_monitors_adjacent() {
+        let monitors = [
+            monitor(0, (0, 0), (2561, 1440), 1.5),
+            monitor(1, (2561, 0), (2561, 1440), 1.5),
+        ];
+        let layout = MonitorLayout::new(&monitors, &MonitorSelection::All, true).unwrap();
+        assert_eq!(layout.coords_scale, 1.5);
+        let (a, b) = (&layout.monitors[0], &layout.monitors[1]);
+        assert_eq!(a.position.0 + a.size.0 as i32, b.position.0);
+        assert_eq!(layout.desktop_size(), (3415, 960));
+    }
 }
@@ -0,0 +1,244 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! Pointer input (mouse buttons and wheels) to RDP mouse events.
+//!
+//! Touch and pen input are not forwarded: they need the multitouch input channel
+//! (RDPEI), which the rdp crate does not have yet, so winit touches stay local.
+
+use std::sync::RwLock;
+
+use shared::log;
+use winit::event::{MouseButton, MouseScrollDelta};
+
+/// Wheel rotation of one notch.
+const WHEEL_DELTA: f32 = 120.0;
+
+/// A mouse event at a desktop point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PointerEvent {
+    pub flags: u16,
+    pub x: u16,
+    pub y: u16,
+    /// Back / Forward buttons, an extended mouse event (`PTR_XFLAGS_*` flags)
+    pub extended: bool,
+}
+
+impl From<PointerEvent> for rdp::messaging::InputEvent {
+    fn from(event: PointerEvent) -> Self {
+        rdp::messaging::InputEvent::Mouse {
+            flags: event.flags,
+            x: event.x,
+            y: event.y,
+        }
+    }
+}
+
+/// Session window (physical pixels) to desktop coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scaler {
+    window: (u32, u32),
+    desktop: (u32, u32),
+}
+
+impl Scaler {
+    pub fn new(window: (u32, u32), desktop: (u32, u32)) -> Self {
+        Scaler {
+            window: (window.0.max(1), window.1.max(1)),
+            desktop: (desktop.0.max(1), desktop.1.max(1)),
+        }
+    }
+
+    /// Desktop point of a window position, clamped to the desktop.
+    pub fn point(&self, x: f64, y: f64) -> (u16, u16) {
+        let scale = |pos: f64, window: u32, desktop: u32| {
+            ((pos * desktop as f64) / window as f64)
+                .round()
+                .clamp(0.0, (desktop - 1) as f64) as u16
+        };
+        (
+            scale(x, self.window.0, self.desktop.0),
+            scale(y, self.window.1, self.desktop.1),
+        )
+    }
+}
+
+/// Event of a mouse button at a desktop point, None for buttons RDP does not have.
+pub fn button(button: MouseButton, pressed: bool, x: u16, y: u16) -> Option<PointerEvent> {
+    let (flags, extended) = match button {
+        MouseButton::Left => (rdp::sys::PTR_FLAGS_BUTTON1, false),
+        MouseButton::Right => (rdp::sys::PTR_FLAGS_BUTTON2, false),
+        MouseButton::Middle => (rdp::sys::PTR_FLAGS_BUTTON3, false),
+        MouseButton::Back => (rdp::sys::PTR_XFLAGS_BUTTON1, true),
+        MouseButton::Forward => (rdp::sys::PTR_XFLAGS_BUTTON2, true),
+        _ => return None,
+    };
+    let down = match (pressed, extended) {
+        (false, _) => 0,
+        (true, false) => rdp::sys::PTR_FLAGS_DOWN,
+        (true, true) => rdp::sys::PTR_XFLAGS_DOWN,
+    };
+    Some(PointerEvent {
+        flags: (flags | down) as u16,
+        x,
+        y,
+        extended,
+    })
+}
+
+/// Send an extended mouse event. There is no `InputEvent` for them, so it goes
+/// straight to the input of the connection, with the GDI (and its context) locked.
+pub fn send_extended(gdi: *mut rdp::sys::rdpGdi, gdi_lock: &RwLock<()>, event: &PointerEvent) {
+    let _gdi_guard = gdi_lock.read().unwrap();
+    // SAFETY: the GDI and its context live as long as the connection
+    let sent = unsafe {
+        let context = (*gdi).context;
+        !context.is_null()
+            && rdp::sys::freerdp_input_send_extended_mouse_event(
+                (*context).input,
+                event.flags,
+                event.x,
+                event.y,
+            ) != 0
+    };
+    if !sent {
+        log::debug!("Extended mouse event not sent: {:?}", event);
+    }
+}
+
+/// Events of a wheel scroll, vertical first. Horizontal scrolling is positive to the
+/// right, as on Windows, and winit scrolls are positive to the left.
+pub fn wheel(delta: &MouseScrollDelta) -> Vec<PointerEvent> {
+    let (dx, dy) = match delta {
+        MouseScrollDelta::LineDelta(x, y) => (*x as i32, *y as i32),
+        MouseScrollDelta::PixelDelta(pos) => (pos.x as i32, pos.y as i32),
+    };
+    let vertical = (dy as f32 * WHEEL_DELTA) as i32;
+    let horizontal = -(dx as f32 * WHEEL_DELTA) as i32;
+    let mut events = wheel_events(rdp::sys::PTR_FLAGS_WHEEL as u16, vertical);
+    events.extend(wheel_events(rdp::sys::PTR_FLAGS_HWHEEL as u16, horizontal));
+    events
+}
+
+/// Wheel rotation in steps of at most 255, 9 bits two's complement each.
+fn wheel_events(wheel_flag: u16, rotation: i32) -> Vec<PointerEvent> {
+    let negative = rotation < 0;
+    let mut left = rotation.unsigned_abs();
+    let mut events = Vec::new();
+    while left > 0 {
+        let step = left.min(0xFF) as u16;
+        left -= step as u32;
+        let flags = if negative {
+            wheel_flag | rdp::sys::PTR_FLAGS_WHEEL_NEGATIVE as u16 | (0x100 - step)
+        } else {
+            wheel_flag | step
+        };
+        events.push(PointerEvent {
+            flags,
+            x: 0,
+            y: 0,
+            extended: false,
+        });
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCALER: Scaler = Scaler {
+        window: (2880, 1800),
+        desktop: (1440, 900),
+    };
+
+    #[test]
+    fn scaling() {
+        assert_eq!(SCALER.point(0.0, 0.0), (0, 0));
+        assert_eq!(SCALER.point(1000.0, 500.0), (500, 250));
+        assert_eq!(SCALER.point(1001.0, 501.0), (501, 251));
+        // Clamped to the desktop
+        assert_eq!(SCALER.point(2879.0, 1799.0), (1439, 899));
+        assert_eq!(SCALER.point(5000.0, -20.0), (1439, 0));
+        // Fixed size session in a larger window
+        let scaler = Scaler::new((1920, 1080), (1024, 768));
+        assert_eq!(scaler.point(960.0, 540.0), (512, 384));
+        // Minimized windows have no size
+        assert_eq!(Scaler::new((0, 0), (1024, 768)).point(0.0, 0.0), (0, 0));
+    }
+
+    #[test]
+    fn buttons() {
+        assert_eq!(
+            button(MouseButton::Left, true, 10, 20),
+            Some(PointerEvent {
+                flags: (rdp::sys::PTR_FLAGS_BUTTON1 | rdp::sys::PTR_FLAGS_DOWN) as u16,
+                x: 10,
+                y: 20,
+                extended: false,
+            })
+        );
+        assert_eq!(
+            button(MouseButton::Middle, false, 1, 2),
+            Some(PointerEvent {
+                flags: rdp::sys::PTR_FLAGS_BUTTON3 as u16,
+                x: 1,
+                y: 2,
+                extended: false,
+            })
+        );
+        // Back / Forward are the X1 / X2 buttons of extended mouse events
+        assert_eq!(
+            button(MouseButton::Back, true, 3, 4),
+            Some(PointerEvent {
+                flags: (rdp::sys::PTR_XFLAGS_BUTTON1 | rdp::sys::PTR_XFLAGS_DOWN) as u16,
+                x: 3,
+                y: 4,
+                extended: true,
+            })
+        );
+        assert_eq!(
+            button(MouseButton::Forward, false, 3, 4),
+            Some(PointerEvent {
+                flags: rdp::sys::PTR_XFLAGS_BUTTON2 as u16,
+                x: 3,
+                y: 4,
+                extended: true,
+            })
+        );
+        assert_eq!(button(MouseButton::Other(9), true, 0, 0), None);
+    }
+
+    #[test]
+    fn wheels() {
+        let flags = |delta| -> Vec<u16> { wheel(&delta).into_iter().map(|e| e.flags).collect() };
+        let wheel_flag = rdp::sys::PTR_FLAGS_WHEEL as u16;
+        let hwheel_flag = rdp::sys::PTR_FLAGS_HWHEEL as u16;
+        let negative = rdp::sys::PTR_FLAGS_WHEEL_NEGATIVE as u16;
+        assert_eq!(
+            flags(MouseScrollDelta::LineDelta(0.0, 1.0)),
+            vec![wheel_flag | 120]
+        );
+        assert_eq!(
+            flags(MouseScrollDelta::LineDelta(0.0, -1.0)),
+            vec![wheel_flag | negative | (0x100 - 120)]
+        );
+        // 360 is split in 255 + 105
+        assert_eq!(
+            flags(MouseScrollDelta::LineDelta(0.0, 3.0)),
+            vec![wheel_flag | 0xFF, wheel_flag | 105]
+        );
+        // Left in winit is negative for RDP
+        assert_eq!(
+            flags(MouseScrollDelta::LineDelta(1.0, 0.0)),
+            vec![hwheel_flag | negative | (0x100 - 120)]
+        );
+        assert_eq!(
+            flags(MouseScrollDelta::LineDelta(-1.0, 1.0)),
+            vec![wheel_flag | 120, hwheel_flag | 120]
+        );
+        assert!(flags(MouseScrollDelta::LineDelta(0.0, 0.0)).is_empty());
+    }
+}
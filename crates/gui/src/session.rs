@@ -5,6 +5,7 @@
 
 use shared::log;
 use winit::event_loop::ActiveEventLoop;
+use zeroize::Zeroizing;
 
 use crate::AppHandler;
 use crate::WindowKind;
@@ -13,6 +14,34 @@ use crate::windows::popup::{PopupKind, PopupState};
 use crate::windows::progress::ProgressPhase;
 
 impl AppHandler {
+    /// PIN asked by the emulated smartcard (`local` PIN policy), once no other
+    /// popup is shown.
+    pub(crate) fn process_pin_requests(&mut self, el: &ActiveEventLoop) {
+        if self.popup.is_some() {
+            return;
+        }
+        let Some(request) = channels::smartcard::next_pin_request() else {
+            return;
+        };
+        let kind = PopupKind::Pin {
+            request: Some(request),
+            entered: Zeroizing::new(String::new()),
+            error: None,
+        };
+        match PopupState::new(el, kind) {
+            Ok(p) => {
+                let wid = p.window.id();
+                self.register_window(wid, WindowKind::Popup);
+                p.window.set_visible(true);
+                p.window.focus_window();
+                p.window.request_redraw();
+                self.popup = Some(p);
+            }
+            // The request is dropped, so the PIN entry is cancelled
+            Err(e) => log::error!("Failed to open the PIN popup: {e}"),
+        }
+    }
+
     pub(crate) fn process_gui_messages(&mut self, el: &ActiveEventLoop) {
         while let Ok(msg) = self.gui_messages_rx.try_recv() {
             match msg {
@@ -70,8 +99,8 @@ impl AppHandler {
                         p.window.request_redraw();
                     }
                 }
-                GuiMessage::ConnectRdp(settings) => {
-                    if let Err(e) = self.open_rdp(el, *settings) {
+                GuiMessage::ConnectRdp(settings, options) => {
+                    if let Err(e) = self.open_rdp(el, *settings, *options) {
                         log::error!("Failed to enter RDP: {e}");
                         self.stop.trigger();
                         el.exit();
@@ -16,10 +16,25 @@ pub enum GuiMessage {
     ShowYesNo(String, Arc<RwLock<Option<oneshot::Sender<bool>>>>),
     ShowProgress,
     Progress(u8, String),
-    ConnectRdp(Box<rdp::settings::RdpSettings>),
+    ConnectRdp(Box<rdp::settings::RdpSettings>, Box<SessionOptions>),
     CloseProgress,
 }
 
+/// Settings of a session that the core RDP settings have no room for, used by
+/// the GUI and the channels
+#[derive(Debug, Clone, Default)]
+pub struct SessionOptions {
+    /// Key of what is stored for the host, the server address if `None`
+    pub host_id: Option<String>,
+    pub monitors: crate::monitor::MonitorSelection,
+    pub keyboard: crate::keymap::KeyboardSettings,
+    pub audio_output_device: Option<String>,
+    pub audio_input_device: Option<String>,
+    pub mic_processing: channels::audio::MicProcessing,
+    pub webcam: channels::webcam::WebcamOptions,
+    pub smartcard: channels::smartcard::SmartcardOptions,
+}
+
 /// Return code from run_gui()
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ReturnCode {
@@ -7,7 +7,11 @@ use std::sync::{Arc, RwLock};
 use tiny_skia::{Color, FillRule, Paint, PathBuilder, Pixmap, Stroke, Transform};
 use tokio::sync::oneshot;
 use wgpu_text::glyph_brush::{OwnedSection, Section, Text};
-use winit::event::WindowEvent;
+use winit::event::{KeyEvent, WindowEvent};
+use winit::keyboard::{Key, NamedKey};
+use zeroize::Zeroizing;
+
+use channels::smartcard::PinRequest;
 
 use crate::draw::ui::{button, text};
 use crate::monitor;
@@ -20,6 +24,13 @@ pub enum PopupKind {
     },
     Warning(String),
     Error(String),
+    /// Smartcard PIN, entered here instead of on the server. The request is
+    /// answered on OK; closing the popup drops it, which cancels the entry.
+    Pin {
+        request: Option<PinRequest>,
+        entered: Zeroizing<String>,
+        error: Option<String>,
+    },
 }
 
 pub struct PopupState {
@@ -40,21 +51,28 @@ impl PopupState {
     ) -> anyhow::Result<Self> {
         let (dw, dh) = crate::monitor::size(0).unwrap_or((1920, 1080));
         let ww = 400.0;
-        let wh = 200.0;
+        let wh = if matches!(kind, PopupKind::Pin { .. }) {
+            250.0
+        } else {
+            200.0
+        };
         let sf = crate::monitor::scale(0) as f32;
         let px = (dw as f32 - ww * sf) / 2.0;
         let py = (dh as f32 - wh * sf) / 2.0;
 
-        let window = Arc::new(
-            event_loop.create_window(
-                winit::window::Window::default_attributes()
-                    .with_visible(false)
-                    .with_title("UDS Alert")
-                    .with_inner_size(winit::dpi::LogicalSize::new(ww, wh))
-                    .with_resizable(false)
-                    .with_position(winit::dpi::PhysicalPosition::new(px as i32, py as i32)),
-            )?,
-        );
+        let mut attributes = winit::window::Window::default_attributes()
+            .with_visible(false)
+            .with_title("UDS Alert")
+            .with_inner_size(winit::dpi::LogicalSize::new(ww, wh))
+            .with_resizable(false)
+            .with_position(winit::dpi::PhysicalPosition::new(px as i32, py as i32));
+        // Over the (maybe fullscreen) session, that is waiting for the PIN
+        if matches!(kind, PopupKind::Pin { .. }) {
+            attributes = attributes
+                .with_title("UDS Smartcard PIN")
+                .with_window_level(winit::window::WindowLevel::AlwaysOnTop);
+        }
+        let window = Arc::new(event_loop.create_window(attributes)?);
         let phys = window.inner_size();
         let scale = *monitor::SCALE_FACTOR as f32;
         let renderer = WgpuRenderer::new(window.clone(), phys.width, phys.height)?;
@@ -65,7 +83,12 @@ impl PopupState {
 
         let mut buttons = Vec::new();
         match &kind {
-            PopupKind::YesNo { .. } => {
+            PopupKind::YesNo { .. } | PopupKind::Pin { .. } => {
+                let (accept, reject) = if matches!(kind, PopupKind::Pin { .. }) {
+                    ("OK", "CANCEL")
+                } else {
+                    ("YES", "NO")
+                };
                 let bw = monitor::scaled_val(100) as f32;
                 let bx_yes = (pw / 2.0) - bw - 10.0 * scale;
                 let bx_no = (pw / 2.0) + 10.0 * scale;
@@ -75,7 +98,7 @@ impl PopupState {
                     by,
                     bw as u32,
                     bh as u32,
-                    "YES".to_string(),
+                    accept.to_string(),
                     crate::draw::ui::button::ButtonStyle {
                         font_scale: monitor::scaled_val(15) as f32,
                         radius: 8.0,
@@ -91,7 +114,7 @@ impl PopupState {
                     by,
                     bw as u32,
                     bh as u32,
-                    "NO".to_string(),
+                    reject.to_string(),
                     crate::draw::ui::button::ButtonStyle {
                         font_scale: monitor::scaled_val(15) as f32,
                         radius: 8.0,
@@ -148,24 +171,77 @@ impl PopupState {
     }
 
     pub fn handle_click(&mut self, x: f32, y: f32) -> bool {
-        for (i, btn) in self.buttons.iter().enumerate() {
-            if btn.contains(x, y) {
-                match &self.kind {
-                    PopupKind::YesNo { response, .. } => {
-                        if let Some(tx) = response.write().unwrap().take() {
-                            let _ = tx.send(i == 0); // 0 is YES, 1 is NO
-                        }
-                        return true;
-                    }
-                    PopupKind::Warning(_) | PopupKind::Error(_) => {
-                        return true;
-                    }
+        let Some(i) = self.buttons.iter().position(|btn| btn.contains(x, y)) else {
+            return false;
+        };
+        match &self.kind {
+            PopupKind::YesNo { response, .. } => {
+                if let Some(tx) = response.write().unwrap().take() {
+                    let _ = tx.send(i == 0); // 0 is YES, 1 is NO
                 }
+                true
             }
+            PopupKind::Warning(_) | PopupKind::Error(_) => true,
+            // 0 is OK, 1 is CANCEL (dropping the request)
+            PopupKind::Pin { .. } => i == 1 || self.submit_pin(),
+        }
+    }
+
+    pub fn is_pin(&self) -> bool {
+        matches!(self.kind, PopupKind::Pin { .. })
+    }
+
+    /// Typing in the PIN popup. Returns true when it must be closed.
+    pub fn handle_pin_key(&mut self, event: &KeyEvent) -> bool {
+        if !event.state.is_pressed() {
+            return false;
         }
+        match &event.logical_key {
+            Key::Named(NamedKey::Enter) => return self.submit_pin(),
+            Key::Named(NamedKey::Escape) => return true,
+            _ => {}
+        }
+        let PopupKind::Pin { entered, error, .. } = &mut self.kind else {
+            return false;
+        };
+        match &event.logical_key {
+            Key::Named(NamedKey::Backspace) => {
+                entered.pop();
+            }
+            _ => match event.text.as_deref() {
+                Some(text) if !text.chars().any(char::is_control) => entered.push_str(text),
+                _ => return false,
+            },
+        }
+        *error = None;
+        self.window.request_redraw();
         false
     }
 
+    /// Answer the PIN request if the PIN follows the policy, else show why not.
+    fn submit_pin(&mut self) -> bool {
+        let PopupKind::Pin {
+            request,
+            entered,
+            error,
+        } = &mut self.kind
+        else {
+            return false;
+        };
+        let Some(policy) = request.as_ref().map(|r| &r.policy) else {
+            return true;
+        };
+        if let Err(reason) = policy.check(entered) {
+            *error = Some(reason);
+            self.window.request_redraw();
+            return false;
+        }
+        if let Some(request) = request.take() {
+            request.answer(Some(std::mem::take(entered)));
+        }
+        true
+    }
+
     pub fn paint(&mut self) {
         let s = self.scale;
         let pw = self.phys_w;
@@ -173,12 +249,27 @@ impl PopupState {
 
         self.renderer.reconfigure(pw, ph);
 
+        let pin_message;
         let (title, message, is_yesno, color) = match &self.kind {
             PopupKind::Error(msg) => ("ERROR", msg.as_str(), false, [0.9, 0.2, 0.2, 1.0]),
             PopupKind::Warning(msg) => ("WARNING", msg.as_str(), false, [1.0, 0.7, 0.1, 1.0]),
             PopupKind::YesNo { message, .. } => {
                 ("CONFIRM", message.as_str(), true, [0.2, 0.6, 1.0, 1.0])
             }
+            PopupKind::Pin { request, .. } => {
+                let retries = request.as_ref().map_or(0, |r| r.retries_left);
+                pin_message = format!(
+                    "Enter the smartcard PIN ({} {} left)",
+                    retries,
+                    if retries == 1 { "try" } else { "tries" }
+                );
+                (
+                    "SMARTCARD PIN",
+                    pin_message.as_str(),
+                    true,
+                    [0.2, 0.6, 1.0, 1.0],
+                )
+            }
         };
 
         let mut sections: Vec<OwnedSection> = Vec::new();
@@ -289,6 +380,85 @@ impl PopupState {
             msg_fs * 1.5,
         ));
 
+        // PIN field: a dot per character, never the PIN itself
+        if let PopupKind::Pin { entered, error, .. } = &self.kind {
+            let field_x = 20.0 * s;
+            let field_y = msg_y + msg_fs * 2.0;
+            let field_w = pw - (40.0 * s) as u32;
+            let field_h = monitor::scaled_val(36) as u32;
+            let mut field_pixmap = Pixmap::new(field_w, field_h).unwrap();
+            let field_rect = button::rounded_rect_path(
+                1.0,
+                1.0,
+                field_w as f32 - 2.0,
+                field_h as f32 - 2.0,
+                6.0 * s,
+            );
+            paint.set_color(Color::from_rgba8(20, 20, 24, 255));
+            field_pixmap.fill_path(
+                &field_rect,
+                &paint,
+                FillRule::Winding,
+                Transform::identity(),
+                None,
+            );
+            let border = if error.is_some() {
+                Color::from_rgba8(230, 50, 50, 255)
+            } else {
+                Color::from_rgba8(80, 80, 100, 255)
+            };
+            paint.set_color(border);
+            let field_stroke = Stroke {
+                width: 1.5 * s,
+                ..Default::default()
+            };
+            field_pixmap.stroke_path(
+                &field_rect,
+                &paint,
+                &field_stroke,
+                Transform::identity(),
+                None,
+            );
+
+            let dot_r = 4.0 * s;
+            let step = 14.0 * s;
+            let max_dots = ((field_w as f32 - 24.0 * s) / step) as usize;
+            let mut dots = PathBuilder::new();
+            for i in 0..entered.chars().count().min(max_dots) {
+                dots.push_circle(
+                    12.0 * s + dot_r + i as f32 * step,
+                    field_h as f32 / 2.0,
+                    dot_r,
+                );
+            }
+            if let Some(dots) = dots.finish() {
+                paint.set_color(Color::from_rgba8(230, 230, 230, 255));
+                field_pixmap.fill_path(
+                    &dots,
+                    &paint,
+                    FillRule::Winding,
+                    Transform::identity(),
+                    None,
+                );
+            }
+            data.push(field_pixmap.take());
+            ov_descs.push((data.len() - 1, field_w, field_h, field_x, field_y));
+
+            if let Some(error) = error {
+                let err_fs = monitor::scaled_val(12) as f32;
+                sections.push(
+                    Section::default()
+                        .add_text(
+                            Text::new(error)
+                                .with_scale(err_fs)
+                                .with_color([0.9, 0.3, 0.3, 1.0]),
+                        )
+                        .with_screen_position((field_x, field_y + field_h as f32 + 6.0 * s))
+                        .to_owned(),
+                );
+            }
+        }
+
         for btn in &self.buttons {
             let (btn_data, btn_text) = btn.render();
             data.push(btn_data);
@@ -314,6 +484,17 @@ impl PopupState {
 }
 
 impl crate::AppHandler {
+    pub(crate) fn handle_pin_key(&mut self, event: &KeyEvent) {
+        let Some(popup) = self.popup.as_mut() else {
+            return;
+        };
+        if popup.handle_pin_key(event) {
+            let wid = popup.window.id();
+            self.unregister_window(wid);
+            self.popup = None;
+        }
+    }
+
     pub(crate) fn handle_popup_event(&mut self, event: WindowEvent) {
         if self.popup.is_none() {
             return;
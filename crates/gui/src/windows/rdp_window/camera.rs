@@ -0,0 +1,181 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+// While the server uses the webcam: a badge on the top left corner saying so
+// (red, amber when paused) and a preview of what is being sent on the bottom right.
+use std::sync::Arc;
+
+use channels::webcam::Privacy;
+
+use crate::monitor;
+
+const BADGE_W: u32 = 130;
+const BADGE_H: u32 = 24;
+const PREVIEW_W: i32 = 200; // Logical width of the preview
+const MARGIN: i32 = 12;
+
+const RED: [u8; 4] = [220, 40, 40, 255];
+const AMBER: [u8; 4] = [230, 160, 0, 255];
+
+// Translucent box with a dot on the left
+fn badge_rgba(color: [u8; 4]) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity((BADGE_W * BADGE_H * 4) as usize);
+    let (cx, cy, r) = (12.0f32, BADGE_H as f32 / 2.0, 6.0f32);
+    for y in 0..BADGE_H {
+        for x in 0..BADGE_W {
+            let (dx, dy) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+            if dx * dx + dy * dy <= r * r {
+                rgba.extend_from_slice(&color);
+            } else {
+                rgba.extend_from_slice(&[0, 0, 0, 160]);
+            }
+        }
+    }
+    rgba
+}
+
+// Opaque frame with a border of `color`
+fn preview_rgba(rgb: &[u8], width: u32, height: u32, color: [u8; 4]) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+    for (i, px) in rgb.chunks_exact(3).enumerate() {
+        let (x, y) = (i as u32 % width, i as u32 / width);
+        if x < 2 || y < 2 || x + 2 >= width || y + 2 >= height {
+            rgba.extend_from_slice(&color);
+        } else {
+            rgba.extend_from_slice(&[px[0], px[1], px[2], 255]);
+        }
+    }
+    rgba
+}
+
+pub struct CameraOverlay {
+    privacy: Arc<Privacy>,
+    // What was drawn last: preview sequence, streaming and paused
+    drawn: (u64, bool, bool),
+    preview: Option<(Vec<u8>, u32, u32)>,
+}
+
+impl CameraOverlay {
+    pub fn new(privacy: Arc<Privacy>) -> Self {
+        Self {
+            privacy,
+            drawn: (0, false, false),
+            preview: None,
+        }
+    }
+
+    fn state(&self) -> (u64, bool, bool) {
+        (
+            self.privacy.preview_seq(),
+            self.privacy.is_streaming(),
+            self.privacy.is_paused(),
+        )
+    }
+
+    /// Whether there is something new to draw
+    pub fn changed(&self) -> bool {
+        self.state() != self.drawn
+    }
+
+    pub fn build(
+        &mut self,
+        phys_w: u32,
+        phys_h: u32,
+        text_sections: &mut Vec<crate::wgpu_render::OwnedSection>,
+        ov_data: &mut Vec<Vec<u8>>,
+    ) -> Vec<crate::wgpu_render::OverlayDesc> {
+        let state = self.state();
+        let (seq, streaming, paused) = state;
+        if !streaming {
+            self.drawn = state;
+            self.preview = None;
+            return Vec::new();
+        }
+        let color = if paused { AMBER } else { RED };
+        let scale = *monitor::SCALE_FACTOR as f32;
+        let margin = monitor::scaled_val(MARGIN) as f32;
+        let mut descs = Vec::new();
+
+        let data_idx = ov_data.len();
+        ov_data.push(badge_rgba(color));
+        descs.push(crate::wgpu_render::OverlayDesc {
+            data_idx,
+            w: BADGE_W,
+            h: BADGE_H,
+            x: margin,
+            y: margin,
+            scale,
+        });
+        let label = match (paused, self.privacy.is_blurred()) {
+            (true, _) => "Camera paused",
+            (false, true) => "Camera on, blur",
+            (false, false) => "Camera on",
+        };
+        text_sections.push(
+            crate::wgpu_render::Section::default()
+                .add_text(
+                    crate::wgpu_render::Text::new(label)
+                        .with_scale(monitor::scaled_val(14) as f32)
+                        .with_color([1.0, 1.0, 1.0, 1.0]),
+                )
+                .with_screen_position((
+                    margin + monitor::scaled_val(24) as f32,
+                    margin + monitor::scaled_val(4) as f32,
+                ))
+                .to_owned(),
+        );
+
+        // Converted only when there is a new frame
+        if seq != self.drawn.0 || paused != self.drawn.2 {
+            self.preview = self.privacy.preview_frame().map(|f| {
+                (
+                    preview_rgba(&f.rgb, f.width, f.height, color),
+                    f.width,
+                    f.height,
+                )
+            });
+        }
+        if let Some((ref rgba, w, h)) = self.preview {
+            let preview_scale = monitor::scaled_val(PREVIEW_W) as f32 / w as f32;
+            let (disp_w, disp_h) = (w as f32 * preview_scale, h as f32 * preview_scale);
+            let data_idx = ov_data.len();
+            ov_data.push(rgba.clone());
+            descs.push(crate::wgpu_render::OverlayDesc {
+                data_idx,
+                w,
+                h,
+                x: (phys_w as f32 - disp_w - margin).max(0.0),
+                y: (phys_h as f32 - disp_h - margin).max(0.0),
+                scale: preview_scale,
+            });
+        }
+        self.drawn = state;
+        descs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preview_has_border() {
+        let rgb = vec![10u8; 8 * 6 * 3];
+        let rgba = preview_rgba(&rgb, 8, 6, RED);
+        assert_eq!(rgba.len(), 8 * 6 * 4);
+        assert_eq!(rgba[0..4], RED);
+        // Pixel (3, 3) is inside
+        let idx = (3 * 8 + 3) * 4;
+        assert_eq!(rgba[idx..idx + 4], [10, 10, 10, 255]);
+    }
+
+    #[test]
+    fn badge_size() {
+        let rgba = badge_rgba(AMBER);
+        assert_eq!(rgba.len(), (BADGE_W * BADGE_H * 4) as usize);
+        let center = ((BADGE_H / 2) * BADGE_W + 12) as usize * 4;
+        assert_eq!(rgba[center..center + 4], AMBER);
+    }
+}
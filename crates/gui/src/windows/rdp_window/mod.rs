@@ -3,16 +3,20 @@
 // All rights reserved.
 // Authors: Adolfo Gómez, dkmaster at dkmon dot com
 
+mod camera;
 mod cursor;
 mod fps;
 mod pinbar;
 mod rail;
 mod session;
+mod volume;
 
+pub use camera::CameraOverlay;
 pub use cursor::Cursor;
 pub use fps::Fps;
 pub use pinbar::Pinbar;
 pub use rail::{RailAction, RailState, RailWindow};
+pub use volume::LocalVolume;
 
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock, atomic::AtomicBool};
@@ -24,6 +28,7 @@ use rdp::settings::RdpSettings;
 use shared::log;
 
 use crate::RawKey;
+use crate::types::SessionOptions;
 
 const FRAMES_IN_FLIGHT: usize = 128;
 
@@ -56,6 +61,11 @@ pub enum RdpMode {
         last_windowed_size: Option<(u32, u32)>,
         last_resize: std::time::Instant,
         fps: Fps,
+        camera: CameraOverlay,
+        /// Monitors spanned by the session window, None for a single monitor
+        layout: Option<crate::monitor::MonitorLayout>,
+        /// OS shortcuts sent to the session, if the platform and settings allow it
+        grab: Option<crate::keymap::KeyboardGrab>,
     },
     Rail(Rail),
 }
@@ -72,11 +82,14 @@ pub struct RdpState {
     pub coords_scale: f64,
     pub desktop_size: (u32, u32),
     pub keys_rx: Receiver<RawKey>,
+    pub hotkeys: crate::keymap::Hotkeys,
 
     pub mode: RdpMode,
     pub pendings: Pendings,
 
     pub cursor: Cursor,
+    pub volume: LocalVolume,
+    pub webcam: Arc<channels::webcam::WebcamHandle>,
 }
 
 #[allow(dead_code)]
@@ -92,6 +105,7 @@ impl RdpState {
     pub fn new(
         window: RdpWindow,
         settings: RdpSettings,
+        options: SessionOptions,
         is_rail: bool,
         coords_scale: f64,
         desktop_size: (u32, u32),
@@ -112,13 +126,26 @@ impl RdpState {
         let scale_factor = settings.options.desktop_scale;
         let rail_title = settings.rail.as_ref().and_then(|r| r.title.clone());
 
+        let audio_output = Arc::new(channels::audio::output::AudioHandle::new(
+            options.audio_output_device,
+        ));
+        let volume = LocalVolume::new(
+            Arc::clone(&audio_output.volume),
+            options.host_id.as_deref().unwrap_or(&settings.server),
+        );
+        let webcam = Arc::new(channels::webcam::WebcamHandle::new(options.webcam));
+
         let integrations = rdp::integrations::RdpIntegrations {
-            audio_output: Some(Arc::new(channels::audio::output::AudioHandle::new())),
-            audio_input: Some(Arc::new(channels::audio::input::MicHandle::new())),
-            webcam: Some(Arc::new(channels::webcam::WebcamHandle::new())),
+            audio_output: Some(audio_output),
+            audio_input: Some(Arc::new(channels::audio::input::MicHandle::new(
+                options.audio_input_device,
+                options.mic_processing,
+            ))),
+            webcam: Some(webcam.clone()),
             clipboard: Some(Arc::new(channels::clipboard::ClipboardHandle::new())),
             smartcard: channels::smartcard::SmartcardHandle::new(
                 settings.redirections.smartcard.emulated.clone(),
+                options.smartcard,
             )
             .map(|h| Arc::new(h) as Arc<dyn rdp::integrations::SmartcardIntegration>),
         };
@@ -187,13 +214,16 @@ impl RdpState {
             })
         } else {
             RdpMode::Desktop {
-                pinbar: Pinbar::new(),
+                pinbar: Pinbar::new(Arc::clone(&volume.control), Arc::clone(&webcam)),
                 full_screen: Arc::new(AtomicBool::new(false)),
                 last_windowed_size: None,
                 last_resize: std::time::Instant::now()
                     .checked_sub(std::time::Duration::from_secs(60))
                     .unwrap_or(std::time::Instant::now()),
                 fps: Fps::new(),
+                camera: CameraOverlay::new(Arc::clone(&webcam.privacy)),
+                layout: None,
+                grab: None,
             }
         };
 
@@ -208,6 +238,7 @@ impl RdpState {
             coords_scale,
             desktop_size,
             keys_rx,
+            hotkeys: options.keyboard.hotkeys,
             mode,
             pendings: Pendings {
                 resize: false,
@@ -216,6 +247,8 @@ impl RdpState {
                 rects: Vec::new(),
             },
             cursor: Cursor::new(coords_scale, use_rgba),
+            volume,
+            webcam,
         })
     }
 }
@@ -260,6 +293,7 @@ impl crate::AppHandler {
         &mut self,
         el: &ActiveEventLoop,
         mut settings: rdp::settings::RdpSettings,
+        options: SessionOptions,
     ) -> Result<()> {
         macro_rules! tr {
             ($msg:expr) => {
@@ -269,6 +303,7 @@ impl crate::AppHandler {
         self.close_progress(); // Ensure progress is closed if it was open before
         let is_rail = settings.rail.is_some();
         let use_rgba = cfg!(target_os = "macos");
+        let grab_mode = options.keyboard.grab;
 
         let monitor_scale = crate::monitor::scale(0);
         let (desktop_w, desktop_h) = crate::monitor::size(0).unwrap_or((1920, 1080));
@@ -301,6 +336,11 @@ impl crate::AppHandler {
                 crate::monitor::phys_2_logic((desktop_w as i32, desktop_h as i32), monitor_scale);
             (lw as f64, lh as f64)
         };
+        let layout = if is_rail {
+            None
+        } else {
+            crate::monitor::session_layout(&options.monitors, use_local_scaler)
+        };
         shared::log::info!(
             "enter_rdp: rail={is_rail} fullscreen={is_fullscreen} logical={rdp_w}x{rdp_h} scale={monitor_scale}"
         );
@@ -328,6 +368,7 @@ impl crate::AppHandler {
             let rdp_state = RdpState::new(
                 rdp_window,
                 settings,
+                options,
                 true,
                 coords_scale,
                 (rdp_w, rdp_h),
@@ -354,6 +395,72 @@ impl crate::AppHandler {
                 })
                 .ok();
             }
+        } else if let Some(layout) = layout {
+            // One borderless window over all the monitors, sharing the GDI surface.
+            // The server sees a single desktop the size of the rectangle they enclose
+            let desktop_size = layout.desktop_size();
+            settings.screen_size = rdp::geom::ScreenSize::Fixed(desktop_size.0, desktop_size.1);
+            settings.options.desktop_scale = layout.primary().scale;
+            shared::log::info!(
+                "enter_rdp: spanning {} monitors, desktop={}x{} phys={:?}@{:?}",
+                layout.monitors.len(),
+                desktop_size.0,
+                desktop_size.1,
+                layout.size,
+                layout.position
+            );
+            let window = Arc::new(
+                el.create_window(
+                    winit::window::Window::default_attributes()
+                        .with_visible(false)
+                        .with_title("UDS Remote Desktop")
+                        .with_decorations(false)
+                        .with_position(winit::dpi::PhysicalPosition::new(
+                            layout.position.0,
+                            layout.position.1,
+                        ))
+                        .with_inner_size(winit::dpi::PhysicalSize::new(
+                            layout.size.0,
+                            layout.size.1,
+                        ))
+                        .with_window_icon(Some(crate::logo::load_icon())),
+                )?,
+            );
+            let wid = window.id();
+            let renderer = crate::wgpu_render::WgpuRenderer::new(
+                window.clone(),
+                layout.size.0,
+                layout.size.1,
+            )?;
+            let rdp_window = RdpWindow {
+                window,
+                renderer,
+                scratch: Vec::new(),
+            };
+            let mut rdp_state = RdpState::new(
+                rdp_window,
+                settings,
+                options,
+                false,
+                layout.coords_scale,
+                desktop_size,
+                self.keys_rx.clone(),
+                use_rgba,
+                tr!("UDS Apps"),
+                tr!("Exit"),
+            )?;
+            if let RdpMode::Desktop {
+                ref full_screen,
+                layout: ref mut mode_layout,
+                ..
+            } = rdp_state.mode
+            {
+                // Behaves as fullscreen (pinbar), but it cannot be left
+                full_screen.store(true, Ordering::Relaxed);
+                *mode_layout = Some(layout);
+            }
+            self.rdp = Some(Box::new(rdp_state));
+            self.register_window(wid, WindowKind::Rdp);
         } else {
             settings.screen_size = rdp::geom::ScreenSize::Fixed(rdp_w, rdp_h);
             let window = Arc::new(
@@ -380,6 +487,7 @@ impl crate::AppHandler {
             let rdp_state = RdpState::new(
                 rdp_window,
                 settings,
+                options,
                 false,
                 coords_scale,
                 desktop_size,
@@ -403,6 +511,22 @@ impl crate::AppHandler {
             self.rdp = Some(Box::new(rdp_state));
             self.register_window(wid, WindowKind::Rdp);
         }
+        if let Some(ref mut state) = self.rdp
+            && let RdpMode::Desktop {
+                ref full_screen,
+                ref mut grab,
+                ..
+            } = state.mode
+        {
+            *grab = crate::keymap::KeyboardGrab::new(
+                &state.window.window,
+                grab_mode,
+                Arc::clone(full_screen),
+                Arc::clone(&self.processing_events),
+                self.keys_tx.clone(),
+                state.hotkeys.clone(),
+            );
+        }
         while self.keys_rx.try_recv().is_ok() {}
         self.processing_events.store(true, Ordering::Relaxed);
         if let Some(ref state) = self.rdp {
@@ -651,8 +775,17 @@ impl crate::AppHandler {
                 rdp::Rdp::set_command_event(&state.command_event);
             }
         }
-        if let RdpMode::Desktop { ref mut fps, .. } = state.mode {
+        if let RdpMode::Desktop {
+            ref mut fps,
+            ref camera,
+            ..
+        } = state.mode
+        {
             fps.record();
+            // The preview changes without any update from the server
+            if camera.changed() {
+                state.window.window.request_redraw();
+            }
         }
     }
 }
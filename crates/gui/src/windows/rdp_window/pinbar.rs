@@ -3,37 +3,58 @@
 // All rights reserved.
 // Authors: Adolfo Gómez, dkmaster at dkmon dot com
 
+use std::sync::Arc;
+
+use channels::audio::VolumeControl;
+use channels::webcam::WebcamHandle;
+
 use crate::monitor;
 
+/// Column of the background image where it is widened to fit the Ctrl+Alt+Del button.
+const CAD_COLUMN: u32 = 136;
+/// Room for the Ctrl+Alt+Del button, buttons after it are shifted by this
+const CAD_W: i32 = 48;
+
 #[allow(dead_code)]
 pub struct Pinbar {
     pub visible: bool,
     pub rect: Option<(u32, u32)>,
+    pub btn_pause_x: std::ops::Range<f32>,
+    pub btn_cam_x: std::ops::Range<f32>,
+    pub btn_vol_down_x: std::ops::Range<f32>,
+    pub btn_mute_x: std::ops::Range<f32>,
+    pub btn_vol_up_x: std::ops::Range<f32>,
+    pub btn_cad_x: std::ops::Range<f32>,
     pub btn_fs_x: std::ops::Range<f32>,
     pub btn_close_x: std::ops::Range<f32>,
     bg_rgba: Vec<u8>,
     bg_w: u32,
     bg_h: u32,
-}
-
-impl Default for Pinbar {
-    fn default() -> Self {
-        Self::new()
-    }
+    volume: Arc<VolumeControl>,
+    webcam: Arc<WebcamHandle>,
 }
 
 impl Pinbar {
-    pub fn new() -> Self {
+    pub fn new(volume: Arc<VolumeControl>, webcam: Arc<WebcamHandle>) -> Self {
         let (bg_rgba, bw, bh) =
             crate::draw::load_png_rgba(include_bytes!("../../images/pinbar.png"));
+        let bg_rgba = crate::draw::widen_rgba(&bg_rgba, bw, CAD_COLUMN, CAD_W as u32);
         Self {
             visible: false,
             rect: None,
+            btn_pause_x: 0.0..0.0,
+            btn_cam_x: 0.0..0.0,
+            btn_vol_down_x: 0.0..0.0,
+            btn_mute_x: 0.0..0.0,
+            btn_vol_up_x: 0.0..0.0,
+            btn_cad_x: 0.0..0.0,
             btn_fs_x: 0.0..0.0,
             btn_close_x: 0.0..0.0,
             bg_rgba,
-            bg_w: bw,
+            bg_w: bw + CAD_W as u32,
             bg_h: bh,
+            volume,
+            webcam,
         }
     }
 
@@ -53,11 +74,15 @@ impl Pinbar {
         let data_idx = ov_data.len();
         ov_data.push(self.bg_rgba.clone());
 
+        // While the camera is in use, the title leaves room for its buttons
+        let streaming = self.webcam.privacy.is_streaming();
+        let camera_active = self.webcam.current_camera().is_some();
+        let title = if streaming { "UDS" } else { "UDS Connection" };
         let font_size = monitor::scaled_val(16) as f32;
         text_sections.push(
             crate::wgpu_render::Section::default()
                 .add_text(
-                    crate::wgpu_render::Text::new("UDS Connection")
+                    crate::wgpu_render::Text::new(title)
                         .with_scale(font_size)
                         .with_color([1.0, 1.0, 1.0, 1.0]),
                 )
@@ -68,10 +93,99 @@ impl Pinbar {
                 .to_owned(),
         );
 
-        self.btn_fs_x =
-            (x + monitor::scaled_val(220) as f32)..(x + monitor::scaled_val(239) as f32);
-        self.btn_close_x =
-            (x + monitor::scaled_val(243) as f32)..(x + monitor::scaled_val(262) as f32);
+        // Camera: pause / resume, and click to switch to the next one
+        if streaming {
+            let label = if self.webcam.privacy.is_paused() {
+                "Play"
+            } else {
+                "Pause"
+            };
+            text_sections.push(
+                crate::wgpu_render::Section::default()
+                    .add_text(
+                        crate::wgpu_render::Text::new(label)
+                            .with_scale(font_size)
+                            .with_color([1.0, 1.0, 1.0, 1.0]),
+                    )
+                    .with_screen_position((
+                        x + monitor::scaled_val(48) as f32,
+                        monitor::scaled_val(8) as f32,
+                    ))
+                    .to_owned(),
+            );
+            self.btn_pause_x =
+                (x + monitor::scaled_val(44) as f32)..(x + monitor::scaled_val(92) as f32);
+        } else {
+            self.btn_pause_x = 0.0..0.0;
+        }
+        if camera_active {
+            text_sections.push(
+                crate::wgpu_render::Section::default()
+                    .add_text(
+                        crate::wgpu_render::Text::new("Cam")
+                            .with_scale(font_size)
+                            .with_color([1.0, 1.0, 1.0, 1.0]),
+                    )
+                    .with_screen_position((
+                        x + monitor::scaled_val(100) as f32,
+                        monitor::scaled_val(8) as f32,
+                    ))
+                    .to_owned(),
+            );
+            self.btn_cam_x =
+                (x + monitor::scaled_val(96) as f32)..(x + monitor::scaled_val(134) as f32);
+        } else {
+            self.btn_cam_x = 0.0..0.0;
+        }
+
+        // Ctrl+Alt+Del, the local OS never lets it through
+        text_sections.push(
+            crate::wgpu_render::Section::default()
+                .add_text(
+                    crate::wgpu_render::Text::new("CAD")
+                        .with_scale(font_size)
+                        .with_color([1.0, 1.0, 1.0, 1.0]),
+                )
+                .with_screen_position((
+                    x + monitor::scaled_val(144) as f32,
+                    monitor::scaled_val(8) as f32,
+                ))
+                .to_owned(),
+        );
+        self.btn_cad_x =
+            (x + monitor::scaled_val(140) as f32)..(x + monitor::scaled_val(184) as f32);
+
+        // Volume: "-", level (click to mute) and "+"
+        let volume_label = if self.volume.is_muted() {
+            "Mute".to_string()
+        } else {
+            format!("{}%", self.volume.local())
+        };
+        for (label, pos) in [("-", 144), (volume_label.as_str(), 160), ("+", 198)] {
+            let pos = CAD_W + pos;
+            text_sections.push(
+                crate::wgpu_render::Section::default()
+                    .add_text(
+                        crate::wgpu_render::Text::new(label)
+                            .with_scale(font_size)
+                            .with_color([1.0, 1.0, 1.0, 1.0]),
+                    )
+                    .with_screen_position((
+                        x + monitor::scaled_val(pos) as f32,
+                        monitor::scaled_val(8) as f32,
+                    ))
+                    .to_owned(),
+            );
+        }
+        let range = |from: i32, to: i32| {
+            (x + monitor::scaled_val(CAD_W + from) as f32)
+                ..(x + monitor::scaled_val(CAD_W + to) as f32)
+        };
+        self.btn_vol_down_x = range(140, 156);
+        self.btn_mute_x = range(158, 192);
+        self.btn_vol_up_x = range(194, 212);
+        self.btn_fs_x = range(220, 239);
+        self.btn_close_x = range(243, 262);
         self.rect = Some((bg_w, bg_h));
 
         Some(crate::wgpu_render::OverlayDesc {
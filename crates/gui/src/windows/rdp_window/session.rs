@@ -9,6 +9,7 @@ use shared::log;
 use super::RdpMode;
 use super::RdpState;
 use crate::monitor;
+use crate::pointer::{self, PointerEvent, Scaler};
 
 fn rect_contains(outer: &rdp::geom::Rect, inner: &rdp::geom::Rect) -> bool {
     inner.x >= outer.x
@@ -194,6 +195,15 @@ impl RdpState {
             ov_descs.push(desc);
         }
 
+        if let RdpMode::Desktop { ref mut camera, .. } = self.mode {
+            ov_descs.extend(camera.build(
+                phys.width,
+                phys.height,
+                &mut text_sections,
+                &mut _ov_data,
+            ));
+        }
+
         if let RdpMode::Desktop { ref mut pinbar, .. } = self.mode
             && let Some(desc) = pinbar.build(phys.width, &mut text_sections, &mut _ov_data)
         {
@@ -237,6 +247,14 @@ impl RdpState {
         let phys = self.window.window.inner_size();
         self.window.renderer.reconfigure(phys.width, phys.height);
 
+        // The desktop of a multi-monitor session follows the monitors, not the window
+        if let RdpMode::Desktop {
+            layout: Some(_), ..
+        } = self.mode
+        {
+            return;
+        }
+
         if self.pendings.resize {
             let is_stuck = if let RdpMode::Desktop { last_resize, .. } = self.mode {
                 last_resize.elapsed() > std::time::Duration::from_secs(2)
@@ -277,6 +295,53 @@ impl RdpState {
         }
     }
 
+    /// Send Ctrl+Alt+Del, that the local OS never lets through.
+    pub fn send_ctrl_alt_del(&self) {
+        use crate::keymap::RdpScanCode;
+        log::debug!("Sending Ctrl+Alt+Del");
+        let keys = [
+            RdpScanCode::LControl,
+            RdpScanCode::LMenu,
+            RdpScanCode::Delete,
+        ];
+        let presses = keys.iter().map(|sc| (*sc, true));
+        let releases = keys.iter().rev().map(|sc| (*sc, false));
+        for (scancode, pressed) in presses.chain(releases) {
+            let _ = self.command_tx.send(rdp::messaging::RdpCommand::Input(
+                rdp::messaging::InputEvent::Keyboard {
+                    scancode: scancode as u16,
+                    pressed,
+                    repeat: false,
+                },
+            ));
+        }
+        rdp::Rdp::set_command_event(&self.command_event);
+    }
+
+    /// Scaler of the desktop window positions to session coordinates.
+    pub fn pointer_scaler(&self) -> Scaler {
+        let phys = self.window.window.inner_size();
+        let (gdi_w, gdi_h) = unsafe { ((*self.gdi).width as u32, (*self.gdi).height as u32) };
+        Scaler::new((phys.width, phys.height), (gdi_w, gdi_h))
+    }
+
+    /// Send pointer events to the server.
+    pub fn send_pointer(&self, events: &[PointerEvent]) {
+        if events.is_empty() {
+            return;
+        }
+        for event in events {
+            if event.extended {
+                pointer::send_extended(self.gdi, &self.gdi_lock, event);
+            } else {
+                let _ = self
+                    .command_tx
+                    .send(rdp::messaging::RdpCommand::Input((*event).into()));
+            }
+        }
+        rdp::Rdp::set_command_event(&self.command_event);
+    }
+
     pub fn on_desktop_resize(&mut self, width: u32, height: u32) {
         log::info!("DesktopResize acknowledged: {width}x{height}");
         self.pendings.resize = false;
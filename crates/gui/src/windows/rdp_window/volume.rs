@@ -0,0 +1,68 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use channels::audio::VolumeControl;
+use shared::{
+    appdata::{AppData, HostVolume},
+    log,
+};
+
+// Local volume of the session, stored per host on AppData when the session closes
+pub struct LocalVolume {
+    pub control: Arc<VolumeControl>,
+    host: String,
+    changed: AtomicBool,
+}
+
+impl LocalVolume {
+    pub fn new(control: Arc<VolumeControl>, host: &str) -> Self {
+        if let Some(saved) = AppData::load().host_volumes.get(host) {
+            log::debug!("Restoring volume for {}: {:?}", host, saved);
+            control.set_local(saved.level);
+            control.set_muted(saved.muted);
+        }
+        LocalVolume {
+            control,
+            host: host.to_string(),
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn toggle_mute(&self) {
+        let muted = self.control.toggle_mute();
+        log::debug!("Audio muted: {}", muted);
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    pub fn step(&self, up: bool) {
+        let level = self.control.step_local(up);
+        log::debug!("Audio volume: {}%", level);
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    fn save(&self) {
+        log::debug!("Saving volume for {}", self.host);
+        let mut app_data = AppData::load();
+        app_data.host_volumes.insert(
+            self.host.clone(),
+            HostVolume {
+                level: self.control.local(),
+                muted: self.control.is_muted(),
+            },
+        );
+        app_data.save();
+    }
+}
+
+impl Drop for LocalVolume {
+    fn drop(&mut self) {
+        if self.changed.load(Ordering::Relaxed) {
+            self.save();
+        }
+    }
+}
@@ -398,7 +398,7 @@ impl AppHandler {
                         ..Default::default()
                     };
                     self.close_testing_launcher();
-                    if let Err(e) = self.open_rdp(el, settings) {
+                    if let Err(e) = self.open_rdp(el, settings, Default::default()) {
                         log::error!("Failed to enter RDP: {e}");
                         self.stop.trigger();
                         el.exit();
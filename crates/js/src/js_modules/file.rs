@@ -12,6 +12,7 @@ use boa_engine::{
 
 use is_executable::IsExecutable; // Trait for is_executable method
 
+use crate::debug;
 use crate::helpers::create_temp_file;
 
 // create temp file with a content, return path
@@ -39,6 +40,14 @@ fn read_file_fn(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<Js
 
 fn write_file_fn(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
     let (path, content) = extract_js_args!(args, ctx, String, String);
+    if debug::is_dry_run() {
+        // The content can be a secret (a key, a password file...), only its size is kept
+        debug::record(
+            "File.write",
+            serde_json::json!({ "path": path, "size": content.len() }),
+        );
+        return Ok(JsValue::from(true));
+    }
     match std::fs::write(&path, content) {
         Ok(_) => Ok(JsValue::from(true)),
         Err(e) => Err(JsError::from(
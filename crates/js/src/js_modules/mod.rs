@@ -23,3 +23,8 @@ pub(super) fn register(ctx: &mut Context) -> Result<()> {
     rdp::register(ctx)?;
     Ok(())
 }
+
+// Logger is the only module that is never mocked
+pub(super) fn register_logger(ctx: &mut Context) -> Result<()> {
+    logger::register(ctx)
+}
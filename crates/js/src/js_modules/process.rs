@@ -17,7 +17,7 @@ use boa_engine::{
 };
 use is_executable::IsExecutable; // Trait for is_executable method
 
-use crate::log;
+use crate::{debug, log};
 
 fn find_executable_fn(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
     let (app_name, extra_path) = extract_js_args!(args, ctx, String, Vec<String>);
@@ -65,6 +65,13 @@ pub fn launch_fn(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<J
         app_path,
         app_args
     );
+    if debug::is_dry_run() {
+        debug::record(
+            "Process.launch",
+            serde_json::json!({ "app": app_path, "args": app_args }),
+        );
+        return Ok(JsValue::from(debug::DRY_RUN_PROCESS_ID));
+    }
     let app_args_re: Vec<&str> = app_args.iter().map(|s| s.as_str()).collect();
     shared::system::launcher::launch(&app_path, app_args_re.as_slice(), None)
         .map(JsValue::from)
@@ -90,25 +97,35 @@ async fn launch_and_wait_fn(
         app_path,
         app_args
     );
-    let app_args_re: Vec<&str> = app_args.iter().map(|s| s.as_str()).collect();
-    // with timeout if set
-    let output = tokio::time::timeout(
-        std::time::Duration::from_millis(timeout_ms as u64),
-        tokio::process::Command::new(&app_path)
-            .args(&app_args_re)
-            .output(),
-    )
-    .await
-    .map_err(|e| JsError::from_native(JsNativeError::typ().with_message(format!("{}", e))))?
-    .map_err(|e| {
-        JsError::from_native(
-            JsNativeError::typ().with_message(format!("Failed to execute process: {}", e)),
+    let (stdout, stderr) = if debug::is_dry_run() {
+        debug::record(
+            "Process.launchAndWait",
+            serde_json::json!({ "app": app_path, "args": app_args }),
+        );
+        (String::new(), String::new())
+    } else {
+        let app_args_re: Vec<&str> = app_args.iter().map(|s| s.as_str()).collect();
+        // with timeout if set
+        let output = tokio::time::timeout(
+            std::time::Duration::from_millis(timeout_ms as u64),
+            tokio::process::Command::new(&app_path)
+                .args(&app_args_re)
+                .output(),
+        )
+        .await
+        .map_err(|e| JsError::from_native(JsNativeError::typ().with_message(format!("{}", e))))?
+        .map_err(|e| {
+            JsError::from_native(
+                JsNativeError::typ().with_message(format!("Failed to execute process: {}", e)),
+            )
+        })?;
+        (
+            String::from_utf8_lossy(&output.stdout).to_string(),
+            String::from_utf8_lossy(&output.stderr).to_string(),
         )
-    })?;
+    };
     let result = {
         let mut ctx_borrow = ctx.borrow_mut();
-        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
         let stdout_js = stdout.try_into_js(*ctx_borrow)?;
         let stderr_js = stderr.try_into_js(*ctx_borrow)?;
         ObjectInitializer::new(*ctx_borrow)
@@ -122,6 +139,10 @@ async fn launch_and_wait_fn(
 pub fn is_running_fn(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
     let process_id = extract_js_args!(args, ctx, u32);
 
+    if process_id == debug::DRY_RUN_PROCESS_ID {
+        return Ok(JsValue::from(false));
+    }
+
     let running = shared::system::launcher::is_running(process_id);
 
     Ok(JsValue::from(running))
@@ -130,6 +151,10 @@ pub fn is_running_fn(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResu
 pub fn kill_fn(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
     let process_id = extract_js_args!(args, ctx, u32);
 
+    if process_id == debug::DRY_RUN_PROCESS_ID {
+        return Ok(JsValue::null());
+    }
+
     let stopped = shared::system::launcher::stop(process_id)
         .map(|_| JsValue::null())
         .map_err(|e| JsError::from_native(JsNativeError::typ().with_message(format!("{}", e))))?;
@@ -147,6 +172,10 @@ pub async fn wait_fn(
         extract_js_args!(args, &mut *ctx_borrow, u32)
     };
 
+    if process_id == debug::DRY_RUN_PROCESS_ID {
+        return Ok(JsValue::null());
+    }
+
     shared::system::launcher::wait(process_id)
         .await
         .map(|_| JsValue::null())
@@ -163,6 +192,10 @@ pub async fn wait_timeout_fn(
         extract_js_args!(args, &mut *ctx_borrow, u32, u32)
     };
 
+    if process_id == debug::DRY_RUN_PROCESS_ID {
+        return Ok(JsValue::from(true));
+    }
+
     let timeout = std::time::Duration::from_millis(timeout_ms as u64);
 
     let triggered = shared::system::launcher::wait_timeout(process_id, timeout)
@@ -15,9 +15,12 @@ use boa_engine::{
 use connection::broker;
 use rdp::{geom::ScreenSize, settings};
 use shared::log;
-use zeroize::{Zeroize, ZeroizeOnDrop};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
-use crate::gui::{GuiMessage, send_message};
+use crate::{
+    debug,
+    gui::{GuiMessage, send_message},
+};
 
 #[derive(Debug, TryFromJs, Zeroize, ZeroizeOnDrop)]
 struct ServerInfo {
@@ -44,14 +47,92 @@ struct WebcamSettings {
     pub quality: Option<u32>,
     pub fps: Option<u32>,
     pub size_limit: Option<(u32, u32)>,
+    /// Camera index or (part of) its name, the first camera if missing
+    pub device: Option<String>,
+    /// Local preview of what is sent, enabled by default
+    pub preview: Option<bool>,
+    pub background_blur: Option<bool>,
+    /// `camera` (default), `screen`, `screen:x,y,width,height`, `window:<title>`
+    /// or `window:0x<id>`
+    pub source: Option<String>,
 }
 
 #[derive(Debug, Default, TryFromJs, Zeroize, ZeroizeOnDrop, Clone)]
 struct SmartcardSettings {
     pub enabled: Option<bool>,
-    /// Emulated card spec (`file:...` / `pem:...` / `userdefined:`). If present
-    /// and valid, the emulated smartcard is active; if invalid, no smartcard.
+    /// Emulated card spec (`file:...` / `pem:...` / `userdefined:...` / `ephemeral`).
+    /// If present and valid, the emulated smartcard is active; if invalid, no
+    /// smartcard.
     pub emulated: Option<String>,
+    /// PIN policy of the emulated card
+    pub pin: Option<PinSettings>,
+    /// Physical readers that are redirected
+    pub readers: Option<ReaderSettings>,
+}
+
+/// Allow / deny lists of reader names (or parts of them, case insensitive)
+#[derive(Debug, Default, TryFromJs, Zeroize, ZeroizeOnDrop, Clone)]
+struct ReaderSettings {
+    pub allow: Option<Vec<String>>,
+    pub deny: Option<Vec<String>>,
+}
+
+impl ReaderSettings {
+    fn to_filter(&self) -> channels::smartcard::ReaderFilter {
+        channels::smartcard::ReaderFilter {
+            allow: self.allow.clone().unwrap_or_default(),
+            deny: self.deny.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Emulated smartcard PIN policy, defaults in `channels::smartcard::PinPolicy`
+#[derive(Debug, Default, TryFromJs, Zeroize, ZeroizeOnDrop, Clone)]
+struct PinSettings {
+    /// Enter the PIN in a launcher popup instead of on the server
+    pub local: Option<bool>,
+    pub max_retries: Option<u8>,
+    pub min_length: Option<u32>,
+    pub max_length: Option<u32>,
+    pub digits_only: Option<bool>,
+    /// Minutes a blocked card stays blocked, 0 until its failures are cleared
+    pub lockout_minutes: Option<u32>,
+    /// Keep the failures between sessions
+    pub persistent: Option<bool>,
+}
+
+impl PinSettings {
+    fn to_policy(&self) -> channels::smartcard::PinPolicy {
+        let defs = channels::smartcard::PinPolicy::default();
+        channels::smartcard::PinPolicy {
+            local: self.local.unwrap_or(defs.local),
+            max_retries: self.max_retries.unwrap_or(defs.max_retries),
+            min_length: self.min_length.map_or(defs.min_length, |len| len as usize),
+            max_length: self.max_length.map_or(defs.max_length, |len| len as usize),
+            digits_only: self.digits_only.unwrap_or(defs.digits_only),
+            lockout_minutes: self.lockout_minutes.unwrap_or(defs.lockout_minutes),
+            persistent: self.persistent.unwrap_or(defs.persistent),
+        }
+    }
+}
+
+/// Microphone processing stages, all disabled by default
+#[derive(Debug, Default, TryFromJs, Zeroize, ZeroizeOnDrop, Clone)]
+struct MicProcessingSettings {
+    pub echo_cancellation: Option<bool>,
+    pub noise_suppression: Option<bool>,
+    pub agc: Option<bool>,
+}
+
+impl MicProcessingSettings {
+    fn to_processing(&self) -> channels::audio::MicProcessing {
+        let defs = channels::audio::MicProcessing::default();
+        channels::audio::MicProcessing {
+            echo_cancellation: self.echo_cancellation.unwrap_or(defs.echo_cancellation),
+            noise_suppression: self.noise_suppression.unwrap_or(defs.noise_suppression),
+            agc: self.agc.unwrap_or(defs.agc),
+        }
+    }
 }
 
 #[derive(Debug, Default, TryFromJs, Zeroize, ZeroizeOnDrop, Clone)]
@@ -64,6 +145,10 @@ struct RdpRedirections {
     pub drives: Option<Vec<String>>,
     pub webcam: Option<WebcamSettings>,
     pub sound_latency_threshold: Option<u16>,
+    /// Audio devices (name or index) to use instead of the OS default ones
+    pub audio_output_device: Option<String>,
+    pub audio_input_device: Option<String>,
+    pub mic_processing: Option<MicProcessingSettings>,
 }
 
 #[derive(Debug, Default, TryFromJs, Zeroize, ZeroizeOnDrop, Clone)]
@@ -74,6 +159,109 @@ struct JsRdpOptions {
     pub use_tunnel: Option<bool>,
 }
 
+/// Keys of the local hotkeys, by action ("none" unbinds one)
+#[derive(Debug, Default, TryFromJs, Zeroize, ZeroizeOnDrop, Clone)]
+struct HotkeysSettings {
+    pub fullscreen: Option<String>,
+    pub fps: Option<String>,
+    pub mute: Option<String>,
+    pub volume_up: Option<String>,
+    pub volume_down: Option<String>,
+    pub camera: Option<String>,
+    pub pause: Option<String>,
+    pub preview: Option<String>,
+    pub blur: Option<String>,
+    pub ctrl_alt_del: Option<String>,
+    pub exit: Option<String>,
+}
+
+impl HotkeysSettings {
+    fn bindings(&self) -> Vec<(&str, &str)> {
+        [
+            ("fullscreen", &self.fullscreen),
+            ("fps", &self.fps),
+            ("mute", &self.mute),
+            ("volume_up", &self.volume_up),
+            ("volume_down", &self.volume_down),
+            ("camera", &self.camera),
+            ("pause", &self.pause),
+            ("preview", &self.preview),
+            ("blur", &self.blur),
+            ("ctrl_alt_del", &self.ctrl_alt_del),
+            ("exit", &self.exit),
+        ]
+        .into_iter()
+        .filter_map(|(action, key)| key.as_deref().map(|key| (action, key)))
+        .collect()
+    }
+}
+
+#[derive(Debug, Default, TryFromJs, Zeroize, ZeroizeOnDrop, Clone)]
+struct KeyboardSettings {
+    /// "fullscreen" (default), "focused" or "never"
+    pub grab: Option<String>,
+    /// Modifiers of the hotkeys, "alt" by default
+    pub escape: Option<String>,
+    pub hotkeys: Option<HotkeysSettings>,
+}
+
+impl KeyboardSettings {
+    fn to_keyboard(&self) -> Result<gui::keymap::KeyboardSettings> {
+        let bindings = self
+            .hotkeys
+            .as_ref()
+            .map(|h| h.bindings())
+            .unwrap_or_default();
+        Ok(gui::keymap::KeyboardSettings {
+            grab: self
+                .grab
+                .as_deref()
+                .map(str::parse)
+                .transpose()?
+                .unwrap_or_default(),
+            hotkeys: gui::keymap::Hotkeys::new(self.escape.as_deref(), &bindings)?,
+        })
+    }
+}
+
+/// Monitors of a desktop session, "all" or a list of monitor indices
+#[derive(Debug, Clone)]
+enum MonitorsSetting {
+    Name(String),
+    Indices(Vec<u32>),
+}
+
+impl TryFromJs for MonitorsSetting {
+    fn try_from_js(value: &JsValue, context: &mut Context) -> JsResult<Self> {
+        match value.as_string() {
+            Some(name) => Ok(MonitorsSetting::Name(name.to_std_string_escaped())),
+            None => Vec::<u32>::try_from_js(value, context).map(MonitorsSetting::Indices),
+        }
+    }
+}
+
+impl MonitorsSetting {
+    fn validate(&self) -> Result<()> {
+        match self {
+            MonitorsSetting::Name(name) if name.eq_ignore_ascii_case("all") => Ok(()),
+            MonitorsSetting::Name(name) => anyhow::bail!("invalid 'monitors' '{}'", name),
+            MonitorsSetting::Indices(indices) if indices.is_empty() => {
+                anyhow::bail!("'monitors' needs at least one monitor index")
+            }
+            MonitorsSetting::Indices(_) => Ok(()),
+        }
+    }
+
+    fn to_selection(&self) -> gui::MonitorSelection {
+        match self {
+            MonitorsSetting::Name(_) => gui::MonitorSelection::All,
+            MonitorsSetting::Indices(indices) => {
+                gui::MonitorSelection::Indices(indices.iter().map(|&i| i as usize).collect())
+            }
+        }
+    }
+}
+
 #[derive(Debug, TryFromJs, Zeroize, ZeroizeOnDrop)]
 struct RdpSettings {
     pub server: String,
@@ -87,6 +275,13 @@ struct RdpSettings {
     pub redirections: Option<RdpRedirections>,
     pub rail: Option<RailSettings>,
     pub options: Option<JsRdpOptions>,
+    #[zeroize(skip)]
+    pub monitors: Option<MonitorsSetting>,
+    pub keyboard: Option<KeyboardSettings>,
+    /// Key of what the client stores for the host (the local volume), the server
+    /// address if missing
+    #[zeroize(skip)]
+    pub host_id: Option<String>,
 }
 
 impl Default for RdpSettings {
@@ -103,13 +298,30 @@ impl Default for RdpSettings {
             redirections: None,
             rail: None,
             options: None,
+            monitors: None,
+            keyboard: None,
+            host_id: None,
         }
     }
 }
 
 impl RdpSettings {
     pub fn is_valid(&self) -> bool {
-        !self.server.is_empty()
+        self.validate().is_ok()
+    }
+
+    /// Why the settings cannot be used
+    pub fn validate(&self) -> Result<()> {
+        if self.server.is_empty() {
+            anyhow::bail!("'server' is required");
+        }
+        if let Some(monitors) = &self.monitors {
+            monitors.validate()?;
+        }
+        if let Some(keyboard) = &self.keyboard {
+            keyboard.to_keyboard()?;
+        }
+        Ok(())
     }
 
     pub fn to_core_settings(&self) -> settings::RdpSettings {
@@ -211,6 +423,73 @@ impl RdpSettings {
             },
         }
     }
+
+    /// What the core settings have no room for: the monitors and the keyboard for
+    /// the GUI, the audio devices, the mic processing, the camera and the smartcard
+    /// options for the channels
+    pub fn to_session_options(&self) -> gui::types::SessionOptions {
+        let redirections = self.redirections.as_ref();
+        let webcam = redirections.and_then(|r| r.webcam.as_ref());
+        let smartcard = redirections.and_then(|r| r.smartcard.as_ref());
+        let privacy_defs = channels::webcam::PrivacySettings::default();
+        gui::types::SessionOptions {
+            host_id: self.host_id.clone().filter(|id| !id.is_empty()),
+            monitors: self
+                .monitors
+                .as_ref()
+                .map(|m| m.to_selection())
+                .unwrap_or_default(),
+            keyboard: self
+                .keyboard
+                .as_ref()
+                .and_then(|k| k.to_keyboard().ok())
+                .unwrap_or_default(),
+            audio_output_device: redirections.and_then(|r| r.audio_output_device.clone()),
+            audio_input_device: redirections.and_then(|r| r.audio_input_device.clone()),
+            mic_processing: redirections
+                .and_then(|r| r.mic_processing.as_ref())
+                .map(|p| p.to_processing())
+                .unwrap_or_default(),
+            webcam: channels::webcam::WebcamOptions {
+                camera: webcam.and_then(|w| w.device.clone()),
+                source: webcam.and_then(|w| w.source.clone()),
+                privacy: channels::webcam::PrivacySettings {
+                    preview: webcam
+                        .and_then(|w| w.preview)
+                        .unwrap_or(privacy_defs.preview),
+                    blur: webcam
+                        .and_then(|w| w.background_blur)
+                        .unwrap_or(privacy_defs.blur),
+                },
+            },
+            smartcard: channels::smartcard::SmartcardOptions {
+                pin: smartcard
+                    .and_then(|s| s.pin.as_ref())
+                    .map(|p| p.to_policy())
+                    .unwrap_or_default(),
+                readers: smartcard
+                    .and_then(|s| s.readers.as_ref())
+                    .map(|r| r.to_filter())
+                    .unwrap_or_default(),
+            },
+        }
+    }
+}
+
+/// Debug form of the core settings, with the emulated card spec shown as its
+/// `EmulatedSpec` (it can hold private keys and PINs).
+fn logged_settings(settings: &settings::RdpSettings) -> Zeroizing<String> {
+    let logged = Zeroizing::new(format!("{:?}", settings));
+    match &settings.redirections.smartcard.emulated {
+        Some(spec) => {
+            let spec = channels::smartcard::EmulatedSpec(spec.clone());
+            Zeroizing::new(logged.replace(
+                &*Zeroizing::new(format!("{:?}", spec.0)),
+                &format!("{:?}", spec),
+            ))
+        }
+        None => logged,
+    }
 }
 
 fn start_rdp_fn(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
@@ -221,15 +500,28 @@ fn start_rdp_fn(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<Js
         rdp_settings.user,
         rdp_settings.port
     );
-    if !rdp_settings.is_valid() {
+    if let Err(e) = rdp_settings.validate() {
         return Err(JsError::from_native(
-            JsNativeError::error().with_message("Invalid RDP settings: 'server' is required"),
+            JsNativeError::error().with_message(format!("Invalid RDP settings: {}", e)),
         ));
     }
 
+    if debug::is_dry_run() {
+        // Record what the script passed (redacted), not the core settings with secrets
+        let settings_json = debug::args_to_json(&args[..1], ctx);
+        debug::record("RDP.start", settings_json[0].clone());
+        return Ok(JsValue::undefined());
+    }
+
     let settings = rdp_settings.to_core_settings();
 
-    log::debug!("Starting RDP with settings: {:?}", settings);
+    log::debug!(
+        "Starting RDP with settings: {}",
+        logged_settings(&settings).as_str()
+    );
+
+    let options = rdp_settings.to_session_options();
+    log::debug!("Session options: {:?}", options);
 
     // If we have a server config and a rail_app, try sending via IPC to an existing session
     if let Some(ref rail) = settings.rail
@@ -251,7 +543,10 @@ fn start_rdp_fn(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<Js
         }
     }
 
-    send_message(GuiMessage::ConnectRdp(Box::new(settings)));
+    send_message(GuiMessage::ConnectRdp(
+        Box::new(settings),
+        Box::new(options),
+    ));
     // Launcher needs to know that RDP client is running
     // so it doesn't close the GUI immediately
     connection::tasks::mark_internal_rdp_as_running();
@@ -259,6 +554,102 @@ fn start_rdp_fn(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<Js
     Ok(JsValue::undefined())
 }
 
+/// `RDP.start` settings of a core one (see `from_rdp_file_fn`). The password is
+/// never there, a `.rdp` file cannot carry it in clear.
+fn core_settings_to_json(settings: &settings::RdpSettings) -> serde_json::Value {
+    let (screen_width, screen_height) = match settings.screen_size {
+        ScreenSize::Fixed(width, height) => (width, height),
+        _ => (0, 0),
+    };
+    let redirections = &settings.redirections;
+    let mut value = serde_json::json!({
+        "server": settings.server,
+        "port": settings.port,
+        "screen_width": screen_width,
+        "screen_height": screen_height,
+        "best_experience": settings.best_experience,
+        "redirections": {
+            "clipboard": redirections.clipboard,
+            "audio": redirections.audio,
+            "mic": redirections.mic,
+            "printing": redirections.printing,
+            "smartcard": { "enabled": redirections.smartcard.enabled },
+            "drives": redirections.drives,
+        },
+        "options": {
+            "verify_cert": settings.options.verify_cert,
+            "use_nla": settings.options.use_nla,
+            "use_local_scaler": settings.options.use_local_scaler,
+            "use_tunnel": settings.options.use_tunnel,
+        },
+    });
+    // Optional fields are omitted, not null (see `RDP.start`)
+    for (name, field) in [("user", &settings.user), ("domain", &settings.domain)] {
+        if !field.is_empty() {
+            value[name] = serde_json::json!(field);
+        }
+    }
+    if let Some(webcam) = &redirections.webcam {
+        value["redirections"]["webcam"] = serde_json::json!({
+            "enabled": webcam.enabled,
+            "quality": webcam.quality,
+            "fps": webcam.fps,
+        });
+    }
+    if let Some(rail) = &settings.rail {
+        let mut rail_value = serde_json::json!({ "app": rail.app });
+        for (name, field) in [
+            ("args", &rail.args),
+            ("working_dir", &rail.working_dir),
+            ("title", &rail.title),
+        ] {
+            if let Some(field) = field {
+                rail_value[name] = serde_json::json!(field);
+            }
+        }
+        value["rail"] = rail_value;
+    }
+    value
+}
+
+/// `RDP.start` settings of the text of a `.rdp` file
+fn from_rdp_file_fn(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let text = extract_js_args!(args, ctx, String);
+    let file = crate::rdp_file::parse(&text).map_err(|e| {
+        JsError::from_native(
+            JsNativeError::error().with_message(format!("Invalid RDP file: {}", e)),
+        )
+    })?;
+    JsValue::from_json(&core_settings_to_json(&file.settings), ctx)
+}
+
+/// Text of a `.rdp` file with the `RDP.start` settings, without the password
+fn to_rdp_file_fn(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let rdp_settings = extract_js_args!(args, ctx, RdpSettings);
+    if let Err(e) = rdp_settings.validate() {
+        return Err(JsError::from_native(
+            JsNativeError::error().with_message(format!("Invalid RDP settings: {}", e)),
+        ));
+    }
+    let file = crate::rdp_file::RdpFile {
+        settings: rdp_settings.to_core_settings(),
+        signature: None,
+    };
+    Ok(JsValue::from(JsString::from(crate::rdp_file::serialize(
+        &file,
+    ))))
+}
+
+/// Unblocks the emulated cards, forgetting the PIN failures kept between sessions
+fn unblock_smartcard_fn(_: &JsValue, _: &[JsValue], _: &mut Context) -> JsResult<JsValue> {
+    if debug::is_dry_run() {
+        debug::record("RDP.unblockSmartcard", serde_json::json!([]));
+        return Ok(JsValue::undefined());
+    }
+    channels::smartcard::clear_pin_failures();
+    Ok(JsValue::undefined())
+}
+
 async fn sign_rdp_fn(
     _: &JsValue,
     args: &[JsValue],
@@ -284,15 +675,104 @@ async fn sign_rdp_fn(
     Ok(JsValue::from(JsString::from(signed_rdp)))
 }
 
+/// Generates the key pair of an ephemeral session smartcard and has the broker
+/// issue its certificate. A later `RDP.start` with the `ephemeral` emulated spec
+/// uses it; the key lives only in memory and is wiped on disconnect.
+async fn issue_smartcard_fn(
+    _: &JsValue,
+    args: &[JsValue],
+    ctx: &std::cell::RefCell<&mut Context>,
+) -> JsResult<JsValue> {
+    let (ticket, common_name) = {
+        let mut ctx_borrow = ctx.borrow_mut();
+        extract_js_args!(args, &mut *ctx_borrow, String, Option<String>)
+    };
+    let smartcard_error = |e: String| {
+        JsError::from_native(
+            JsNativeError::error()
+                .with_message(format!("Failed to issue the session smartcard: {}", e)),
+        )
+    };
+    let api = broker::api::get_api().map_err(|e| {
+        JsError::from_native(
+            JsNativeError::error().with_message(format!("Failed to get broker API: {}", e)),
+        )
+    })?;
+    // RSA key generation takes a while, keep it off the runtime thread
+    let key = tokio::task::spawn_blocking(channels::smartcard::EphemeralKey::generate)
+        .await
+        .map_err(|e| smartcard_error(e.to_string()))?
+        .map_err(smartcard_error)?;
+    let csr = key
+        .csr_pem(common_name.as_deref())
+        .map_err(smartcard_error)?;
+    let certificate = api
+        .request_smartcard_certificate(&ticket, &csr)
+        .await
+        .map_err(|e| smartcard_error(e.to_string()))?;
+    key.into_session_card(&certificate)
+        .map_err(smartcard_error)?;
+    log::info!("Ephemeral smartcard issued for the session");
+    Ok(JsValue::undefined())
+}
+
+/// Cameras of the client with the formats they capture, for the `device` webcam setting.
+/// Probing opens every camera, so it runs off the runtime thread.
+async fn webcams_fn(
+    _: &JsValue,
+    _: &[JsValue],
+    ctx: &std::cell::RefCell<&mut Context>,
+) -> JsResult<JsValue> {
+    let cameras = tokio::task::spawn_blocking(channels::webcam::list_cameras)
+        .await
+        .map_err(|e| {
+            JsError::from_native(
+                JsNativeError::error().with_message(format!("Failed to list cameras: {}", e)),
+            )
+        })?;
+    let cameras: Vec<serde_json::Value> = cameras
+        .iter()
+        .map(|camera| {
+            let formats: Vec<serde_json::Value> = camera
+                .formats
+                .iter()
+                .map(|format| {
+                    serde_json::json!({
+                        "width": format.width(),
+                        "height": format.height(),
+                        "fps": format.frame_rate(),
+                        "format": format.format().to_string(),
+                    })
+                })
+                .collect();
+            serde_json::json!({
+                "index": camera.index.to_string(),
+                "name": camera.name,
+                "formats": formats,
+            })
+        })
+        .collect();
+    JsValue::from_json(&serde_json::Value::Array(cameras), &mut ctx.borrow_mut())
+}
+
 pub(super) fn register(ctx: &mut Context) -> Result<()> {
     // Disable format that would make this less readable
     register_js_module!(
         ctx,
         "RDP",
         // Sync functions
-        [("start", start_rdp_fn, 1),],
+        [
+            ("start", start_rdp_fn, 1),
+            ("fromRdpFile", from_rdp_file_fn, 1),
+            ("toRdpFile", to_rdp_file_fn, 1),
+            ("unblockSmartcard", unblock_smartcard_fn, 0),
+        ],
         // Async functions
-        [("sign", sign_rdp_fn, 2),],
+        [
+            ("sign", sign_rdp_fn, 2),
+            ("issueSmartcard", issue_smartcard_fn, 2),
+            ("webcams", webcams_fn, 0),
+        ],
     );
     Ok(())
 }
@@ -300,11 +780,35 @@ pub(super) fn register(ctx: &mut Context) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{create_context, exec_script};
+    use crate::{create_context, exec_script, exec_script_with_result};
     use anyhow::Result;
     use flume::{Receiver, Sender, bounded};
+    use gui::types::SessionOptions;
     use shared::log;
 
+    // Context with the RDP module, and the receiver of what it sends to the GUI
+    fn rdp_context() -> Result<(Context, Receiver<GuiMessage>)> {
+        log::setup_logging("debug", log::LogType::Test);
+        let (messages_tx, messages_rx) = bounded(32);
+        crate::gui::set_sender(messages_tx);
+        let mut ctx = create_context(None)?;
+        register(&mut ctx)?;
+        Ok((ctx, messages_rx))
+    }
+
+    // Runs `RDP.start(<js_settings>)`, returning the session it asked the GUI for
+    async fn start(
+        ctx: &mut Context,
+        messages_rx: &Receiver<GuiMessage>,
+        js_settings: &str,
+    ) -> Result<(Box<settings::RdpSettings>, Box<SessionOptions>)> {
+        exec_script(ctx, &format!("RDP.start({});", js_settings)).await?;
+        match messages_rx.try_recv() {
+            Ok(GuiMessage::ConnectRdp(settings, options)) => Ok((settings, options)),
+            other => anyhow::bail!("Expected GuiMessage::ConnectRdp, got {:?}", other),
+        }
+    }
+
     #[tokio::test]
     #[serial_test::serial(js_modules)]
     async fn test_init_ctx() -> Result<()> {
@@ -341,7 +845,7 @@ mod tests {
         // Verify that a GuiMessage::ConnectRdp was sent
         match messages_rx.try_recv() {
             Ok(gui_msg) => match gui_msg {
-                GuiMessage::ConnectRdp(settings) => {
+                GuiMessage::ConnectRdp(settings, _) => {
                     assert_eq!(settings.server, "localhost");
                     assert_eq!(settings.port, 3389);
                     assert_eq!(settings.user, "testuser");
@@ -396,7 +900,7 @@ mod tests {
 
         match messages_rx.try_recv() {
             Ok(gui_msg) => match gui_msg {
-                GuiMessage::ConnectRdp(settings) => {
+                GuiMessage::ConnectRdp(settings, _) => {
                     assert_eq!(settings.server, "localhost");
                     assert_eq!(settings.port, 3389);
                     assert_eq!(settings.user, "");
@@ -425,6 +929,33 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    #[serial_test::serial(js_modules)]
+    async fn test_session_option_defaults() -> Result<()> {
+        let (mut ctx, messages_rx) = rdp_context()?;
+        let (_, options) = start(&mut ctx, &messages_rx, r#"{ server: "localhost" }"#).await?;
+        assert_eq!(options.host_id, None);
+        assert_eq!(options.monitors, gui::MonitorSelection::Single);
+        assert_eq!(options.keyboard, gui::keymap::KeyboardSettings::default());
+        assert_eq!(options.audio_output_device, None);
+        assert_eq!(options.audio_input_device, None);
+        assert_eq!(
+            options.mic_processing,
+            channels::audio::MicProcessing::default()
+        );
+        assert_eq!(options.webcam.camera, None);
+        assert_eq!(options.webcam.source, None);
+        assert_eq!(
+            options.webcam.privacy,
+            channels::webcam::PrivacySettings::default()
+        );
+        assert_eq!(
+            options.smartcard,
+            channels::smartcard::SmartcardOptions::default()
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     #[serial_test::serial(js_modules)]
     async fn test_smartcard_settings_mapping() -> Result<()> {
@@ -453,7 +984,7 @@ mod tests {
         _ = exec_script(&mut ctx, script).await;
 
         match messages_rx.try_recv() {
-            Ok(GuiMessage::ConnectRdp(settings)) => {
+            Ok(GuiMessage::ConnectRdp(settings, _)) => {
                 assert!(settings.redirections.smartcard.enabled);
                 assert_eq!(
                     settings.redirections.smartcard.emulated.as_deref(),
@@ -466,6 +997,288 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    #[serial_test::serial(js_modules)]
+    async fn test_smartcard_spec_not_logged() -> Result<()> {
+        let (mut ctx, messages_rx) = rdp_context()?;
+        let js_settings = r#"{
+            server: "localhost",
+            redirections: {
+                smartcard: {
+                    enabled: true,
+                    emulated: "userdefined:pkcs11,module=/lib/p11.so,pin=123456;ephemeral"
+                }
+            }
+        }"#;
+        let (settings, _) = start(&mut ctx, &messages_rx, js_settings).await?;
+        let logged = logged_settings(&settings);
+        assert!(!logged.contains("123456"));
+        assert!(logged.contains(r#"EmulatedSpec("userdefined:***;ephemeral")"#));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial_test::serial(js_modules)]
+    async fn test_smartcard_pin_policy() -> Result<()> {
+        let (mut ctx, messages_rx) = rdp_context()?;
+        let js_settings = r#"{
+            server: "localhost",
+            redirections: {
+                smartcard: {
+                    emulated: "file:/tmp/card.pem",
+                    pin: { local: true, max_retries: 5, min_length: 6, digits_only: true, persistent: true }
+                }
+            }
+        }"#;
+        let (_, options) = start(&mut ctx, &messages_rx, js_settings).await?;
+        assert_eq!(
+            options.smartcard.pin,
+            channels::smartcard::PinPolicy {
+                local: true,
+                max_retries: 5,
+                min_length: 6,
+                digits_only: true,
+                persistent: true,
+                ..Default::default()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial_test::serial(js_modules)]
+    async fn test_smartcard_readers() -> Result<()> {
+        let (mut ctx, messages_rx) = rdp_context()?;
+        let js_settings = r#"{
+            server: "localhost",
+            redirections: {
+                smartcard: {
+                    enabled: true,
+                    readers: { allow: ["YubiKey", "Gemalto"], deny: ["Integrated"] }
+                }
+            }
+        }"#;
+        let (_, options) = start(&mut ctx, &messages_rx, js_settings).await?;
+        let filter = &options.smartcard.readers;
+        assert_eq!(filter.allow, vec!["YubiKey", "Gemalto"]);
+        assert_eq!(filter.deny, vec!["Integrated"]);
+        assert!(filter.allows("Yubico YubiKey OTP+FIDO+CCID 00 00"));
+        assert!(!filter.allows("Integrated Smart Card Reader 00 00"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial_test::serial(js_modules)]
+    async fn test_monitors() -> Result<()> {
+        let (mut ctx, messages_rx) = rdp_context()?;
+        for (monitors, expected) in [
+            (r#""all""#, gui::MonitorSelection::All),
+            (r#""All""#, gui::MonitorSelection::All),
+            ("[1, 0]", gui::MonitorSelection::Indices(vec![1, 0])),
+        ] {
+            let js_settings = format!(r#"{{ server: "desktop", monitors: {} }}"#, monitors);
+            let (_, options) = start(&mut ctx, &messages_rx, &js_settings).await?;
+            assert_eq!(options.monitors, expected, "{}", monitors);
+        }
+
+        // Invalid ones do not connect
+        for monitors in [r#""some""#, "[]", r#"["a"]"#] {
+            let js_settings = format!(r#"{{ server: "desktop", monitors: {} }}"#, monitors);
+            assert!(
+                start(&mut ctx, &messages_rx, &js_settings).await.is_err(),
+                "{}",
+                monitors
+            );
+            assert!(messages_rx.try_recv().is_err(), "{}", monitors);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial_test::serial(js_modules)]
+    async fn test_keyboard() -> Result<()> {
+        use gui::keymap::{GrabMode, HotkeyAction, Modifiers, key_from_name};
+
+        let (mut ctx, messages_rx) = rdp_context()?;
+        let js_settings = r#"{
+            server: "desktop",
+            keyboard: {
+                grab: "focused",
+                escape: "ctrl+alt",
+                hotkeys: { fullscreen: "F11", exit: "none" }
+            }
+        }"#;
+        let (_, options) = start(&mut ctx, &messages_rx, js_settings).await?;
+        let keyboard = &options.keyboard;
+        assert_eq!(keyboard.grab, GrabMode::Focused);
+        let ctrl_alt = Modifiers {
+            ctrl: true,
+            alt: true,
+            ..Default::default()
+        };
+        assert_eq!(keyboard.hotkeys.chord, ctrl_alt);
+        assert_eq!(
+            keyboard
+                .hotkeys
+                .action(key_from_name("F11").unwrap(), &ctrl_alt),
+            Some(HotkeyAction::Fullscreen)
+        );
+        assert_eq!(keyboard.hotkeys.key(HotkeyAction::Exit), None);
+        assert_eq!(keyboard.hotkeys.key(HotkeyAction::Mute), key_from_name("M"));
+
+        // Invalid ones do not connect
+        for keyboard in [
+            r#"{ grab: "always" }"#,
+            r#"{ escape: "" }"#,
+            r#"{ escape: "alt+hyper" }"#,
+            r#"{ hotkeys: { fps: "NoSuchKey" } }"#,
+            r#"{ hotkeys: { fps: "M" } }"#,
+        ] {
+            let js_settings = format!(r#"{{ server: "desktop", keyboard: {} }}"#, keyboard);
+            assert!(
+                start(&mut ctx, &messages_rx, &js_settings).await.is_err(),
+                "{}",
+                keyboard
+            );
+            assert!(messages_rx.try_recv().is_err(), "{}", keyboard);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial_test::serial(js_modules)]
+    async fn test_rdp_file() -> Result<()> {
+        let (mut ctx, messages_rx) = rdp_context()?;
+        let script = r#"
+            let settings = RDP.fromRdpFile(
+                "full address:s:rdp.example.com:3390\r\n" +
+                "username:s:CORP\\jdoe\r\n" +
+                "screen mode id:i:1\r\ndesktopwidth:i:1280\r\ndesktopheight:i:800\r\n" +
+                "redirectprinters:i:1\r\ndrivestoredirect:s:C:\\;D:\\;\r\n" +
+                "remoteapplicationmode:i:1\r\nremoteapplicationprogram:s:||calc\r\n"
+            );
+            settings.password = "secret";
+            RDP.start(settings);
+        "#;
+        exec_script(&mut ctx, script).await?;
+        match messages_rx.try_recv() {
+            Ok(GuiMessage::ConnectRdp(settings, _)) => {
+                assert_eq!(settings.server, "rdp.example.com");
+                assert_eq!(settings.port, 3390);
+                assert_eq!(settings.user, "jdoe");
+                assert_eq!(settings.domain, "CORP");
+                assert_eq!(settings.password, "secret");
+                match settings.screen_size {
+                    ScreenSize::Fixed(w, h) => assert_eq!((w, h), (1280, 800)),
+                    _ => panic!("Expected fixed screen size, got {:?}", settings.screen_size),
+                }
+                assert!(settings.redirections.printing);
+                assert_eq!(settings.redirections.drives, vec!["C", "D"]);
+                assert_eq!(
+                    settings.rail.as_ref().map(|r| r.app.as_str()),
+                    Some("||calc")
+                );
+            }
+            _ => panic!("Expected GuiMessage::ConnectRdp"),
+        }
+
+        let script = r#"
+            RDP.toRdpFile({ server: "host", port: 3390, user: "me", password: "secret" });
+        "#;
+        let text: String = exec_script_with_result(&mut ctx, script)
+            .await?
+            .try_js_into(&mut ctx)
+            .map_err(|e| anyhow::anyhow!("Failed to convert result from JsValue: {}", e))?;
+        assert!(text.contains("full address:s:host:3390\r\n"));
+        assert!(text.contains("username:s:me\r\n"));
+        assert!(!text.contains("secret"));
+
+        assert!(
+            exec_script(&mut ctx, r#"RDP.fromRdpFile("username:s:me");"#)
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial_test::serial(js_modules)]
+    async fn test_webcams() -> Result<()> {
+        let (mut ctx, _messages_rx) = rdp_context()?;
+        unsafe {
+            std::env::set_var(channels::webcam::devices::CAM_MOCK_ENV, "true");
+        }
+        let result = exec_script_with_result(
+            &mut ctx,
+            "RDP.webcams().then((cameras) => JSON.stringify(cameras));",
+        )
+        .await;
+        unsafe {
+            std::env::remove_var(channels::webcam::devices::CAM_MOCK_ENV);
+        }
+        let text: String = result?
+            .try_js_into(&mut ctx)
+            .map_err(|e| anyhow::anyhow!("Failed to convert result from JsValue: {}", e))?;
+        let cameras: serde_json::Value = serde_json::from_str(&text)?;
+        assert_eq!(cameras.as_array().map(|c| c.len()), Some(1));
+        assert_eq!(cameras[0]["index"], "0");
+        let formats = cameras[0]["formats"].as_array().unwrap();
+        assert!(!formats.is_empty());
+        assert!(
+            formats
+                .iter()
+                .any(|f| f["width"] == 1280 && f["height"] == 720)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial_test::serial(js_modules)]
+    async fn test_session_options() -> Result<()> {
+        let (mut ctx, messages_rx) = rdp_context()?;
+        let js_settings = r#"{
+            server: "localhost",
+            host_id: "service-1",
+            redirections: {
+                mic: true,
+                audio_output_device: "Speakers",
+                audio_input_device: "Headset",
+                mic_processing: { echo_cancellation: true, agc: true },
+                webcam: {
+                    enabled: true,
+                    device: "USB",
+                    source: "screen",
+                    preview: false,
+                    background_blur: true
+                }
+            }
+        }"#;
+        let (_, options) = start(&mut ctx, &messages_rx, js_settings).await?;
+        assert_eq!(options.host_id.as_deref(), Some("service-1"));
+        assert_eq!(options.audio_output_device.as_deref(), Some("Speakers"));
+        assert_eq!(options.audio_input_device.as_deref(), Some("Headset"));
+        assert_eq!(
+            options.mic_processing,
+            channels::audio::MicProcessing {
+                echo_cancellation: true,
+                noise_suppression: false,
+                agc: true,
+            }
+        );
+        assert_eq!(options.webcam.camera.as_deref(), Some("USB"));
+        assert_eq!(options.webcam.source.as_deref(), Some("screen"));
+        assert_eq!(
+            options.webcam.privacy,
+            channels::webcam::PrivacySettings {
+                preview: false,
+                blur: true,
+            }
+        );
+        Ok(())
+    }
+
     #[test]
     fn settings_is_valid_empty() {
         let s = RdpSettings::default();
@@ -479,6 +1292,32 @@ mod tests {
         assert!(s.is_valid());
     }
 
+    #[test]
+    fn settings_is_valid_monitors() {
+        let mut s = RdpSettings::default();
+        s.server = "host".into();
+        s.monitors = Some(MonitorsSetting::Name("all".into()));
+        assert!(s.is_valid());
+        s.monitors = Some(MonitorsSetting::Indices(vec![0, 2]));
+        assert!(s.is_valid());
+        s.monitors = Some(MonitorsSetting::Name("primary".into()));
+        assert!(!s.is_valid());
+        s.monitors = Some(MonitorsSetting::Indices(Vec::new()));
+        assert!(!s.is_valid());
+    }
+
+    #[test]
+    fn settings_is_valid_keyboard() {
+        let mut s = RdpSettings::default();
+        s.server = "host".into();
+        s.keyboard = Some(KeyboardSettings::default());
+        assert!(s.is_valid());
+        if let Some(keyboard) = s.keyboard.as_mut() {
+            keyboard.grab = Some("sometimes".into());
+        }
+        assert!(!s.is_valid());
+    }
+
     #[test]
     fn settings_defaults() {
         let s = RdpSettings::default();
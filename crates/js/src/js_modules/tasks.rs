@@ -16,6 +16,11 @@ use boa_engine::{
 use connection::{tasks, types::TunnelConnectInfo};
 use shared::{appdata, log};
 
+use crate::debug;
+
+// Port returned by startTunnel on dry runs when no local_port is requested
+const DRY_RUN_TUNNEL_PORT: u16 = 65000;
+
 fn add_early_unlinkable_file_fn(
     _: &JsValue,
     args: &[JsValue],
@@ -43,6 +48,10 @@ fn add_late_unlinkable_file_fn(
 fn add_waitable_app_fn(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
     let task_handle = extract_js_args!(args, ctx, u32);
 
+    if task_handle == debug::DRY_RUN_PROCESS_ID {
+        return Ok(JsValue::undefined());
+    }
+
     tasks::add_waitable_app(task_handle);
 
     Ok(JsValue::undefined())
@@ -121,10 +130,28 @@ async fn start_tunel_fn(
         .to_connect_info(appdata.verify_ssl)
         .map_err(|e| JsError::from_native(JsNativeError::error().with_message(e.to_string())))?;
 
-    let port = connection::start_tunnel(tunnel_info)
-        .await
-        .map(JsValue::from)
-        .map_err(|e| JsError::from_native(JsNativeError::error().with_message(format!("{}", e))))?;
+    let port = if debug::is_dry_run() {
+        debug::record(
+            "Tasks.startTunnel",
+            serde_json::json!({
+                "addr": tunnel_info.addr,
+                "port": tunnel_info.port,
+                "ticket": params.ticket,
+                "check_certificate": tunnel_info.check_certificate,
+                "local_port": tunnel_info.local_port,
+                "startup_time_ms": tunnel_info.startup_time_ms,
+                "enable_ipv6": tunnel_info.enable_ipv6,
+            }),
+        );
+        JsValue::from(tunnel_info.local_port.unwrap_or(DRY_RUN_TUNNEL_PORT))
+    } else {
+        connection::start_tunnel(tunnel_info)
+            .await
+            .map(JsValue::from)
+            .map_err(|e| {
+                JsError::from_native(JsNativeError::error().with_message(format!("{}", e)))
+            })?
+    };
 
     // Re-borrow the context to create the result object
     let result = {
@@ -7,7 +7,7 @@ use std::rc::Rc;
 
 use anyhow::Result;
 use boa_engine::{
-    Context, JsValue, Module, js_string,
+    Context, JsValue, Module, Source, js_string,
     module::{MapModuleLoader, SyntheticModuleInitializer},
 };
 
@@ -15,21 +15,30 @@ use connection::broker::api::types::{Script, ScriptType};
 
 use shared::log;
 
-pub mod gui;
-
 // Helpers functions for javascript rust bindings
 #[macro_use]
 mod macros;
 
+pub mod debug;
+pub mod gui;
+
 mod executor;
 mod helpers;
 
 mod js_modules;
 
+pub mod rdp_file;
+
+pub mod testing;
+
 pub use executor::{create_context, exec_script, exec_script_with_result};
 
 fn init_runtime(ctx: &mut Context) -> Result<()> {
     js_modules::register(ctx)?;
+    // Script tester only, the launcher runs without it
+    if debug::is_enabled() {
+        debug::register(ctx)?;
+    }
     Ok(())
 }
 
@@ -41,6 +50,7 @@ fn create_runtime_module(ctx: &mut Context) -> Module {
     let utils = global.get(js_string!("Utils"), ctx).unwrap();
     let tasks = global.get(js_string!("Tasks"), ctx).unwrap();
     let rdp = global.get(js_string!("RDP"), ctx).unwrap();
+    let debug = global.get(js_string!("Debug"), ctx).unwrap();
 
     Module::synthetic(
         &[
@@ -50,10 +60,11 @@ fn create_runtime_module(ctx: &mut Context) -> Module {
             js_string!("Utils"),
             js_string!("Tasks"),
             js_string!("RDP"),
+            js_string!("Debug"),
         ],
         SyntheticModuleInitializer::from_copy_closure_with_captures(
             move |module: &boa_engine::module::SyntheticModule,
-                  (process, logger, file, utils, tasks, rdp),
+                  (process, logger, file, utils, tasks, rdp, debug),
                   _ctx| {
                 module.set_export(&js_string!("Process"), process.clone())?;
                 module.set_export(&js_string!("Logger"), logger.clone())?;
@@ -61,9 +72,10 @@ fn create_runtime_module(ctx: &mut Context) -> Module {
                 module.set_export(&js_string!("Utils"), utils.clone())?;
                 module.set_export(&js_string!("Tasks"), tasks.clone())?;
                 module.set_export(&js_string!("RDP"), rdp.clone())?;
+                module.set_export(&js_string!("Debug"), debug.clone())?;
                 Ok(())
             },
-            (process, logger, file, utils, tasks, rdp),
+            (process, logger, file, utils, tasks, rdp, debug),
         ),
         None,
         None,
@@ -71,17 +83,39 @@ fn create_runtime_module(ctx: &mut Context) -> Module {
     )
 }
 
-pub async fn run_js(script: &str, data: Option<serde_json::Value>) -> Result<()> {
-    log::debug!("Running JS script:\n");
+/// Creates a context with the runtime modules registered, the "runtime" module
+/// importable and `data` set as a global (undefined if None).
+/// `libraries` are (name, code) of modules importable by name, already verified.
+pub fn create_runtime_context(
+    data: Option<serde_json::Value>,
+    libraries: &[(String, String)],
+) -> Result<Context> {
+    build_runtime_context(data, init_runtime, libraries)
+}
 
+// Runtime modules are registered by `init` (real ones or mocks, see testing module)
+fn build_runtime_context(
+    data: Option<serde_json::Value>,
+    init: fn(&mut Context) -> Result<()>,
+    libraries: &[(String, String)],
+) -> Result<Context> {
     let loader = Rc::new(MapModuleLoader::new());
 
     let mut ctx = create_context(Some(loader.clone()))?;
-    init_runtime(&mut ctx)?;
+    init(&mut ctx)?;
 
     let runtime_module = create_runtime_module(&mut ctx);
     loader.insert("runtime", runtime_module);
 
+    for (name, code) in libraries {
+        if name == "runtime" {
+            return Err(anyhow::anyhow!("Library module cannot be named 'runtime'"));
+        }
+        let module = Module::parse(Source::from_bytes(code.as_bytes()), None, &mut ctx)
+            .map_err(|e| anyhow::anyhow!("Failed to parse library module {}: {}", name, e))?;
+        loader.insert(name.as_str(), module);
+    }
+
     if let Some(data) = data {
         let js_value = JsValue::from_json(&data, &mut ctx)
             .map_err(|e| anyhow::anyhow!("Failed to convert JSON data to JsValue: {}", e))?;
@@ -101,6 +135,22 @@ pub async fn run_js(script: &str, data: Option<serde_json::Value>) -> Result<()>
         .map_err(|e| anyhow::anyhow!("Failed to register global property: {}", e))?;
     }
 
+    Ok(ctx)
+}
+
+pub async fn run_js(script: &str, data: Option<serde_json::Value>) -> Result<()> {
+    run_js_with_libraries(script, data, &[]).await
+}
+
+pub async fn run_js_with_libraries(
+    script: &str,
+    data: Option<serde_json::Value>,
+    libraries: &[(String, String)],
+) -> Result<()> {
+    log::debug!("Running JS script:\n");
+
+    let mut ctx = create_runtime_context(data, libraries)?;
+
     let res = exec_script(&mut ctx, script).await;
     if res.is_err() {
         for frame in ctx.stack_trace() {
@@ -133,7 +183,10 @@ pub async fn run_script(script: &Script) -> Result<()> {
         params["shared_secret"] = serde_json::to_value(shared_secret)?;
     }
 
-    run_js(&script_content, Some(params)).await
+    // Library modules, verified against their signatures
+    let libraries = connection::broker::modules::resolve_all(&script.modules)?;
+
+    run_js_with_libraries(&script_content, Some(params), &libraries).await
 }
 
 #[cfg(test)]
@@ -194,6 +247,46 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_run_js_with_libraries() -> Result<()> {
+        log::setup_logging("debug", log::LogType::Test);
+        let libraries = vec![
+            (
+                "uds/math".to_string(),
+                "export function add(a, b) { return a + b; }".to_string(),
+            ),
+            (
+                "uds/check".to_string(),
+                r#"
+                    import { add } from "uds/math";
+                    import { Logger } from "runtime";
+                    export function check(a, b, expected) {
+                        Logger.info("Checking " + a + " + " + b);
+                        if (add(a, b) !== expected) throw new Error("Bad sum");
+                    }
+                "#
+                .to_string(),
+            ),
+        ];
+        let script = r#"
+            import { check } from "uds/check";
+            check(data.value1, data.value2, 42);
+        "#;
+        let data = serde_json::json!({ "value1": 20, "value2": 22 });
+        run_js_with_libraries(script, Some(data.clone()), &libraries).await?;
+
+        // Missing library
+        assert!(run_js(script, Some(data.clone())).await.is_err());
+        // Reserved name
+        let runtime = vec![("runtime".to_string(), "export const x = 1;".to_string())];
+        assert!(
+            run_js_with_libraries(script, Some(data), &runtime)
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_run_js_with_data() -> Result<()> {
         log::setup_logging("debug", log::LogType::Test);
@@ -37,19 +37,40 @@ macro_rules! register_js_module {
         let realm = $ctx.realm();
 
         // Sync functions
+        // Every function is wrapped so calls can be traced (see debug module)
         $(
-            register_fn!(obj, realm, $name,
-                boa_engine::native_function::NativeFunction::from_fn_ptr($fn),
-                $len
-            );
+            {
+                fn traced(
+                    this: &boa_engine::JsValue,
+                    args: &[boa_engine::JsValue],
+                    ctx: &mut boa_engine::Context,
+                ) -> boa_engine::JsResult<boa_engine::JsValue> {
+                    $crate::debug::trace_call($mod_name, $name, args, ctx);
+                    $fn(this, args, ctx)
+                }
+                register_fn!(obj, realm, $name,
+                    boa_engine::native_function::NativeFunction::from_fn_ptr(traced),
+                    $len
+                );
+            }
         )*
 
         // Async functions
         $(
-            register_fn!(obj, realm, $aname,
-                boa_engine::native_function::NativeFunction::from_async_fn($afn),
-                $alen
-            );
+            {
+                async fn traced(
+                    this: &boa_engine::JsValue,
+                    args: &[boa_engine::JsValue],
+                    ctx: &std::cell::RefCell<&mut boa_engine::Context>,
+                ) -> boa_engine::JsResult<boa_engine::JsValue> {
+                    $crate::debug::trace_call($mod_name, $aname, args, &mut ctx.borrow_mut());
+                    $afn(this, args, ctx).await
+                }
+                register_fn!(obj, realm, $aname,
+                    boa_engine::native_function::NativeFunction::from_async_fn(traced),
+                    $alen
+                );
+            }
         )*
 
         $ctx.register_global_property(
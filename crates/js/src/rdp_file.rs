@@ -0,0 +1,530 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! Microsoft `.rdp` files.
+//!
+//! A `.rdp` file is a list of `name:type:value` lines (`s` string, `i` integer,
+//! `b` binary), saved by mstsc as UTF-16 with BOM. `parse` reads the fields the
+//! client supports into the core `RdpSettings` (unknown ones are skipped, as
+//! mstsc does) and `serialize` writes them back. The `signscope` / `signature`
+//! block of a signed file is kept as is, it is not checked here.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use rdp::{geom::ScreenSize, settings};
+
+use shared::log;
+
+const DEFAULT_PORT: u32 = 3389;
+
+/// Signature block of a signed `.rdp` file (see `BrokerApi::request_rdp_sign`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RdpSignature {
+    /// Fields covered by the signature, `,` separated
+    pub scope: String,
+    pub signature: String,
+}
+
+#[derive(Debug)]
+pub struct RdpFile {
+    pub settings: settings::RdpSettings,
+    pub signature: Option<RdpSignature>,
+}
+
+/// Text of a `.rdp` file, UTF-16 (with BOM, as mstsc saves it) or UTF-8.
+pub fn decode(bytes: &[u8]) -> Result<String> {
+    let utf16 = |bytes: &[u8], from: fn([u8; 2]) -> u16| {
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| from([pair[0], pair[1]]))
+            .collect();
+        String::from_utf16(&units).map_err(|e| anyhow::anyhow!("Invalid UTF-16 RDP file: {}", e))
+    };
+    match bytes {
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        [0xEF, 0xBB, 0xBF, rest @ ..] => Ok(String::from_utf8(rest.to_vec())?),
+        _ => Ok(String::from_utf8(bytes.to_vec())?),
+    }
+}
+
+/// Reads and parses a `.rdp` file.
+pub fn read(path: &str) -> Result<RdpFile> {
+    let bytes =
+        std::fs::read(path).map_err(|e| anyhow::anyhow!("Cannot read RDP file {}: {}", path, e))?;
+    parse(&decode(&bytes)?)
+}
+
+/// Fields of the file, the last one wins if repeated.
+struct Fields(HashMap<String, (char, String)>);
+
+impl Fields {
+    fn new(text: &str) -> Self {
+        let mut fields = HashMap::new();
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let mut parts = line.splitn(3, ':');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(name), Some(kind @ ("s" | "i" | "b")), Some(value)) => {
+                    fields.insert(
+                        name.trim().to_lowercase(),
+                        (kind.chars().next().unwrap_or('s'), value.to_string()),
+                    );
+                }
+                _ => log::debug!("Skipping invalid RDP file line: {}", line),
+            }
+        }
+        Fields(fields)
+    }
+
+    fn string(&self, name: &str) -> Option<&str> {
+        match self.0.get(name) {
+            Some(('s', value)) if !value.is_empty() => Some(value.as_str()),
+            _ => None,
+        }
+    }
+
+    fn int(&self, name: &str) -> Option<i64> {
+        match self.0.get(name) {
+            Some(('i', value)) => value.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn flag(&self, name: &str) -> Option<bool> {
+        self.int(name).map(|value| value != 0)
+    }
+}
+
+/// Host and port of a `full address` (`host`, `host:port`, `[v6]:port` or a bare v6).
+fn split_address(address: &str) -> (String, Option<u32>) {
+    if let Some(rest) = address.strip_prefix('[')
+        && let Some((host, port)) = rest.split_once(']')
+    {
+        let port = port.strip_prefix(':').and_then(|p| p.parse().ok());
+        return (host.to_string(), port);
+    }
+    match address.split_once(':') {
+        Some((host, port)) if !port.contains(':') => (host.to_string(), port.parse().ok()),
+        _ => (address.to_string(), None),
+    }
+}
+
+/// Drive letters of `drivestoredirect` (`*` for all, or `C:\;D:\;...`).
+fn parse_drives(value: &str) -> Vec<String> {
+    if value.split(';').any(|drive| drive.trim() == "*") {
+        return vec!["all".to_string()];
+    }
+    value
+        .split(';')
+        .filter_map(|drive| {
+            let mut chars = drive.trim().chars();
+            match (chars.next(), chars.next()) {
+                (Some(letter), Some(':')) if letter.is_ascii_alphabetic() => {
+                    Some(letter.to_ascii_uppercase().to_string())
+                }
+                // DynamicDrives and the like
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Parses the text of a `.rdp` file. Only the address is required.
+pub fn parse(text: &str) -> Result<RdpFile> {
+    let fields = Fields::new(text);
+    let mut settings = settings::RdpSettings::default();
+
+    let address = fields
+        .string("alternate full address")
+        .or_else(|| fields.string("full address"))
+        .ok_or_else(|| anyhow::anyhow!("The RDP file has no full address"))?;
+    let (server, port) = split_address(address);
+    settings.server = server;
+    settings.port = port
+        .or_else(|| {
+            fields
+                .int("server port")
+                .and_then(|p| u32::try_from(p).ok())
+        })
+        .unwrap_or(DEFAULT_PORT);
+
+    if let Some(user) = fields.string("username") {
+        match user.split_once('\\') {
+            Some((domain, user)) => {
+                settings.domain = domain.to_string();
+                settings.user = user.to_string();
+            }
+            None => settings.user = user.to_string(),
+        }
+    }
+    if let Some(domain) = fields.string("domain") {
+        settings.domain = domain.to_string();
+    }
+    if fields.0.contains_key("password 51") {
+        // DPAPI encrypted, only readable by the user that saved it on Windows
+        log::debug!("Ignoring the encrypted password of the RDP file");
+    }
+
+    let size = (fields.int("desktopwidth"), fields.int("desktopheight"));
+    settings.screen_size = match (fields.int("screen mode id"), size) {
+        (Some(2), _) => ScreenSize::Full,
+        (_, (Some(width), Some(height))) if width > 0 && height > 0 => {
+            ScreenSize::Fixed(width as u32, height as u32)
+        }
+        _ => ScreenSize::Full,
+    };
+
+    let redirections = &mut settings.redirections;
+    if let Some(clipboard) = fields.flag("redirectclipboard") {
+        redirections.clipboard = clipboard;
+    }
+    // 0 plays on this computer, 1 on the remote one, 2 nowhere
+    if let Some(mode) = fields.int("audiomode") {
+        redirections.audio = mode == 0;
+    }
+    if let Some(mic) = fields.flag("audiocapturemode") {
+        redirections.mic = mic;
+    }
+    if let Some(printing) = fields.flag("redirectprinters") {
+        redirections.printing = printing;
+    }
+    if let Some(smartcard) = fields.flag("redirectsmartcards") {
+        redirections.smartcard.enabled = smartcard;
+    }
+    match (
+        fields.0.get("drivestoredirect"),
+        fields.flag("redirectdrives"),
+    ) {
+        (Some(('s', drives)), _) => redirections.drives = parse_drives(drives),
+        (_, Some(true)) => redirections.drives = vec!["all".to_string()],
+        (_, Some(false)) => redirections.drives = Vec::new(),
+        _ => {}
+    }
+    if fields.string("camerastoredirect").is_some() {
+        redirections.webcam = Some(settings::WebcamSettings {
+            enabled: true,
+            quality: 80,
+            fps: 15,
+            ..settings::WebcamSettings::default()
+        });
+    }
+
+    // 1 always uses the gateway, 2 only if the server is not reachable. The rdp
+    // crate has no gateway transport yet, so a file that needs one can't connect.
+    match (
+        fields.int("gatewayusagemethod"),
+        fields.string("gatewayhostname"),
+    ) {
+        (Some(1), Some(gateway)) => anyhow::bail!(
+            "The RDP file needs the RD Gateway {}, which is not supported",
+            gateway
+        ),
+        (Some(2), Some(gateway)) => {
+            log::warn!("Ignoring the RD Gateway {} of the RDP file", gateway)
+        }
+        _ => {}
+    }
+
+    if let Some(nla) = fields.flag("enablecredsspsupport") {
+        settings.options.use_nla = nla;
+    }
+    // 0 connects anyway, 1 does not connect, 2 warns
+    if let Some(level) = fields.int("authentication level") {
+        settings.options.verify_cert = level != 0;
+    }
+
+    if fields.flag("remoteapplicationmode") == Some(true) {
+        let app = fields
+            .string("remoteapplicationprogram")
+            .ok_or_else(|| anyhow::anyhow!("The RDP file has no remoteapplicationprogram"))?;
+        settings.rail = Some(settings::RailSettings {
+            app: app.to_string(),
+            args: fields
+                .string("remoteapplicationcmdline")
+                .map(str::to_string),
+            working_dir: fields.string("shell working directory").map(str::to_string),
+            title: fields.string("remoteapplicationname").map(str::to_string),
+            ..Default::default()
+        });
+    }
+
+    let signature = match (fields.string("signscope"), fields.string("signature")) {
+        (Some(scope), Some(signature)) => Some(RdpSignature {
+            scope: scope.to_string(),
+            signature: signature.to_string(),
+        }),
+        _ => None,
+    };
+
+    Ok(RdpFile {
+        settings,
+        signature,
+    })
+}
+
+/// Text of a `.rdp` file (CRLF lines) with the settings. The password is never
+/// written; the signature block, if any, goes last.
+pub fn serialize(file: &RdpFile) -> String {
+    let settings = &file.settings;
+    let mut lines = Vec::new();
+    let mut string = |name: &str, value: &str| lines.push(format!("{}:s:{}", name, value));
+
+    let host = if settings.server.contains(':') {
+        format!("[{}]", settings.server)
+    } else {
+        settings.server.clone()
+    };
+    if settings.port == DEFAULT_PORT {
+        string("full address", &host);
+    } else {
+        string("full address", &format!("{}:{}", host, settings.port));
+    }
+    if !settings.user.is_empty() {
+        string("username", &settings.user);
+    }
+    if !settings.domain.is_empty() {
+        string("domain", &settings.domain);
+    }
+    let redirections = &settings.redirections;
+    let drives = if redirections.drives.iter().any(|d| d == "all") {
+        "*".to_string()
+    } else {
+        redirections
+            .drives
+            .iter()
+            .map(|drive| format!("{}:\\;", drive))
+            .collect()
+    };
+    string("drivestoredirect", &drives);
+    if redirections.webcam.as_ref().is_some_and(|w| w.enabled) {
+        string("camerastoredirect", "*");
+    }
+    if let Some(rail) = &settings.rail {
+        string("remoteapplicationprogram", &rail.app);
+        if let Some(title) = &rail.title {
+            string("remoteapplicationname", title);
+        }
+        if let Some(args) = &rail.args {
+            string("remoteapplicationcmdline", args);
+        }
+        if let Some(working_dir) = &rail.working_dir {
+            string("shell working directory", working_dir);
+        }
+    }
+
+    let mut int = |name: &str, value: i64| lines.push(format!("{}:i:{}", name, value));
+    match settings.screen_size {
+        ScreenSize::Fixed(width, height) => {
+            int("screen mode id", 1);
+            int("desktopwidth", width.into());
+            int("desktopheight", height.into());
+        }
+        _ => int("screen mode id", 2),
+    }
+    int("redirectclipboard", redirections.clipboard.into());
+    int("audiomode", if redirections.audio { 0 } else { 2 });
+    int("audiocapturemode", redirections.mic.into());
+    int("redirectprinters", redirections.printing.into());
+    int("redirectsmartcards", redirections.smartcard.enabled.into());
+    int("enablecredsspsupport", settings.options.use_nla.into());
+    int(
+        "authentication level",
+        if settings.options.verify_cert { 2 } else { 0 },
+    );
+    int("remoteapplicationmode", settings.rail.is_some().into());
+
+    if let Some(signature) = &file.signature {
+        lines.push(format!("signscope:s:{}", signature.scope));
+        lines.push(format!("signature:s:{}", signature.signature));
+    }
+
+    let mut text = lines.join("\r\n");
+    text.push_str("\r\n");
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MSTSC_FILE: &str = "screen mode id:i:1\r\n\
+        desktopwidth:i:1280\r\n\
+        desktopheight:i:800\r\n\
+        full address:s:rdp.example.com:3390\r\n\
+        audiomode:i:2\r\n\
+        audiocapturemode:i:1\r\n\
+        redirectprinters:i:1\r\n\
+        redirectsmartcards:i:1\r\n\
+        redirectclipboard:i:0\r\n\
+        drivestoredirect:s:C:\\;DynamicDrives;e:\\;\r\n\
+        camerastoredirect:s:*\r\n\
+        authentication level:i:0\r\n\
+        enablecredsspsupport:i:0\r\n\
+        username:s:CORP\\jdoe\r\n\
+        password 51:b:01000000D08C9DDF0115D1118C7A00C04FC297EB\r\n\
+        session bpp:i:32\r\n\
+        this is not a field\r\n";
+
+    #[test]
+    fn test_parse_mstsc_file() -> Result<()> {
+        let file = parse(MSTSC_FILE)?;
+        let settings = &file.settings;
+        assert_eq!(settings.server, "rdp.example.com");
+        assert_eq!(settings.port, 3390);
+        assert_eq!(settings.user, "jdoe");
+        assert_eq!(settings.domain, "CORP");
+        assert_eq!(settings.password, "");
+        match settings.screen_size {
+            ScreenSize::Fixed(w, h) => assert_eq!((w, h), (1280, 800)),
+            _ => panic!("Expected fixed screen size, got {:?}", settings.screen_size),
+        }
+        assert!(!settings.redirections.clipboard);
+        assert!(!settings.redirections.audio);
+        assert!(settings.redirections.mic);
+        assert!(settings.redirections.printing);
+        assert!(settings.redirections.smartcard.enabled);
+        assert_eq!(settings.redirections.drives, vec!["C", "E"]);
+        assert!(
+            settings
+                .redirections
+                .webcam
+                .as_ref()
+                .is_some_and(|w| w.enabled)
+        );
+        assert!(!settings.options.verify_cert);
+        assert!(!settings.options.use_nla);
+        assert!(settings.rail.is_none());
+        assert!(file.signature.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_defaults() -> Result<()> {
+        let file = parse("full address:s:10.0.0.1\n")?;
+        let defs = settings::RdpSettings::default();
+        let settings = &file.settings;
+        assert_eq!(settings.server, "10.0.0.1");
+        assert_eq!(settings.port, 3389);
+        assert!(matches!(settings.screen_size, ScreenSize::Full));
+        assert_eq!(settings.redirections.drives, defs.redirections.drives);
+        assert_eq!(settings.redirections.audio, defs.redirections.audio);
+        assert_eq!(settings.options.use_nla, defs.options.use_nla);
+
+        assert!(parse("username:s:jdoe\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_gateway() -> Result<()> {
+        let gateway = "full address:s:desktop\r\ngatewayhostname:s:gw.example.com\r\n";
+        assert!(parse(&format!("{}gatewayusagemethod:i:1\r\n", gateway)).is_err());
+        // Direct when reachable, or never through the gateway
+        for usage in [0, 2, 4] {
+            let file = parse(&format!("{}gatewayusagemethod:i:{}\r\n", gateway, usage))?;
+            assert_eq!(file.settings.server, "desktop");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_addresses() {
+        assert_eq!(split_address("host"), ("host".to_string(), None));
+        assert_eq!(split_address("host:4000"), ("host".to_string(), Some(4000)));
+        assert_eq!(
+            split_address("[fe80::1]:4000"),
+            ("fe80::1".to_string(), Some(4000))
+        );
+        assert_eq!(split_address("fe80::1"), ("fe80::1".to_string(), None));
+    }
+
+    #[test]
+    fn test_remoteapp_and_signature() -> Result<()> {
+        let text = "full address:s:apps.example.com\n\
+            server port:i:3391\n\
+            alternate full address:s:gw-apps.example.com\n\
+            remoteapplicationmode:i:1\n\
+            remoteapplicationprogram:s:||calc\n\
+            remoteapplicationname:s:Calculator\n\
+            remoteapplicationcmdline:s:/x\n\
+            signscope:s:Full Address,Server Port,RemoteApplicationMode\n\
+            signature:s:AQABAAEAAAB+CwAAMIILegYJKoZIhvcNAQcCoIILazCCC2c=\n";
+        let file = parse(text)?;
+        assert_eq!(file.settings.server, "gw-apps.example.com");
+        assert_eq!(file.settings.port, 3391);
+        let rail = file.settings.rail.as_ref().expect("RemoteApp settings");
+        assert_eq!(rail.app, "||calc");
+        assert_eq!(rail.title.as_deref(), Some("Calculator"));
+        assert_eq!(rail.args.as_deref(), Some("/x"));
+        assert_eq!(rail.working_dir, None);
+        let signature = file.signature.as_ref().expect("Signature block");
+        assert_eq!(
+            signature.scope,
+            "Full Address,Server Port,RemoteApplicationMode"
+        );
+
+        let serialized = serialize(&file);
+        assert!(serialized.ends_with(&format!("signature:s:{}\r\n", signature.signature)));
+        let again = parse(&serialized)?;
+        assert_eq!(again.signature, file.signature);
+        assert_eq!(
+            again.settings.rail.as_ref().map(|r| r.app.as_str()),
+            Some("||calc")
+        );
+
+        // Missing program
+        assert!(parse("full address:s:host\nremoteapplicationmode:i:1\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let file = parse(MSTSC_FILE)?;
+        let text = serialize(&file);
+        assert!(text.contains("full address:s:rdp.example.com:3390\r\n"));
+        assert!(text.contains("drivestoredirect:s:C:\\;E:\\;\r\n"));
+        assert!(!text.contains("password"));
+
+        let again = parse(&text)?.settings;
+        let settings = &file.settings;
+        assert_eq!(again.server, settings.server);
+        assert_eq!(again.port, settings.port);
+        assert_eq!(again.user, settings.user);
+        assert_eq!(again.domain, settings.domain);
+        assert_eq!(again.redirections.drives, settings.redirections.drives);
+        assert_eq!(
+            again.redirections.clipboard,
+            settings.redirections.clipboard
+        );
+        assert_eq!(again.redirections.audio, settings.redirections.audio);
+        assert_eq!(again.redirections.mic, settings.redirections.mic);
+        assert_eq!(again.options.use_nla, settings.options.use_nla);
+        assert_eq!(again.options.verify_cert, settings.options.verify_cert);
+        assert!(matches!(again.screen_size, ScreenSize::Fixed(1280, 800)));
+
+        // IPv6 with a port, all drives
+        let mut file = parse("full address:s:[fe80::1]:4000\ndrivestoredirect:s:*\n")?;
+        assert_eq!(file.settings.redirections.drives, vec!["all"]);
+        file.settings.rail = None;
+        let text = serialize(&file);
+        assert!(text.contains("full address:s:[fe80::1]:4000\r\n"));
+        assert!(text.contains("drivestoredirect:s:*\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode() -> Result<()> {
+        let text = "full address:s:host\r\n";
+        let mut utf16 = vec![0xFF, 0xFE];
+        utf16.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(decode(&utf16)?, text);
+        let mut utf8 = vec![0xEF, 0xBB, 0xBF];
+        utf8.extend_from_slice(text.as_bytes());
+        assert_eq!(decode(&utf8)?, text);
+        assert_eq!(decode(text.as_bytes())?, text);
+        assert!(decode(&[0xFF, 0xFE, 0x00, 0xD8]).is_err());
+        Ok(())
+    }
+}
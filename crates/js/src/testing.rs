@@ -0,0 +1,845 @@
+// BSD 3-Clause License
+// Copyright (c) 2026, Virtual Cable S.L.
+// All rights reserved.
+// Authors: Adolfo Gómez, dkmaster at dkmon dot com
+
+//! Test harness for transport scripts.
+//!
+//! Runs a script against mock runtime modules (`Utils`, `File`, `Process`, `Tasks`, `RDP`)
+//! that record every call and return scripted values, so scripts can be checked without
+//! launching anything. `Logger` and `Debug` are the real ones.
+//!
+//! Rust tests use `MockRuntime` and the assertions of `MockReport`. CI uses `TestCase`,
+//! a JSON description of the mocks and the expected calls.
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use boa_engine::{
+    Context, JsResult, JsString, JsValue,
+    error::{JsError, JsNativeError},
+    native_function::NativeFunction,
+    object::{FunctionObjectBuilder, JsObject, builtins::JsPromise},
+    property::{Attribute, PropertyDescriptorBuilder},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use shared::log;
+
+use crate::{build_runtime_context, debug, exec_script, js_modules};
+
+/// Port returned by `Tasks.startTunnel` when neither the test nor the script sets one
+pub const DEFAULT_TUNNEL_PORT: u16 = 44000;
+
+// First handle returned by Process.launch, incremented on every launch
+const FIRST_PROCESS_ID: u32 = 1000;
+
+// (name, length, async) of every mocked function, per module
+type MockedModule = (&'static str, &'static [(&'static str, usize, bool)]);
+
+const MOCKED_MODULES: &[MockedModule] = &[
+    (
+        "Utils",
+        &[
+            ("expandVars", 1, false),
+            ("cryptProtectData", 1, false),
+            ("writeHkcu", 3, false),
+            ("writeHkcuDword", 3, false),
+            ("readHkcu", 2, false),
+            ("readHklm", 2, false),
+            ("testServer", 3, true),
+            ("sleep", 1, true),
+        ],
+    ),
+    (
+        "File",
+        &[
+            ("createTempFile", 3, false),
+            ("read", 1, false),
+            ("write", 2, false),
+            ("exists", 1, false),
+            ("isExecutable", 1, false),
+            ("isDirectory", 1, false),
+            ("getTempDirectory", 0, false),
+            ("getHomeDirectory", 0, false),
+            ("listFolder", 1, false),
+            ("chdir", 1, false),
+            ("getCwd", 0, false),
+        ],
+    ),
+    (
+        "Process",
+        &[
+            ("findExecutable", 2, false),
+            ("launch", 2, false),
+            ("isRunning", 1, false),
+            ("kill", 1, false),
+            ("launchAndWait", 3, true),
+            ("wait", 1, true),
+            ("waitTimeout", 2, true),
+            ("sleep", 1, true),
+        ],
+    ),
+    (
+        "Tasks",
+        &[
+            ("addEarlyUnlinkableFile", 1, false),
+            ("addLateUnlinkableFile", 1, false),
+            ("addWaitableApp", 1, false),
+            ("startTunnel", 8, true),
+        ],
+    ),
+    (
+        "RDP",
+        &[
+            ("start", 1, false),
+            ("fromRdpFile", 1, false),
+            ("toRdpFile", 1, false),
+            ("unblockSmartcard", 0, false),
+            ("sign", 2, true),
+            ("issueSmartcard", 2, true),
+            ("webcams", 0, true),
+        ],
+    ),
+];
+
+/// A runtime call made by the script, as `Module.function` plus its json arguments
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MockCall {
+    pub call: String,
+    pub args: Vec<Value>,
+}
+
+/// Scripted behavior of a mocked function.
+///
+/// Values in `returns` are used one per call, the last one is kept for the rest of calls.
+/// If `error` is set, the function throws it instead.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MockSpec {
+    #[serde(default)]
+    pub returns: VecDeque<Value>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct MockState {
+    specs: HashMap<String, MockSpec>,
+    calls: Vec<MockCall>,
+    tunnel_port: u16,
+    next_process_id: u32,
+}
+
+// Contexts are not Send, so a script always runs on the thread that created it.
+// Keeping the state per thread allows tests to run in parallel.
+thread_local! {
+    static STATE: RefCell<Option<MockState>> = const { RefCell::new(None) };
+}
+
+/// Builder for a mocked run of a script
+#[derive(Default)]
+pub struct MockRuntime {
+    specs: HashMap<String, MockSpec>,
+    tunnel_port: Option<u16>,
+    libraries: Vec<(String, String)>,
+}
+
+impl MockRuntime {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a return value for `call` (i.e. "File.exists"). Values are returned in order.
+    pub fn returns(mut self, call: &str, value: Value) -> Self {
+        self.specs
+            .entry(call.to_string())
+            .or_default()
+            .returns
+            .push_back(value);
+        self
+    }
+
+    /// Makes `call` throw an error with the given message
+    pub fn fails(mut self, call: &str, message: &str) -> Self {
+        self.specs.entry(call.to_string()).or_default().error = Some(message.to_string());
+        self
+    }
+
+    /// Port returned by `Tasks.startTunnel` if the script does not request a `local_port`
+    pub fn tunnel_port(mut self, port: u16) -> Self {
+        self.tunnel_port = Some(port);
+        self
+    }
+
+    /// Makes a library module importable by name (signatures are not checked here)
+    pub fn library(mut self, name: &str, code: &str) -> Self {
+        self.libraries.push((name.to_string(), code.to_string()));
+        self
+    }
+
+    /// Runs the script (as a module, like the launcher does) and returns the recorded calls.
+    /// Script errors are not an Err here, they are stored on the report.
+    pub async fn run(self, script: &str, data: Option<Value>) -> Result<MockReport> {
+        STATE.with_borrow_mut(|state| {
+            *state = Some(MockState {
+                specs: self.specs,
+                calls: Vec::new(),
+                tunnel_port: self.tunnel_port.unwrap_or(DEFAULT_TUNNEL_PORT),
+                next_process_id: FIRST_PROCESS_ID,
+            })
+        });
+
+        let result = match build_runtime_context(data, register_mocks, &self.libraries) {
+            Ok(mut ctx) => exec_script(&mut ctx, script).await,
+            Err(e) => Err(e),
+        };
+
+        let state = STATE
+            .with_borrow_mut(|state| state.take())
+            .unwrap_or_default();
+        Ok(MockReport {
+            calls: state.calls,
+            error: result.err().map(|e| e.to_string()),
+        })
+    }
+}
+
+fn mock_error(message: &str) -> JsError {
+    JsError::from_native(JsNativeError::error().with_message(message.to_string()))
+}
+
+// Value returned when the test did not script one. Mimics a "happy" system.
+fn default_return(state: &mut MockState, call: &str, args: &[Value]) -> Option<Value> {
+    let first_arg = || args.first().cloned().unwrap_or(Value::Null);
+    let value = match call {
+        "Utils.expandVars" | "Utils.cryptProtectData" | "RDP.sign" => first_arg(),
+        "Utils.readHklm" | "File.read" | "RDP.toRdpFile" => json!(""),
+        "RDP.fromRdpFile" => json!({}),
+        "Utils.testServer" | "File.write" | "File.isExecutable" | "File.chdir" => json!(true),
+        "Process.waitTimeout" => json!(true),
+        "File.exists" | "File.isDirectory" | "Process.isRunning" => json!(false),
+        "File.createTempFile" => json!(
+            std::env::temp_dir()
+                .join("uds-mock-temp-file")
+                .to_string_lossy()
+        ),
+        "File.getTempDirectory" => json!(std::env::temp_dir().to_string_lossy()),
+        "File.getHomeDirectory" => {
+            json!(std::env::home_dir().unwrap_or_default().to_string_lossy())
+        }
+        "File.getCwd" => json!(
+            std::env::current_dir()
+                .unwrap_or_default()
+                .to_string_lossy()
+        ),
+        "File.listFolder" => json!([]),
+        "Process.findExecutable" => Value::Null,
+        "Process.launch" => {
+            state.next_process_id += 1;
+            json!(state.next_process_id - 1)
+        }
+        "Process.launchAndWait" => json!({ "stdout": "", "stderr": "" }),
+        "Tasks.startTunnel" => {
+            let port = first_arg()
+                .get("local_port")
+                .and_then(Value::as_u64)
+                .unwrap_or(state.tunnel_port as u64);
+            json!({ "port": port })
+        }
+        _ => return None, // undefined
+    };
+    Some(value)
+}
+
+fn mock_call(
+    module: &str,
+    function: &str,
+    is_async: bool,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let call = format!("{}.{}", module, function);
+    let args = match debug::args_to_json(args, ctx) {
+        Value::Array(args) => args,
+        _ => Vec::new(),
+    };
+    log::debug!("[mock] {}({:?})", call, args);
+
+    let outcome = STATE.with_borrow_mut(|state| {
+        let Some(state) = state.as_mut() else {
+            return Err(format!("{} called without an active mock runtime", call));
+        };
+        state.calls.push(MockCall {
+            call: call.clone(),
+            args: args.clone(),
+        });
+        match state.specs.get_mut(&call) {
+            Some(MockSpec {
+                error: Some(error), ..
+            }) => Err(error.clone()),
+            Some(spec) if !spec.returns.is_empty() => {
+                if spec.returns.len() > 1 {
+                    Ok(spec.returns.pop_front())
+                } else {
+                    Ok(spec.returns.front().cloned())
+                }
+            }
+            _ => Ok(default_return(state, &call, &args)),
+        }
+    });
+
+    let value = match outcome.map_err(|e| mock_error(&e))? {
+        Some(value) => JsValue::from_json(&value, ctx)?,
+        None => JsValue::undefined(),
+    };
+
+    if is_async {
+        Ok(JsPromise::resolve(value, ctx).into())
+    } else {
+        Ok(value)
+    }
+}
+
+fn register_mocks(ctx: &mut Context) -> Result<()> {
+    let realm = ctx.realm().clone();
+    for &(module, functions) in MOCKED_MODULES {
+        let obj = JsObject::with_null_proto();
+        for &(name, length, is_async) in functions.iter() {
+            let function = NativeFunction::from_copy_closure(
+                move |_: &JsValue, args: &[JsValue], ctx: &mut Context| {
+                    mock_call(module, name, is_async, args, ctx)
+                },
+            );
+            let fn_obj = FunctionObjectBuilder::new(&realm, function)
+                .name(JsString::from(name))
+                .length(length)
+                .build();
+            obj.insert_property(
+                JsString::from(name),
+                PropertyDescriptorBuilder::new()
+                    .value(fn_obj)
+                    .writable(false)
+                    .enumerable(false)
+                    .configurable(false),
+            );
+        }
+        ctx.register_global_property(JsString::from(module), obj, Attribute::all())
+            .map_err(|e| anyhow::anyhow!("Failed to register mock {} object: {}", module, e))?;
+    }
+    js_modules::register_logger(ctx)?;
+    debug::register(ctx)?;
+    Ok(())
+}
+
+// Expected values only need to be a subset of the actual ones: object keys not in
+// `expected` are ignored, arrays must have the same length.
+fn json_matches(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => expected
+            .iter()
+            .all(|(k, v)| actual.get(k).is_some_and(|a| json_matches(v, a))),
+        (Value::Array(expected), Value::Array(actual)) => {
+            expected.len() == actual.len()
+                && expected
+                    .iter()
+                    .zip(actual.iter())
+                    .all(|(e, a)| json_matches(e, a))
+        }
+        _ => expected == actual,
+    }
+}
+
+impl MockCall {
+    /// True if this is `call` and its first arguments match `args` (see `json_matches`)
+    pub fn matches(&self, call: &str, args: Option<&[Value]>) -> bool {
+        self.call == call
+            && args.is_none_or(|args| {
+                args.len() <= self.args.len()
+                    && args
+                        .iter()
+                        .zip(self.args.iter())
+                        .all(|(e, a)| json_matches(e, a))
+            })
+    }
+}
+
+/// Result of a mocked run
+#[derive(Debug, Clone, Serialize)]
+pub struct MockReport {
+    pub calls: Vec<MockCall>,
+    /// Script error, if the script failed
+    pub error: Option<String>,
+}
+
+impl MockReport {
+    pub fn calls_to(&self, call: &str) -> Vec<&MockCall> {
+        self.calls.iter().filter(|c| c.call == call).collect()
+    }
+
+    pub fn assert_success(&self) -> &Self {
+        if let Some(error) = &self.error {
+            panic!("Script failed: {}", error);
+        }
+        self
+    }
+
+    pub fn assert_error_contains(&self, text: &str) -> &Self {
+        match &self.error {
+            Some(error) if error.contains(text) => self,
+            Some(error) => panic!("Script error '{}' does not contain '{}'", error, text),
+            None => panic!("Script succeeded, expected error containing '{}'", text),
+        }
+    }
+
+    /// Returns the first call to `call`
+    pub fn assert_called(&self, call: &str) -> &MockCall {
+        self.calls
+            .iter()
+            .find(|c| c.call == call)
+            .unwrap_or_else(|| panic!("{} was not called. Calls: {:?}", call, self.call_names()))
+    }
+
+    /// Returns the first call to `call` whose first arguments match `args`
+    pub fn assert_called_with(&self, call: &str, args: Value) -> &MockCall {
+        let args = match args {
+            Value::Array(args) => args,
+            other => vec![other],
+        };
+        self.calls
+            .iter()
+            .find(|c| c.matches(call, Some(&args)))
+            .unwrap_or_else(|| {
+                panic!(
+                    "{} was not called with {:?}. Calls to it: {:?}",
+                    call,
+                    args,
+                    self.calls_to(call)
+                )
+            })
+    }
+
+    pub fn assert_not_called(&self, call: &str) -> &Self {
+        let calls = self.calls_to(call);
+        if !calls.is_empty() {
+            panic!("{} was called {} time(s): {:?}", call, calls.len(), calls);
+        }
+        self
+    }
+
+    pub fn assert_call_count(&self, call: &str, count: usize) -> &Self {
+        let actual = self.calls_to(call).len();
+        if actual != count {
+            panic!("{} was called {} time(s), expected {}", call, actual, count);
+        }
+        self
+    }
+
+    /// Checks that the calls happened in this order (other calls may be in between)
+    pub fn assert_calls_in_order(&self, calls: &[&str]) -> &Self {
+        let expected: Vec<ExpectedCall> = calls
+            .iter()
+            .map(|c| ExpectedCall {
+                call: c.to_string(),
+                args: None,
+            })
+            .collect();
+        if let Err(e) = self.check_order(&expected) {
+            panic!("{}", e);
+        }
+        self
+    }
+
+    fn call_names(&self) -> Vec<&str> {
+        self.calls.iter().map(|c| c.call.as_str()).collect()
+    }
+
+    fn check_order(&self, expected: &[ExpectedCall]) -> Result<(), String> {
+        let mut pending = self.calls.iter();
+        for exp in expected {
+            if !pending.any(|c| c.matches(&exp.call, exp.args.as_deref())) {
+                return Err(format!(
+                    "Expected call {}{} not found (in order). Calls: {:?}",
+                    exp.call,
+                    exp.args
+                        .as_ref()
+                        .map(|a| format!(" with {:?}", a))
+                        .unwrap_or_default(),
+                    self.call_names()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the report against the expectations of a test case, returning all failures
+    pub fn check(&self, expect: &Expectations) -> Vec<String> {
+        let mut failures = Vec::new();
+        match (&expect.error, &self.error) {
+            (None, Some(error)) => failures.push(format!("Script failed: {}", error)),
+            (Some(text), None) => {
+                failures.push(format!("Script succeeded, expected error '{}'", text))
+            }
+            (Some(text), Some(error)) if !error.contains(text.as_str()) => failures.push(format!(
+                "Script error '{}' does not contain '{}'",
+                error, text
+            )),
+            _ => {}
+        }
+        if let Err(e) = self.check_order(&expect.calls) {
+            failures.push(e);
+        }
+        for call in &expect.not_called {
+            let count = self.calls_to(call).len();
+            if count > 0 {
+                failures.push(format!("{} was called {} time(s)", call, count));
+            }
+        }
+        failures
+    }
+}
+
+/// Expected call on a test case. `args` only needs to match the first arguments.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExpectedCall {
+    pub call: String,
+    #[serde(default)]
+    pub args: Option<Vec<Value>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Expectations {
+    /// Calls that must happen, in this order
+    #[serde(default)]
+    pub calls: Vec<ExpectedCall>,
+    #[serde(default)]
+    pub not_called: Vec<String>,
+    /// If set, the script must fail with an error containing this text
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// JSON test case for a transport script. See doc/js-runtime.md for the format.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TestCase {
+    pub name: String,
+    /// Inline script source
+    #[serde(default)]
+    pub script: Option<String>,
+    /// Script path, relative to the test case file
+    #[serde(default)]
+    pub script_file: Option<PathBuf>,
+    #[serde(default)]
+    pub data: Option<Value>,
+    #[serde(default)]
+    pub mocks: HashMap<String, MockSpec>,
+    #[serde(default)]
+    pub tunnel_port: Option<u16>,
+    #[serde(default)]
+    pub expect: Expectations,
+    /// Library modules, as name -> path relative to the test case file
+    #[serde(default)]
+    pub libraries: HashMap<String, PathBuf>,
+    #[serde(skip)]
+    base_dir: PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(Box<TestCase>),
+    Many(Vec<TestCase>),
+}
+
+impl TestCase {
+    /// Parses a test case, or a list of them
+    pub fn from_json(json: &str) -> Result<Vec<TestCase>> {
+        let cases = match serde_json::from_str::<OneOrMany>(json)? {
+            OneOrMany::One(case) => vec![*case],
+            OneOrMany::Many(cases) => cases,
+        };
+        Ok(cases)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Vec<TestCase>> {
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Error reading test case {}: {}", path.display(), e))?;
+        let base_dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        Ok(Self::from_json(&json)?
+            .into_iter()
+            .map(|case| TestCase {
+                base_dir: base_dir.clone(),
+                ..case
+            })
+            .collect())
+    }
+
+    fn script_source(&self) -> Result<String> {
+        match (&self.script, &self.script_file) {
+            (Some(script), _) => Ok(script.clone()),
+            (None, Some(file)) => {
+                let path = self.base_dir.join(file);
+                std::fs::read_to_string(&path)
+                    .map_err(|e| anyhow::anyhow!("Error reading {}: {}", path.display(), e))
+            }
+            (None, None) => Err(anyhow::anyhow!(
+                "Test case '{}' has no script nor script_file",
+                self.name
+            )),
+        }
+    }
+
+    /// Runs the case. Fails with every unmet expectation.
+    pub async fn run(&self) -> Result<MockReport> {
+        let script = self.script_source()?;
+        let libraries = self
+            .libraries
+            .iter()
+            .map(|(name, file)| {
+                let path = self.base_dir.join(file);
+                std::fs::read_to_string(&path)
+                    .map(|code| (name.clone(), code))
+                    .map_err(|e| anyhow::anyhow!("Error reading {}: {}", path.display(), e))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let runtime = MockRuntime {
+            specs: self.mocks.clone(),
+            tunnel_port: self.tunnel_port,
+            libraries,
+        };
+        let report = runtime.run(&script, self.data.clone()).await?;
+        let failures = report.check(&self.expect);
+        if failures.is_empty() {
+            Ok(report)
+        } else {
+            Err(anyhow::anyhow!(
+                "Test case '{}' failed:\n  {}",
+                self.name,
+                failures.join("\n  ")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = r#"
+        import { Process, File, Tasks, RDP, Logger } from "runtime";
+
+        const exe = Process.findExecutable("xfreerdp");
+        if (!exe) {
+            throw new Error("xfreerdp not found");
+        }
+        const tunnel = await Tasks.startTunnel({ addr: data.tunnel.host, port: 443, ticket: data.ticket });
+        Logger.info("Tunnel on " + tunnel.port);
+        if (File.exists("/etc/uds-use-internal")) {
+            RDP.start({ server: "127.0.0.1", port: tunnel.port, user: data.user });
+        } else {
+            const pid = Process.launch(exe, ["/v:127.0.0.1:" + tunnel.port, "/u:" + data.user]);
+            Tasks.addWaitableApp(pid);
+        }
+    "#;
+
+    fn data() -> Value {
+        json!({ "tunnel": { "host": "tunnel.example.com" }, "ticket": "T".repeat(48), "user": "john" })
+    }
+
+    #[tokio::test]
+    async fn test_mocked_launch() -> Result<()> {
+        log::setup_logging("debug", log::LogType::Test);
+        let report = MockRuntime::new()
+            .returns("Process.findExecutable", json!("/usr/bin/xfreerdp"))
+            .tunnel_port(40000)
+            .run(SCRIPT, Some(data()))
+            .await?;
+
+        report
+            .assert_success()
+            .assert_calls_in_order(&[
+                "Process.findExecutable",
+                "Tasks.startTunnel",
+                "Process.launch",
+                "Tasks.addWaitableApp",
+            ])
+            .assert_not_called("RDP.start");
+        report.assert_called_with(
+            "Tasks.startTunnel",
+            json!([{ "addr": "tunnel.example.com", "port": 443 }]),
+        );
+        report.assert_called_with(
+            "Process.launch",
+            json!(["/usr/bin/xfreerdp", ["/v:127.0.0.1:40000", "/u:john"]]),
+        );
+        report.assert_called_with("Tasks.addWaitableApp", json!([FIRST_PROCESS_ID]));
+        // Logger is not mocked
+        assert!(report.calls_to("Logger.info").is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mocked_sequence_and_errors() -> Result<()> {
+        log::setup_logging("debug", log::LogType::Test);
+        let report = MockRuntime::new()
+            .returns("Process.findExecutable", json!("/usr/bin/xfreerdp"))
+            .returns("File.exists", json!(true))
+            .run(SCRIPT, Some(data()))
+            .await?;
+        report
+            .assert_success()
+            .assert_call_count("RDP.start", 1)
+            .assert_not_called("Process.launch");
+        report.assert_called_with(
+            "RDP.start",
+            json!({ "server": "127.0.0.1", "port": DEFAULT_TUNNEL_PORT }),
+        );
+
+        let report = MockRuntime::new().run(SCRIPT, Some(data())).await?;
+        report
+            .assert_error_contains("xfreerdp not found")
+            .assert_not_called("Tasks.startTunnel");
+
+        let report = MockRuntime::new()
+            .returns("Process.findExecutable", json!("/usr/bin/xfreerdp"))
+            .fails("Tasks.startTunnel", "tunnel refused")
+            .run(SCRIPT, Some(data()))
+            .await?;
+        report.assert_error_contains("tunnel refused");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_returns_in_order() -> Result<()> {
+        log::setup_logging("debug", log::LogType::Test);
+        let report = MockRuntime::new()
+            .returns("File.exists", json!(true))
+            .returns("File.exists", json!(false))
+            .run(
+                r#"
+                import { File, Logger } from "runtime";
+                const results = [File.exists("a"), File.exists("b"), File.exists("c")];
+                if (results.join(",") !== "true,false,false") {
+                    throw new Error("Unexpected " + results);
+                }
+                "#,
+                None,
+            )
+            .await?;
+        report.assert_success().assert_call_count("File.exists", 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mocked_library() -> Result<()> {
+        log::setup_logging("debug", log::LogType::Test);
+        let report = MockRuntime::new()
+            .library(
+                "uds/clients",
+                r#"
+                import { Process } from "runtime";
+                export function findClient(names) {
+                    return names.map((n) => Process.findExecutable(n)).find((p) => p) || null;
+                }
+                "#,
+            )
+            .returns("Process.findExecutable", json!(null))
+            .returns("Process.findExecutable", json!("/usr/bin/remmina"))
+            .run(
+                r#"
+                import { findClient } from "uds/clients";
+                import { Process } from "runtime";
+                Process.launch(findClient(["xfreerdp", "remmina"]), []);
+                "#,
+                None,
+            )
+            .await?;
+        report
+            .assert_success()
+            .assert_call_count("Process.findExecutable", 2)
+            .assert_called_with("Process.launch", json!(["/usr/bin/remmina"]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_json_case() -> Result<()> {
+        log::setup_logging("debug", log::LogType::Test);
+        let json = json!([
+            {
+                "name": "launches xfreerdp",
+                "script": SCRIPT,
+                "data": data(),
+                "mocks": { "Process.findExecutable": { "returns": ["/usr/bin/xfreerdp"] } },
+                "tunnel_port": 40001,
+                "expect": {
+                    "calls": [
+                        { "call": "Tasks.startTunnel" },
+                        { "call": "Process.launch", "args": ["/usr/bin/xfreerdp", ["/v:127.0.0.1:40001", "/u:john"]] }
+                    ],
+                    "not_called": ["RDP.start"]
+                }
+            },
+            {
+                "name": "fails without xfreerdp",
+                "script": SCRIPT,
+                "data": data(),
+                "expect": { "error": "not found", "not_called": ["Process.launch"] }
+            },
+            {
+                "name": "wrong expectation",
+                "script": SCRIPT,
+                "data": data(),
+                "expect": { "calls": [{ "call": "RDP.start" }] }
+            }
+        ]);
+        let cases = TestCase::from_json(&json.to_string())?;
+        assert_eq!(cases.len(), 3);
+        cases[0].run().await?;
+        cases[1].run().await?;
+        let err = cases[2].run().await.unwrap_err().to_string();
+        assert!(err.contains("Script failed"), "{}", err);
+        assert!(err.contains("RDP.start"), "{}", err);
+        Ok(())
+    }
+
+    // The mocks are a hand-kept copy of the runtime modules, so they must not drift
+    #[test]
+    fn test_mocks_match_modules() -> Result<()> {
+        let mut ctx = crate::create_context(None)?;
+        js_modules::register(&mut ctx)?;
+        for &(module, functions) in MOCKED_MODULES {
+            let script = format!(
+                "Object.getOwnPropertyNames({module}).filter((n) => typeof {module}[n] === 'function').map((n) => [n, {module}[n].length])"
+            );
+            let real = ctx
+                .eval(boa_engine::Source::from_bytes(script.as_bytes()))
+                .and_then(|v| v.to_json(&mut ctx))
+                .map_err(|e| anyhow::anyhow!("Failed to list {} functions: {}", module, e))?;
+            let mut real: Vec<(String, usize)> = serde_json::from_value(real.into())?;
+            let mut mocked: Vec<(String, usize)> = functions
+                .iter()
+                .map(|&(name, length, _)| (name.to_string(), length))
+                .collect();
+            real.sort();
+            mocked.sort();
+            assert_eq!(mocked, real, "mocked {} differs from the real one", module);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_json_matches() {
+        assert!(json_matches(&json!({"a": 1}), &json!({"a": 1, "b": 2})));
+        assert!(!json_matches(
+            &json!({"a": 1, "c": 3}),
+            &json!({"a": 1, "b": 2})
+        ));
+        assert!(json_matches(&json!([{"a": 1}]), &json!([{"a": 1, "b": 2}])));
+        assert!(!json_matches(&json!([1]), &json!([1, 2])));
+        assert!(!json_matches(&json!("1"), &json!(1)));
+    }
+}
@@ -4,6 +4,8 @@
 // Authors: Adolfo Gómez, dkmaster at dkmon dot com
 
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+use std::sync::{Arc, RwLock};
+
 use flume::{Receiver, Sender, bounded};
 
 use shared::{log, system::trigger::Trigger};
@@ -25,7 +27,22 @@ fn parse_udssv2_url(raw: &str) -> Option<(String, String, String)> {
     Some((host.to_string(), ticket.to_string(), scrambler.to_string()))
 }
 
-fn collect_arguments() -> Option<(String, String, String)> {
+/// What the launcher was started for
+enum Launch {
+    /// udssv2://host/ticket/scrambler
+    Broker(String, String, String),
+    /// A Microsoft `.rdp` file, connected without broker
+    RdpFile(String),
+}
+
+fn parse_argument(arg: &str) -> Option<Launch> {
+    if arg.to_lowercase().ends_with(".rdp") {
+        return Some(Launch::RdpFile(arg.to_string()));
+    }
+    parse_udssv2_url(arg).map(|(host, ticket, scrambler)| Launch::Broker(host, ticket, scrambler))
+}
+
+fn collect_arguments() -> Option<Launch> {
     let args: Vec<String> = std::env::args().collect();
 
     // For debugging purposes, allow setting args via env variable
@@ -36,11 +53,93 @@ fn collect_arguments() -> Option<(String, String, String)> {
         args
     };
 
-    // Should have only 1 argument, "udssv2://host/ticket/scrambler"
+    // Should have only 1 argument, "udssv2://host/ticket/scrambler" or a .rdp file
     if args.len() != 2 {
         return None;
     }
-    parse_udssv2_url(&args[1])
+    parse_argument(&args[1])
+}
+
+/// Question shown before connecting a `.rdp` file. Its signature is not verified,
+/// so the user is told where the file connects and what it redirects.
+fn rdp_file_question(file: &js::rdp_file::RdpFile) -> String {
+    let settings = &file.settings;
+    let redirections = &settings.redirections;
+
+    let mut redirected = Vec::new();
+    if redirections.clipboard {
+        redirected.push(tr!("Clipboard"));
+    }
+    if redirections.audio {
+        redirected.push(tr!("Audio"));
+    }
+    if redirections.mic {
+        redirected.push(tr!("Microphone"));
+    }
+    if redirections.printing {
+        redirected.push(tr!("Printers"));
+    }
+    if redirections.smartcard.enabled {
+        redirected.push(tr!("Smartcards"));
+    }
+    if !redirections.drives.is_empty() {
+        redirected.push(tr!("Drives: {}", redirections.drives.join(", ")));
+    }
+    if redirections.webcam.as_ref().is_some_and(|w| w.enabled) {
+        redirected.push(tr!("Webcam"));
+    }
+
+    let mut text = if file.signature.is_some() {
+        tr!("This RDP file is signed, but its signature cannot be verified.")
+    } else {
+        tr!("This RDP file is not signed.")
+    };
+    text.push('\n');
+    text.push_str(&tr!("Server: {}:{}", settings.server, settings.port));
+    if let Some(rail) = &settings.rail {
+        text.push('\n');
+        text.push_str(&tr!("Application: {}", rail.app));
+    }
+    text.push('\n');
+    if redirected.is_empty() {
+        text.push_str(&tr!("Redirections: none"));
+    } else {
+        text.push_str(&tr!("Redirections: {}", redirected.join(", ")));
+    }
+    text.push('\n');
+    text.push_str(&tr!(
+        "Only connect to servers you trust.\nDo you want to continue?"
+    ));
+    text
+}
+
+fn connect_rdp_file(tx: &Sender<gui::types::GuiMessage>, path: &str) {
+    let file = match js::rdp_file::read(path) {
+        Ok(file) => file,
+        Err(e) => {
+            log::error!("{}", e);
+            tx.send(gui::types::GuiMessage::ShowError(e.to_string()))
+                .ok();
+            return;
+        }
+    };
+
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    tx.send(gui::types::GuiMessage::ShowYesNo(
+        rdp_file_question(&file),
+        Arc::new(RwLock::new(Some(reply_tx))),
+    ))
+    .ok();
+    if reply_rx.blocking_recv().unwrap_or(false) {
+        tx.send(gui::types::GuiMessage::ConnectRdp(
+            Box::new(file.settings),
+            Box::default(),
+        ))
+        .ok();
+    } else {
+        log::info!("RDP file {} not approved by user.", path);
+        tx.send(gui::types::GuiMessage::Close).ok();
+    }
 }
 
 fn main() {
@@ -57,19 +156,12 @@ fn main() {
 
     // Setup tls, with default secure ciphers
     shared::tls::init_tls(None);
-    let (host, ticket, scrambler) = collect_arguments().unwrap_or_else(|| {
+    let launch = collect_arguments().unwrap_or_else(|| {
         // Show about window if no valid arguments
         gui::windows::about::show_about_window();
         std::process::exit(0);
     });
 
-    log::debug!(
-        "Host: {}, Ticket: {}, Scrambler: {}",
-        host,
-        ticket,
-        scrambler
-    );
-
     let stop = Trigger::new();
     let (messages_tx, messages_rx): (
         Sender<gui::types::GuiMessage>,
@@ -78,8 +170,26 @@ fn main() {
 
     js::gui::set_sender(messages_tx.clone());
 
-    // Launch async thread with tokio runtime
-    asyncthread::run(messages_tx, stop.clone(), host, ticket, scrambler);
+    match launch {
+        Launch::Broker(host, ticket, scrambler) => {
+            log::debug!(
+                "Host: {}, Ticket: {}, Scrambler: {}",
+                host,
+                ticket,
+                scrambler
+            );
+            // Launch async thread with tokio runtime
+            asyncthread::run(messages_tx, stop.clone(), host, ticket, scrambler);
+        }
+        Launch::RdpFile(path) => {
+            log::debug!("RDP file: {}", path);
+            // Run on its own thread, the confirmation waits for the GUI started below
+            std::thread::spawn({
+                let tx = messages_tx.clone();
+                move || connect_rdp_file(&tx, &path)
+            });
+        }
+    }
 
     // Read app data, which may contain overrides for proxy and ssl settings, and fps limit
     let app_data = shared::appdata::AppData::load();
@@ -149,4 +259,28 @@ mod tests {
     fn host_only() {
         assert!(parse_udssv2_url("udssv2://host").is_none());
     }
+
+    #[test]
+    fn rdp_file() {
+        assert!(matches!(
+            parse_argument("C:\\Users\\me\\Desktop\\Work.RDP"),
+            Some(Launch::RdpFile(path)) if path == "C:\\Users\\me\\Desktop\\Work.RDP"
+        ));
+        let url = format!("udssv2://host/{}/scrambler", valid_ticket());
+        assert!(matches!(parse_argument(&url), Some(Launch::Broker(..))));
+        assert!(parse_argument("/tmp/notes.txt").is_none());
+    }
+
+    #[test]
+    fn rdp_file_question_lists_host_and_redirections() {
+        let file = js::rdp_file::parse(
+            "full address:s:rdp.example.com:3390\nredirectclipboard:i:1\ndrivestoredirect:s:C:;D:\n",
+        )
+        .unwrap();
+        let text = rdp_file_question(&file);
+        assert!(text.contains("not signed"));
+        assert!(text.contains("rdp.example.com:3390"));
+        assert!(text.contains("Clipboard"));
+        assert!(text.contains("Drives: C, D"));
+    }
 }
@@ -13,25 +13,87 @@ use flume::{Receiver, Sender, bounded};
 use connection::broker::api::types;
 use shared::{log, system::trigger::Trigger};
 
+const USAGE: &str =
+    "Usage: script-tester [--trace] [--dry-run] [--repl] [--lib=name=path...] [script.js data.json]
+       script-tester --test case.json [case.json...]
+
+  --trace    Log every runtime call (Process, File, Tasks, RDP...) with redacted arguments
+  --dry-run  Do not launch apps, tunnels or RDP sessions, print what would have been done
+  --repl     Stop on Debug.breakpoint() and open a console when the script ends
+  --lib      Make a local file importable as a library module (not signature checked)
+  --test     Run JSON test cases against mocked runtime modules (exit code 1 on failures)";
+
+struct CliArgs {
+    script_path: String,
+    params_path: String,
+    debug: js::debug::DebugOptions,
+    test_cases: Vec<String>,
+    libraries: Vec<(String, String)>, // name, code
+}
+
+fn parse_args() -> Result<CliArgs> {
+    let mut debug = js::debug::DebugOptions::default();
+    let mut positional: Vec<String> = Vec::new();
+    let mut test_mode = false;
+    let mut libraries = Vec::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--trace" => debug.trace = true,
+            "--dry-run" => debug.dry_run = true,
+            "--repl" => debug.interactive = true,
+            "--test" => test_mode = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ if arg.starts_with("--lib=") => {
+                let (name, path) = arg["--lib=".len()..]
+                    .split_once('=')
+                    .ok_or_else(|| anyhow::anyhow!("--lib expects name=path\n{}", USAGE))?;
+                let code = std::fs::read_to_string(path)
+                    .map_err(|e| anyhow::anyhow!("Error reading library {}: {}", path, e))?;
+                libraries.push((name.to_string(), code));
+            }
+            _ if arg.starts_with("--") => {
+                return Err(anyhow::anyhow!("Unknown option {}\n{}", arg, USAGE));
+            }
+            _ => positional.push(arg),
+        }
+    }
+    if test_mode {
+        if positional.is_empty() {
+            return Err(anyhow::anyhow!(
+                "--test needs at least one case file\n{}",
+                USAGE
+            ));
+        }
+        return Ok(CliArgs {
+            script_path: String::new(),
+            params_path: String::new(),
+            debug,
+            test_cases: positional,
+            libraries,
+        });
+    }
+    // Both or none, as before
+    if positional.len() < 2 {
+        positional = vec![
+            "crates/script-tester/testdata/script.js".to_string(),
+            "crates/script-tester/testdata/data.json".to_string(),
+        ];
+    }
+    Ok(CliArgs {
+        script_path: positional[0].clone(),
+        params_path: positional[1].clone(),
+        debug,
+        test_cases: Vec::new(),
+        libraries,
+    })
+}
+
 // Get script and json with params from args
 // The signature file is the script file + mldsa65.sig
-async fn get_script_and_params() -> Result<types::Script> {
-    let args: Vec<String> = std::env::args().collect();
-    let args = if args.len() < 3 {
-        [
-            "not_used",
-            "crates/script-tester/testdata/script.js",
-            "crates/script-tester/testdata/data.json",
-        ]
-        .iter()
-        .map(|s| s.to_string())
-        .collect()
-    } else {
-        args
-    };
-    let script_path = &args[1];
-    let params_json_path = &args[2];
-
+async fn get_script_and_params(script_path: &str, params_json_path: &str) -> Result<types::Script> {
     // Script is bz2 + base64 encoded
     let script_bytes = std::fs::read(script_path)
         .map_err(|e| anyhow::anyhow!("Error reading script file: {}", e))?;
@@ -62,17 +124,28 @@ async fn get_script_and_params() -> Result<types::Script> {
             ticket: None,
         },
         shared_secret: None,
+        modules: Vec::new(),
     })
 }
 
-fn run_script() {
+// Runs the script and, on interactive mode, opens a console over the same context
+// once it finishes, so globals set by the script can be inspected
+async fn run_interactive(script: &types::Script, libraries: &[(String, String)]) -> Result<()> {
+    let mut ctx = js::create_runtime_context(Some(script.decoded_params()?), libraries)?;
+    if let Err(e) = js::exec_script(&mut ctx, &script.decoded_script()?).await {
+        println!("Script failed: {}", e);
+    }
+    js::debug::run_repl(&mut ctx).await
+}
+
+fn run_script(args: &CliArgs) {
     let rt = tokio::runtime::Builder::new_current_thread()
         .enable_all()
         .build()
         .unwrap();
 
     rt.block_on(async {
-        let script = match get_script_and_params().await {
+        let script = match get_script_and_params(&args.script_path, &args.params_path).await {
             Ok(s) => s,
             Err(e) => {
                 log::error!("Error getting script and params: {}", e);
@@ -80,11 +153,66 @@ fn run_script() {
             }
         };
 
-        match js::run_script(&script).await {
+        let result = if args.debug.interactive {
+            run_interactive(&script, &args.libraries).await
+        } else if !args.libraries.is_empty() {
+            match (script.decoded_script(), script.decoded_params()) {
+                (Ok(code), Ok(params)) => {
+                    js::run_js_with_libraries(&code, Some(params), &args.libraries).await
+                }
+                (Err(e), _) | (_, Err(e)) => Err(e),
+            }
+        } else {
+            js::run_script(&script).await
+        };
+        match result {
             Ok(_) => log::info!("Script executed successfully."),
             Err(e) => log::error!("Error executing script: {}", e),
         }
     });
+
+    if args.debug.dry_run {
+        let recorded = js::debug::take_recorded();
+        println!(
+            "Dry run, {} action(s) recorded:\n{}",
+            recorded.len(),
+            serde_json::to_string_pretty(&recorded).unwrap_or_default()
+        );
+    }
+}
+
+// Runs every test case, printing a line per case. Returns true if all passed.
+fn run_test_cases(files: &[String]) -> bool {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let (mut passed, mut failed) = (0, 0);
+    for file in files {
+        let cases = match js::testing::TestCase::from_file(std::path::Path::new(file)) {
+            Ok(cases) => cases,
+            Err(e) => {
+                println!("ERROR {}: {}", file, e);
+                failed += 1;
+                continue;
+            }
+        };
+        for case in cases {
+            match rt.block_on(case.run()) {
+                Ok(_) => {
+                    println!("ok    {}: {}", file, case.name);
+                    passed += 1;
+                }
+                Err(e) => {
+                    println!("FAIL  {}: {}", file, e);
+                    failed += 1;
+                }
+            }
+        }
+    }
+    println!("{} passed, {} failed", passed, failed);
+    failed == 0
 }
 
 fn main() -> Result<()> {
@@ -106,6 +234,22 @@ fn main() -> Result<()> {
     //     return Ok(());
     // }
 
+    let args = parse_args()?;
+    js::debug::set_options(args.debug);
+
+    if !args.test_cases.is_empty() {
+        if !run_test_cases(&args.test_cases) {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // Dry runs never open an RDP session, so no gui is needed
+    if args.debug.dry_run {
+        run_script(&args);
+        return Ok(());
+    }
+
     let fake_catalog = gettext::Catalog::empty(); // Empty catalog for now
     let (_messages_tx, messages_rx): (
         Sender<gui::types::GuiMessage>,
@@ -116,7 +260,7 @@ fn main() -> Result<()> {
     js::gui::set_sender(_messages_tx.clone());
 
     // Run the script on a thread
-    std::thread::spawn(run_script);
+    std::thread::spawn(move || run_script(&args));
 
     let app_data = shared::appdata::AppData::load();
 
@@ -3,6 +3,8 @@
 // All rights reserved.
 // Authors: Adolfo Gómez, dkmaster at dkmon dot com
 
+use std::collections::HashMap;
+
 use directories_next::ProjectDirs;
 use serde::{Deserialize, Serialize};
 
@@ -13,6 +15,20 @@ const APP_QUALIFIER: &str = "org";
 const APP_ORGANIZATION: &str = "openuds";
 const APP_APPLICATION: &str = "launcher";
 
+// Local audio volume chosen for a host
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HostVolume {
+    pub level: u32, // percent
+    pub muted: bool,
+}
+
+// Wrong PINs entered for an emulated smartcard certificate
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PinFailures {
+    pub count: u8,
+    pub last_failure: u64, // unix time, seconds
+}
+
 #[derive(Serialize, Deserialize, Default)]
 pub struct AppData {
     pub approved_hosts: Vec<String>,
@@ -21,6 +37,15 @@ pub struct AppData {
     pub disable_proxy: Option<bool>,
     pub verify_ssl: Option<bool>,
     pub fps_limit: Option<u32>,
+    // Audio devices by name (or index), "default" or missing follows the OS default
+    pub audio_output_device: Option<String>,
+    pub audio_input_device: Option<String>,
+    // Keyed by RDP server
+    #[serde(default)]
+    pub host_volumes: HashMap<String, HostVolume>,
+    // Keyed by certificate SHA-256 (hex); remove an entry to unblock its card
+    #[serde(default)]
+    pub smartcard_pin_failures: HashMap<String, PinFailures>,
     // On mac, also allow override launcher path
     #[cfg(target_os = "macos")]
     pub launcher_path: Option<String>,
@@ -70,6 +70,30 @@ declare module "runtime" {
       };
       screen_width?: number;
       screen_height?: number;
+      /** Key of what the client stores for the host (the local volume), the server if missing */
+      host_id?: string;
+      /** Local monitors the desktop spans, "all" or their indices */
+      monitors?: "all" | number[];
+      keyboard?: {
+        /** Default "fullscreen". Windows and X11 only, ignored on Wayland and macOS */
+        grab?: "fullscreen" | "focused" | "never";
+        /** Hotkey modifiers, "alt" by default */
+        escape?: string;
+        /** Key names by action, "none" disables one */
+        hotkeys?: {
+          fullscreen?: string;
+          fps?: string;
+          mute?: string;
+          volume_up?: string;
+          volume_down?: string;
+          camera?: string;
+          pause?: string;
+          preview?: string;
+          blur?: string;
+          ctrl_alt_del?: string;
+          exit?: string;
+        };
+      };
       clipboard_redirection?: boolean;
       audio_redirection?: boolean;
       microphone_redirection?: boolean;
@@ -77,16 +101,53 @@ declare module "runtime" {
       drives_to_redirect?: string[];
       smartcard?: {
         enabled?: boolean;
-        /** Emulated card spec: file:<path> | pem:<cert>,<key> | userdefined: */
+        /** Emulated card spec: file:<path> | pem:<cert>,<key> | userdefined:pkcs11,... | userdefined:http,... | ephemeral, several separated by `;` */
         emulated?: string;
+        /** PIN policy of the emulated card */
+        pin?: {
+          /** Enter the PIN in a launcher popup, checked on the client (default false) */
+          local?: boolean;
+          /** Wrong PINs before the card is blocked, 1 to 15 (default 3) */
+          max_retries?: number;
+          min_length?: number;
+          /** 0 for no limit */
+          max_length?: number;
+          digits_only?: boolean;
+          /** Minutes a blocked card stays blocked (default 15), 0 until its failures are cleared */
+          lockout_minutes?: number;
+          /** Keep the wrong PINs between sessions (default false) */
+          persistent?: boolean;
+        };
+        /** Physical readers redirected, by (part of) their name, case insensitive. Deny wins over allow */
+        readers?: {
+          allow?: string[];
+          deny?: string[];
+        };
       };
       webcam?: {
         enabled: boolean;
         quality?: number;
         fps?: number;
         size_limit?: [number, number];
+        /** Camera name (or index). Missing or "default" uses the first camera */
+        device?: string;
+        /** Local preview of what is sent while the camera is in use (default true) */
+        preview?: boolean;
+        /** Blurs the frame but its center (default false) */
+        background_blur?: boolean;
+        /** camera (default) | screen | screen:x,y,width,height | window:<title> | window:0x<id> */
+        source?: string;
       };
       sound_latency_threshold?: number;
+      /** Audio device name (or index). Missing or "default" follows the OS default */
+      audio_output_device?: string;
+      audio_input_device?: string;
+      /** Microphone processing stages, all disabled by default */
+      mic_processing?: {
+        echo_cancellation?: boolean;
+        noise_suppression?: boolean;
+        agc?: boolean;
+      };
       best_experience?: boolean;
       rail?: {
         app: string;
@@ -96,6 +157,28 @@ declare module "runtime" {
         server_info?: { id: string; token: string };
       };
     }): void;
+    /** `start` settings of the text of a `.rdp` file (without password nor signature) */
+    function fromRdpFile(text: string): Parameters<typeof start>[0];
+    /** Text of a `.rdp` file with `start` settings, without the password */
+    function toRdpFile(settings: Parameters<typeof start>[0]): string;
+    /** Unblocks the emulated cards, forgetting the wrong PINs kept between sessions */
+    function unblockSmartcard(): void;
     function sign(rdp_string: string, ticket: string): Promise<string>;
+    /** Issues the in-memory session card used by the `ephemeral` emulated spec */
+    function issueSmartcard(ticket: string, common_name?: string): Promise<void>;
+    /** Cameras of the client, for the `device` webcam setting */
+    function webcams(): Promise<
+      {
+        index: string;
+        name: string;
+        formats: { width: number; height: number; fps: number; format: string }[];
+      }[]
+    >;
+  }
+
+  /** Only registered under script-tester, `undefined` in the launcher. */
+  export namespace Debug {
+    function breakpoint(label?: string): void;
+    function isDryRun(): boolean;
   }
 }